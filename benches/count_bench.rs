@@ -0,0 +1,39 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::Criterion;
+use criterion::black_box;
+use bitarray::bitarray::BitArray;
+
+const BITS: usize = 1 << 20;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut arr = BitArray::new(BITS);
+    arr.randfill();
+
+    // what counting took before there was count_ones
+    c.bench_function("count by get, 1M bits", |b| {
+        b.iter(|| {
+            let a = black_box(&arr);
+            (0 .. a.get_bit_sz()).filter(|&i| a.get(i)).count()
+        })
+    });
+    c.bench_function("count by get_unchecked, 1M bits", |b| {
+        b.iter(|| {
+            let a = black_box(&arr);
+            (0 .. a.get_bit_sz()).filter(|&i| unsafe { a.get_unchecked(i) }).count()
+        })
+    });
+    c.bench_function("BitArray::count_ones, 1M bits", |b| {
+        b.iter(|| black_box(&arr).count_ones())
+    });
+    c.bench_function("BitArray::count_zeros, 1M bits", |b| {
+        b.iter(|| black_box(&arr).count_zeros())
+    });
+    c.bench_function("BitArray::parity, 1M bits", |b| {
+        b.iter(|| black_box(&arr).parity())
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
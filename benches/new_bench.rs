@@ -0,0 +1,35 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use criterion::black_box;
+use bitarray::bitarray::BitArray;
+
+const ARRAYS: usize = 1000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BitArray construction");
+    group.throughput(Throughput::Elements(ARRAYS as u64));
+    // new keeps up to 128 bits inline; from_raw_parts always takes a heap
+    // buffer, which is what new did for every size before
+    for &bits in &[8, 64, 128, 129, 1024] {
+        group.bench_with_input(BenchmarkId::new("new", bits), &bits, |b, &bits| {
+            b.iter(|| {
+                for _ in 0 .. ARRAYS {
+                    black_box(BitArray::new(black_box(bits)));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("from_raw_parts", bits), &bits, |b, &bits| {
+            b.iter(|| {
+                for _ in 0 .. ARRAYS {
+                    black_box(BitArray::from_raw_parts(vec![0; bits.div_ceil(64)], black_box(bits)).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
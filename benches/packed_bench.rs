@@ -0,0 +1,31 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::Criterion;
+use criterion::black_box;
+use bitarray::packed::PackedIntVec;
+
+const N: u64 = 100_000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("PackedIntVec::push 100k x 13 bits", |b| {
+        b.iter(|| {
+            let mut v = PackedIntVec::new(13);
+            for i in 0 .. N {
+                v.push(i & 0x1fff);
+            }
+            v
+        })
+    });
+
+    let mut v = PackedIntVec::new(13);
+    for i in 0 .. N {
+        v.push(i & 0x1fff);
+    }
+    c.bench_function("PackedIntVec::iter sum 100k x 13 bits", |b| {
+        b.iter(|| black_box(&v).iter().sum::<u64>())
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,17 +1,59 @@
 #[macro_use]
 extern crate criterion;
 
-use criterion::Criterion;
+use criterion::{BenchmarkId, Criterion};
 //use criterion::black_box;
-use bitarray::bitarray::BitArray;
+use bitarray::bitarray::{BitArray, RotateStrategy};
 
 fn criterion_benchmark(c: &mut Criterion) {
     let mut arr = BitArray::new(40);
-    
+
     c.bench_function("BitArray::randfill", |b| {
         b.iter(|| arr.randfill())
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+fn strategies(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BitArray::rotate_with");
+    // Naive is O(n * amount), far too slow for big arrays by a third.
+    // 2^24 bits is 2 MiB, past most L2 caches, where the access patterns
+    // of the others differ most.
+    for &(bits, strategies) in &[(1 << 10, &[RotateStrategy::Naive, RotateStrategy::Reverse,
+                                            RotateStrategy::CycleLeader, RotateStrategy::BlockSwap,
+                                            RotateStrategy::WordMoves][..]),
+                                 (1 << 16, &[RotateStrategy::Reverse, RotateStrategy::CycleLeader,
+                                             RotateStrategy::BlockSwap, RotateStrategy::WordMoves][..]),
+                                 (1 << 24, &[RotateStrategy::Reverse, RotateStrategy::BlockSwap,
+                                             RotateStrategy::WordMoves][..])] {
+        let mut arr = BitArray::new(bits);
+        arr.randfill();
+        for &strategy in strategies {
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", strategy), bits), &bits, |b, &bits| {
+                b.iter(|| arr.rotate_with(strategy, 0, bits, bits as isize / 3))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn beyond_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BitArray::rotate_with/beyond_cache");
+    group.sample_size(10);
+    // 1 MiB fits in the L2 or L3 of most machines, 16 MiB in the biggest
+    // last level caches, and 256 MiB in none.
+    for &bytes in &[1 << 20, 16 << 20, 256 << 20] {
+        let bits = 8 * bytes;
+        let mut arr = BitArray::new(bits);
+        arr.randfill();
+        for &strategy in &[RotateStrategy::Reverse, RotateStrategy::WordMoves, RotateStrategy::Blocked] {
+            let id = BenchmarkId::new(format!("{:?}", strategy), format!("{}MiB", bytes >> 20));
+            group.bench_with_input(id, &bits, |b, &bits| {
+                b.iter(|| arr.rotate_with(strategy, 0, bits, bits as isize / 3))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark, strategies, beyond_cache);
 criterion_main!(benches);
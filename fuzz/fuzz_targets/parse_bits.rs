@@ -0,0 +1,29 @@
+// BitArray::from_str and from_hex on arbitrary strings.
+//
+//     cargo +nightly fuzz run parse_bits
+#![no_main]
+use bitarray::bitarray::BitArray;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    // from_str panics on anything but 0s and 1s by design
+    if text.chars().all(|c| c == '0' || c == '1') {
+        let ba = BitArray::from_str(text);
+        assert_eq!(ba.get_bit_sz(), text.len());
+        assert_eq!(ba.show(), text);
+        assert_eq!(ba.count_ones(), text.matches('1').count());
+    }
+
+    match BitArray::from_hex(text) {
+        Ok(ba) => {
+            assert_eq!(ba.get_bit_sz(), 4 * text.chars().count());
+            assert_eq!(ba.to_hex(), text.to_ascii_lowercase());
+            assert_eq!(BitArray::from_hex(&ba.to_hex()), Ok(ba));
+        }
+        Err(e) => {
+            assert_eq!(text.chars().nth(e.index), Some(e.found));
+            assert!(!e.found.is_ascii_hexdigit());
+            assert!(text.chars().take(e.index).all(|c| c.is_ascii_hexdigit()));
+        }
+    }
+});
@@ -0,0 +1,51 @@
+// Rotations of arbitrary arrays, checked against a Vec<bool> model.
+//
+//     cargo +nightly fuzz run rotate
+#![no_main]
+use arbitrary::Arbitrary;
+use bitarray::bitarray::BitArray;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    bytes: Vec<u8>,
+    /// Bits of the last byte to drop, so sizes are not all multiples of 8.
+    trim: u8,
+    offset: usize,
+    length: usize,
+    amount: isize,
+}
+
+fuzz_target!(|input: Input| {
+    // rotate is quadratic for now; big inputs only find timeouts
+    if input.bytes.len() > 512 {
+        return;
+    }
+    let bit_sz = (8 * input.bytes.len()).saturating_sub(input.trim as usize % 8);
+    let mut ba = BitArray::new(bit_sz);
+    let mut model: Vec<bool> = (0 .. bit_sz).map(|i| input.bytes[i / 8] >> (i % 8) & 1 == 1).collect();
+    for (i, &b) in model.iter().enumerate() {
+        ba.set(i, b);
+    }
+
+    // any range that fits the array
+    let offset = input.offset % (bit_sz + 1);
+    let length = input.length % (bit_sz - offset + 1);
+    ba.rotate(offset, length, input.amount);
+    if length > 0 {
+        let shift = input.amount.rem_euclid(length as isize) as usize;
+        model[offset .. offset + length].rotate_right(shift);
+    }
+
+    assert_eq!(ba.get_bit_sz(), bit_sz);
+    for (i, &b) in model.iter().enumerate() {
+        assert_eq!(ba.get(i), b, "bit {} after rotate({}, {}, {})", i, offset, length, input.amount);
+    }
+    // count_ones works on whole bytes, so this also catches dirty padding
+    assert_eq!(ba.count_ones(), model.iter().filter(|&&b| b).count());
+
+    ba.rotate(offset, length, input.amount.wrapping_neg());
+    for (i, b) in ba.iter().enumerate() {
+        assert_eq!(b, input.bytes[i / 8] >> (i % 8) & 1 == 1, "bit {} after rotating back", i);
+    }
+});
@@ -0,0 +1,41 @@
+// The -t test file parser and runner on arbitrary text.  Neither may
+// panic: bad lines are parse errors and bad rotations are failures.
+//
+//     cargo +nightly fuzz run testfile
+#![no_main]
+use bitarray::testfile::{self, Command};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    let lines = text.lines().count();
+    let tests = match testfile::parse(text) {
+        Ok(tests) => tests,
+        Err(e) => {
+            assert!(e.line >= 1 && e.line <= lines);
+            return;
+        }
+    };
+
+    let mut last = 0;
+    for test in &tests {
+        assert!(test.line > last);
+        last = test.line;
+        for step in &test.steps {
+            assert!(step.line > last && step.line <= lines);
+            last = step.line;
+            // keep the runner from spending forever in a huge rotation
+            if let Command::Rotate { length, .. } = step.command {
+                if length > 1 << 12 {
+                    return;
+                }
+            }
+        }
+    }
+
+    let (ran, failures) = testfile::run_all(&tests, None);
+    assert_eq!(ran, tests.len());
+    for failure in &failures {
+        assert!(tests.iter().filter(|t| t.number == failure.test)
+                .any(|t| t.steps.iter().any(|s| s.line == failure.line)));
+    }
+});
@@ -0,0 +1,369 @@
+// A bit array that stores itself as whichever of a dense BitArray or a
+// sorted list of set indices is smaller, switching as its density moves.
+use std::slice;
+
+use crate::bitarray::{BitArray, IterOnes};
+
+/// Stay dense until fewer than one bit in SPARSE_BELOW is set.  Each index
+/// in the sparse form costs 64 bits, so it's the smaller one below 1/64.
+pub const SPARSE_BELOW: usize = 128;
+
+/// Go dense once more than one bit in DENSE_ABOVE is set.  The gap between
+/// the two thresholds keeps a mask hovering near one of them from
+/// converting back and forth on every set.
+pub const DENSE_ABOVE: usize = 64;
+
+#[derive(Debug, Clone)]
+enum Repr {
+    Dense(BitArray),
+    /// Set bit indices, sorted ascending.
+    Sparse(Vec<usize>),
+}
+
+/// A bit array with the BitArray basics (get, set, fill, fill_range,
+/// invert, rotate, read_bits, write_bits, count_ones, iter_ones) over a
+/// representation that adapts to how many bits are set.  Starts sparse,
+/// since a new array is all zeros.
+#[derive(Debug, Clone)]
+pub struct AdaptiveBitArray {
+    bit_sz: usize,
+    ones: usize,
+    repr: Repr,
+}
+
+impl AdaptiveBitArray {
+    pub fn new(bit_sz: usize) -> AdaptiveBitArray {
+        AdaptiveBitArray { bit_sz, ones: 0, repr: Repr::Sparse(vec![]) }
+    }
+
+    pub fn from_bitarray(ba: BitArray) -> AdaptiveBitArray {
+        let mut arr = AdaptiveBitArray { bit_sz: ba.get_bit_sz(), ones: ba.count_ones(),
+                                         repr: Repr::Dense(ba) };
+        arr.adapt();
+        arr
+    }
+
+    pub fn to_bitarray(&self) -> BitArray {
+        match &self.repr {
+            Repr::Dense(ba) => ba.clone(),
+            Repr::Sparse(idx) => {
+                let mut ba = BitArray::new(self.bit_sz);
+                for &i in idx {
+                    ba.set(i, true);
+                }
+                ba
+            }
+        }
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bit_sz
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.repr, Repr::Sparse(_))
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        assert!(bit_index < self.bit_sz);
+        match &self.repr {
+            Repr::Dense(ba) => ba.get(bit_index),
+            Repr::Sparse(idx) => idx.binary_search(&bit_index).is_ok(),
+        }
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        assert!(bit_index < self.bit_sz);
+        let was = match &mut self.repr {
+            Repr::Dense(ba) => {
+                let was = ba.get(bit_index);
+                ba.set(bit_index, val);
+                was
+            }
+            Repr::Sparse(idx) => match (idx.binary_search(&bit_index), val) {
+                (Ok(_), true) => true,
+                (Ok(pos), false) => {
+                    idx.remove(pos);
+                    true
+                }
+                (Err(pos), true) => {
+                    idx.insert(pos, bit_index);
+                    false
+                }
+                (Err(_), false) => false,
+            },
+        };
+        if was != val {
+            if val {
+                self.ones += 1;
+            } else {
+                self.ones -= 1;
+            }
+            self.adapt();
+        }
+    }
+
+    /// The positions in idx of the indices in [bit_offset, bit_offset +
+    /// bit_length).
+    fn sparse_span(idx: &[usize], bit_offset: usize, bit_length: usize) -> std::ops::Range<usize> {
+        idx.partition_point(|&i| i < bit_offset) .. idx.partition_point(|&i| i < bit_offset + bit_length)
+    }
+
+    /// Switches to the dense form, for a change that could set more
+    /// bits than the sparse form holds cheaply; adapt switches back if
+    /// it doesn't.
+    fn densify(&mut self) -> &mut BitArray {
+        if let Repr::Sparse(_) = self.repr {
+            self.repr = Repr::Dense(self.to_bitarray());
+        }
+        match &mut self.repr {
+            Repr::Dense(ba) => ba,
+            Repr::Sparse(_) => unreachable!(),
+        }
+    }
+
+    /// Sets every bit to val.
+    pub fn fill(&mut self, val: bool) {
+        if val {
+            let mut ba = BitArray::new(self.bit_sz);
+            ba.fill(true);
+            self.ones = self.bit_sz;
+            self.repr = Repr::Dense(ba);
+        } else {
+            self.ones = 0;
+            self.repr = Repr::Sparse(vec![]);
+        }
+        self.adapt();
+    }
+
+    /// Sets bits [bit_offset, bit_offset + bit_length) to val.
+    pub fn fill_range(&mut self, bit_offset: usize, bit_length: usize, val: bool) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        if let (Repr::Sparse(idx), false) = (&mut self.repr, val) {
+            let span = AdaptiveBitArray::sparse_span(idx, bit_offset, bit_length);
+            self.ones -= span.len();
+            idx.drain(span);
+        } else {
+            let ba = self.densify();
+            let before = ba.count_ones_range(bit_offset, bit_length);
+            ba.fill_range(bit_offset, bit_length, val);
+            let after = if val { bit_length } else { 0 };
+            self.ones = self.ones - before + after;
+        }
+        self.adapt();
+    }
+
+    /// Flips every bit.
+    pub fn invert(&mut self) {
+        self.densify().invert();
+        self.ones = self.bit_sz - self.ones;
+        self.adapt();
+    }
+
+    /// As BitArray::rotate: rotates [bit_offset, bit_offset + bit_length)
+    /// right by bit_right_amount, left if it is negative.  The number of
+    /// set bits doesn't change, so neither does the representation.
+    pub fn rotate(&mut self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        match &mut self.repr {
+            Repr::Dense(ba) => ba.rotate(bit_offset, bit_length, bit_right_amount),
+            Repr::Sparse(_) if bit_length == 0 => {}
+            Repr::Sparse(idx) => {
+                let right = BitArray::modulo(bit_right_amount, bit_length);
+                let span = AdaptiveBitArray::sparse_span(idx, bit_offset, bit_length);
+                let moved = &mut idx[span];
+                for i in moved.iter_mut() {
+                    *i = bit_offset + (*i - bit_offset + right) % bit_length;
+                }
+                // the indices that wrapped past the end now come first
+                let wrapped = moved.partition_point(|&i| i >= bit_offset + right);
+                moved.rotate_left(wrapped);
+            }
+        }
+    }
+
+    /// The bit_width (at most 64) bits from bit_offset on, as
+    /// BitArray::read_bits.
+    pub fn read_bits(&self, bit_offset: usize, bit_width: usize) -> u64 {
+        assert!(bit_width <= 64);
+        assert!(bit_offset + bit_width <= self.bit_sz);
+        match &self.repr {
+            Repr::Dense(ba) => ba.read_bits(bit_offset, bit_width),
+            Repr::Sparse(idx) => idx[AdaptiveBitArray::sparse_span(idx, bit_offset, bit_width)]
+                .iter()
+                .fold(0, |value, &i| value | 1 << (i - bit_offset)),
+        }
+    }
+
+    /// Writes the low bit_width (at most 64) bits of value from bit_offset
+    /// on, as BitArray::write_bits.
+    pub fn write_bits(&mut self, bit_offset: usize, bit_width: usize, value: u64) {
+        assert!(bit_width <= 64);
+        assert!(bit_offset + bit_width <= self.bit_sz);
+        assert!(bit_width == 64 || value >> bit_width == 0);
+        match &mut self.repr {
+            Repr::Dense(ba) => {
+                let before = ba.count_ones_range(bit_offset, bit_width);
+                ba.write_bits(bit_offset, bit_width, value);
+                self.ones = self.ones - before + value.count_ones() as usize;
+            }
+            Repr::Sparse(idx) => {
+                let span = AdaptiveBitArray::sparse_span(idx, bit_offset, bit_width);
+                self.ones = self.ones - span.len() + value.count_ones() as usize;
+                let ones = (0 .. bit_width).filter(|k| value >> k & 1 == 1).map(|k| bit_offset + k);
+                idx.splice(span, ones);
+            }
+        }
+        self.adapt();
+    }
+
+    /// Converts if the density has crossed the threshold for the other
+    /// representation.
+    fn adapt(&mut self) {
+        match &self.repr {
+            Repr::Dense(ba) if self.ones * SPARSE_BELOW < self.bit_sz => {
+                self.repr = Repr::Sparse(ba.iter_ones().collect());
+            }
+            Repr::Sparse(_) if self.ones * DENSE_ABOVE > self.bit_sz => {
+                self.repr = Repr::Dense(self.to_bitarray());
+            }
+            _ => {}
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.ones
+    }
+
+    /// The indices of the set bits, in increasing order.
+    pub fn iter_ones(&self) -> AdaptiveIterOnes<'_> {
+        match &self.repr {
+            Repr::Dense(ba) => AdaptiveIterOnes::Dense(ba.iter_ones()),
+            Repr::Sparse(idx) => AdaptiveIterOnes::Sparse(idx.iter()),
+        }
+    }
+}
+
+impl PartialEq for AdaptiveBitArray {
+    fn eq(&self, other: &Self) -> bool {
+        self.bit_sz == other.bit_sz && self.ones == other.ones
+            && self.iter_ones().eq(other.iter_ones())
+    }
+}
+impl Eq for AdaptiveBitArray {}
+
+/// Iterator over the set bits of an AdaptiveBitArray.
+#[derive(Debug, Clone)]
+pub enum AdaptiveIterOnes<'a> {
+    Dense(IterOnes<'a>),
+    Sparse(slice::Iter<'a, usize>),
+}
+
+impl<'a> Iterator for AdaptiveIterOnes<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            AdaptiveIterOnes::Dense(it) => it.next(),
+            AdaptiveIterOnes::Sparse(it) => it.next().copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switches_representation() {
+        let n = 64 * 100;
+        let mut a = AdaptiveBitArray::new(n);
+        assert!(a.is_sparse());
+        for i in 0 .. 100 {
+            a.set(i * 64, true);
+        }
+        assert!(a.is_sparse());
+        a.set(1, true);
+        assert!(!a.is_sparse());
+        // hysteresis: dropping back under 1/64 isn't enough
+        a.set(1, false);
+        a.set(0, false);
+        assert!(!a.is_sparse());
+        for i in 1 .. 51 {
+            a.set(i * 64, false);
+        }
+        assert!(a.is_sparse());
+        assert_eq!(a.count_ones(), 49);
+        assert_eq!(a.iter_ones().next(), Some(51 * 64));
+    }
+
+    #[test]
+    fn test_same_answers_either_way() {
+        let mut ba = BitArray::new(5000);
+        ba.randfill();
+        let mut dense = AdaptiveBitArray::from_bitarray(ba.clone());
+        assert!(!dense.is_sparse());
+        let mut sparse = AdaptiveBitArray::new(5000);
+        for i in (0 .. 5000).step_by(500) {
+            sparse.set(i, true);
+            dense.set(i, true);
+            ba.set(i, true);
+        }
+        assert!(sparse.is_sparse());
+        assert_eq!(dense.to_bitarray(), ba);
+        assert_eq!(dense.count_ones(), ba.count_ones());
+        assert_eq!(dense.iter_ones().collect::<Vec<_>>(), ba.iter_ones().collect::<Vec<_>>());
+        assert_eq!(sparse.iter_ones().collect::<Vec<_>>(), (0 .. 5000).step_by(500).collect::<Vec<_>>());
+        assert!(sparse.get(500) && !sparse.get(501));
+        assert_eq!(AdaptiveBitArray::from_bitarray(sparse.to_bitarray()), sparse);
+    }
+
+    #[test]
+    fn test_bulk_ops() {
+        let n = 64 * 100;
+        let mut ba = BitArray::new(n);
+        let mut a = AdaptiveBitArray::new(n);
+        let check = |a: &AdaptiveBitArray, ba: &BitArray| {
+            assert_eq!(&a.to_bitarray(), ba);
+            assert_eq!(a.count_ones(), ba.count_ones());
+        };
+        for &i in &[3, 63, 64, 700, 6399] {
+            a.set(i, true);
+            ba.set(i, true);
+        }
+        for &(offset, length, amount) in &[(0, n, 5), (60, 700, -50), (3, 0, 9), (6000, 400, 1)] {
+            a.rotate(offset, length, amount);
+            ba.rotate(offset, length, amount);
+            assert!(a.is_sparse());
+            check(&a, &ba);
+        }
+        a.write_bits(60, 10, 0b10_0110_0001);
+        ba.write_bits(60, 10, 0b10_0110_0001);
+        check(&a, &ba);
+        assert_eq!(a.read_bits(58, 64), ba.read_bits(58, 64));
+
+        a.fill_range(100, 1000, true);
+        ba.fill_range(100, 1000, true);
+        assert!(!a.is_sparse());
+        check(&a, &ba);
+        a.rotate(50, 5000, 1234);
+        ba.rotate(50, 5000, 1234);
+        assert_eq!(a.read_bits(1300, 40), ba.read_bits(1300, 40));
+        a.write_bits(1300, 40, 0);
+        ba.write_bits(1300, 40, 0);
+        check(&a, &ba);
+        a.fill_range(0, n - 10, false);
+        ba.fill_range(0, n - 10, false);
+        assert!(a.is_sparse());
+        check(&a, &ba);
+
+        a.invert();
+        ba.invert();
+        assert!(!a.is_sparse());
+        check(&a, &ba);
+        a.fill(false);
+        assert!(a.is_sparse() && a.count_ones() == 0);
+        a.fill(true);
+        assert!(!a.is_sparse() && a.count_ones() == n);
+    }
+}
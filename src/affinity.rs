@@ -0,0 +1,65 @@
+// Keeping a benchmark on one core (`everybit -l --pin-core 2`), so the
+// timings don't jump when the scheduler moves the process, and checking
+// that the core's frequency governor won't scale the clock mid-run.
+// Pinning needs Linux and the affinity feature; elsewhere pin_to_core
+// says so and the timing runs unpinned.
+use std::io;
+
+/// Restricts the calling thread, and threads it starts afterwards, to
+/// core.
+#[cfg(all(target_os = "linux", feature = "affinity"))]
+pub fn pin_to_core(core: usize) -> io::Result<()> {
+    // cpu_set_t is a plain bitmask, all zeros is the empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if core >= 8 * std::mem::size_of::<libc::cpu_set_t>() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no core {}", core)));
+    }
+    unsafe { libc::CPU_SET(core, &mut set) };
+    let r = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if r == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "affinity")))]
+pub fn pin_to_core(_core: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "pinning needs Linux and the affinity feature"))
+}
+
+/// The cpufreq governor of core, None where there is no cpufreq (not
+/// Linux, most VMs and containers).
+pub fn governor(core: usize) -> Option<String> {
+    let path = format!("/sys/devices/system/cpu/cpu{}/cpufreq/scaling_governor", core);
+    std::fs::read_to_string(path).ok().map(|g| g.trim().to_string())
+}
+
+/// A warning to print before timing on core, if its governor is known
+/// and is not "performance".
+pub fn governor_warning(core: usize) -> Option<String> {
+    governor(core).filter(|g| g != "performance").map(|g| {
+        format!("warning: core {} uses the {} cpufreq governor, timings will vary with the clock; \
+                 try `cpupower frequency-set -g performance`", core, g)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(target_os = "linux", feature = "affinity"))]
+    #[test]
+    fn test_pin_to_core() {
+        // core 0 always exists, but a container may not let us use it
+        let allowed = std::thread::spawn(|| pin_to_core(0)).join().unwrap();
+        if allowed.is_ok() {
+            assert!(std::thread::spawn(|| pin_to_core(1 << 20)).join().unwrap().is_err());
+        }
+    }
+
+    #[test]
+    fn test_governor() {
+        match governor(0) {
+            Some(g) => assert_eq!(governor_warning(0).is_some(), g != "performance"),
+            None => assert_eq!(governor_warning(0), None),
+        }
+        assert_eq!(governor(1 << 20), None);
+    }
+}
@@ -0,0 +1,175 @@
+// A concurrent ID allocator: each bit of an AtomicBitArray says whether
+// an ID (a slab slot, a page, ...) is in use.
+use std::sync::atomic::Ordering;
+
+use crate::atomic::AtomicBitArray;
+use crate::sync::AtomicUsize;
+
+/// Hands out the IDs 0 .. capacity to any number of threads without a
+/// lock.  Uncontended, the lowest free ID goes first; while another thread
+/// is freeing a lower one, a claim may pass it by and get a higher one.
+///
+/// A hint remembers the word where the last claim succeeded so a busy
+/// allocator doesn't rescan (and contend on) the full words at the front
+/// every time; freeing an ID moves the hint back so the space is reused.
+#[derive(Debug)]
+pub struct BitmapAllocator {
+    bits: AtomicBitArray,
+    hint: AtomicUsize,
+}
+
+impl BitmapAllocator {
+    pub fn new(capacity: usize) -> BitmapAllocator {
+        BitmapAllocator { bits: AtomicBitArray::new(capacity), hint: AtomicUsize::new(0) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bits.get_bit_sz()
+    }
+
+    /// Claims a free ID, or returns None when every ID is in use.
+    pub fn alloc(&self) -> Option<usize> {
+        let n = self.bits.word_count();
+        if n == 0 {
+            return None;
+        }
+        let start = self.hint.load(Ordering::Relaxed) % n;
+        for w in (start .. n).chain(0 .. start) {
+            if let Some(id) = self.bits.claim_in_word(w, Ordering::Acquire) {
+                if w != start {
+                    self.hint.store(w, Ordering::Relaxed);
+                }
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Releases an ID returned by alloc.  Panics if it isn't allocated.
+    pub fn free(&self, id: usize) {
+        let was_set = self.bits.test_and_clear(id, Ordering::Release);
+        assert!(was_set, "BitmapAllocator::free of unallocated id {}", id);
+        self.hint.fetch_min(id / 64, Ordering::Relaxed);
+    }
+
+    pub fn is_allocated(&self, id: usize) -> bool {
+        self.bits.get(id, Ordering::Acquire)
+    }
+
+    /// The underlying bitmap, one bit per ID.
+    pub fn bitmap(&self) -> &AtomicBitArray {
+        &self.bits
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_alloc_in_order_until_full() {
+        let a = BitmapAllocator::new(130);
+        for i in 0 .. 130 {
+            assert_eq!(a.alloc(), Some(i));
+        }
+        assert_eq!(a.alloc(), None);
+        a.free(70);
+        a.free(3);
+        assert_eq!(a.alloc(), Some(3));
+        assert_eq!(a.alloc(), Some(70));
+        assert_eq!(a.alloc(), None);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(BitmapAllocator::new(0).alloc(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_double_free() {
+        let a = BitmapAllocator::new(10);
+        let id = a.alloc().unwrap();
+        a.free(id);
+        a.free(id);
+    }
+
+    #[test]
+    fn test_threads_get_distinct_ids() {
+        let a = Arc::new(BitmapAllocator::new(4000));
+        let handles: Vec<_> = (0 .. 8).map(|_| {
+            let a = Arc::clone(&a);
+            thread::spawn(move || {
+                let mut mine = vec![];
+                for round in 0 .. 500 {
+                    let id = a.alloc().unwrap();
+                    // give some back to exercise the hint
+                    if round % 3 == 0 {
+                        a.free(id);
+                    } else {
+                        mine.push(id);
+                    }
+                }
+                mine
+            })
+        }).collect();
+        let mut all = HashSet::new();
+        for h in handles {
+            for id in h.join().unwrap() {
+                assert!(all.insert(id));
+                assert!(a.is_allocated(id));
+            }
+        }
+        assert_eq!(all.len(), 8 * 333);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn test_alloc_is_exclusive() {
+        // one ID guarding one slot: whoever holds the ID may write the slot,
+        // and loom flags any unsynchronised access to the cell
+        loom::model(|| {
+            let a = Arc::new(BitmapAllocator::new(1));
+            let slot = Arc::new(UnsafeCell::new(0usize));
+            let handles: Vec<_> = (0 .. 2).map(|t| {
+                let a = Arc::clone(&a);
+                let slot = Arc::clone(&slot);
+                thread::spawn(move || {
+                    if let Some(id) = a.alloc() {
+                        slot.with_mut(|p| unsafe { *p = t });
+                        a.free(id);
+                    }
+                })
+            }).collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+            assert!(!a.is_allocated(0));
+        });
+    }
+
+    #[test]
+    fn test_free_makes_id_reusable() {
+        loom::model(|| {
+            let a = Arc::new(BitmapAllocator::new(65));
+            let first = a.alloc().unwrap();
+            let other = Arc::clone(&a);
+            let t = thread::spawn(move || other.free(first));
+            let second = a.alloc().unwrap();
+            t.join().unwrap();
+            // either the freed ID was reused or the next one was handed out
+            assert!(second == 0 || second == 1);
+            assert_eq!(a.alloc().map(|id| id != second), Some(true));
+        });
+    }
+}
@@ -0,0 +1,91 @@
+// quickcheck::Arbitrary for BitArray, behind the quickcheck feature.
+use quickcheck::{Arbitrary, Gen};
+
+use crate::bitarray::BitArray;
+
+impl Arbitrary for BitArray {
+    /// A random array of up to g.size() bits.
+    fn arbitrary(g: &mut Gen) -> BitArray {
+        let n = usize::arbitrary(g) % (g.size() + 1);
+        let mut ba = BitArray::new(n);
+        for i in 0 .. n {
+            ba.set(i, bool::arbitrary(g));
+        }
+        ba
+    }
+
+    /// Smaller candidates, most aggressive first: shorter arrays (the
+    /// first half, all but the last bit, then each clear bit cut out),
+    /// then the same size with fewer ones (the lower half of the ones
+    /// cleared, then each one cleared in turn).
+    fn shrink(&self) -> Box<dyn Iterator<Item = BitArray>> {
+        let n = self.get_bit_sz();
+        let mut candidates = vec![];
+        if n > 0 {
+            if n > 1 {
+                candidates.push(self.slice(0 .. n / 2).to_bitarray());
+            }
+            candidates.push(self.slice(0 .. n - 1).to_bitarray());
+        }
+        for (i, _) in self.iter().enumerate().filter(|&(_, b)| !b) {
+            let mut cut = BitArray::new(n - 1);
+            for j in 0 .. n - 1 {
+                cut.set(j, self.get(if j < i { j } else { j + 1 }));
+            }
+            candidates.push(cut);
+        }
+        let ones: Vec<usize> = self.iter_ones().collect();
+        if ones.len() > 1 {
+            let mut fewer = self.clone();
+            for &i in &ones[.. ones.len() / 2] {
+                fewer.set(i, false);
+            }
+            candidates.push(fewer);
+        }
+        let this = self.clone();
+        Box::new(candidates.into_iter().chain(ones.into_iter().map(move |i| {
+            let mut fewer = this.clone();
+            fewer.set(i, false);
+            fewer
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{quickcheck, QuickCheck, TestResult};
+
+    #[test]
+    fn test_invert_twice() {
+        fn prop(ba: BitArray) -> bool {
+            let mut b = ba.clone();
+            b.invert();
+            b.invert();
+            b == ba
+        }
+        quickcheck(prop as fn(BitArray) -> bool);
+    }
+
+    #[test]
+    fn test_shrink_candidates_are_smaller() {
+        let ba = BitArray::from_str("1011001");
+        for s in ba.shrink() {
+            assert!(s.get_bit_sz() < 7 || s.count_ones() < 4);
+        }
+        assert_eq!(BitArray::new(0).shrink().count(), 0);
+    }
+
+    #[test]
+    fn test_shrinks_to_minimal_failure() {
+        // "no array has two ones" fails; the minimal counterexample is 11
+        fn prop(ba: BitArray) -> TestResult {
+            TestResult::from_bool(ba.count_ones() < 2)
+        }
+        let result = std::panic::catch_unwind(|| {
+            QuickCheck::new().tests(1000).quickcheck(prop as fn(BitArray) -> TestResult)
+        });
+        let msg = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("bit_sz: 2"), "{}", msg);
+    }
+}
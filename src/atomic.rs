@@ -0,0 +1,309 @@
+// A fixed size bit array that many threads can update at once without a
+// lock, one bit at a time.
+use std::sync::atomic::Ordering;
+
+use crate::bitarray::BitArray;
+use crate::sync::AtomicU64;
+
+/// An array of bits stored in AtomicU64 words.  Bit i lives in bit i % 64
+/// of word i / 64, the same layout read_bits uses, so converting to and
+/// from a BitArray is a word copy.
+///
+/// Every operation takes the memory Ordering to use, as the std atomics
+/// do; Relaxed is enough for independent flags, use Acquire/Release (or
+/// AcqRel) when a bit publishes other data.
+#[derive(Debug)]
+pub struct AtomicBitArray {
+    bit_sz: usize,
+    words: Vec<AtomicU64>,
+}
+
+impl AtomicBitArray {
+    /// bit_sz bits, all clear.
+    pub fn new(bit_sz: usize) -> AtomicBitArray {
+        let words = (0 .. bit_sz.div_ceil(64)).map(|_| AtomicU64::new(0)).collect();
+        AtomicBitArray { bit_sz, words }
+    }
+
+    pub fn from_bitarray(ba: &BitArray) -> AtomicBitArray {
+        let arr = AtomicBitArray::new(ba.get_bit_sz());
+        for (w, word) in arr.words.iter().enumerate() {
+            let width = usize::min(64, arr.bit_sz - w * 64);
+            word.store(ba.read_bits(w * 64, width), Ordering::Relaxed);
+        }
+        arr
+    }
+
+    /// Copies the current contents out, loading each word with order.  The
+    /// copy is only a consistent snapshot if nobody is writing.
+    pub fn to_bitarray(&self, order: Ordering) -> BitArray {
+        let mut ba = BitArray::new(self.bit_sz);
+        for (w, word) in self.words.iter().enumerate() {
+            let width = usize::min(64, self.bit_sz - w * 64);
+            ba.write_bits(w * 64, width, word.load(order));
+        }
+        ba
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bit_sz
+    }
+
+    /// The number of AtomicU64 words backing the array.
+    pub(crate) fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Atomically sets the lowest clear bit of word w and returns its bit
+    /// index, or None if every bit of the word is already set.
+    pub(crate) fn claim_in_word(&self, w: usize, order: Ordering) -> Option<usize> {
+        // bits past the end of the array count as set
+        let used = usize::min(64, self.bit_sz - w * 64);
+        let full = if used == 64 { u64::MAX } else { (1 << used) - 1 };
+        let word = &self.words[w];
+        let mut old = word.load(Ordering::Relaxed);
+        loop {
+            let free = !old & full;
+            if free == 0 {
+                return None;
+            }
+            let bit = free.trailing_zeros() as usize;
+            let prev = word.fetch_or(1 << bit, order);
+            if prev & (1 << bit) == 0 {
+                return Some(w * 64 + bit);
+            }
+            // someone beat us to that bit
+            old = prev;
+        }
+    }
+
+    fn locate(&self, bit_index: usize) -> (&AtomicU64, u64) {
+        assert!(bit_index < self.bit_sz);
+        (&self.words[bit_index / 64], 1 << (bit_index % 64))
+    }
+
+    pub fn get(&self, bit_index: usize, order: Ordering) -> bool {
+        let (word, mask) = self.locate(bit_index);
+        word.load(order) & mask != 0
+    }
+
+    /// Sets the bit to one.
+    pub fn set(&self, bit_index: usize, order: Ordering) {
+        let (word, mask) = self.locate(bit_index);
+        word.fetch_or(mask, order);
+    }
+
+    /// Sets the bit to zero.
+    pub fn clear(&self, bit_index: usize, order: Ordering) {
+        let (word, mask) = self.locate(bit_index);
+        word.fetch_and(!mask, order);
+    }
+
+    /// Sets the bit to val.
+    pub fn store(&self, bit_index: usize, val: bool, order: Ordering) {
+        if val {
+            self.set(bit_index, order)
+        } else {
+            self.clear(bit_index, order)
+        }
+    }
+
+    /// Xors the bit with val and returns its previous value.
+    pub fn fetch_xor(&self, bit_index: usize, val: bool, order: Ordering) -> bool {
+        let (word, mask) = self.locate(bit_index);
+        let prev = word.fetch_xor(if val { mask } else { 0 }, order);
+        prev & mask != 0
+    }
+
+    /// Sets the bit to one and returns its previous value, so exactly one
+    /// of several racing callers sees false.
+    pub fn test_and_set(&self, bit_index: usize, order: Ordering) -> bool {
+        let (word, mask) = self.locate(bit_index);
+        word.fetch_or(mask, order) & mask != 0
+    }
+
+    /// Sets the bit to zero and returns its previous value.
+    pub fn test_and_clear(&self, bit_index: usize, order: Ordering) -> bool {
+        let (word, mask) = self.locate(bit_index);
+        word.fetch_and(!mask, order) & mask != 0
+    }
+
+    /// Stores new in the bit if it currently holds current.  Like
+    /// AtomicU64::compare_exchange the result is Ok(previous) on success
+    /// and Err(actual) otherwise.  Concurrent changes to the other bits of
+    /// the same word never cause a failure, the word is just retried.
+    pub fn compare_exchange(&self,
+                            bit_index: usize,
+                            current: bool,
+                            new: bool,
+                            success: Ordering,
+                            failure: Ordering) -> Result<bool, bool> {
+        let (word, mask) = self.locate(bit_index);
+        let mut old = word.load(failure);
+        loop {
+            if (old & mask != 0) != current {
+                return Err(!current);
+            }
+            let updated = if new { old | mask } else { old & !mask };
+            match word.compare_exchange_weak(old, updated, success, failure) {
+                Ok(_) => return Ok(current),
+                Err(actual) => old = actual,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_get_set_clear() {
+        let arr = AtomicBitArray::new(130);
+        arr.set(0, Ordering::Relaxed);
+        arr.set(64, Ordering::Relaxed);
+        arr.set(129, Ordering::Release);
+        assert!(arr.get(0, Ordering::Relaxed));
+        assert!(arr.get(129, Ordering::Acquire));
+        assert!(!arr.get(1, Ordering::Relaxed));
+        arr.clear(64, Ordering::Relaxed);
+        assert!(!arr.get(64, Ordering::Relaxed));
+        arr.store(3, true, Ordering::Relaxed);
+        assert!(arr.get(3, Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_fetch_xor() {
+        let arr = AtomicBitArray::new(10);
+        assert!(!arr.fetch_xor(7, true, Ordering::AcqRel));
+        assert!(arr.fetch_xor(7, false, Ordering::AcqRel));
+        assert!(arr.fetch_xor(7, true, Ordering::AcqRel));
+        assert!(!arr.get(7, Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_test_and_set_clear() {
+        let arr = AtomicBitArray::new(70);
+        assert!(!arr.test_and_set(65, Ordering::AcqRel));
+        assert!(arr.test_and_set(65, Ordering::AcqRel));
+        assert!(arr.test_and_clear(65, Ordering::AcqRel));
+        assert!(!arr.test_and_clear(65, Ordering::AcqRel));
+        assert!(!arr.get(65, Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_compare_exchange() {
+        let arr = AtomicBitArray::new(70);
+        arr.set(1, Ordering::Relaxed);
+        let (s, f) = (Ordering::AcqRel, Ordering::Acquire);
+        assert_eq!(arr.compare_exchange(0, true, false, s, f), Err(false));
+        assert_eq!(arr.compare_exchange(0, false, true, s, f), Ok(false));
+        assert_eq!(arr.compare_exchange(0, false, true, s, f), Err(true));
+        assert_eq!(arr.compare_exchange(0, true, false, s, f), Ok(true));
+        assert_eq!(arr.compare_exchange(0, false, false, s, f), Ok(false));
+        assert!(arr.get(1, Ordering::Relaxed));
+        assert!(!arr.get(0, Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_test_and_set_has_one_winner() {
+        let arr = Arc::new(AtomicBitArray::new(64));
+        let handles: Vec<_> = (0 .. 8).map(|_| {
+            let arr = Arc::clone(&arr);
+            thread::spawn(move || {
+                (0 .. 64).filter(|&i| !arr.test_and_set(i, Ordering::AcqRel)).count()
+            })
+        }).collect();
+        let won: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(won, 64);
+    }
+
+    #[test]
+    fn test_bitarray_round_trip() {
+        let mut ba = BitArray::new(200);
+        ba.randfill();
+        let arr = AtomicBitArray::from_bitarray(&ba);
+        for i in 0 .. 200 {
+            assert_eq!(arr.get(i, Ordering::Relaxed), ba.get(i));
+        }
+        assert_eq!(arr.to_bitarray(Ordering::Relaxed), ba);
+    }
+
+    #[test]
+    fn test_threads_share_words() {
+        // every thread sets its own bits, all interleaved in the same words
+        let arr = Arc::new(AtomicBitArray::new(8 * 1000));
+        let handles: Vec<_> = (0 .. 8).map(|t| {
+            let arr = Arc::clone(&arr);
+            thread::spawn(move || {
+                for i in 0 .. 1000 {
+                    arr.set(i * 8 + t, Ordering::Relaxed);
+                }
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert!((0 .. 8000).all(|i| arr.get(i, Ordering::Relaxed)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_bounds() {
+        AtomicBitArray::new(64).set(64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn test_test_and_set_has_one_winner() {
+        loom::model(|| {
+            let arr = Arc::new(AtomicBitArray::new(64));
+            let other = Arc::clone(&arr);
+            let t = thread::spawn(move || other.test_and_set(5, Ordering::AcqRel));
+            let mine = arr.test_and_set(5, Ordering::AcqRel);
+            let theirs = t.join().unwrap();
+            assert!(mine != theirs);
+        });
+    }
+
+    #[test]
+    fn test_compare_exchange_ignores_neighbours() {
+        // a concurrent write to another bit of the word must not make the
+        // exchange fail
+        loom::model(|| {
+            let arr = Arc::new(AtomicBitArray::new(64));
+            let other = Arc::clone(&arr);
+            let t = thread::spawn(move || other.set(1, Ordering::Release));
+            let r = arr.compare_exchange(0, false, true, Ordering::AcqRel, Ordering::Acquire);
+            t.join().unwrap();
+            assert_eq!(r, Ok(false));
+            assert!(arr.get(0, Ordering::Acquire) && arr.get(1, Ordering::Acquire));
+        });
+    }
+
+    #[test]
+    fn test_claims_are_distinct() {
+        // 3 bits, so the last claim of the word finds it full
+        loom::model(|| {
+            let arr = Arc::new(AtomicBitArray::new(3));
+            let other = Arc::clone(&arr);
+            let t = thread::spawn(move || {
+                [other.claim_in_word(0, Ordering::Acquire), other.claim_in_word(0, Ordering::Acquire)]
+            });
+            let mine = arr.claim_in_word(0, Ordering::Acquire);
+            let [a, b] = t.join().unwrap();
+            let mut got: Vec<usize> = vec![mine, a, b].into_iter().flatten().collect();
+            got.sort();
+            assert_eq!(got, vec![0, 1, 2]);
+            assert_eq!(arr.claim_in_word(0, Ordering::Acquire), None);
+        });
+    }
+}
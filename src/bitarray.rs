@@ -20,102 +20,356 @@
 //  * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS
 //  * IN THE SOFTWARE.
 //  **/
-use rand::Rng;
-   
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use self::counters::Counter;
+use self::storage::Words;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(test)]
+mod alloc_count;
+mod batch;
+mod builder;
+mod bulk;
+pub mod counters;
+mod dump;
+mod extract;
+mod hex;
+#[cfg(all(feature = "hugepages", target_os = "linux"))]
+mod hugepage;
+mod ingest;
+mod iter;
+pub(crate) mod kernels;
+mod merge;
+mod moves;
+mod net;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod patch;
+mod plan;
+#[cfg(kani)]
+mod proofs;
+mod radix;
+mod raw;
+mod rotations;
+mod runs;
+mod sample;
+mod show;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+mod slice;
+mod storage;
+mod trace;
+mod words;
+pub use self::batch::{FoldOp, FOLD_TILE_BYTES};
+pub use self::builder::BitArrayBuilder;
+#[cfg(feature = "counters")]
+pub use self::counters::OpCounts;
+pub use self::dump::DumpOptions;
+pub use self::hex::InvalidHexDigit;
+#[cfg(all(feature = "hugepages", target_os = "linux"))]
+pub use self::hugepage::{AllocOptions, HUGE_PAGE_BYTES};
+pub use self::iter::{Iter, IterOnes};
+pub use self::merge::{MergePolicy, Merged, MERGE_WORD_BITS};
+pub use self::patch::PatchError;
+pub use self::plan::{RotatePath, RotatePlan};
+pub use self::radix::ParseRadixError;
+pub use self::raw::RawPartsError;
+pub use self::rotations::{RotateStrategy, ROTATE_TILE_BYTES};
+pub use self::runs::{Runs, ZeroRuns};
+pub use self::show::{Grouped, ShowOptions};
+#[cfg(feature = "rayon")]
+pub use self::bulk::PARALLEL_THRESHOLD_BYTES;
+pub use self::slice::{BitSlice, BitSliceMut};
+pub use self::storage::{Allocator, Global};
+pub use self::trace::TraceStep;
+
+/// Bytes of buffer filled by each generator in BitArray::randfill_seeded.
+pub const RANDFILL_CHUNK_BYTES: usize = 1 << 14;
+
+/// Ranges at least this long are reversed a word at a time rather than a
+/// bit at a time; below it the two take about as long.
+pub const REVERSE_BY_WORDS_BITS: usize = 32;
+
+/// Ranges of at most this many bits are rotated in a u128 register.
+pub const ROTATE_IN_REGISTER_BITS: usize = 128;
+
+/// True when the unsafe and unchecked fast paths are routed back through
+/// plain checked code: with the checked feature (for ASAN builds, which
+/// cargo cannot detect) and always under Miri.  Every such fast path
+/// tests this first, so the slow path is what the sanitizers see.
+pub const CHECKED: bool = cfg!(any(feature = "checked", miri));
+
 /// Abstract data type representing an array of bits.
-#[derive(Debug)]
-pub struct BitArray {
+#[derive(Debug, Clone)]
+pub struct BitArray<A: Allocator + Clone = Global> {
     /// The number of bits represented by this bit array.
-    /// Need not be divisible by 8.
+    /// Need not be divisible by 64.
     bit_sz: usize,
 
     /// The underlying memory buffer that stores the bits in
-    /// packed form (8 per byte).
-    data: Vec<u8>,
+    /// packed form, 64 per word, bit i in bit i % 64 of word i / 64.
+    /// The bits past bit_sz are always zero, so whole-word operations
+    /// can ignore the end of the array.  Up to 128 bits are kept inline.
+    data: Words<A>,
 }
 
 impl BitArray {
     /// Allocates space for a new bit array.
     /// bit_sz is the number of bits storable in the resultant bit array
     /// bitarray_t* bitarray_new(const size_t bit_sz);
+    /// Arrays of up to 128 bits are stored inline, without allocating.
     pub fn new(bit_sz: usize) -> BitArray {
-        let num_els = bit_sz / 8;
-        let data = vec![0; num_els + 1];
+        let data = Words::zeroed(BitArray::words_for(bit_sz));
         BitArray { bit_sz, data }
     }
+
+    /// The number of words that hold bit_sz bits.
+    pub(crate) fn words_for(bit_sz: usize) -> usize {
+        bit_sz.div_ceil(64)
+    }
     
+    /// A new zeroed array of bit_sz bits built in buf, reusing its
+    /// allocation when the capacity is enough.  Whatever buf held is lost.
+    pub fn in_buffer(mut buf: Vec<u64>, bit_sz: usize) -> BitArray {
+        buf.clear();
+        buf.resize(BitArray::words_for(bit_sz), 0);
+        BitArray { bit_sz, data: Words::from(buf) }
+    }
+
+    /// Gives up the backing buffer, for in_buffer to reuse.
+    pub fn into_buffer(self) -> Vec<u64> {
+        self.into_raw_parts().0
+    }
+
     pub fn from_u8(n: u8) -> BitArray {
-        let data = vec![n];
+        let mut data = Words::zeroed(1);
+        data[0] = n as u64;
         BitArray { bit_sz: 8, data }
     }
     
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(bits: &str) -> BitArray {
         let mut arr = BitArray::new(bits.len());
         for (i, b) in bits.chars().rev().enumerate() {
             arr.set(i, match b {
                 '0' => false,
                 '1' => true,
-                _ => panic!(format!("BitArray::from_str gets bad input {}", b)),
+                _ => panic!("BitArray::from_str gets bad input {}", b),
             });
         }
-        return arr;
+        arr
     }
-    
+
+    fn bitmask(bit_index: usize) -> u64 {
+        1 << (bit_index % 64)
+    }
+
+    /// A word with its low n bits set, 1 <= n <= 64.
+    fn low_bits(n: usize) -> u64 {
+        u64::MAX >> (64 - n)
+    }
+
+    pub(crate) fn modulo(n: isize, m: usize) -> usize {
+        let signed_m = m as isize;
+        assert!(signed_m > 0);
+        let result = ((n % signed_m) + signed_m) % signed_m;
+        assert!(result >= 0);
+        result as usize
+    }
+
+    /// Copies the len bits of src at src_offset to self at dst_offset, up
+    /// to 64 at a time.
+    pub(crate) fn copy_bits(&mut self, dst_offset: usize, src: &BitArray, src_offset: usize, len: usize) {
+        let mut done = 0;
+        while done < len {
+            let width = usize::min(64, len - done);
+            self.write_bits(dst_offset + done, width, src.read_bits(src_offset + done, width));
+            done += width;
+        }
+    }
+}
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// new, with the words of an array too long to keep inline coming
+    /// from alloc, say an arena shared by a batch of arrays dropped
+    /// together, instead of the global allocator.
+    #[cfg(feature = "allocator_api")]
+    pub fn new_in(bit_sz: usize, alloc: A) -> BitArray<A> {
+        let data = Words::zeroed_in(BitArray::words_for(bit_sz), alloc);
+        BitArray { bit_sz, data }
+    }
+
+    /// The allocator given to new_in.
+    #[cfg(feature = "allocator_api")]
+    pub fn allocator(&self) -> &A {
+        self.data.allocator()
+    }
+
+    /// Makes the array bit_sz zero bits, in place.  No allocation happens
+    /// unless bit_sz needs more room than the array has ever had, so a
+    /// loop that rebuilds a bitmap every frame can reuse one.
+    pub fn clear_and_resize(&mut self, bit_sz: usize) {
+        self.data.resize(0);
+        self.data.resize(BitArray::words_for(bit_sz));
+        self.bit_sz = bit_sz;
+        self.check_invariants();
+    }
+
+    /// The number of bits the array can grow to with clear_and_resize
+    /// before it reallocates.
+    pub fn capacity(&self) -> usize {
+        64 * self.data.capacity()
+    }
+
     /// Returns the number of bits stored in a bit array.
     /// Note the invariant bitarray_get_bit_sz(bitarray_new(n)) = n.
     pub fn get_bit_sz(&self) -> usize {
         self.bit_sz
     }
 
+    /// Changes the size to new_bit_sz bits.  The bits below both sizes are
+    /// kept and any bits added at the end are zero.
+    pub fn resize(&mut self, new_bit_sz: usize) {
+        self.data.resize(BitArray::words_for(new_bit_sz));
+        self.bit_sz = new_bit_sz;
+        self.clear_padding();
+    }
+
     /// Does a random fill of all the bits in the bit array.
     pub fn randfill(&mut self) {
         // possible optimizations:
         // put the generator in the struct
-        // use unsafe to cast our vector of u8 to u32 to vectorize the randomization
-        let mut rng = rand::thread_rng();
-
-        for byte in &mut self.data {
-            *byte = rng.gen();
+        #[cfg(feature = "rayon")]
+        {
+            if 8 * self.data.len() >= bulk::PARALLEL_THRESHOLD_BYTES {
+                // each worker uses its own thread_rng
+                self.data.par_chunks_mut(bulk::CHUNK_BYTES / 8).for_each(|chunk| {
+                    rand::thread_rng().fill(chunk);
+                });
+                self.clear_padding();
+                return;
+            }
         }
+        rand::thread_rng().fill(&mut self.data[..]);
+        self.clear_padding();
+    }
+
+    /// Does a reproducible random fill: the same seed always gives the same
+    /// bits.  The buffer is split into chunks of RANDFILL_CHUNK_BYTES, and
+    /// chunk k gets its own generator seeded from seed and k, so with the
+    /// rayon feature the chunks are filled in parallel without changing
+    /// the result.
+    pub fn randfill_seeded(&mut self, seed: u64) {
+        // filling words draws the same stream as filling their bytes did
+        let fill_chunk = |(k, chunk): (usize, &mut [u64])| {
+            let chunk_seed = seed ^ (k as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            StdRng::seed_from_u64(chunk_seed).fill(chunk);
+        };
+        #[cfg(feature = "rayon")]
+        self.data.par_chunks_mut(RANDFILL_CHUNK_BYTES / 8).enumerate().for_each(fill_chunk);
+        #[cfg(not(feature = "rayon"))]
+        self.data.chunks_mut(RANDFILL_CHUNK_BYTES / 8).enumerate().for_each(fill_chunk);
+        self.clear_padding();
     }
 
     /// Indexes into a bit array, retreiving the bit at the specified zero-based
     /// index.
     pub fn get(&self, bit_index: usize) -> bool {
-        assert_eq!(true, bit_index < self.bit_sz);
-        let byte_idx = bit_index / 8;
-        let target_byte = self.data[byte_idx];
-        target_byte & BitArray::bitmask(bit_index) != 0
+        assert!(bit_index < self.bit_sz);
+        counters::bump(Counter::Get);
+        let word_idx = bit_index / 64;
+        let target_word = self.data[word_idx];
+        target_word & BitArray::bitmask(bit_index) != 0
     }
     
-    fn bitmask(bit_index: usize) -> u8 {
-        1 << (bit_index % 8)
+    /// get without the bounds check, for inner loops whose indices are
+    /// known to be in range.  In CHECKED builds it is get, check and all.
+    ///
+    /// # Safety
+    ///
+    /// bit_index must be less than get_bit_sz().  Past that, the bit read
+    /// is padding or memory past the end of the buffer.
+    #[inline]
+    pub unsafe fn get_unchecked(&self, bit_index: usize) -> bool {
+        if CHECKED {
+            return self.get(bit_index);
+        }
+        debug_assert!(bit_index < self.bit_sz);
+        counters::bump(Counter::Get);
+        self.data.get_unchecked(bit_index / 64) & BitArray::bitmask(bit_index) != 0
     }
 
     /// Indexes into a bit array, setting the bit at the specified zero-based index.
     pub fn set(&mut self, bit_index: usize, val: bool) {
-        assert_eq!(true, bit_index < self.bit_sz);
-        let byte_idx = bit_index / 8;
-        // self.data[byte_idx] &= BitArray::bitmask(bit_index);
+        assert!(bit_index < self.bit_sz);
+        counters::bump(Counter::Set);
+        let word_idx = bit_index / 64;
 
-        // We're storing bits in packed form, 8 per byte.  So to set
-        // the nth bit, we want to set the (n mod 8)th bit of the
-        // (floor(n/8)th) byte.
+        // We're storing bits in packed form, 64 per word.  So to set
+        // the nth bit, we want to set the (n mod 64)th bit of the
+        // (floor(n/64)th) word.
         //
         // In rust, integer division is floored explicitly, so we can
-        // just do it to get the byte; we then bitwise-and the byte
+        // just do it to get the word; we then bitwise-and the word
         // with an appropriate mask to clear out the bit we're about
-        // to set.  We bitwise-or the result with a byte that has
+        // to set.  We bitwise-or the result with a word that has
         // either a 1 or a 0 in the correct place.
         let mask = BitArray::bitmask(bit_index);
         if val {
             // set a one
-            self.data[byte_idx] |= mask
+            self.data[word_idx] |= mask
         } else {
             // set a zero
-            self.data[byte_idx] &= !mask            
+            self.data[word_idx] &= !mask
         }
-        
+        self.check_invariants();
+    }
+
+    /// set without the bounds check, as get_unchecked.  In CHECKED builds
+    /// it is set.
+    ///
+    /// # Safety
+    ///
+    /// bit_index must be less than get_bit_sz().  Past that, the write
+    /// breaks the zero padding or lands outside the buffer.
+    #[inline]
+    pub unsafe fn set_unchecked(&mut self, bit_index: usize, val: bool) {
+        if CHECKED {
+            self.set(bit_index, val);
+            return;
+        }
+        debug_assert!(bit_index < self.bit_sz);
+        counters::bump(Counter::Set);
+        let mask = BitArray::bitmask(bit_index);
+        let word = self.data.get_unchecked_mut(bit_index / 64);
+        if val {
+            *word |= mask
+        } else {
+            *word &= !mask
+        }
+        self.check_invariants();
+    }
+
+    /// Reads the bit_width (at most 64) bits starting at bit_offset as an
+    /// unsigned integer.  Bit bit_offset + i of the array becomes bit i of
+    /// the result.
+    pub fn read_bits(&self, bit_offset: usize, bit_width: usize) -> u64 {
+        assert!(bit_width <= 64);
+        assert!(bit_offset + bit_width <= self.bit_sz);
+        kernels::read_bits(&self.data, bit_offset, bit_width)
+    }
+
+    /// Writes the low bit_width (at most 64) bits of value into the array
+    /// starting at bit_offset, the inverse of read_bits.  value must fit in
+    /// bit_width bits.
+    pub fn write_bits(&mut self, bit_offset: usize, bit_width: usize, value: u64) {
+        assert!(bit_width <= 64);
+        assert!(bit_offset + bit_width <= self.bit_sz);
+        assert!(bit_width == 64 || value >> bit_width == 0);
+        kernels::write_bits(&mut self.data, bit_offset, bit_width, value);
+        self.check_invariants();
     }
 
     /// Rotates a subarray.
@@ -142,12 +396,18 @@ impl BitArray {
     /// bitarray.rotate(2, 5, 2) rotates the third through seventh
     /// (inclusive) bits right two places.  After the rotation, ba contains the
     /// byte 0b10110100.
-    
+    ///
+    /// The rotation is in place: nothing is allocated on the heap, the only
+    /// scratch being a fixed buffer on the stack.  The exception is the
+    /// rayon feature's parallel path for very long ranges, whose threads
+    /// need a little bookkeeping.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self),
+                                                        fields(bit_sz = self.bit_sz)))]
     pub fn rotate(&mut self,
                   bit_offset: usize,
                   bit_length: usize,
                   bit_right_amount: isize) {
-        assert_eq!(true, bit_offset + bit_offset <= self.bit_sz);
+        assert!(bit_offset + bit_length <= self.bit_sz);
         
         if bit_length == 0 {
             return;
@@ -155,15 +415,100 @@ impl BitArray {
         
         // Convert a rotate left or right to a left rotate only, and eliminate
         // multiple full rotations.
-        self.rotate_left(bit_offset,
-                         bit_length,
-                         BitArray::modulo(-bit_right_amount, bit_length));
+        // (negating the amount first would overflow on isize::MIN)
+        let right = BitArray::modulo(bit_right_amount, bit_length);
+        let left = (bit_length - right) % bit_length;
+        self.rotate_left_by_path(bit_offset, bit_length, left);
+    }
+
+    /// rotate by bit_left_amount places to the left, as an unsigned amount.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self),
+                                                        fields(bit_sz = self.bit_sz)))]
+    pub fn rotate_left(&mut self, bit_offset: usize, bit_length: usize, bit_left_amount: usize) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        if bit_length == 0 {
+            return;
+        }
+        self.rotate_left_by_path(bit_offset, bit_length, bit_left_amount % bit_length);
     }
 
-    fn rotate_left(&mut self,
-                       bit_offset: usize,
-                       bit_length: usize,
-                       bit_left_amount: usize) {
+    /// rotate by bit_right_amount places to the right, as an unsigned
+    /// amount.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self),
+                                                        fields(bit_sz = self.bit_sz)))]
+    pub fn rotate_right(&mut self, bit_offset: usize, bit_length: usize, bit_right_amount: usize) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        if bit_length == 0 {
+            return;
+        }
+        let left = (bit_length - bit_right_amount % bit_length) % bit_length;
+        self.rotate_left_by_path(bit_offset, bit_length, left);
+    }
+
+    /// A copy of the array with rotate done to it, leaving self as it is;
+    /// for checking one rotation against another.
+    pub fn rotated(&self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) -> BitArray<A> {
+        let mut out = self.clone();
+        out.rotate(bit_offset, bit_length, bit_right_amount);
+        out
+    }
+
+    /// Rotates [bit_offset, bit_offset + bit_length) left by
+    /// bit_left_amount < bit_length, the way plan_rotate says.
+    fn rotate_left_by_path(&mut self, bit_offset: usize, bit_length: usize, bit_left_amount: usize) {
+        let (offset, length, left) = (bit_offset, bit_length, bit_left_amount);
+        match BitArray::rotate_path(length, left) {
+            RotatePath::Identity => {}
+            RotatePath::Register => self.rotate_left_in_register(offset, length, left),
+            #[cfg(feature = "rayon")]
+            RotatePath::ParallelReversal => self.rotate_left_by_reversal(offset, length, left),
+            RotatePath::WordMoves => self.rotate_left_by_word_moves(offset, length, left, |_, _| {}),
+        }
+    }
+
+    /// Rotates left by loading the range, at most ROTATE_IN_REGISTER_BITS
+    /// bits, into a u128, rotating that, and storing it back.  Bit 0 of
+    /// the range is the low bit of the register, so a left rotation of the
+    /// range is a right rotation of the register.
+    fn rotate_left_in_register(&mut self,
+                               bit_offset: usize,
+                               bit_length: usize,
+                               bit_left_amount: usize) {
+        let low = usize::min(64, bit_length);
+        let mut value = self.read_bits(bit_offset, low) as u128;
+        if bit_length > 64 {
+            value |= (self.read_bits(bit_offset + 64, bit_length - 64) as u128) << 64;
+        }
+        let turned = if bit_length == 128 {
+            value.rotate_right(bit_left_amount as u32)
+        } else {
+            // 0 < bit_left_amount < bit_length < 128, so neither shift
+            // overflows
+            ((value >> bit_left_amount) | (value << (bit_length - bit_left_amount))) & ((1 << bit_length) - 1)
+        };
+        self.write_bits(bit_offset, low, turned as u64);
+        if bit_length > 64 {
+            self.write_bits(bit_offset + 64, bit_length - 64, (turned >> 64) as u64);
+        }
+    }
+
+    /// Rotates left by reversing the first bit_left_amount bits, then the
+    /// rest, then the whole range: ab -> (a^R b^R)^R = ba.  Every bit moves
+    /// twice, so the cost is linear in bit_length whatever the amount.
+    fn rotate_left_by_reversal(&mut self,
+                               bit_offset: usize,
+                               bit_length: usize,
+                               bit_left_amount: usize) {
+        self.reverse_range(bit_offset, bit_left_amount);
+        self.reverse_range(bit_offset + bit_left_amount, bit_length - bit_left_amount);
+        self.reverse_range(bit_offset, bit_length);
+    }
+
+    /// Rotates left one bit at a time, RotateStrategy::Naive.
+    fn rotate_left_naive(&mut self,
+                         bit_offset: usize,
+                         bit_length: usize,
+                         bit_left_amount: usize) {
         for _ in 0 .. bit_left_amount {
             self.rotate_left_one(bit_offset, bit_length);
         }
@@ -175,49 +520,164 @@ impl BitArray {
         let first_bit = self.get(bit_offset);
         let mut i = bit_offset;
         
+        // rotate checked that the range is inside the array
         while i + 1 < bit_offset + bit_length {
-            self.set(i, self.get(i+1));
+            unsafe { self.set_unchecked(i, self.get_unchecked(i+1)) };
             i += 1;
         }
         self.set(i, first_bit) ;
     }
     
-    fn modulo(n: isize, m: usize) -> usize {
-        let signed_m = m as isize;
-        assert_eq!(true, signed_m > 0);
-        let result = ((n % signed_m) + signed_m) % signed_m;
-        assert_eq!(true, result >= 0);
-        return result as usize;
+    /// Reverses the order of the bits in [bit_offset, bit_offset + bit_length)
+    /// by swapping bits in from both ends.  Ranges of REVERSE_BY_WORDS_BITS
+    /// or more swap up to 64 bits from each end at a time, and with the
+    /// simd feature the ends are swapped in blocks with vector instructions
+    /// where the CPU can.  With the rayon feature, ranges of
+    /// 8 * PARALLEL_THRESHOLD_BYTES bits or more are reversed on all cores.
+    pub fn reverse_range(&mut self, bit_offset: usize, bit_length: usize) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        let (start, end) = (bit_offset, bit_offset + bit_length);
+        #[cfg(feature = "rayon")]
+        {
+            if bit_length >= parallel::PARALLEL_BITS {
+                self.reverse_range_parallel(start, end);
+                return;
+            }
+        }
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        let (start, end) = if simd::available() { self.reverse_ends_simd(start, end) } else { (start, end) };
+        if end - start >= REVERSE_BY_WORDS_BITS {
+            self.reverse_by_words(start, end);
+            return;
+        }
+        if end - start < 2 {
+            return;
+        }
+        let (mut i, mut j) = (start, end - 1);
+        // start <= i < j < end <= bit_sz, asserted above
+        while i < j {
+            let (a, b) = unsafe { (self.get_unchecked(i), self.get_unchecked(j)) };
+            unsafe {
+                self.set_unchecked(i, b);
+                self.set_unchecked(j, a);
+            }
+            i += 1;
+            j -= 1;
+        }
     }
 
-    pub fn show(&self) -> String {
-        let mut s = String::from("");
-        let n = self.get_bit_sz();
-        for i in 0 .. n {
-            if self.get(i) {
-                s.insert(0, '1');
-            } else {
-                s.insert(0, '0');
+    /// reverse_range of [i, j) a word at a time, see
+    /// kernels::reverse_by_words.
+    fn reverse_by_words(&mut self, i: usize, j: usize) {
+        kernels::reverse_by_words(&mut self.data, i, j);
+        self.check_invariants();
+    }
+
+    /// Reverses the whole array, so that bit 0 and bit bit_sz - 1 trade
+    /// places and show() reads backwards.
+    pub fn reverse(&mut self) {
+        self.reverse_range(0, self.bit_sz);
+    }
+
+    /// Panics unless the buffer has room for bit_sz bits and every bit of
+    /// it past bit_sz is zero.  Mutations call this themselves with the
+    /// paranoid feature.
+    pub fn assert_invariants(&self) {
+        assert!(64 * self.data.len() >= self.bit_sz,
+                "{} bits in a {} word buffer", self.bit_sz, self.data.len());
+        let used = self.bit_sz / 64;
+        if used < self.data.len() {
+            assert_eq!(self.data[used] >> (self.bit_sz % 64), 0,
+                       "padding bits set in word {} of a {} bit array", used, self.bit_sz);
+            if let Some(i) = self.data[used + 1 ..].iter().position(|&w| w != 0) {
+                panic!("padding word {} set in a {} bit array", used + 1 + i, self.bit_sz);
+            }
+        }
+    }
+
+    /// assert_invariants with the paranoid feature, nothing without.
+    #[inline(always)]
+    pub(crate) fn check_invariants(&self) {
+        #[cfg(feature = "paranoid")]
+        self.assert_invariants();
+    }
+
+    /// Zeroes the bits of the buffer past bit_sz so whole-word operations
+    /// don't leave garbage behind the end of the array.
+    fn clear_padding(&mut self) {
+        let used = self.bit_sz / 64;
+        if used < self.data.len() {
+            self.data[used] &= (1u64 << (self.bit_sz % 64)) - 1;
+            for word in &mut self.data[used + 1 ..] {
+                *word = 0;
             }
         }
-        s
+        // every whole-word mutation ends here
+        self.check_invariants();
+    }
+
+    /// Byte i of the bits, holding bits [8i, 8i + 8) with bit 8i lowest:
+    /// the array as the packed bytes it used to be stored in.
+    pub(crate) fn byte(&self, i: usize) -> u8 {
+        (self.data[i / 8] >> (8 * (i % 8))) as u8
+    }
+
+    /// Replaces byte i, see byte.  Bits past bit_sz are the caller's to
+    /// clear.
+    pub(crate) fn set_byte(&mut self, i: usize, val: u8) {
+        let shift = 8 * (i % 8);
+        self.data[i / 8] = (self.data[i / 8] & !(0xff << shift)) | ((val as u64) << shift);
+    }
+
+    /// The words' memory, which on a little-endian machine is those bytes
+    /// in order.
+    #[cfg(target_endian = "little")]
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        // u8 has no alignment or validity requirements, and the length is
+        // exactly that of the words
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr() as *const u8, 8 * self.data.len()) }
+    }
+
+    /// as_bytes, mutably.
+    #[cfg(target_endian = "little")]
+    pub(crate) fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data.as_mut_ptr() as *mut u8, 8 * self.data.len()) }
+    }
+
+    pub fn show(&self) -> String {
+        // bit 0 is printed last
+        (0 .. self.get_bit_sz()).rev()
+            .map(|i| if self.get(i) { '1' } else { '0' })
+            .collect()
     }
 }
 
-impl PartialEq for BitArray {
+/// The paranoid feature checks the invariants one last time on drop, to
+/// catch corruption by code that never calls another mutator.
+#[cfg(feature = "paranoid")]
+impl<A: Allocator + Clone> Drop for BitArray<A> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            self.assert_invariants();
+        }
+    }
+}
+
+/// Compares a word at a time.  Only the bits below bit_sz count: the rest
+/// of the last word is masked off, so the padding never decides equality.
+impl<A: Allocator + Clone> PartialEq for BitArray<A> {
     fn eq(&self, other: &Self) -> bool {
-        if self.get_bit_sz() != other.get_bit_sz() {
+        if self.bit_sz != other.bit_sz {
             return false;
         }
-        for i in 0 .. self.get_bit_sz() {
-            if self.get(i) != other.get(i) {
-                return false;
-            }
+        let (full, rest) = (self.bit_sz / 64, self.bit_sz % 64);
+        if self.data[.. full] != other.data[.. full] {
+            return false;
         }
-        return true;
+        rest == 0 || (self.data[full] ^ other.data[full]) & BitArray::low_bits(rest) == 0
     }
 }
-impl Eq for BitArray {}
+impl<A: Allocator + Clone> Eq for BitArray<A> {}
 
 
 #[cfg(test)]
@@ -230,7 +690,7 @@ mod tests {
         let mut arr = BitArray::new(N);
         for i in 0..N {
             arr.set(i, true);
-            assert_eq!(arr.get(i), true);
+            assert!(arr.get(i));
         }
     }
 
@@ -239,7 +699,7 @@ mod tests {
         let mut arr = BitArray::new(N);
         for i in 0..N {
             arr.set(i, false);
-            assert_eq!(arr.get(i), false);
+            assert!(!arr.get(i));
         }
     }
 
@@ -260,14 +720,14 @@ mod tests {
         let ba = BitArray::from_u8(0b10010110);
         assert_eq!(ba.get_bit_sz(), 8);
         
-        assert_eq!(ba.get(0), false);
-        assert_eq!(ba.get(1), true);
-        assert_eq!(ba.get(2), true);
-        assert_eq!(ba.get(3), false);
-        assert_eq!(ba.get(4), true);
-        assert_eq!(ba.get(5), false);
-        assert_eq!(ba.get(6), false);
-        assert_eq!(ba.get(7), true);
+        assert!(!ba.get(0));
+        assert!(ba.get(1));
+        assert!(ba.get(2));
+        assert!(!ba.get(3));
+        assert!(ba.get(4));
+        assert!(!ba.get(5));
+        assert!(!ba.get(6));
+        assert!(ba.get(7));
     }
 
     #[test]
@@ -286,11 +746,82 @@ mod tests {
         assert_eq!(15, ba.get_bit_sz());
         
         for i in 0 .. ba.get_bit_sz() {        
-            assert_eq!(ba.get(i), true);
+            assert!(ba.get(i));
         }
     }
 
     
+    #[test]
+    fn test_rotate_in_register() {
+        let mut ba = BitArray::new(150);
+        ba.randfill();
+        // ranges starting and ending either side of byte and word
+        // boundaries, up to the longest a register takes
+        for offset in 0 .. 10 {
+            for &length in &[1, 2, 7, 8, 9, 15, 63, 64, 65, 100, 127, 128] {
+                for &amount in &[1, -1, 3, -5, 64, length as isize / 2, length as isize - 1] {
+                    let mut want = ba.clone();
+                    want.rotate_with(RotateStrategy::Naive, offset, length, amount);
+                    let mut got = ba.clone();
+                    got.rotate(offset, length, amount);
+                    assert_eq!(got, want, "{} {} {}", offset, length, amount);
+                }
+            }
+        }
+        let mut ba = BitArray::from_str("10010110");
+        ba.rotate(2, 5, 2);
+        assert_eq!(ba.show(), "11010010");
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut a = BitArray::new(200);
+        a.randfill();
+        let mut b = a.clone();
+        assert_eq!(a, b);
+        b.set(199, !a.get(199));
+        assert_ne!(a, b);
+        b.set(199, a.get(199));
+        b.set(0, !a.get(0));
+        assert_ne!(a, b);
+        assert_ne!(BitArray::new(200), BitArray::new(201));
+        assert_eq!(BitArray::new(0), BitArray::new(0));
+    }
+
+    #[test]
+    fn test_eq_ignores_padding() {
+        let mut a = BitArray::new(130);
+        a.randfill();
+        let mut b = a.clone();
+        // only bits past 130 differ; put them back before the drop checks
+        b.data[2] |= 1 << 63;
+        let equal = (a == b, b == a);
+        b.data[2] &= !(1 << 63);
+        assert_eq!(equal, (true, true));
+        // and spare words given to from_raw_parts are dropped
+        let (mut buf, len) = a.clone().into_raw_parts();
+        buf.extend([0, 0]);
+        assert_eq!(BitArray::from_raw_parts(buf, len).unwrap(), a);
+        let whole = BitArray::from_str(&"10".repeat(64));
+        let (mut buf, len) = whole.clone().into_raw_parts();
+        buf.push(0);
+        assert_eq!(BitArray::from_raw_parts(buf, len).unwrap(), whole);
+    }
+
+    #[test]
+    fn test_unchecked() {
+        let mut ba = BitArray::new(130);
+        ba.randfill();
+        let mut copy = BitArray::new(130);
+        for i in 0 .. 130 {
+            unsafe { copy.set_unchecked(i, ba.get_unchecked(i)) };
+            assert_eq!(unsafe { copy.get_unchecked(i) }, ba.get(i));
+        }
+        assert_eq!(copy, ba);
+        unsafe { copy.set_unchecked(129, !ba.get(129)) };
+        assert_ne!(copy, ba);
+    }
+
     #[test]
     fn test_rotate_left_one_1() {
         let mut ba = BitArray::from_u8(0b10010110);
@@ -305,7 +836,7 @@ mod tests {
                                  0b10010110);
         let mut ba = BitArray::from_u8(start);
         let exp_ba = BitArray::from_u8(expected);
-        ba.rotate_left(0, 8, 0);        
+        ba.rotate_left_naive(0, 8, 0);        
         assert_eq!(ba.data, exp_ba.data);
     }
 
@@ -327,7 +858,7 @@ mod tests {
                                  0b01001011);
         let mut ba = BitArray::from_u8(start);
         let exp_ba = BitArray::from_u8(expected);
-        ba.rotate_left(0, 8, 1);        
+        ba.rotate_left_naive(0, 8, 1);        
         assert_eq!(ba.data, exp_ba.data);
     }
 
@@ -337,7 +868,7 @@ mod tests {
                                  0b10100101,);
         let mut ba = BitArray::from_u8(start);
         let exp_ba = BitArray::from_u8(expected);
-        ba.rotate_left(0, 8, 2);        
+        ba.rotate_left_naive(0, 8, 2);        
         assert_eq!(ba.data, exp_ba.data);
     }
 
@@ -348,7 +879,7 @@ mod tests {
                                  0b10011100,);
         let mut ba = BitArray::from_u8(start);
         let exp_ba = BitArray::from_u8(expected);
-        ba.rotate_left(1, 4, 2);        
+        ba.rotate_left_naive(1, 4, 2);        
         println!("{:?} =? {:?}", ba.show(), exp_ba.show());
         assert_eq!(ba.data, exp_ba.data);
     }
@@ -360,7 +891,7 @@ mod tests {
                                  0b10010110,);
         let mut ba = BitArray::from_u8(start);
         let exp_ba = BitArray::from_u8(expected);
-        ba.rotate_left(1, 4, 4);        
+        ba.rotate_left_naive(1, 4, 4);        
         println!("{:?} =? {:?}", ba.show(), exp_ba.show());
         assert_eq!(ba.data, exp_ba.data);
     }
@@ -390,6 +921,281 @@ mod tests {
         assert_eq!(ba.data, exp_ba.data);
     }
 
+    #[test]
+    fn test_rotate_extreme_amounts() {
+        // 2^63 = 1 (mod 7), so isize::MIN is a left rotation by one
+        let mut ba = BitArray::from_str("10010110");
+        ba.rotate(0, 7, isize::MIN);
+        assert_eq!(ba.show(), "10001011");
+        ba.rotate(0, 7, isize::MAX);
+        assert_eq!(ba.show(), "10001011");
+        ba.rotate(0, 7, 1);
+        assert_eq!(ba.show(), "10010110");
+    }
+
+    #[test]
+    fn test_rotate_left_and_right() {
+        let mut ba = BitArray::new(300);
+        ba.randfill();
+        for &(offset, length, amount) in &[(0, 300, 1), (5, 290, 149), (3, 7, usize::MAX), (64, 64, 640),
+                                           (9, 0, 3), (0, 128, 127)] {
+            let mut left = ba.clone();
+            left.rotate_left(offset, length, amount);
+            let mut right = ba.clone();
+            right.rotate_right(offset, length, amount);
+            // the same amounts as rotate's, reduced first so they fit
+            let reduced = if length == 0 { 0 } else { (amount % length) as isize };
+            assert_eq!(left, ba.rotated(offset, length, -reduced), "{} {} {}", offset, length, amount);
+            assert_eq!(right, ba.rotated(offset, length, reduced), "{} {} {}", offset, length, amount);
+            right.rotate_left(offset, length, amount);
+            assert_eq!(right, ba);
+        }
+    }
+
+    #[test]
+    fn test_rotated() {
+        let ba = BitArray::from_str("10010110");
+        assert_eq!(ba.rotated(0, 8, -1).show(), "01001011");
+        assert_eq!(ba.rotated(2, 5, 2).show(), "11010010");
+        assert_eq!(ba.show(), "10010110");
+    }
+
+    #[test]
+    fn test_rotate_past_the_middle() {
+        // rotate used to assert 2 * bit_offset <= bit_sz
+        let mut ba = BitArray::from_str("10010110");
+        ba.rotate(5, 3, 1);
+        assert_eq!(ba.show(), "00110110");
+        ba.rotate(7, 1, 3);
+        assert_eq!(ba.show(), "00110110");
+    }
+
+    #[test]
+    fn test_rotate_by_reversal_matches_one_at_a_time() {
+        let mut ba = BitArray::new(300);
+        ba.randfill();
+        for &(offset, length, left) in &[(0, 300, 2), (5, 290, 149), (17, 100, 99), (64, 64, 32)] {
+            let mut want = ba.clone();
+            want.rotate_left_naive(offset, length, left);
+            ba.rotate_left_by_reversal(offset, length, left);
+            assert_eq!(ba, want);
+        }
+    }
+
+    #[test]
+    fn test_randfill_seeded() {
+        let n = 5 * RANDFILL_CHUNK_BYTES * 8 + 3;
+        let (mut a, mut b, mut c) = (BitArray::new(n), BitArray::new(n), BitArray::new(n));
+        a.randfill_seeded(42);
+        b.randfill_seeded(42);
+        c.randfill_seeded(43);
+        assert_eq!(a, b);
+        assert!(a != c);
+        assert_eq!(a.data.last(), Some(&(a.data.last().unwrap() & 0b111)));
+
+        // no two chunks get the same stream
+        let chunk = RANDFILL_CHUNK_BYTES / 8;
+        assert!(a.data[.. chunk] != a.data[chunk .. 2 * chunk]);
+    }
+
+    #[test]
+    fn test_read_bits() {
+        let ba = BitArray::from_str("1111000010010110");
+        assert_eq!(ba.read_bits(0, 8), 0b10010110);
+        assert_eq!(ba.read_bits(4, 8), 0b00001001);
+        assert_eq!(ba.read_bits(1, 15), 0b111100001001011);
+        assert_eq!(ba.read_bits(5, 0), 0);
+    }
+
+    #[test]
+    fn test_write_bits() {
+        let mut ba = BitArray::new(100);
+        ba.write_bits(3, 64, 0xdead_beef_0123_4567);
+        ba.write_bits(67, 5, 0b10101);
+        assert_eq!(ba.read_bits(3, 64), 0xdead_beef_0123_4567);
+        assert_eq!(ba.read_bits(67, 5), 0b10101);
+        assert_eq!(ba.read_bits(0, 3), 0);
+        ba.write_bits(5, 3, 0);
+        assert_eq!(ba.read_bits(3, 8), 0x67 & 0b11100011);
+    }
+
+    #[test]
+    fn test_bits_across_words() {
+        let mut ba = BitArray::new(200);
+        let value = 0x8123_4567_89ab_cdef;
+        for offset in 0 .. 136 {
+            ba.fill(false);
+            ba.write_bits(offset, 64, value);
+            assert_eq!(ba.read_bits(offset, 64), value);
+            assert_eq!(ba.count_ones(), value.count_ones() as usize);
+            assert!(ba.get(offset) && ba.get(offset + 63));
+            assert_eq!(ba.read_bits(offset + 56, 8), 0x81);
+        }
+    }
+
+    #[test]
+    fn test_reverse_range() {
+        let mut ba = BitArray::from_str("1111000010010110");
+        ba.reverse_range(3, 9);
+        assert_eq!(ba, BitArray::from_str("1111010010000110"));
+        ba.reverse_range(0, 16);
+        assert_eq!(ba, BitArray::from_str("0110000100101111"));
+        ba.reverse_range(5, 1);
+        ba.reverse_range(16, 0);
+        assert_eq!(ba, BitArray::from_str("0110000100101111"));
+    }
+
+    #[test]
+    fn test_reverse_range_across_boundaries() {
+        let mut ba = BitArray::new(300);
+        ba.randfill();
+        // unaligned at both ends, crossing byte and word boundaries
+        for &(offset, length) in &[(3, 9), (7, 2), (5, 70), (61, 7), (1, 298), (63, 130), (100, 101)] {
+            let mut reversed = ba.clone();
+            reversed.reverse_range(offset, length);
+            for i in 0 .. 300 {
+                let from = if (offset .. offset + length).contains(&i) { 2 * offset + length - 1 - i } else { i };
+                assert_eq!(reversed.get(i), ba.get(from), "{} {} bit {}", offset, length, i);
+            }
+            reversed.reverse_range(offset, length);
+            assert_eq!(reversed, ba);
+        }
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut ba = BitArray::from_str("1111000010");
+        ba.reverse();
+        assert_eq!(ba.show(), "0100001111");
+        let mut empty = BitArray::new(0);
+        empty.reverse();
+        assert_eq!(empty.get_bit_sz(), 0);
+    }
+
+    #[test]
+    fn test_inline_sizes() {
+        use self::alloc_count::allocations;
+        for &n in &[0, 1, 63, 64, 65, 127, 128, 129, 200] {
+            let mut ba = BitArray::new(0);
+            assert_eq!(allocations(|| ba = BitArray::new(n)) == 0, n <= 128, "{}", n);
+            assert_eq!(ba.data.len(), BitArray::words_for(n));
+            assert!(ba.capacity() >= n);
+            if n > 0 {
+                ba.set(n - 1, true);
+                assert_eq!((ba.get(n - 1), ba.count_ones()), (true, 1));
+                ba.rotate(0, n, 1);
+                assert!(ba.get(0));
+            }
+            // clones and whole-array operations of small arrays stay inline too
+            let mut copy = ba.clone();
+            assert_eq!(allocations(|| {
+                copy = ba.clone();
+                copy.invert();
+                copy ^= &ba;
+            }) == 0, n <= 128, "{}", n);
+            assert_eq!(copy.count_ones(), n);
+        }
+        // growing past 128 bits keeps the bits, and so does shrinking back
+        let mut ba = BitArray::from_str(&"1".repeat(100));
+        ba.resize(300);
+        assert_eq!(ba.count_ones(), 100);
+        ba.set(299, true);
+        ba.resize(120);
+        assert_eq!(ba, BitArray::from_str(&("0".repeat(20) + &"1".repeat(100))));
+        ba.clear_and_resize(128);
+        assert_eq!(ba.count_ones(), 0);
+    }
+
+    /// Global, counting what it hands out.
+    #[cfg(feature = "allocator_api")]
+    #[derive(Debug, Clone, Default)]
+    struct Counting(std::rc::Rc<std::cell::Cell<usize>>);
+
+    #[cfg(feature = "allocator_api")]
+    unsafe impl Allocator for Counting {
+        fn allocate(&self, layout: std::alloc::Layout) -> Result<std::ptr::NonNull<[u8]>, std::alloc::AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn test_new_in() {
+        let alloc = Counting::default();
+        let mut small = BitArray::new_in(100, alloc.clone());
+        assert_eq!(alloc.0.get(), 0);
+        let mut big = BitArray::new_in(1000, alloc.clone());
+        assert_eq!(alloc.0.get(), 1);
+        big.fill_range(3, 500, true);
+        big.rotate(1, 990, -77);
+        let mut want = BitArray::new(1000);
+        want.fill_range(3, 500, true);
+        want.rotate(1, 990, -77);
+        assert_eq!(big.show(), want.show());
+        assert_eq!(big.plan_rotate(1, 990, -77).path, want.plan_rotate(1, 990, -77).path);
+        // growing past the inline words goes to the same allocator
+        small.resize(129);
+        assert_eq!(alloc.0.get(), 2);
+        assert_eq!(small.allocator().0.get(), 2);
+        small.set(128, true);
+        let copy = small.clone();
+        assert_eq!((alloc.0.get(), copy), (3, small));
+    }
+
+    #[test]
+    fn test_invert() {
+        let mut ba = BitArray::from_str("1111000010");
+        ba.invert();
+        assert_eq!(ba, BitArray::from_str("0000111101"));
+        assert_eq!(*ba.data, [0b00111101]);
+    }
+
+    #[test]
+    fn test_invariants() {
+        BitArray::new(13).assert_invariants();
+        BitArray::from_u8(0xff).assert_invariants();
+        let mut ba = BitArray::new(77);
+        ba.data[1] = 1 << 13;
+        let err = std::panic::catch_unwind(|| ba.assert_invariants()).unwrap_err();
+        assert!(err.downcast_ref::<String>().unwrap()
+                .contains("padding bits set in word 1 of a 77 bit array"));
+        ba.data[1] = 0;
+        ba.data.resize(3);
+        ba.data[2] = 1;
+        let err = std::panic::catch_unwind(|| ba.assert_invariants()).unwrap_err();
+        assert!(err.downcast_ref::<String>().unwrap().contains("padding word 2 set"));
+        ba.data.resize(1);
+        assert!(std::panic::catch_unwind(|| ba.assert_invariants()).is_err());
+        // whole again, so the paranoid drop check passes
+        ba.data.resize(2);
+    }
+
+    #[cfg(feature = "paranoid")]
+    #[test]
+    #[should_panic(expected = "padding bits set")]
+    fn test_paranoid_catches_corruption() {
+        let mut ba = BitArray::new(13);
+        ba.data[0] |= 1 << 20;
+        ba.set(0, true);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut ba = BitArray::from_str("1111000010010110");
+        ba.resize(5);
+        assert_eq!(ba.show(), "10110");
+        ba.resize(21);
+        assert_eq!(ba.show(), "000000000000000010110");
+        assert_eq!(ba.count_ones(), 3);
+        ba.resize(0);
+        assert_eq!(ba.count_ones(), 0);
+    }
+
     #[test]
     fn test_modulo() {
         // these cases were generated from the output of the C modulo
@@ -435,5 +1241,26 @@ mod tests {
         assert_eq!(BitArray::modulo(4, 3), 1);
         assert_eq!(BitArray::modulo(4, 4), 0);
     }   
+
+    #[test]
+    fn test_clear_and_resize() {
+        let mut ba = BitArray::new(1000);
+        ba.fill(true);
+        let ptr = ba.data.as_ptr();
+        for &sz in &[10, 1000, 0, 1007] {
+            ba.clear_and_resize(sz);
+            assert_eq!(ba.get_bit_sz(), sz);
+            assert_eq!(ba.count_ones(), 0);
+            ba.fill(true);
+            assert_eq!(ba.data.as_ptr(), ptr);
+        }
+        assert!(ba.capacity() >= 1007);
+        ba.clear_and_resize(ba.capacity());
+        assert_eq!(ba.data.as_ptr(), ptr);
+
+        let buf = ba.into_buffer();
+        let ba = BitArray::in_buffer(buf, 300);
+        assert_eq!((ba.get_bit_sz(), ba.count_ones(), ba.data.as_ptr()), (300, 0, ptr));
+    }
 }
 
@@ -0,0 +1,136 @@
+// Folding many arrays into one, e.g. the AND of 50 filter bitmaps.  Doing
+// that pairwise streams the whole accumulator through the cache once per
+// input; here the work is split into tiles small enough to stay in L1, and
+// each tile of the result is folded with that tile of every input before
+// moving on.  With the rayon feature, big arrays fold their tiles on all
+// cores.
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(feature = "rayon")]
+use super::bulk::PARALLEL_THRESHOLD_BYTES;
+use super::BitArray;
+
+/// Bytes of each array folded together before moving to the next tile.
+pub const FOLD_TILE_BYTES: usize = 1 << 12;
+
+/// The bitwise operations BitArray::fold_many knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldOp {
+    And,
+    Or,
+    Xor,
+}
+
+impl BitArray {
+    /// Combines arrays, all of the same size, into one with op.  Panics if
+    /// arrays is empty.
+    pub fn fold_many(op: FoldOp, arrays: &[&BitArray]) -> BitArray {
+        match op {
+            FoldOp::And => BitArray::fold_many_with(arrays, |a, b| *a &= b),
+            FoldOp::Or => BitArray::fold_many_with(arrays, |a, b| *a |= b),
+            FoldOp::Xor => BitArray::fold_many_with(arrays, |a, b| *a ^= b),
+        }
+    }
+
+    /// Like fold_many with an arbitrary word operation: the result starts
+    /// as a copy of arrays[0] and f folds in each later array's words, in
+    /// order.  As with the bulk operations f may scribble on the padding.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all,
+                                                        fields(arrays = arrays.len())))]
+    pub fn fold_many_with<F>(arrays: &[&BitArray], f: F) -> BitArray
+    where F: Fn(&mut u64, u64) + Sync + Send {
+        assert!(!arrays.is_empty(), "BitArray::fold_many needs at least one array");
+        let mut out = arrays[0].clone();
+        let rest = &arrays[1 ..];
+        for a in rest {
+            assert_eq!(a.bit_sz, out.bit_sz);
+        }
+
+        let tile_words = FOLD_TILE_BYTES / 8;
+        let fold_tile = |t: usize, tile: &mut [u64]| {
+            let start = t * tile_words;
+            for a in rest {
+                for (x, &y) in tile.iter_mut().zip(&a.data[start ..]) {
+                    f(x, y);
+                }
+            }
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            if 8 * out.data.len() >= PARALLEL_THRESHOLD_BYTES {
+                out.data.par_chunks_mut(tile_words)
+                    .enumerate()
+                    .for_each(|(t, tile)| fold_tile(t, tile));
+                out.clear_padding();
+                return out;
+            }
+        }
+        for (t, tile) in out.data.chunks_mut(tile_words).enumerate() {
+            fold_tile(t, tile);
+        }
+        out.clear_padding();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(n: usize, count: usize) -> Vec<BitArray> {
+        (0 .. count).map(|k| {
+            let mut ba = BitArray::new(n);
+            ba.randfill_seeded(k as u64);
+            ba
+        }).collect()
+    }
+
+    #[test]
+    fn test_fold_many_matches_pairwise() {
+        for &n in &[1, 100, 8 * FOLD_TILE_BYTES + 5, (1 << 20) + 13] {
+            let arrays = random(n, 5);
+            let refs: Vec<&BitArray> = arrays.iter().collect();
+            let (mut and, mut or, mut xor) = (arrays[0].clone(), arrays[0].clone(),
+                                              arrays[0].clone());
+            for a in &arrays[1 ..] {
+                and &= a;
+                or |= a;
+                xor ^= a;
+            }
+            assert_eq!(BitArray::fold_many(FoldOp::And, &refs), and);
+            assert_eq!(BitArray::fold_many(FoldOp::Or, &refs), or);
+            assert_eq!(BitArray::fold_many(FoldOp::Xor, &refs), xor);
+        }
+    }
+
+    #[test]
+    fn test_fold_many_of_fifty() {
+        let mut arrays = random(1000, 50);
+        for a in &mut arrays {
+            a.fill_range(100, 10, true);
+        }
+        let refs: Vec<&BitArray> = arrays.iter().collect();
+        let all = BitArray::fold_many(FoldOp::And, &refs);
+        assert!((100 .. 110).all(|i| all.get(i)));
+        assert_eq!(all.count_ones(), 10);
+    }
+
+    #[test]
+    fn test_fold_many_with() {
+        // everything in the first array that no other array has
+        let a = BitArray::from_str("11111111");
+        let b = BitArray::from_str("00001111");
+        let c = BitArray::from_str("00110000");
+        let r = BitArray::fold_many_with(&[&a, &b, &c], |x, y| *x &= !y);
+        assert_eq!(r.show(), "11000000");
+        assert_eq!(BitArray::fold_many(FoldOp::Or, &[&c]), c);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_fold_many_size_mismatch() {
+        BitArray::fold_many(FoldOp::And, &[&BitArray::new(8), &BitArray::new(9)]);
+    }
+}
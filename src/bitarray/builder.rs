@@ -0,0 +1,136 @@
+// Building an array front to back, for encoders that produce a stream of
+// bits, bytes and fixed width fields.
+use super::BitArray;
+
+/// Appends bits in order and hands back the finished BitArray.  Bits go
+/// straight into the buffer that becomes the array's, so with a capacity
+/// given up front the whole build is a single allocation.
+#[derive(Debug, Clone, Default)]
+pub struct BitArrayBuilder {
+    data: Vec<u64>,
+    bit_sz: usize,
+}
+
+impl BitArrayBuilder {
+    pub fn new() -> BitArrayBuilder {
+        BitArrayBuilder::default()
+    }
+
+    /// A builder with room for bits bits before it reallocates.
+    pub fn with_capacity(bits: usize) -> BitArrayBuilder {
+        BitArrayBuilder { data: Vec::with_capacity(BitArray::words_for(bits)), bit_sz: 0 }
+    }
+
+    /// The number of bits pushed so far.
+    pub fn len(&self) -> usize {
+        self.bit_sz
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bit_sz == 0
+    }
+
+    pub fn push_bit(&mut self, val: bool) -> &mut BitArrayBuilder {
+        self.push_bits(val as u64, 1)
+    }
+
+    /// Appends the low bit_width (at most 64) bits of value, bit 0 first,
+    /// the same order write_bits stores them in.  value must fit in
+    /// bit_width bits.
+    pub fn push_bits(&mut self, value: u64, bit_width: usize) -> &mut BitArrayBuilder {
+        assert!(bit_width <= 64);
+        assert!(bit_width == 64 || value >> bit_width == 0);
+        let mut done = 0;
+        while done < bit_width {
+            let shift = self.bit_sz % 64;
+            if shift == 0 {
+                self.data.push(0);
+            }
+            let take = usize::min(64 - shift, bit_width - done);
+            let bits = (value >> done) & (u64::MAX >> (64 - take));
+            *self.data.last_mut().unwrap() |= bits << shift;
+            self.bit_sz += take;
+            done += take;
+        }
+        self
+    }
+
+    /// Appends 8 bits per byte, each byte low bit first.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> &mut BitArrayBuilder {
+        for chunk in bytes.chunks(8) {
+            let word = chunk.iter().rev().fold(0u64, |w, &b| (w << 8) | b as u64);
+            self.push_bits(word, 8 * chunk.len());
+        }
+        self
+    }
+
+    /// Appends every bit of an array.
+    pub fn push_bitarray(&mut self, ba: &BitArray) -> &mut BitArrayBuilder {
+        let mut done = 0;
+        while done < ba.bit_sz {
+            let width = usize::min(64, ba.bit_sz - done);
+            self.push_bits(ba.read_bits(done, width), width);
+            done += width;
+        }
+        self
+    }
+
+    /// The bits pushed, as an array of exactly that many bits.
+    pub fn finish(self) -> BitArray {
+        let BitArrayBuilder { data, bit_sz } = self;
+        // a word is pushed as soon as a bit lands in it, so this is
+        // already the size BitArray::new allocates
+        debug_assert_eq!(data.len(), BitArray::words_for(bit_sz));
+        let ba = BitArray { bit_sz, data: data.into() };
+        ba.check_invariants();
+        ba
+    }
+}
+
+impl BitArray {
+    /// A BitArrayBuilder, for building an array front to back.
+    pub fn builder() -> BitArrayBuilder {
+        BitArrayBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let mut b = BitArrayBuilder::with_capacity(29);
+        b.push_bit(true).push_bits(0b101, 3).push_bytes(&[0x96, 0xf0]).push_bits(0x1f, 5);
+        assert_eq!(b.len(), 25);
+        let ba = b.finish();
+        assert_eq!(ba.get_bit_sz(), 25);
+        assert_eq!(ba.read_bits(0, 4), 0b1011);
+        assert_eq!(ba.read_bits(4, 16), 0xf096);
+        assert_eq!(ba.read_bits(20, 5), 0x1f);
+    }
+
+    #[test]
+    fn test_matches_write_bits() {
+        let mut src = BitArray::new(333);
+        src.randfill();
+        let mut b = BitArray::builder();
+        b.push_bits(src.read_bits(0, 7), 7)
+            .push_bits(src.read_bits(7, 64), 64)
+            .push_bytes(&[src.read_bits(71, 8) as u8]);
+        b.push_bitarray(&src.slice(79 .. 333).to_bitarray());
+        assert_eq!(b.finish(), src);
+    }
+
+    #[test]
+    fn test_one_allocation() {
+        let mut b = BitArrayBuilder::with_capacity(64);
+        let ptr = b.data.as_ptr();
+        b.push_bytes(&[0xff; 7]).push_bits(0, 8);
+        let ba = b.finish();
+        assert_eq!(ba.data.as_ptr(), ptr);
+        assert_eq!(ba.data.len(), 1);
+        assert_eq!(ba.count_ones(), 56);
+        assert_eq!(BitArrayBuilder::new().finish().get_bit_sz(), 0);
+    }
+}
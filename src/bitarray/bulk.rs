@@ -0,0 +1,269 @@
+// Whole-array bitwise operations, done a word at a time instead of a bit
+// at a time.  With the rayon feature, big arrays are split into chunks of
+// words that are processed on all cores.
+use std::ops::{BitAndAssign, BitOrAssign, BitXorAssign};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use super::counters::{self, Counter};
+use super::{kernels, Allocator, BitArray};
+
+/// Arrays with at least this many bytes are processed in parallel when the
+/// rayon feature is on; below it the threads cost more than they save.
+#[cfg(feature = "rayon")]
+pub const PARALLEL_THRESHOLD_BYTES: usize = 1 << 16;
+
+/// Bytes handed to each rayon task.
+#[cfg(feature = "rayon")]
+pub(crate) const CHUNK_BYTES: usize = 1 << 14;
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// Applies f to every word of self paired with the same word of other.
+    /// The padding is cleared afterwards, so f may do anything to the bits
+    /// past the end of the array.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all,
+                                                        fields(words = self.data.len())))]
+    fn zip_words<F>(&mut self, other: &BitArray<A>, f: F)
+    where F: Fn(&mut u64, u64) + Sync + Send {
+        assert_eq!(self.bit_sz, other.bit_sz);
+        counters::bump_by(Counter::WordRead, 2 * self.data.len());
+        counters::bump_by(Counter::WordWrite, self.data.len());
+        #[cfg(feature = "rayon")]
+        {
+            if 8 * self.data.len() >= PARALLEL_THRESHOLD_BYTES {
+                self.data.par_chunks_mut(CHUNK_BYTES / 8)
+                    .zip(other.data.par_chunks(CHUNK_BYTES / 8))
+                    .for_each(|(mine, theirs)| {
+                        for (a, &b) in mine.iter_mut().zip(theirs) {
+                            f(a, b);
+                        }
+                    });
+                self.clear_padding();
+                return;
+            }
+        }
+        for (a, &b) in self.data.iter_mut().zip(&other.data) {
+            f(a, b);
+        }
+        self.clear_padding();
+    }
+
+    /// Applies f to every word, see zip_words.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all,
+                                                        fields(words = self.data.len())))]
+    fn map_words<F>(&mut self, f: F)
+    where F: Fn(&mut u64) + Sync + Send {
+        counters::bump_by(Counter::WordRead, self.data.len());
+        counters::bump_by(Counter::WordWrite, self.data.len());
+        #[cfg(feature = "rayon")]
+        {
+            if 8 * self.data.len() >= PARALLEL_THRESHOLD_BYTES {
+                self.data.par_chunks_mut(CHUNK_BYTES / 8).for_each(|chunk| {
+                    for a in chunk {
+                        f(a);
+                    }
+                });
+                self.clear_padding();
+                return;
+            }
+        }
+        for a in &mut self.data {
+            f(a);
+        }
+        self.clear_padding();
+    }
+
+    /// Sets every bit in the array to val.
+    pub fn fill(&mut self, val: bool) {
+        let word = if val { u64::MAX } else { 0 };
+        self.map_words(|a| *a = word);
+    }
+
+    /// Flips every bit in the array.
+    pub fn invert(&mut self) {
+        self.map_words(|a| *a = !*a);
+    }
+
+    /// The number of bits set to one, counted a word at a time (and on all
+    /// cores for big arrays with the rayon feature).
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all,
+                                                        fields(words = self.data.len())))]
+    pub fn count_ones(&self) -> usize {
+        #[cfg(feature = "rayon")]
+        {
+            if 8 * self.data.len() >= PARALLEL_THRESHOLD_BYTES {
+                // counted here, the workers' counts are their own
+                counters::bump_by(Counter::WordRead, self.data.len());
+                return self.data.par_chunks(CHUNK_BYTES / 8)
+                    .map(|chunk| chunk.iter().map(|w| w.count_ones() as usize).sum::<usize>())
+                    .sum();
+            }
+        }
+        kernels::count_ones(&self.data)
+    }
+
+    /// The number of bits set to zero.  The padding past bit_sz is all
+    /// zero, so it comes from count_ones without masking the last word.
+    pub fn count_zeros(&self) -> usize {
+        self.bit_sz - self.count_ones()
+    }
+
+    /// True when an odd number of bits are set: the xor of all the words,
+    /// then the parity of that.
+    pub fn parity(&self) -> bool {
+        counters::bump_by(Counter::WordRead, self.data.len());
+        self.data.iter().fold(0u64, |acc, &w| acc ^ w).count_ones() % 2 == 1
+    }
+
+    /// The number of ones in [bit_offset, bit_offset + bit_length).
+    pub fn count_ones_range(&self, bit_offset: usize, bit_length: usize) -> usize {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        let mut ones = 0;
+        let mut done = 0;
+        while done < bit_length {
+            let width = usize::min(64, bit_length - done);
+            ones += self.read_bits(bit_offset + done, width).count_ones() as usize;
+            done += width;
+        }
+        ones
+    }
+
+    /// Sets every bit in [bit_offset, bit_offset + bit_length) to val.
+    pub fn fill_range(&mut self, bit_offset: usize, bit_length: usize, val: bool) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        let mut done = 0;
+        while done < bit_length {
+            let width = usize::min(64, bit_length - done);
+            let bits = if val { u64::MAX >> (64 - width) } else { 0 };
+            self.write_bits(bit_offset + done, width, bits);
+            done += width;
+        }
+    }
+}
+
+impl<A: Allocator + Clone> BitAndAssign<&BitArray<A>> for BitArray<A> {
+    /// Intersects with another array of the same size.
+    fn bitand_assign(&mut self, other: &BitArray<A>) {
+        self.zip_words(other, |a, b| *a &= b);
+    }
+}
+
+impl<A: Allocator + Clone> BitOrAssign<&BitArray<A>> for BitArray<A> {
+    /// Unions with another array of the same size.
+    fn bitor_assign(&mut self, other: &BitArray<A>) {
+        self.zip_words(other, |a, b| *a |= b);
+    }
+}
+
+impl<A: Allocator + Clone> BitXorAssign<&BitArray<A>> for BitArray<A> {
+    /// Xors with another array of the same size.
+    fn bitxor_assign(&mut self, other: &BitArray<A>) {
+        self.zip_words(other, |a, b| *a ^= b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Big enough to take the parallel path when it is compiled in, and not
+    // a multiple of 64 so there is a partial tail word.
+    const BIG: usize = (1 << 20) + 13;
+
+    fn random(n: usize) -> BitArray {
+        let mut ba = BitArray::new(n);
+        ba.randfill();
+        ba
+    }
+
+    #[test]
+    fn test_binary_ops() {
+        for &n in &[0, 1, 13, 64, BIG] {
+            let a = random(n);
+            let b = random(n);
+            let (mut and, mut or, mut xor) = (a.clone(), a.clone(), a.clone());
+            and &= &b;
+            or |= &b;
+            xor ^= &b;
+            for i in 0 .. n {
+                assert_eq!(and.get(i), a.get(i) & b.get(i));
+                assert_eq!(or.get(i), a.get(i) | b.get(i));
+                assert_eq!(xor.get(i), a.get(i) ^ b.get(i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_and_invert_keep_padding_clear() {
+        for &n in &[5, 64, BIG] {
+            let mut a = random(n);
+            a.fill(true);
+            assert!((0 .. n).all(|i| a.get(i)));
+            assert_eq!(a.data.last().unwrap().count_ones() as usize, n - 64 * (a.data.len() - 1));
+            a.invert();
+            assert!(a.data.iter().all(|&w| w == 0));
+            a.invert();
+            a.fill(false);
+            assert!(a.data.iter().all(|&w| w == 0));
+        }
+    }
+
+    #[test]
+    fn test_invert_twice() {
+        let a = random(BIG);
+        let mut b = a.clone();
+        b.invert();
+        assert!(b != a);
+        b.invert();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_count_ones() {
+        assert_eq!(BitArray::from_str("1111000010010110").count_ones(), 8);
+        assert_eq!(BitArray::new(100).count_ones(), 0);
+        let a = random(BIG);
+        assert_eq!(a.count_ones(), (0 .. BIG).filter(|&i| a.get(i)).count());
+        let mut ones = BitArray::new(BIG);
+        ones.fill(true);
+        assert_eq!(ones.count_ones(), BIG);
+        assert_eq!(ones.count_zeros(), 0);
+        assert_eq!(a.count_zeros(), BIG - a.count_ones());
+        assert_eq!(BitArray::new(100).count_zeros(), 100);
+    }
+
+    #[test]
+    fn test_parity() {
+        assert!(!BitArray::new(0).parity());
+        assert!(BitArray::from_str("1000000000").parity());
+        assert!(!BitArray::from_str("1111000010010110").parity());
+        let mut a = random(BIG);
+        let odd = a.count_ones() % 2 == 1;
+        assert_eq!(a.parity(), odd);
+        a.set(BIG - 1, !a.get(BIG - 1));
+        assert_eq!(a.parity(), !odd);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut a = BitArray::new(300);
+        a.fill_range(3, 200, true);
+        assert_eq!(a.count_ones(), 200);
+        assert_eq!(a.count_ones_range(0, 300), 200);
+        assert_eq!(a.count_ones_range(0, 4), 1);
+        assert_eq!(a.count_ones_range(200, 100), 3);
+        assert!(!a.get(2) && a.get(3) && a.get(202) && !a.get(203));
+        a.fill_range(10, 0, false);
+        a.fill_range(100, 50, false);
+        assert_eq!(a.count_ones(), 150);
+        assert_eq!(a.count_ones_range(100, 50), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_size_mismatch() {
+        let mut a = BitArray::new(10);
+        a &= &BitArray::new(11);
+    }
+}
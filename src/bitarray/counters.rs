@@ -0,0 +1,167 @@
+// Counts of the primitive operations, behind the counters feature, so the
+// work an algorithm does can be compared and not just its time: a rotate
+// by k bits one bit at a time does about k times the gets and sets of a
+// rotate by reversals.
+//
+// The counts are per thread, which keeps them exact while tests run in
+// parallel.  The whole-word loops are counted up front on the calling
+// thread, so they add up the same when rayon splits them; anything else
+// done on rayon's worker threads is not seen.  Without the
+// feature the hooks compile to nothing.
+#[cfg(feature = "counters")]
+use std::cell::Cell;
+#[cfg(feature = "counters")]
+use std::fmt;
+
+/// The operations that are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Counter {
+    Get,
+    Set,
+    WordRead,
+    WordWrite,
+}
+
+/// How many of each operation the current thread has done.
+#[cfg(feature = "counters")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCounts {
+    pub gets: u64,
+    pub sets: u64,
+    /// Words read, up to 64 bits at a time by read_bits or whole by the
+    /// word-at-a-time loops (count_ones, the bulk operations and the
+    /// moves of a rotate).
+    pub word_reads: u64,
+    /// Words written, the same way.
+    pub word_writes: u64,
+}
+
+#[cfg(feature = "counters")]
+impl OpCounts {
+    pub fn total(&self) -> u64 {
+        self.gets + self.sets + self.word_reads + self.word_writes
+    }
+}
+
+#[cfg(feature = "counters")]
+impl std::ops::Sub for OpCounts {
+    type Output = OpCounts;
+
+    fn sub(self, earlier: OpCounts) -> OpCounts {
+        OpCounts {
+            gets: self.gets - earlier.gets,
+            sets: self.sets - earlier.sets,
+            word_reads: self.word_reads - earlier.word_reads,
+            word_writes: self.word_writes - earlier.word_writes,
+        }
+    }
+}
+
+#[cfg(feature = "counters")]
+impl fmt::Display for OpCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} gets, {} sets, {} word reads, {} word writes ({} in all)",
+               self.gets, self.sets, self.word_reads, self.word_writes, self.total())
+    }
+}
+
+#[cfg(feature = "counters")]
+thread_local! {
+    static COUNTS: Cell<OpCounts> = Cell::new(OpCounts::default());
+}
+
+/// Called by the primitives; free without the counters feature.
+#[inline(always)]
+pub(crate) fn bump(which: Counter) {
+    bump_by(which, 1)
+}
+
+/// bump, n times over, for the loops that go through many words at once.
+#[inline(always)]
+pub(crate) fn bump_by(_which: Counter, _n: usize) {
+    #[cfg(feature = "counters")]
+    COUNTS.with(|c| {
+        let mut counts = c.get();
+        let n = _n as u64;
+        match _which {
+            Counter::Get => counts.gets += n,
+            Counter::Set => counts.sets += n,
+            Counter::WordRead => counts.word_reads += n,
+            Counter::WordWrite => counts.word_writes += n,
+        }
+        c.set(counts);
+    });
+}
+
+/// The current thread's counts since it started or last called reset.
+#[cfg(feature = "counters")]
+pub fn counts() -> OpCounts {
+    COUNTS.with(|c| c.get())
+}
+
+#[cfg(feature = "counters")]
+pub fn reset() {
+    COUNTS.with(|c| c.set(OpCounts::default()));
+}
+
+/// Runs f and returns its result along with the operations it did.
+#[cfg(feature = "counters")]
+pub fn measure<R, F: FnOnce() -> R>(f: F) -> (R, OpCounts) {
+    let before = counts();
+    let result = f();
+    (result, counts() - before)
+}
+
+#[cfg(all(test, feature = "counters"))]
+mod tests {
+    use super::*;
+    use crate::bitarray::{BitArray, RotateStrategy};
+    use crate::viz::{rotation_steps, Algorithm};
+
+    #[test]
+    fn test_counts() {
+        let mut ba = BitArray::new(100);
+        let ((), c) = measure(|| {
+            ba.set(3, true);
+            ba.get(3);
+            ba.write_bits(10, 64, 5);
+            ba.read_bits(10, 64);
+        });
+        assert_eq!(c, OpCounts { gets: 1, sets: 1, word_reads: 1, word_writes: 1 });
+        assert_eq!(c.to_string(), "1 gets, 1 sets, 1 word reads, 1 word writes (4 in all)");
+        reset();
+        assert_eq!(counts().total(), 0);
+    }
+
+    #[test]
+    fn test_word_loops() {
+        let mut ba = BitArray::new(640);
+        let other = BitArray::new(640);
+        let (_, c) = measure(|| ba.count_ones());
+        assert_eq!((c.word_reads, c.word_writes), (10, 0));
+        let (_, c) = measure(|| ba.parity());
+        assert_eq!((c.word_reads, c.word_writes), (10, 0));
+        let ((), c) = measure(|| ba.invert());
+        assert_eq!((c.word_reads, c.word_writes), (10, 10));
+        let ((), c) = measure(|| ba ^= &other);
+        assert_eq!((c.word_reads, c.word_writes), (20, 10));
+        // save 2 words, copy_within the other 8 down, restore the 2
+        let ((), c) = measure(|| ba.rotate_with(RotateStrategy::WordMoves, 0, 640, -128));
+        assert_eq!((c.word_reads, c.word_writes), (10, 10));
+        // save 2, funnel 2 into each of 8, move the last 28 bits, restore
+        let ((), c) = measure(|| ba.rotate_with(RotateStrategy::WordMoves, 0, 640, -100));
+        assert_eq!((c.word_reads, c.word_writes), (2 + 16 + 1, 8 + 1 + 2));
+    }
+
+    #[test]
+    fn test_work_ratio() {
+        let mut ba = BitArray::new(64);
+        ba.randfill();
+        let (_, naive) = measure(|| rotation_steps(&ba, 0, 64, -20, Algorithm::Naive));
+        let (_, reversal) = measure(|| rotation_steps(&ba, 0, 64, -20, Algorithm::Reversal));
+        // 20 passes setting all 64 bits against three reversals that
+        // set each bit twice; the gets are mostly the snapshots
+        assert_eq!(naive.sets, 20 * 64);
+        assert_eq!(reversal.sets, 2 * 64);
+    }
+}
@@ -0,0 +1,117 @@
+// xxd-style dumps of a bit array, for looking at big arrays where
+// show() would hand back a million character string.
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::BitArray;
+
+/// Controls the layout of BitArray::dump_with.
+#[derive(Debug, Clone)]
+pub struct DumpOptions {
+    /// Number of bits printed together before a space.
+    pub group_bits: usize,
+
+    /// Number of bits printed on each line.
+    pub line_bits: usize,
+
+    /// The half-open range of bits to dump, None for the whole array.
+    pub range: Option<Range<usize>>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> DumpOptions {
+        DumpOptions { group_bits: 8, line_bits: 64, range: None }
+    }
+}
+
+impl BitArray {
+    /// Dumps the whole bit array using the default options, see dump_with.
+    pub fn dump(&self) -> String {
+        self.dump_with(&DumpOptions::default())
+    }
+
+    /// Produces an xxd-style dump of the bit array.  Each line starts with
+    /// the (hex) bit offset of its first bit, followed by the bits in
+    /// groups, followed by the hex value of the underlying bytes that hold
+    /// those bits.
+    ///
+    /// Like show(), each group is printed with its highest index on the
+    /// left, so with 8 bit groups starting on a byte boundary every group
+    /// reads the same as the byte next to it in the hex column.
+    ///
+    /// Example:
+    /// BitArray::from_str("1111000010010110").dump() gives
+    /// "00000000: 10010110 11110000  96 f0\n"
+    pub fn dump_with(&self, opts: &DumpOptions) -> String {
+        assert!(opts.group_bits > 0);
+        assert!(opts.line_bits > 0);
+        let range = opts.range.clone().unwrap_or(0 .. self.bit_sz);
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+
+        let groups_per_line = opts.line_bits.div_ceil(opts.group_bits);
+        let bin_width = groups_per_line * (opts.group_bits + 1);
+
+        let mut out = String::new();
+        let mut line_start = range.start;
+        while line_start < range.end {
+            let line_end = usize::min(line_start + opts.line_bits, range.end);
+            write!(out, "{:08x}: ", line_start).unwrap();
+
+            let mut bin = String::new();
+            let mut group_start = line_start;
+            while group_start < line_end {
+                let group_end = usize::min(group_start + opts.group_bits, line_end);
+                for i in (group_start .. group_end).rev() {
+                    bin.push(if self.get(i) { '1' } else { '0' });
+                }
+                bin.push(' ');
+                group_start = group_end;
+            }
+            write!(out, "{:width$}", bin, width = bin_width).unwrap();
+
+            out.push(' ');
+            let bytes = line_start / 8 ..= (line_end - 1) / 8;
+            let hex: Vec<String> = bytes.map(|i| format!("{:02x}", self.byte(i))).collect();
+            out.push_str(&hex.join(" "));
+            out.push('\n');
+
+            line_start = line_end;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_two_bytes() {
+        let ba = BitArray::from_str("1111000010010110");
+        assert_eq!(ba.dump_with(&DumpOptions { line_bits: 16, ..Default::default() }),
+                   "00000000: 10010110 11110000  96 f0\n");
+    }
+
+    #[test]
+    fn test_dump_lines_and_groups() {
+        let ba = BitArray::from_str("1111000010010110");
+        let opts = DumpOptions { group_bits: 4, line_bits: 8, range: None };
+        assert_eq!(ba.dump_with(&opts),
+                   "00000000: 0110 1001  96\n\
+                    00000008: 0000 1111  f0\n");
+    }
+
+    #[test]
+    fn test_dump_range() {
+        let ba = BitArray::from_str("1111000010010110");
+        let opts = DumpOptions { group_bits: 3, line_bits: 6, range: Some(6 .. 13) };
+        assert_eq!(ba.dump_with(&opts),
+                   "00000006: 010 000  96 f0\n\
+                    0000000c: 1        f0\n");
+    }
+
+    #[test]
+    fn test_dump_empty() {
+        assert_eq!(BitArray::new(0).dump(), "");
+    }
+}
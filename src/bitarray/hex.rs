@@ -0,0 +1,70 @@
+// Hex strings, four bits per digit, for writing long test arrays compactly.
+use std::fmt;
+
+use super::BitArray;
+
+/// The error from BitArray::from_hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHexDigit {
+    /// Character position of the offending character.
+    pub index: usize,
+    pub found: char,
+}
+
+impl fmt::Display for InvalidHexDigit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid hex digit {:?} at position {}", self.found, self.index)
+    }
+}
+
+impl std::error::Error for InvalidHexDigit {}
+
+impl BitArray {
+    /// Parses a string of hex digits (either case) into an array of four
+    /// bits per digit.  Like from_str the rightmost digit holds bits 0-3,
+    /// so from_hex("96") equals from_str("10010110").
+    pub fn from_hex(hex: &str) -> Result<BitArray, InvalidHexDigit> {
+        let digits = hex.chars().count();
+        let mut arr = BitArray::new(4 * digits);
+        for (i, c) in hex.chars().enumerate() {
+            let nibble = c.to_digit(16).ok_or(InvalidHexDigit { index: i, found: c })?;
+            arr.write_bits(4 * (digits - 1 - i), 4, nibble as u64);
+        }
+        Ok(arr)
+    }
+
+    /// The bits as lower case hex, highest digit first.  A partial top
+    /// digit is padded with zeros, so this only round trips through
+    /// from_hex when the size is a multiple of 4.
+    pub fn to_hex(&self) -> String {
+        let digits = self.bit_sz.div_ceil(4);
+        (0 .. digits).rev().map(|d| {
+            let width = usize::min(4, self.bit_sz - 4 * d);
+            std::char::from_digit(self.read_bits(4 * d, width) as u32, 16).unwrap()
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_hex() {
+        assert_eq!(BitArray::from_hex("96").unwrap(), BitArray::from_str("10010110"));
+        assert_eq!(BitArray::from_hex("F0a").unwrap(), BitArray::from_str("111100001010"));
+        assert_eq!(BitArray::from_hex("").unwrap().get_bit_sz(), 0);
+        assert_eq!(BitArray::from_hex("1g"), Err(InvalidHexDigit { index: 1, found: 'g' }));
+    }
+
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(BitArray::from_str("10010110").to_hex(), "96");
+        assert_eq!(BitArray::from_str("110010110").to_hex(), "196");
+        assert_eq!(BitArray::new(0).to_hex(), "");
+
+        let mut ba = BitArray::new(260);
+        ba.randfill();
+        assert_eq!(BitArray::from_hex(&ba.to_hex()).unwrap(), ba);
+    }
+}
@@ -0,0 +1,174 @@
+// Backing buffers for very large arrays, placed with huge pages and/or on
+// a chosen NUMA node.  Linux only, behind the hugepages feature.
+//
+// The buffer stays an ordinary Vec<u64> so everything else keeps working;
+// the kernel is only told how to back its pages before they are touched.
+// BitArray::new takes its words zeroed from the allocator without writing
+// them, and a big buffer is a fresh anonymous mapping, so nothing is
+// faulted in yet when the advice is given.
+use std::io;
+
+use super::BitArray;
+
+/// Size of a transparent huge page on x86-64 and aarch64 with 4K pages.
+pub const HUGE_PAGE_BYTES: usize = 2 << 20;
+
+/// mbind(2) policy and flag, from linux/mempolicy.h.
+const MPOL_BIND: libc::c_int = 2;
+const MPOL_MF_MOVE: libc::c_uint = 1 << 1;
+
+/// How to place the backing buffer of a BitArray, see
+/// BitArray::with_alloc_options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllocOptions {
+    /// Ask for transparent huge pages with madvise(MADV_HUGEPAGE).  Cuts
+    /// TLB misses when walking multi-gigabyte arrays.
+    pub huge_pages: bool,
+    /// Bind the buffer's memory to this NUMA node with mbind(2).
+    pub numa_node: Option<usize>,
+}
+
+/// The largest page aligned part of [addr, addr + len), as (start, len).
+fn page_aligned(addr: usize, len: usize, page: usize) -> (usize, usize) {
+    let start = addr.div_ceil(page) * page;
+    let end = (addr + len) / page * page;
+    if end <= start {
+        (start, 0)
+    } else {
+        (start, end - start)
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+impl BitArray {
+    /// Allocates a zeroed bit array whose buffer is placed as opts asks.
+    /// Only the page aligned middle of the buffer can be advised, so this
+    /// is only worth it for arrays of many megabytes.  Fails with the
+    /// kernel's error if the advice is refused, e.g. an offline node.
+    /// In CHECKED builds no advice is given at all: Miri cannot make the
+    /// system calls, and the placement is only about speed.
+    pub fn with_alloc_options(bit_sz: usize, opts: &AllocOptions) -> io::Result<BitArray> {
+        let ba = BitArray::new(bit_sz);
+        if super::CHECKED {
+            return Ok(ba);
+        }
+        let (start, len) = page_aligned(ba.data.as_ptr() as usize, 8 * ba.data.len(), page_size());
+        if len == 0 {
+            return Ok(ba);
+        }
+        let addr = start as *mut libc::c_void;
+
+        if opts.huge_pages && unsafe { libc::madvise(addr, len, libc::MADV_HUGEPAGE) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if let Some(node) = opts.numa_node {
+            let word_bits = 8 * std::mem::size_of::<libc::c_ulong>();
+            let mut mask: Vec<libc::c_ulong> = vec![0; node / word_bits + 1];
+            mask[node / word_bits] |= 1 << (node % word_bits);
+            // maxnode counts bits of the mask, plus one for the kernel's
+            // historical off-by-one
+            let maxnode = (mask.len() * word_bits + 1) as libc::c_ulong;
+            let ret = unsafe {
+                libc::syscall(libc::SYS_mbind, addr, len, MPOL_BIND, mask.as_ptr(),
+                              maxnode, MPOL_MF_MOVE)
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(ba)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_aligned() {
+        assert_eq!(page_aligned(0, 8192, 4096), (0, 8192));
+        assert_eq!(page_aligned(1, 8192, 4096), (4096, 4096));
+        assert_eq!(page_aligned(4095, 4098, 4096), (4096, 4096));
+        assert_eq!(page_aligned(4097, 100, 4096), (8192, 0));
+    }
+
+    /// The AnonHugePages, in kB, of the mapping holding addr, from
+    /// /proc/self/smaps.
+    fn anon_huge_kb(addr: usize) -> usize {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let mut inside = false;
+        for line in smaps.lines() {
+            // a mapping starts with its address range, then its fields
+            let range = line.split(' ').next().and_then(|r| r.split_once('-'));
+            if let Some((Ok(start), Ok(end))) = range.map(|(a, b)| (usize::from_str_radix(a, 16),
+                                                                     usize::from_str_radix(b, 16))) {
+                inside = (start .. end).contains(&addr);
+            } else if let Some(kb) = line.strip_prefix("AnonHugePages:").filter(|_| inside) {
+                return kb.trim().trim_end_matches("kB").trim().parse().unwrap();
+            }
+        }
+        panic!("no mapping holds {:#x}", addr)
+    }
+
+    /// How many times, machine wide, a fault wanted a huge page and the
+    /// kernel had none to give.
+    fn thp_fallbacks() -> usize {
+        let vmstat = std::fs::read_to_string("/proc/vmstat").unwrap();
+        vmstat.lines().find_map(|l| l.strip_prefix("thp_fault_fallback ")).map_or(0, |n| n.parse().unwrap())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // 32MB is too slow under Miri, and there are no huge pages
+    fn test_huge_pages() {
+        let opts = AllocOptions { huge_pages: true, numa_node: None };
+        let bit_sz = 8 * 4 * HUGE_PAGE_BYTES + 3;
+        match BitArray::with_alloc_options(bit_sz, &opts) {
+            Ok(mut ba) => {
+                let fallbacks = thp_fallbacks();
+                assert_eq!(ba.count_ones(), 0);
+                ba.set(bit_sz - 1, true);
+                ba.fill_range(8 * HUGE_PAGE_BYTES, 64, true);
+                assert_eq!(ba.count_ones(), 65);
+                // touching every page faults them in, as huge pages if the
+                // advice came before anything was written
+                ba.fill(true);
+                let thp = std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/enabled");
+                // CHECKED builds give no advice
+                if !crate::bitarray::CHECKED && thp.is_ok_and(|mode| !mode.contains("[never]")) {
+                    // short of free huge pages the kernel falls back to small
+                    // ones, which it only tries for advised memory
+                    let kb = anon_huge_kb(ba.data.as_ptr() as usize + HUGE_PAGE_BYTES);
+                    assert!(kb >= HUGE_PAGE_BYTES / 1024 || thp_fallbacks() > fallbacks,
+                            "{} kB of huge pages", kb);
+                }
+            }
+            // kernels built without transparent huge pages
+            Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EINVAL)),
+        }
+    }
+
+    #[test]
+    fn test_numa_node_zero() {
+        // node 0 exists on every NUMA kernel; without NUMA there is no mbind
+        let opts = AllocOptions { huge_pages: false, numa_node: Some(0) };
+        match BitArray::with_alloc_options(1 << 24, &opts) {
+            Ok(ba) if crate::bitarray::CHECKED => assert_eq!(ba.count_ones(), 0),
+            Ok(ba) => {
+                assert_eq!(ba.count_ones(), 0);
+                // the policy of the mapping holding the buffer's middle,
+                // the last to start at or before it
+                let addr = ba.data.as_ptr() as usize + (1 << 20);
+                let numa_maps = std::fs::read_to_string("/proc/self/numa_maps").unwrap();
+                let mapping = numa_maps.lines()
+                    .rfind(|l| usize::from_str_radix(l.split(' ').next().unwrap(), 16).unwrap() <= addr)
+                    .unwrap();
+                assert_eq!(mapping.split(' ').nth(1), Some("bind:0"));
+            }
+            Err(e) => assert_eq!(e.raw_os_error(), Some(libc::ENOSYS)),
+        }
+    }
+}
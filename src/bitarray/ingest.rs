@@ -0,0 +1,76 @@
+// Writing bits that arrive as bools, such as the masks other libraries
+// produce.  They are packed 64 at a time and written a word at a time,
+// with one bounds check per call rather than one per bit.
+use super::BitArray;
+
+impl BitArray {
+    /// Sets bits [bit_offset, bit_offset + vals.len()) to vals, vals[0]
+    /// going to bit_offset.
+    pub fn set_from_bools(&mut self, bit_offset: usize, vals: &[bool]) {
+        assert!(bit_offset + vals.len() <= self.bit_sz);
+        for (i, chunk) in vals.chunks(64).enumerate() {
+            let word = chunk.iter().rev().fold(0u64, |w, &b| (w << 1) | b as u64);
+            self.write_bits(bit_offset + 64 * i, chunk.len(), word);
+        }
+    }
+
+    /// Sets bits from bit_offset on to the values of vals, until it runs
+    /// out, and returns how many were written.  Panics if vals has more
+    /// bits than fit; what was written before that stays written.
+    pub fn set_from_iter<I: IntoIterator<Item = bool>>(&mut self, bit_offset: usize, vals: I) -> usize {
+        assert!(bit_offset <= self.bit_sz);
+        let mut vals = vals.into_iter();
+        let mut pos = bit_offset;
+        loop {
+            let mut word = 0u64;
+            let mut width = 0;
+            for b in vals.by_ref().take(64) {
+                word |= (b as u64) << width;
+                width += 1;
+            }
+            assert!(pos + width <= self.bit_sz, "more than {} bits", self.bit_sz - bit_offset);
+            self.write_bits(pos, width, word);
+            pos += width;
+            if width < 64 {
+                return pos - bit_offset;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_from_bools() {
+        let mut ba = BitArray::new(200);
+        let vals: Vec<bool> = (0 .. 150).map(|i| i % 3 == 0).collect();
+        ba.set_from_bools(7, &vals);
+        for i in 0 .. 200 {
+            assert_eq!(ba.get(i), (7 .. 157).contains(&i) && (i - 7) % 3 == 0, "{}", i);
+        }
+        let mut ba = BitArray::from_str("0000");
+        ba.set_from_bools(1, &[true, true]);
+        ba.set_from_bools(4, &[]);
+        assert_eq!(ba.show(), "0110");
+    }
+
+    #[test]
+    fn test_set_from_iter() {
+        let mut ba = BitArray::new(200);
+        ba.fill(true);
+        assert_eq!(ba.set_from_iter(10, (0 .. 128).map(|i| i % 2 == 1)), 128);
+        assert_eq!(ba.count_ones(), 200 - 64);
+        assert!(ba.get(9) && !ba.get(10) && ba.get(11) && ba.get(138));
+        assert_eq!(ba.set_from_iter(200, std::iter::empty()), 0);
+        assert_eq!(ba.set_from_iter(195, vec![false; 5]), 5);
+        assert_eq!(ba.set_from_iter(0, ba.clone().iter()), 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than 3 bits")]
+    fn test_set_from_iter_overflow() {
+        BitArray::new(8).set_from_iter(5, std::iter::repeat(true));
+    }
+}
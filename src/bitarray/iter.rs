@@ -0,0 +1,172 @@
+// Iterators over the bits of an array, and their rayon counterparts.
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use super::BitArray;
+
+/// Iterator over every bit of an array, see BitArray::iter.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    arr: &'a BitArray,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        // front < back <= bit_sz
+        Some(unsafe { self.arr.get_unchecked(self.front - 1) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.back - self.front;
+        (left, Some(left))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<bool> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(unsafe { self.arr.get_unchecked(self.back) })
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+/// Iterator over the indices of the set bits, see BitArray::iter_ones.
+#[derive(Debug, Clone)]
+pub struct IterOnes<'a> {
+    arr: &'a BitArray,
+    /// The range of the array being scanned; indices are relative to start.
+    start: usize,
+    end: usize,
+    /// Start of the next 64 bit chunk to load.
+    pos: usize,
+    /// Start of the chunk in word.
+    base: usize,
+    /// The not yet returned ones of the current chunk.
+    word: u64,
+}
+
+impl<'a> Iterator for IterOnes<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            if self.pos >= self.end {
+                return None;
+            }
+            let width = usize::min(64, self.end - self.pos);
+            self.word = self.arr.read_bits(self.pos, width);
+            self.base = self.pos;
+            self.pos += width;
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.base + bit - self.start)
+    }
+}
+
+impl<'a> IterOnes<'a> {
+    /// The set bits of arr in [start, end), numbered from start.
+    pub(crate) fn new(arr: &'a BitArray, start: usize, end: usize) -> IterOnes<'a> {
+        IterOnes { arr, start, end, pos: start, base: start, word: 0 }
+    }
+}
+
+impl BitArray {
+    /// Iterates over the bits, from index 0 up.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { arr: self, front: 0, back: self.bit_sz }
+    }
+
+    /// Iterates over the indices of the bits that are set, in increasing
+    /// order, skipping 64 clear bits at a time.
+    pub fn iter_ones(&self) -> IterOnes<'_> {
+        IterOnes::new(self, 0, self.bit_sz)
+    }
+
+    /// A rayon iterator over the bits.  It is indexed, so it can be zipped
+    /// with other indexed iterators or collected in order.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = bool> + '_ {
+        (0 .. self.bit_sz).into_par_iter().map(move |i| self.get(i))
+    }
+
+    /// A rayon iterator over the indices of the set bits.  The array is
+    /// split on 64 bit boundaries, so each task scans whole words.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter_ones(&self) -> impl ParallelIterator<Item = usize> + '_ {
+        (0 .. self.bit_sz.div_ceil(64)).into_par_iter().flat_map_iter(move |w| {
+            let base = w * 64;
+            let mut word = self.read_bits(base, usize::min(64, self.bit_sz - base));
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(base + bit)
+            })
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a BitArray {
+    type Item = bool;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter() {
+        let ba = BitArray::from_str("10010110");
+        let bits: Vec<bool> = ba.iter().collect();
+        assert_eq!(bits, vec![false, true, true, false, true, false, false, true]);
+        assert_eq!(ba.iter().next_back(), Some(true));
+        assert_eq!(ba.iter().len(), 8);
+        assert_eq!((&ba).into_iter().filter(|&b| b).count(), 4);
+    }
+
+    #[test]
+    fn test_iter_ones() {
+        let ba = BitArray::from_str("10010110");
+        assert_eq!(ba.iter_ones().collect::<Vec<_>>(), vec![1, 2, 4, 7]);
+        assert_eq!(BitArray::new(200).iter_ones().next(), None);
+
+        let mut ba = BitArray::new(300);
+        ba.randfill();
+        let expected: Vec<usize> = (0 .. 300).filter(|&i| ba.get(i)).collect();
+        assert_eq!(ba.iter_ones().collect::<Vec<_>>(), expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_iter() {
+        let mut ba = BitArray::new(100_003);
+        ba.randfill();
+        let bits: Vec<bool> = ba.par_iter().collect();
+        assert_eq!(bits, ba.iter().collect::<Vec<_>>());
+        let mut ones: Vec<usize> = ba.par_iter_ones().collect();
+        ones.sort();
+        assert_eq!(ones, ba.iter_ones().collect::<Vec<_>>());
+        assert_eq!(ba.par_iter().zip(ba.par_iter()).filter(|&(a, b)| a && b).count(),
+                   ba.count_ones());
+    }
+}
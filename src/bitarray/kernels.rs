@@ -0,0 +1,79 @@
+// The loops over a bare slice of words behind BitArray's bit-field and
+// reversal methods, bit i in bit i % 64 of word i / 64.  They check
+// nothing beyond what slice indexing does; the callers check bounds and
+// keep the padding clear.  BitArrayMmap runs its words through the same
+// code.
+use super::counters::{self, Counter};
+use super::BitArray;
+
+/// The bit_width (at most 64) bits of words from bit_offset on, bit
+/// bit_offset in bit 0.
+pub(crate) fn read_bits(words: &[u64], bit_offset: usize, bit_width: usize) -> u64 {
+    counters::bump(Counter::WordRead);
+    if bit_width == 0 {
+        return 0;
+    }
+    // The bits are in one word, or straddle two: the low part comes from
+    // the top of the first and the rest from the bottom of the second.
+    let (word, shift) = (bit_offset / 64, bit_offset % 64);
+    let mut value = words[word] >> shift;
+    if shift + bit_width > 64 {
+        value |= words[word + 1] << (64 - shift);
+    }
+    value & BitArray::low_bits(bit_width)
+}
+
+/// Writes the low bit_width (at most 64) bits of value to words from
+/// bit_offset on, the inverse of read_bits.
+pub(crate) fn write_bits(words: &mut [u64], bit_offset: usize, bit_width: usize, value: u64) {
+    counters::bump(Counter::WordWrite);
+    if bit_width == 0 {
+        return;
+    }
+    let (word, shift) = (bit_offset / 64, bit_offset % 64);
+    let take = usize::min(64 - shift, bit_width);
+    let mask = BitArray::low_bits(take) << shift;
+    words[word] = (words[word] & !mask) | ((value << shift) & mask);
+    if take < bit_width {
+        let mask = BitArray::low_bits(bit_width - take);
+        words[word + 1] = (words[word + 1] & !mask) | ((value >> take) & mask);
+    }
+}
+
+/// Reverses the bits [i, j) of words by reading a window from each end,
+/// reversing each with u64::reverse_bits, and writing it back at the
+/// other end.  Windows are 64 bits until the ends meet, then half of what
+/// is left, so at most one bit in the middle stays put.
+pub(crate) fn reverse_by_words(words: &mut [u64], mut i: usize, mut j: usize) {
+    while j - i >= 2 {
+        let w = usize::min(64, (j - i) / 2);
+        let (a, b) = (read_bits(words, i, w), read_bits(words, j - w, w));
+        write_bits(words, i, w, b.reverse_bits() >> (64 - w));
+        write_bits(words, j - w, w, a.reverse_bits() >> (64 - w));
+        i += w;
+        j -= w;
+    }
+}
+
+/// The number of ones in words.
+pub(crate) fn count_ones(words: &[u64]) -> usize {
+    counters::bump_by(Counter::WordRead, words.len());
+    words.iter().map(|w| w.count_ones() as usize).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_straddling() {
+        let mut words = [0u64; 3];
+        write_bits(&mut words, 60, 10, 0b11_0000_0101);
+        assert_eq!(words[.. 2], [0b0101 << 60, 0b11_0000]);
+        assert_eq!(read_bits(&words, 60, 10), 0b11_0000_0101);
+        assert_eq!(read_bits(&words, 62, 0), 0);
+        reverse_by_words(&mut words, 60, 70);
+        assert_eq!(read_bits(&words, 60, 10), 0b10_1000_0011);
+        assert_eq!(count_ones(&words), 4);
+    }
+}
@@ -0,0 +1,113 @@
+// Three-way merge of two arrays that were both changed from a common
+// ancestor, the way version control merges files.  Bit by bit there is
+// nothing to resolve: a bit either side changed can only have changed
+// one way.  But when both sides changed the same 64-bit word they were
+// likely editing the same thing (one allocation in an allocation map, a
+// region of a mask), and taking half of each can be wrong, so such words
+// are conflicts and the policy decides them.
+use std::ops::Range;
+
+use super::BitArray;
+
+/// The width of the region both sides must touch to conflict.
+pub const MERGE_WORD_BITS: usize = 64;
+
+/// How a word changed on both sides is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep our word.
+    Ours,
+    /// Keep their word.
+    Theirs,
+    /// A bit is set if either side has it set, so no allocation is lost.
+    Or,
+    /// A bit is set only if both sides have it set.
+    And,
+}
+
+/// What merge produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merged {
+    pub bits: BitArray,
+    /// The bit ranges both sides changed, adjacent ones joined.  Empty
+    /// when the policy had nothing to decide.
+    pub conflicts: Vec<Range<usize>>,
+}
+
+impl BitArray {
+    /// Combines the changes ours and theirs each made to base.  A word
+    /// only one side changed takes that side's change; a word both sides
+    /// changed differently is a conflict and policy picks its bits.  The
+    /// three arrays must be the same size.
+    pub fn merge(base: &BitArray, ours: &BitArray, theirs: &BitArray, policy: MergePolicy) -> Merged {
+        assert_eq!(base.bit_sz, ours.bit_sz);
+        assert_eq!(base.bit_sz, theirs.bit_sz);
+        let mut bits = base.clone();
+        let mut conflicts: Vec<Range<usize>> = vec![];
+        for start in (0 .. base.bit_sz).step_by(MERGE_WORD_BITS) {
+            let width = usize::min(MERGE_WORD_BITS, base.bit_sz - start);
+            let b = base.read_bits(start, width);
+            let o = ours.read_bits(start, width);
+            let t = theirs.read_bits(start, width);
+            let word = if o == b || o == t {
+                t
+            } else if t == b {
+                o
+            } else {
+                match conflicts.last_mut() {
+                    Some(last) if last.end == start => last.end += width,
+                    _ => conflicts.push(start .. start + width),
+                }
+                match policy {
+                    MergePolicy::Ours => o,
+                    MergePolicy::Theirs => t,
+                    MergePolicy::Or => o | t,
+                    MergePolicy::And => o & t,
+                }
+            };
+            bits.write_bits(start, width, word);
+        }
+        Merged { bits, conflicts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_merge() {
+        let base = BitArray::new(200);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        ours.set(3, true);
+        theirs.set(100, true);
+        // the same change on both sides is not a conflict
+        ours.set(150, true);
+        theirs.set(150, true);
+        let m = BitArray::merge(&base, &ours, &theirs, MergePolicy::Ours);
+        assert!(m.conflicts.is_empty());
+        assert_eq!(m.bits.to_ranges(), vec![3 .. 4, 100 .. 101, 150 .. 151]);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut base = BitArray::new(200);
+        base.fill_range(0, 4, true);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        // both allocate in word 0, theirs also frees bit 0; both edit the
+        // short last word, bits 192..200
+        ours.fill_range(4, 2, true);
+        theirs.fill_range(8, 2, true);
+        theirs.set(0, false);
+        ours.set(195, true);
+        theirs.set(199, true);
+        let merge = |policy| BitArray::merge(&base, &ours, &theirs, policy);
+        assert_eq!(merge(MergePolicy::Ours).conflicts, vec![0 .. 64, 192 .. 200]);
+        assert_eq!(merge(MergePolicy::Ours).bits.to_ranges(), vec![0 .. 6, 195 .. 196]);
+        assert_eq!(merge(MergePolicy::Theirs).bits.to_ranges(), vec![1 .. 4, 8 .. 10, 199 .. 200]);
+        assert_eq!(merge(MergePolicy::Or).bits.to_ranges(), vec![0 .. 6, 8 .. 10, 195 .. 196, 199 .. 200]);
+        assert_eq!(merge(MergePolicy::And).bits.to_ranges(), vec![1 .. 4]);
+    }
+}
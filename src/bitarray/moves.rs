@@ -0,0 +1,197 @@
+// Rotation by moving words rather than bits, the path rotate takes for
+// all but short ranges.  A left rotation by k is a shift: save the first
+// k bits, move the rest down k places, and put the saved bits at the end.
+// If the shorter of the two parts fits in a fixed scratch buffer on the
+// stack, that is one pass over the range; otherwise Gries-Mills block
+// swaps shrink it until it does.  Moving a stretch of bits works in three
+// pieces: the bits up to the first word boundary of the destination and
+// those past the last one go through read_bits/write_bits, and the whole
+// words in between are written directly, copied with copy_within when
+// the shift is a whole number of words and funnel-shifted from two source
+// words otherwise.  Nothing is allocated.
+use super::counters::{self, Counter};
+use super::{Allocator, BitArray};
+
+/// Words of the scratch buffer the saved part is kept in.
+const SCRATCH_WORDS: usize = 64;
+
+/// The longest part the scratch buffer holds.
+pub(crate) const SCRATCH_BITS: usize = 64 * SCRATCH_WORDS;
+
+/// One step of a rotation by word moves, for rotate_traced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WordMove {
+    /// [a, a + len) and [b, b + len) traded places.
+    Swap { a: usize, b: usize, len: usize },
+    /// [start + by, end) moved down to [start, end - by), the first by
+    /// bits saved.
+    ShiftDown { start: usize, end: usize, by: usize },
+    /// [start, end - by) moved up to [start + by, end), the last by bits
+    /// saved.
+    ShiftUp { start: usize, end: usize, by: usize },
+    /// The saved bits written back to [start, start + len).
+    Restore { start: usize, len: usize },
+}
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// Rotates [bit_offset, bit_offset + bit_length) left by
+    /// bit_left_amount with word moves, calling on_step after each.
+    pub(crate) fn rotate_left_by_word_moves<F>(&mut self,
+                                               bit_offset: usize,
+                                               bit_length: usize,
+                                               bit_left_amount: usize,
+                                               mut on_step: F)
+    where F: FnMut(&BitArray<A>, WordMove) {
+        // the unplaced bits are [p - i, p + j), a rotation left by i
+        let p = bit_offset + bit_left_amount;
+        let (mut i, mut j) = (bit_left_amount, bit_length - bit_left_amount);
+        while i > SCRATCH_BITS && j > SCRATCH_BITS {
+            if i < j {
+                self.swap_blocks(p - i, p + j - i, i);
+                on_step(self, WordMove::Swap { a: p - i, b: p + j - i, len: i });
+                j -= i;
+            } else {
+                self.swap_blocks(p - i, p, j);
+                on_step(self, WordMove::Swap { a: p - i, b: p, len: j });
+                i -= j;
+            }
+        }
+        if i == 0 || j == 0 {
+            return;
+        }
+
+        let mut scratch = [0u64; SCRATCH_WORDS];
+        let (start, end) = (p - i, p + j);
+        if i <= j {
+            self.save(start, i, &mut scratch);
+            self.move_bits_down(start, end, i);
+            on_step(self, WordMove::ShiftDown { start, end, by: i });
+            self.restore(end - i, i, &scratch);
+            on_step(self, WordMove::Restore { start: end - i, len: i });
+        } else {
+            self.save(end - j, j, &mut scratch);
+            self.move_bits_up(start, end, j);
+            on_step(self, WordMove::ShiftUp { start, end, by: j });
+            self.restore(start, j, &scratch);
+            on_step(self, WordMove::Restore { start, len: j });
+        }
+    }
+
+    fn save(&self, start: usize, len: usize, scratch: &mut [u64; SCRATCH_WORDS]) {
+        for (k, word) in scratch.iter_mut().enumerate().take(len.div_ceil(64)) {
+            *word = self.read_bits(start + 64 * k, usize::min(64, len - 64 * k));
+        }
+    }
+
+    fn restore(&mut self, start: usize, len: usize, scratch: &[u64; SCRATCH_WORDS]) {
+        for (k, &word) in scratch.iter().enumerate().take(len.div_ceil(64)) {
+            self.write_bits(start + 64 * k, usize::min(64, len - 64 * k), word);
+        }
+    }
+
+    /// The 64 bits from bit q on; q + 64 may not be past the buffer.
+    fn funnel(&self, q: usize) -> u64 {
+        let (w, shift) = (q / 64, q % 64);
+        if shift == 0 {
+            counters::bump(Counter::WordRead);
+            self.data[w]
+        } else {
+            counters::bump_by(Counter::WordRead, 2);
+            (self.data[w] >> shift) | (self.data[w + 1] << (64 - shift))
+        }
+    }
+
+    /// Copies len bits from src to dst, through read_bits and write_bits.
+    fn move_bits(&mut self, dst: usize, src: usize, len: usize) {
+        if len > 0 {
+            self.write_bits(dst, len, self.read_bits(src, len));
+        }
+    }
+
+    /// Moves [start + by, end) to [start, end - by), ascending so every
+    /// source bit is read before it is overwritten.
+    fn move_bits_down(&mut self, start: usize, end: usize, by: usize) {
+        let stop = end - by;
+        let head = usize::min(stop - start, (64 - start % 64) % 64);
+        self.move_bits(start, start + by, head);
+        let first = (start + head) / 64;
+        let words = (stop - start - head) / 64;
+        counters::bump_by(Counter::WordWrite, words);
+        if by.is_multiple_of(64) {
+            counters::bump_by(Counter::WordRead, words);
+            self.data.copy_within(first + by / 64 .. first + by / 64 + words, first);
+        } else {
+            for w in first .. first + words {
+                self.data[w] = self.funnel(64 * w + by);
+            }
+        }
+        let done = start + head + 64 * words;
+        self.move_bits(done, done + by, stop - done);
+        self.check_invariants();
+    }
+
+    /// Moves [start, end - by) to [start + by, end), descending.
+    fn move_bits_up(&mut self, start: usize, end: usize, by: usize) {
+        let low = start + by;
+        let tail = usize::min(end - low, end % 64);
+        self.move_bits(end - tail, end - tail - by, tail);
+        let top = (end - tail) / 64;
+        let words = (end - tail - low) / 64;
+        counters::bump_by(Counter::WordWrite, words);
+        if by.is_multiple_of(64) {
+            counters::bump_by(Counter::WordRead, words);
+            self.data.copy_within(top - words - by / 64 .. top - by / 64, top - words);
+        } else {
+            for w in (top - words .. top).rev() {
+                self.data[w] = self.funnel(64 * w - by);
+            }
+        }
+        let done = end - tail - 64 * words;
+        self.move_bits(low, low - by, done - low);
+        self.check_invariants();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(bit_sz: usize, offset: usize, length: usize, left: usize) {
+        let mut ba = BitArray::new(bit_sz);
+        ba.randfill();
+        let mut want = ba.clone();
+        want.rotate_with(super::super::RotateStrategy::Reverse, offset, length, -(left as isize));
+        ba.rotate_left_by_word_moves(offset, length, left, |_, _| {});
+        assert_eq!(ba, want, "{} {} {} {}", bit_sz, offset, length, left);
+    }
+
+    #[test]
+    fn test_shifts() {
+        for &(offset, length) in &[(0usize, 1000usize), (3, 997), (64, 640), (7, 129), (60, 10), (1, 64)] {
+            for &left in &[1, 2, 63, 64, 65, 128, length / 2, length.saturating_sub(64), length - 1] {
+                if 0 < left && left < length {
+                    check(1000, offset, length, left);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_swaps_then_shift() {
+        let n = 5 * SCRATCH_BITS + 77;
+        for &left in &[SCRATCH_BITS + 1, 2 * SCRATCH_BITS + 3, n / 2, n - SCRATCH_BITS - 5, 3 * SCRATCH_BITS] {
+            check(n + 20, 9, n, left);
+        }
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut ba = BitArray::new(3 * SCRATCH_BITS);
+        let mut steps = vec![];
+        ba.rotate_left_by_word_moves(0, 3 * SCRATCH_BITS, SCRATCH_BITS + 10, |_, s| steps.push(s));
+        let (i, j) = (SCRATCH_BITS + 10, 2 * SCRATCH_BITS - 10);
+        assert_eq!(steps, vec![WordMove::Swap { a: 0, b: j, len: i },
+                               WordMove::ShiftUp { start: 0, end: j, by: j - i },
+                               WordMove::Restore { start: 0, len: j - i }]);
+    }
+}
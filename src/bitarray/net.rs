@@ -0,0 +1,160 @@
+// Field access the way packet headers are specified: the array is read
+// as a stream of bits in wire order, and each multi-bit field has its
+// most significant bit first (at the lowest index).
+use super::BitArray;
+
+impl BitArray {
+    /// Builds a bit array from bytes in network order, so that the most
+    /// significant bit of bytes[0] is bit 0 of the array.  Header offsets
+    /// from an RFC diagram can then be used as bit offsets directly.
+    pub fn from_network_bytes(bytes: &[u8]) -> BitArray {
+        let mut arr = BitArray::new(bytes.len() * 8);
+        for (i, byte) in bytes.iter().enumerate() {
+            arr.set_byte(i, byte.reverse_bits());
+        }
+        arr
+    }
+
+    /// The inverse of from_network_bytes.  A trailing partial byte is
+    /// padded with zeros in its least significant bits.
+    pub fn to_network_bytes(&self) -> Vec<u8> {
+        let num_bytes = self.bit_sz.div_ceil(8);
+        let mut bytes: Vec<u8> = (0 .. num_bytes).map(|i| self.byte(i).reverse_bits()).collect();
+        if !self.bit_sz.is_multiple_of(8) {
+            let last = num_bytes - 1;
+            bytes[last] &= 0xff << (8 - self.bit_sz % 8);
+        }
+        bytes
+    }
+
+    /// Reads the bit_width (at most 64) bits starting at bit_offset as a
+    /// big-endian unsigned integer: bit bit_offset is the most significant.
+    pub fn read_be_uint(&self, bit_offset: usize, bit_width: usize) -> u64 {
+        assert!(bit_width <= 64, "a field is at most 64 bits, not {}", bit_width);
+        if bit_width == 0 {
+            return 0;
+        }
+        self.read_bits(bit_offset, bit_width).reverse_bits() >> (64 - bit_width)
+    }
+
+    /// Writes value as a big-endian unsigned field, the inverse of
+    /// read_be_uint.  value must fit in bit_width bits.
+    pub fn write_be_uint(&mut self, bit_offset: usize, bit_width: usize, value: u64) {
+        assert!(bit_width <= 64, "a field is at most 64 bits, not {}", bit_width);
+        assert!(bit_width == 64 || value >> bit_width == 0);
+        if bit_width == 0 {
+            return;
+        }
+        self.write_bits(bit_offset, bit_width, value.reverse_bits() >> (64 - bit_width));
+    }
+
+    /// Reads a big-endian two's complement field, sign extending it.
+    pub fn read_be_int(&self, bit_offset: usize, bit_width: usize) -> i64 {
+        assert!(bit_width <= 64, "a field is at most 64 bits, not {}", bit_width);
+        if bit_width == 0 {
+            return 0;
+        }
+        let shift = 64 - bit_width;
+        ((self.read_be_uint(bit_offset, bit_width) << shift) as i64) >> shift
+    }
+
+    /// Writes value as a big-endian two's complement field.  value must be
+    /// representable in bit_width bits.
+    pub fn write_be_int(&mut self, bit_offset: usize, bit_width: usize, value: i64) {
+        assert!(bit_width <= 64, "a field is at most 64 bits, not {}", bit_width);
+        if bit_width == 0 {
+            assert_eq!(value, 0);
+            return;
+        }
+        let shift = 64 - bit_width;
+        assert_eq!((value << shift) >> shift, value, "{} does not fit in {} bits", value, bit_width);
+        let mask = u64::MAX >> shift;
+        self.write_be_uint(bit_offset, bit_width, value as u64 & mask);
+    }
+
+    /// Reads a one bit flag.  The same as get, spelled for header code.
+    pub fn read_flag(&self, bit_offset: usize) -> bool {
+        self.get(bit_offset)
+    }
+
+    /// Writes a one bit flag.
+    pub fn write_flag(&mut self, bit_offset: usize, val: bool) {
+        self.set(bit_offset, val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An IPv4 header with version 4, IHL 5, total length 0x0054, flags 0b010
+    // (don't fragment), fragment offset 0 and TTL 64.
+    const IPV4: [u8; 10] = [0x45, 0x00, 0x00, 0x54, 0x1c, 0x46, 0x40, 0x00, 0x40, 0x01];
+
+    #[test]
+    fn test_ipv4_header() {
+        let ba = BitArray::from_network_bytes(&IPV4);
+        assert_eq!(ba.read_be_uint(0, 4), 4);
+        assert_eq!(ba.read_be_uint(4, 4), 5);
+        assert_eq!(ba.read_be_uint(16, 16), 0x54);
+        assert_eq!(ba.read_be_uint(32, 16), 0x1c46);
+        assert!(!ba.read_flag(48));
+        assert!(ba.read_flag(49));
+        assert_eq!(ba.read_be_uint(48, 3), 0b010);
+        assert_eq!(ba.read_be_uint(51, 13), 0);
+        assert_eq!(ba.read_be_uint(64, 8), 64);
+        assert_eq!(ba.read_be_uint(72, 8), 1);
+    }
+
+    #[test]
+    fn test_network_bytes_round_trip() {
+        let ba = BitArray::from_network_bytes(&IPV4);
+        assert_eq!(ba.to_network_bytes(), IPV4.to_vec());
+
+        let mut ba = BitArray::new(12);
+        ba.write_be_uint(0, 12, 0xabc);
+        assert_eq!(ba.to_network_bytes(), vec![0xab, 0xc0]);
+    }
+
+    #[test]
+    fn test_write_be_fields() {
+        let mut ba = BitArray::from_network_bytes(&IPV4);
+        ba.write_be_uint(64, 8, 1);
+        ba.write_flag(49, false);
+        ba.write_be_uint(51, 13, 0x1abc);
+        assert_eq!(ba.read_be_uint(64, 8), 1);
+        assert_eq!(ba.read_be_uint(48, 16), 0x1abc);
+        assert_eq!(ba.read_be_uint(0, 8), 0x45);
+        assert_eq!(ba.read_be_uint(72, 8), 1);
+
+        let mut wide = BitArray::new(70);
+        wide.write_be_uint(3, 64, 0x0123_4567_89ab_cdef);
+        assert_eq!(wide.read_be_uint(3, 64), 0x0123_4567_89ab_cdef);
+    }
+
+    #[test]
+    fn test_signed_fields() {
+        let mut ba = BitArray::new(20);
+        ba.write_be_int(1, 5, -3);
+        assert_eq!(ba.read_be_int(1, 5), -3);
+        assert_eq!(ba.read_be_uint(1, 5), 0b11101);
+        ba.write_be_int(6, 12, 2047);
+        assert_eq!(ba.read_be_int(6, 12), 2047);
+        ba.write_be_int(6, 12, -2048);
+        assert_eq!(ba.read_be_int(6, 12), -2048);
+        assert_eq!(ba.read_be_int(1, 5), -3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_signed_overflow() {
+        let mut ba = BitArray::new(8);
+        ba.write_be_int(0, 4, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "a field is at most 64 bits, not 65")]
+    fn test_signed_too_wide() {
+        BitArray::new(100).read_be_int(0, 65);
+    }
+}
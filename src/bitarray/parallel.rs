@@ -0,0 +1,156 @@
+// Reversal on all cores, behind the rayon feature, for ranges too long for
+// one core to get through quickly.  Splitting a reversal of [start, end)
+// at arbitrary bits would leave neighbouring chunks sharing words, so the
+// work is done on whole words instead: the words covering the range are
+// reversed end for end (each task swapping a chunk from the front with its
+// mirror at the back), which leaves the range reversed but misplaced by
+// the difference between the partial words at its two ends, and a second
+// pass shifts everything back by that much, each task funnel-shifting its
+// own chunk with the one word it needs from its neighbour read first.  The
+// bits of the end words outside the range are saved beforehand and put
+// back afterwards.  A rotate of such a range is three of these; see
+// plan.rs.
+use std::iter;
+
+use rayon::prelude::*;
+
+use super::bulk::{CHUNK_BYTES, PARALLEL_THRESHOLD_BYTES};
+use super::{Allocator, BitArray};
+
+/// Ranges of at least this many bits are reversed in parallel.
+pub(crate) const PARALLEL_BITS: usize = 8 * PARALLEL_THRESHOLD_BYTES;
+
+/// Words handed to each rayon task.
+const CHUNK_WORDS: usize = CHUNK_BYTES / 8;
+
+/// A word with its low n bits set, 0 <= n <= 64.
+fn below(n: usize) -> u64 {
+    if n == 0 { 0 } else { u64::MAX >> (64 - n) }
+}
+
+/// Reverses the bits of words, the first bit of the first word becoming
+/// the last bit of the last.
+fn reverse_words(words: &mut [u64]) {
+    let (front, rest) = words.split_at_mut(words.len() / 2);
+    let (middle, back) = rest.split_at_mut(rest.len() - front.len());
+    for w in middle {
+        *w = w.reverse_bits();
+    }
+    front.par_chunks_mut(CHUNK_WORDS).zip(back.par_rchunks_mut(CHUNK_WORDS)).for_each(|(f, b)| {
+        for (x, y) in f.iter_mut().zip(b.iter_mut().rev()) {
+            let t = x.reverse_bits();
+            *x = y.reverse_bits();
+            *y = t;
+        }
+    });
+}
+
+/// Shifts the bits of words towards the first by 0 < by < 64 places,
+/// zeros coming in at the top.
+fn shift_words_down(words: &mut [u64], by: usize) {
+    let after: Vec<u64> = words.chunks(CHUNK_WORDS).skip(1).map(|c| c[0]).chain(iter::once(0)).collect();
+    words.par_chunks_mut(CHUNK_WORDS).zip(after).for_each(|(chunk, after)| {
+        for k in 0 .. chunk.len() {
+            let next = if k + 1 < chunk.len() { chunk[k + 1] } else { after };
+            chunk[k] = (chunk[k] >> by) | (next << (64 - by));
+        }
+    });
+}
+
+/// Shifts the bits of words towards the last by 0 < by < 64 places,
+/// zeros coming in at the bottom.
+fn shift_words_up(words: &mut [u64], by: usize) {
+    let before: Vec<u64> = iter::once(0).chain(words.chunks(CHUNK_WORDS).map(|c| c[c.len() - 1]))
+        .take(words.len().div_ceil(CHUNK_WORDS)).collect();
+    words.par_chunks_mut(CHUNK_WORDS).zip(before).for_each(|(chunk, before)| {
+        for k in (0 .. chunk.len()).rev() {
+            let prev = if k > 0 { chunk[k - 1] } else { before };
+            chunk[k] = (chunk[k] << by) | (prev >> (64 - by));
+        }
+    });
+}
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// reverse_range of [start, end), on all cores.
+    pub(crate) fn reverse_range_parallel(&mut self, start: usize, end: usize) {
+        if end - start < 2 {
+            return;
+        }
+        let (a, b) = (start / 64, end.div_ceil(64));
+        // the bits of the first and last words outside the range
+        let (head, tail) = (start - 64 * a, 64 * b - end);
+        let saved_head = self.data[a] & below(head);
+        let saved_tail = self.data[b - 1] & !below(64 - tail);
+        let words = &mut self.data[a .. b];
+        reverse_words(words);
+        // the range is now at [64a + tail, 64b - head)
+        if head > tail {
+            shift_words_up(words, head - tail);
+        } else if tail > head {
+            shift_words_down(words, tail - head);
+        }
+        self.data[a] = (self.data[a] & !below(head)) | saved_head;
+        self.data[b - 1] = (self.data[b - 1] & below(64 - tail)) | saved_tail;
+        self.check_invariants();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn sequential(ba: &BitArray, start: usize, end: usize) -> BitArray {
+        let mut want = ba.clone();
+        want.reverse_by_words(start, end);
+        want
+    }
+
+    #[test]
+    fn test_shift_words() {
+        let mut rng = rand::thread_rng();
+        let words: Vec<u64> = (0 .. 3 * CHUNK_WORDS + 5).map(|_| rng.gen()).collect();
+        let at = |k: usize| words.get(k).cloned().unwrap_or(0);
+        for &by in &[1, 13, 63] {
+            let mut down = words.clone();
+            shift_words_down(&mut down, by);
+            let mut up = words.clone();
+            shift_words_up(&mut up, by);
+            for k in 0 .. words.len() {
+                assert_eq!(down[k], (at(k) >> by) | (at(k + 1) << (64 - by)));
+                let prev = if k > 0 { at(k - 1) >> (64 - by) } else { 0 };
+                assert_eq!(up[k], (at(k) << by) | prev);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let mut ba = BitArray::new(1000);
+        ba.randfill();
+        for &(start, end) in &[(0, 1000), (0, 999), (1, 1000), (3, 997), (64, 640), (60, 70), (5, 6),
+                               (100, 1000), (7, 8), (127, 129)] {
+            let mut got = ba.clone();
+            got.reverse_range_parallel(start, end);
+            assert_eq!(got, sequential(&ba, start, end), "{} {}", start, end);
+        }
+    }
+
+    #[test]
+    fn test_matches_sequential_across_chunks() {
+        let n = 64 * (5 * CHUNK_WORDS + 3) + 17;
+        let mut ba = BitArray::new(n);
+        ba.randfill();
+        for &(start, end) in &[(0, n), (9, n - 40), (64 * CHUNK_WORDS, n), (30, 64 * 2 * CHUNK_WORDS + 1)] {
+            let mut got = ba.clone();
+            got.reverse_range_parallel(start, end);
+            assert_eq!(got, sequential(&ba, start, end), "{} {}", start, end);
+        }
+        // rotate goes parallel past PARALLEL_BITS
+        let mut got = ba.clone();
+        got.rotate(11, n - 20, 12_345);
+        let mut want = ba.clone();
+        want.rotate_with(super::super::RotateStrategy::WordMoves, 11, n - 20, 12_345);
+        assert_eq!(got, want);
+    }
+}
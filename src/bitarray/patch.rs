@@ -0,0 +1,216 @@
+// Patches between two versions of an array, for keeping a copy of a
+// large, slowly changing bitmap in sync without sending all of it.  A
+// patch is a sequence of LEB128 varints followed by raw bytes:
+//
+//     old bit_sz, new bit_sz, number of runs,
+//     then per run: bytes skipped since the last run, run length, the bytes
+//
+// where a run is a stretch of bytes of the new array that differ from the
+// old one.  Bytes past the end of the old array count as zero.
+use std::fmt;
+
+use super::BitArray;
+
+/// Unchanged stretches shorter than this are sent anyway, since the two
+/// varints of a new run would cost about as much.
+const MIN_GAP_BYTES: usize = 4;
+
+/// The error from BitArray::apply_patch.  The array is left unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The patch ends in the middle of something.
+    Truncated,
+    /// The patch was made against an array of a different size.
+    WrongBase { expected: usize, found: usize },
+    /// A run reaches past the end of the new array, or something follows
+    /// the last run.
+    OutOfRange,
+    /// The new array would be bigger than the caller allows.
+    TooLarge { bit_sz: usize, max: usize },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::Truncated => write!(f, "truncated patch"),
+            PatchError::WrongBase { expected, found } => {
+                write!(f, "patch is for a {} bit array, not {} bits", expected, found)
+            }
+            PatchError::OutOfRange => write!(f, "patch writes past the end of the array"),
+            PatchError::TooLarge { bit_sz, max } => {
+                write!(f, "patch makes a {} bit array, more than the {} allowed", bit_sz, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+fn push_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(patch: &[u8], pos: &mut usize) -> Result<usize, PatchError> {
+    let mut n = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *patch.get(*pos).ok_or(PatchError::Truncated)?;
+        *pos += 1;
+        if shift >= usize::BITS {
+            return Err(PatchError::OutOfRange);
+        }
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+    }
+}
+
+impl BitArray {
+    /// A patch that turns old into new, see apply_patch.  Its size is
+    /// about the number of bytes that differ.
+    pub fn diff_serialize(old: &BitArray, new: &BitArray) -> Vec<u8> {
+        let old_bytes = old.bit_sz.div_ceil(8);
+        let old_byte = |i: usize| if i < old_bytes { old.byte(i) } else { 0 };
+        // (start, end) byte ranges of new that differ, small gaps merged
+        let mut runs: Vec<(usize, usize)> = vec![];
+        for i in (0 .. new.bit_sz.div_ceil(8)).filter(|&i| new.byte(i) != old_byte(i)) {
+            match runs.last_mut() {
+                Some(run) if i - run.1 < MIN_GAP_BYTES => run.1 = i + 1,
+                _ => runs.push((i, i + 1)),
+            }
+        }
+        let mut patch = vec![];
+        push_varint(&mut patch, old.bit_sz);
+        push_varint(&mut patch, new.bit_sz);
+        push_varint(&mut patch, runs.len());
+        let mut pos = 0;
+        for (start, end) in runs {
+            push_varint(&mut patch, start - pos);
+            push_varint(&mut patch, end - start);
+            patch.extend((start .. end).map(|i| new.byte(i)));
+            pos = end;
+        }
+        patch
+    }
+
+    /// Applies a patch from diff_serialize(old, new) to old, making it
+    /// equal to new, resizing it if the two sizes differ.  A malformed
+    /// patch, one made against an array of another size, or one that would
+    /// make the array more than max_bit_sz bits is an error and leaves the
+    /// array alone.  The new size is read from the patch, and a patch that
+    /// only grows the array carries no bytes for the growth, so max_bit_sz
+    /// is what keeps a few bytes from asking for terabytes.
+    pub fn apply_patch(&mut self, patch: &[u8], max_bit_sz: usize) -> Result<(), PatchError> {
+        let mut pos = 0;
+        let old_sz = read_varint(patch, &mut pos)?;
+        if old_sz != self.bit_sz {
+            return Err(PatchError::WrongBase { expected: old_sz, found: self.bit_sz });
+        }
+        let new_sz = read_varint(patch, &mut pos)?;
+        if new_sz > max_bit_sz {
+            return Err(PatchError::TooLarge { bit_sz: new_sz, max: max_bit_sz });
+        }
+        let new_len = new_sz.div_ceil(8);
+        // check the whole patch before touching the array
+        let mut runs = vec![];
+        let mut at = 0usize;
+        for _ in 0 .. read_varint(patch, &mut pos)? {
+            let start = at.checked_add(read_varint(patch, &mut pos)?).ok_or(PatchError::OutOfRange)?;
+            let len = read_varint(patch, &mut pos)?;
+            at = start.checked_add(len).filter(|&end| end <= new_len).ok_or(PatchError::OutOfRange)?;
+            let bytes = patch.get(pos ..).and_then(|rest| rest.get(.. len)).ok_or(PatchError::Truncated)?;
+            runs.push((start, bytes));
+            pos += len;
+        }
+        if pos != patch.len() {
+            return Err(PatchError::OutOfRange);
+        }
+
+        self.data.resize(BitArray::words_for(new_sz));
+        self.bit_sz = new_sz;
+        for (start, bytes) in runs {
+            for (i, &b) in bytes.iter().enumerate() {
+                self.set_byte(start + i, b);
+            }
+        }
+        // shrinking within a word leaves old bits behind the new end
+        self.clear_padding();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 1 << 20;
+
+    #[test]
+    fn test_diff_and_apply() {
+        let mut old = BitArray::new(100_000);
+        old.randfill();
+        let mut new = old.clone();
+        new.set(5, !new.get(5));
+        new.fill_range(50_000, 100, true);
+        new.set(99_999, !new.get(99_999));
+        let patch = BitArray::diff_serialize(&old, &new);
+        assert!(patch.len() < 50, "{} bytes", patch.len());
+        let mut copy = old.clone();
+        copy.apply_patch(&patch, MAX).unwrap();
+        assert_eq!(copy, new);
+
+        let same = BitArray::diff_serialize(&old, &old);
+        assert_eq!(same.len(), 3 + 3 + 1);
+        copy.apply_patch(&BitArray::diff_serialize(&new, &new), MAX).unwrap();
+        assert_eq!(copy, new);
+    }
+
+    #[test]
+    fn test_resize() {
+        let old = BitArray::from_str("1111111111");
+        for new in &[BitArray::from_str("101"), BitArray::from_str("10110011100011110000"), BitArray::new(0)] {
+            let mut copy = old.clone();
+            copy.apply_patch(&BitArray::diff_serialize(&old, new), MAX).unwrap();
+            assert_eq!(&copy, new);
+            // padding included
+            assert_eq!(copy.data, new.data);
+        }
+    }
+
+    #[test]
+    fn test_bad_patch() {
+        let old = BitArray::from_str("0000");
+        let patch = BitArray::diff_serialize(&old, &BitArray::from_str("0110"));
+        let mut ba = BitArray::from_str("000");
+        assert_eq!(ba.apply_patch(&patch, MAX), Err(PatchError::WrongBase { expected: 4, found: 3 }));
+        let mut ba = old.clone();
+        assert_eq!(ba.apply_patch(&patch[.. patch.len() - 1], MAX), Err(PatchError::Truncated));
+        assert_eq!(ba.apply_patch(&[4, 4, 1, 5, 1, 0], MAX), Err(PatchError::OutOfRange));
+        assert_eq!(ba.apply_patch(&[4, 4, 0, 0], MAX), Err(PatchError::OutOfRange));
+        assert_eq!(ba.apply_patch(&[0xff; 12], MAX), Err(PatchError::OutOfRange));
+        assert_eq!(ba, old);
+        assert_eq!(PatchError::Truncated.to_string(), "truncated patch");
+    }
+
+    #[test]
+    fn test_huge_declared_size() {
+        // a 10 byte patch for a 2^55 bit array
+        let patch = [4, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x3f, 0];
+        let mut ba = BitArray::from_str("0110");
+        let err = ba.apply_patch(&patch, MAX).unwrap_err();
+        assert_eq!(err, PatchError::TooLarge { bit_sz: (1 << 55) - 1, max: MAX });
+        assert_eq!(ba, BitArray::from_str("0110"));
+        assert_eq!(err.to_string(),
+                   format!("patch makes a {} bit array, more than the {} allowed", (1usize << 55) - 1, MAX));
+        // growing by zeros carries no bytes, up to the limit
+        let patch = BitArray::diff_serialize(&ba, &BitArray::from_str(&format!("{}0110", "0".repeat(MAX - 4))));
+        ba.apply_patch(&patch, MAX).unwrap();
+        assert_eq!((ba.get_bit_sz(), ba.count_ones()), (MAX, 2));
+    }
+}
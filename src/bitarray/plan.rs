@@ -0,0 +1,206 @@
+// How rotate does a rotation and why, worked out before any bit moves so
+// that `everybit --explain` can print the same decision rotate acts on.
+// Each new way of rotating gets a RotatePath and a rule in plan_rotate.
+use std::fmt;
+
+use super::moves::SCRATCH_BITS;
+#[cfg(feature = "rayon")]
+use super::parallel::PARALLEL_BITS;
+use super::{Allocator, BitArray, ROTATE_IN_REGISTER_BITS};
+
+/// The ways rotate can carry out a rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotatePath {
+    /// An empty range or a whole number of turns: nothing moves.
+    Identity,
+    /// Load the range into a u128, rotate it there, and store it back.
+    /// For ranges of at most ROTATE_IN_REGISTER_BITS.
+    Register,
+    /// Block swaps until the shorter part fits in a scratch buffer, then
+    /// one pass shifting the rest past it a word at a time.
+    WordMoves,
+    /// Three reversals, each split across all cores.  For very long
+    /// ranges with the rayon feature.
+    #[cfg(feature = "rayon")]
+    ParallelReversal,
+}
+
+/// The decision rotate makes for one call, see BitArray::plan_rotate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotatePlan {
+    pub bit_offset: usize,
+    pub bit_length: usize,
+    pub bit_right_amount: isize,
+    /// The same rotation as a left rotation by less than bit_length.
+    pub left_amount: usize,
+    pub path: RotatePath,
+    /// Why that path, one line per deciding fact.
+    pub reasons: Vec<String>,
+}
+
+impl BitArray {
+    /// The path rotate takes; cheap enough to run on every call, unlike
+    /// plan_rotate which also writes out the reasons.
+    pub(crate) fn rotate_path(bit_length: usize, left_amount: usize) -> RotatePath {
+        if bit_length == 0 || left_amount == 0 {
+            return RotatePath::Identity;
+        }
+        #[cfg(feature = "rayon")]
+        {
+            if bit_length >= PARALLEL_BITS {
+                return RotatePath::ParallelReversal;
+            }
+        }
+        if bit_length <= ROTATE_IN_REGISTER_BITS {
+            RotatePath::Register
+        } else {
+            RotatePath::WordMoves
+        }
+    }
+}
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// What rotate(bit_offset, bit_length, bit_right_amount) would do.
+    /// Panics, as rotate would, if the range runs past the array.
+    pub fn plan_rotate(&self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) -> RotatePlan {
+        assert!(bit_offset + bit_length <= self.get_bit_sz());
+        let left_amount = if bit_length == 0 {
+            0
+        } else {
+            (bit_length - BitArray::modulo(bit_right_amount, bit_length)) % bit_length
+        };
+        let path = BitArray::rotate_path(bit_length, left_amount);
+        let reasons = match path {
+            RotatePath::Identity if bit_length == 0 => vec!["the range is empty".to_string()],
+            RotatePath::Identity => {
+                vec![format!("{} is a whole number of turns of {} bits", bit_right_amount, bit_length)]
+            }
+            RotatePath::Register => vec![
+                format!("{} bits fit in a {} bit register", bit_length, ROTATE_IN_REGISTER_BITS),
+                "one load, one rotate and one store, whatever the amount".to_string(),
+            ],
+            #[cfg(feature = "rayon")]
+            RotatePath::ParallelReversal => vec![
+                format!("{} bits are at least the parallel threshold of {}", bit_length, PARALLEL_BITS),
+                format!("reversing {} and {} bits, then all {}, splits across {} threads",
+                        left_amount, bit_length - left_amount, bit_length, rayon::current_num_threads()),
+            ],
+            RotatePath::WordMoves => {
+                let shorter = usize::min(left_amount, bit_length - left_amount);
+                let mut reasons = vec![format!("{} bits are enough to move whole words", bit_length)];
+                if shorter > SCRATCH_BITS {
+                    reasons.push(format!("block swaps shrink the shorter part, {} bits, until it fits \
+                                          in the {} bit scratch buffer", shorter, SCRATCH_BITS));
+                } else {
+                    reasons.push(format!("the shorter part, {} bits, fits in the {} bit scratch buffer, \
+                                          so the rest shifts past it in one pass", shorter, SCRATCH_BITS));
+                }
+                // the parts the block swaps leave, as rotate_left_by_word_moves
+                // finds them
+                let (mut i, mut j) = (left_amount, bit_length - left_amount);
+                while i > SCRATCH_BITS && j > SCRATCH_BITS {
+                    if i < j {
+                        j -= i
+                    } else {
+                        i -= j
+                    }
+                }
+                let by = usize::min(i, j);
+                if by == 0 {
+                    reasons.push("the block swaps leave nothing to shift".to_string());
+                } else if by.is_multiple_of(64) {
+                    reasons.push(format!("the shift, {} bits, is whole words, so the words in between \
+                                          are copied as they are", by));
+                } else {
+                    reasons.push(format!("the shift, {} bits, is not whole words, so each word in between \
+                                          is put together from two", by));
+                }
+                reasons
+            }
+        };
+        RotatePlan { bit_offset, bit_length, bit_right_amount, left_amount, path, reasons }
+    }
+}
+
+impl fmt::Display for RotatePlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let end = self.bit_offset + self.bit_length;
+        writeln!(f, "rotate [{}, {}) right by {}, that is left by {}",
+                 self.bit_offset, end, self.bit_right_amount, self.left_amount)?;
+        let aligned = |bit: usize| if bit.is_multiple_of(64) { "word aligned" } else { "not word aligned" };
+        writeln!(f, "  starts at bit {} of word {} ({}), ends at bit {} of word {} ({})",
+                 self.bit_offset % 64, self.bit_offset / 64, aligned(self.bit_offset),
+                 end % 64, end / 64, aligned(end))?;
+        writeln!(f, "  path: {:?}", self.path)?;
+        for reason in &self.reasons {
+            writeln!(f, "    because {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_rotate() {
+        let ba = BitArray::new(200);
+        assert_eq!(ba.plan_rotate(3, 0, 5).path, RotatePath::Identity);
+        assert_eq!(ba.plan_rotate(3, 10, -20).path, RotatePath::Identity);
+        let plan = ba.plan_rotate(2, 5, 2);
+        assert_eq!(plan.left_amount, 3);
+        assert_eq!(plan.path, RotatePath::Register);
+        assert_eq!(ba.plan_rotate(2, 5, -1).path, RotatePath::Register);
+        assert_eq!(ba.plan_rotate(0, 8, isize::MIN).left_amount, 0);
+        // up to 128 bits go through a register, even to shift by one
+        assert_eq!(ba.plan_rotate(0, 128, -1).path, RotatePath::Register);
+        assert_eq!(ba.plan_rotate(0, 129, -1).path, RotatePath::WordMoves);
+    }
+
+    #[test]
+    fn test_explain() {
+        let text = BitArray::new(64).plan_rotate(8, 12, -1).to_string();
+        assert_eq!(text, concat!("rotate [8, 20) right by -1, that is left by 1\n",
+                                 "  starts at bit 8 of word 0 (not word aligned), ",
+                                 "ends at bit 20 of word 0 (not word aligned)\n",
+                                 "  path: Register\n",
+                                 "    because 12 bits fit in a 128 bit register\n",
+                                 "    because one load, one rotate and one store, whatever the amount\n"));
+        let text = BitArray::new(1000).plan_rotate(0, 640, -3).to_string();
+        assert!(text.ends_with(concat!("  path: WordMoves\n",
+                                       "    because 640 bits are enough to move whole words\n",
+                                       "    because the shorter part, 3 bits, fits in the 4096 bit scratch buffer, ",
+                                       "so the rest shifts past it in one pass\n",
+                                       "    because the shift, 3 bits, is not whole words, ",
+                                       "so each word in between is put together from two\n")));
+        let text = BitArray::new(1000).plan_rotate(64, 640, 128).to_string();
+        assert!(text.starts_with("rotate [64, 704) right by 128, that is left by 512\n  \
+                                  starts at bit 0 of word 1 (word aligned), ends at bit 0 of word 11 (word aligned)\n"));
+        assert!(text.contains("the shift, 128 bits, is whole words"));
+        let plan = BitArray::new(20_000).plan_rotate(0, 20_000, 9000);
+        assert!(plan.reasons[1].starts_with("block swaps shrink the shorter part, 9000 bits,"));
+        // left by 11000, and one swap of 9000 bits leaves 2000 to shift
+        assert_eq!(plan.reasons[2], "the shift, 2000 bits, is not whole words, so each word in between \
+                                     is put together from two");
+        assert_eq!(BitArray::new(20_000).plan_rotate(0, 20_000, 10_000).reasons[2],
+                   "the block swaps leave nothing to shift");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_plan_past_the_end() {
+        BitArray::new(8).plan_rotate(4, 8, 1);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_plan_parallel() {
+        let plan = BitArray::new(PARALLEL_BITS).plan_rotate(0, PARALLEL_BITS, 3);
+        assert_eq!(plan.path, RotatePath::ParallelReversal);
+        assert_eq!(plan.reasons[0], format!("{} bits are at least the parallel threshold of {}",
+                                            PARALLEL_BITS, PARALLEL_BITS));
+        assert_eq!(BitArray::new(PARALLEL_BITS).plan_rotate(1, PARALLEL_BITS - 1, 3).path,
+                   RotatePath::WordMoves);
+    }
+}
@@ -0,0 +1,62 @@
+// Kani proof harnesses for the core bit operations, checked for every
+// array of up to MAX_BITS bits and every index into it:
+//
+//     cargo kani --lib
+//
+// cfg(kani) is only set by Kani, so none of this is in a normal build.
+use super::BitArray;
+
+/// The largest array the harnesses consider.  The proofs are exhaustive
+/// below it, so this bounds the solver's work, not the coverage of the
+/// indices.
+const MAX_BITS: usize = 20;
+
+/// An arbitrary well formed array of at most MAX_BITS bits.
+fn any_array() -> BitArray {
+    let bit_sz: usize = kani::any();
+    kani::assume(bit_sz <= MAX_BITS);
+    let mut ba = BitArray::new(bit_sz);
+    for word in &mut ba.data {
+        *word = kani::any();
+    }
+    ba.clear_padding();
+    ba
+}
+
+#[kani::proof]
+fn bitmask_selects_one_bit() {
+    let i: usize = kani::any();
+    let mask = BitArray::bitmask(i);
+    assert_eq!(mask.count_ones(), 1);
+    assert_eq!(mask.trailing_zeros() as usize, i % 64);
+}
+
+// the loops run at most once per bit, so MAX_BITS + 2 unwindings suffice
+#[kani::proof]
+#[kani::unwind(22)]
+fn get_after_set() {
+    let mut ba = any_array();
+    let (i, j): (usize, usize) = (kani::any(), kani::any());
+    kani::assume(i < ba.bit_sz && j < ba.bit_sz && i != j);
+    let val: bool = kani::any();
+    let other = ba.get(j);
+
+    ba.set(i, val);
+    assert_eq!(ba.get(i), val);
+    assert_eq!(ba.get(j), other);
+    ba.assert_invariants();
+}
+
+#[kani::proof]
+#[kani::unwind(22)]
+fn rotate_by_zero_or_length_is_identity() {
+    let original = any_array();
+    let (offset, length): (usize, usize) = (kani::any(), kani::any());
+    kani::assume(offset <= original.bit_sz && length <= original.bit_sz - offset);
+    let turns: i8 = kani::any();
+
+    let mut ba = original.clone();
+    ba.rotate(offset, length, length as isize * turns as isize);
+    assert!(ba == original);
+    ba.assert_invariants();
+}
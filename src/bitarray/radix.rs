@@ -0,0 +1,134 @@
+// Numbers in any base from 2 to 36 as bit arrays, for specs that give
+// fields in octal or base 32.  The array holds the number's binary value,
+// bit 0 the least significant, so from_radix("226", 8, 8) equals
+// from_str("10010110").
+use std::fmt;
+
+use super::BitArray;
+
+/// The error from BitArray::from_radix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseRadixError {
+    /// A character that is not a digit in the radix, at this character
+    /// position.
+    InvalidDigit { index: usize, found: char },
+    /// The value needs more than the bits asked for.
+    Overflow { bit_len: usize },
+}
+
+impl fmt::Display for ParseRadixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseRadixError::InvalidDigit { index, found } => {
+                write!(f, "invalid digit {:?} at position {}", found, index)
+            }
+            ParseRadixError::Overflow { bit_len } => write!(f, "value does not fit in {} bits", bit_len),
+        }
+    }
+}
+
+impl std::error::Error for ParseRadixError {}
+
+impl BitArray {
+    /// Parses s as a number in radix (2 to 36, digits then letters in
+    /// either case) into an array of bit_len bits.  Fails if a character
+    /// is not a digit or the value needs more than bit_len bits.
+    pub fn from_radix(s: &str, radix: u32, bit_len: usize) -> Result<BitArray, ParseRadixError> {
+        assert!((2 ..= 36).contains(&radix));
+        // little endian 64 bit limbs, multiplied up one digit at a time
+        let mut limbs: Vec<u64> = vec![0; bit_len.div_ceil(64)];
+        for (index, found) in s.chars().enumerate() {
+            let digit = found.to_digit(radix).ok_or(ParseRadixError::InvalidDigit { index, found })?;
+            let mut carry = digit as u64;
+            for limb in &mut limbs {
+                let wide = *limb as u128 * radix as u128 + carry as u128;
+                *limb = wide as u64;
+                carry = (wide >> 64) as u64;
+            }
+            let top_bits = bit_len % 64;
+            let spilled = top_bits != 0 && limbs.last().is_some_and(|&top| top >> top_bits != 0);
+            if carry != 0 || spilled || (limbs.is_empty() && digit != 0) {
+                return Err(ParseRadixError::Overflow { bit_len });
+            }
+        }
+        let mut arr = BitArray::new(bit_len);
+        for (i, &limb) in limbs.iter().enumerate() {
+            let width = usize::min(64, bit_len - 64 * i);
+            arr.write_bits(64 * i, width, limb);
+        }
+        Ok(arr)
+    }
+
+    /// The array as a number in radix (2 to 36, lower case letters), with
+    /// no leading zeros; "0" for an array of zeros.  Unlike to_hex the
+    /// width of the array is not kept.
+    pub fn to_radix(&self, radix: u32) -> String {
+        assert!((2 ..= 36).contains(&radix));
+        let mut limbs: Vec<u64> = (0 .. self.bit_sz.div_ceil(64))
+            .map(|i| self.read_bits(64 * i, usize::min(64, self.bit_sz - 64 * i)))
+            .collect();
+        let mut digits = vec![];
+        loop {
+            // divide by radix, most significant limb first
+            let mut rem = 0u128;
+            for limb in limbs.iter_mut().rev() {
+                let wide = (rem << 64) | *limb as u128;
+                *limb = (wide / radix as u128) as u64;
+                rem = wide % radix as u128;
+            }
+            digits.push(std::char::from_digit(rem as u32, radix).unwrap());
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+            if limbs.is_empty() {
+                break;
+            }
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_radix() {
+        let byte = BitArray::from_str("10010110");
+        assert_eq!(BitArray::from_radix("226", 8, 8).unwrap(), byte);
+        assert_eq!(BitArray::from_radix("150", 10, 8).unwrap(), byte);
+        assert_eq!(BitArray::from_radix("4M", 32, 8).unwrap(), byte);
+        assert_eq!(BitArray::from_radix("0096", 16, 12).unwrap().show(), "000010010110");
+        assert_eq!(BitArray::from_radix("", 10, 3).unwrap().show(), "000");
+        assert_eq!(BitArray::from_radix("0", 10, 0).unwrap().get_bit_sz(), 0);
+    }
+
+    #[test]
+    fn test_from_radix_errors() {
+        assert_eq!(BitArray::from_radix("129", 8, 8),
+                   Err(ParseRadixError::InvalidDigit { index: 2, found: '9' }));
+        assert_eq!(BitArray::from_radix("256", 10, 8), Err(ParseRadixError::Overflow { bit_len: 8 }));
+        assert!(BitArray::from_radix("255", 10, 8).is_ok());
+        assert!(BitArray::from_radix("1", 2, 0).is_err());
+        // 2^64 needs a second limb
+        assert!(BitArray::from_radix("18446744073709551616", 10, 64).is_err());
+        assert!(BitArray::from_radix("18446744073709551616", 10, 65).is_ok());
+    }
+
+    #[test]
+    fn test_to_radix() {
+        let byte = BitArray::from_str("10010110");
+        assert_eq!(byte.to_radix(8), "226");
+        assert_eq!(byte.to_radix(10), "150");
+        assert_eq!(byte.to_radix(32), "4m");
+        assert_eq!(byte.to_radix(2), "10010110");
+        assert_eq!(BitArray::new(100).to_radix(10), "0");
+        assert_eq!(BitArray::new(0).to_radix(7), "0");
+
+        let mut big = BitArray::new(200);
+        big.randfill();
+        for &radix in &[3, 10, 32, 36] {
+            assert_eq!(BitArray::from_radix(&big.to_radix(radix), radix, 200).unwrap(), big);
+        }
+    }
+}
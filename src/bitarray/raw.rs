@@ -0,0 +1,157 @@
+// Handing the backing buffer to and from other owners (FFI, an mmap
+// wrapper, an arena) without copying it.  The buffer is the packed words,
+// bit i in bit i % 64 of word i / 64, and every bit of it past the length
+// is zero.  For owners that deal in bytes, bit i in bit i % 8 of byte
+// i / 8, there is the same pair over Vec<u8>; those copy, since a byte
+// allocation can't become a word one.
+use std::fmt;
+
+use super::BitArray;
+
+/// Why from_raw_parts refused a buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawPartsError {
+    /// The buffer has fewer than bit_len.div_ceil(64) words.
+    TooShort { bit_len: usize, words: usize },
+    /// A bit past bit_len is set, in this word.
+    PaddingSet { word: usize },
+    /// The byte buffer has fewer than bit_len.div_ceil(8) bytes.
+    TooFewBytes { bit_len: usize, bytes: usize },
+    /// A bit past bit_len is set, in this byte.
+    BytePaddingSet { byte: usize },
+}
+
+impl fmt::Display for RawPartsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawPartsError::TooShort { bit_len, words } => {
+                write!(f, "{} bits don't fit in {} words", bit_len, words)
+            }
+            RawPartsError::PaddingSet { word } => write!(f, "bits past the end are set in word {}", word),
+            RawPartsError::TooFewBytes { bit_len, bytes } => {
+                write!(f, "{} bits don't fit in {} bytes", bit_len, bytes)
+            }
+            RawPartsError::BytePaddingSet { byte } => write!(f, "bits past the end are set in byte {}", byte),
+        }
+    }
+}
+
+impl std::error::Error for RawPartsError {}
+
+impl BitArray {
+    /// Takes buf as the storage of a bit_len bit array.  buf may be longer
+    /// than needed, but the bits past bit_len must all be zero; the spare
+    /// words are dropped, their room kept as capacity, since everything
+    /// else expects exactly bit_len.div_ceil(64) words.
+    pub fn from_raw_parts(mut buf: Vec<u64>, bit_len: usize) -> Result<BitArray, RawPartsError> {
+        if buf.len() < BitArray::words_for(bit_len) {
+            return Err(RawPartsError::TooShort { bit_len, words: buf.len() });
+        }
+        let used = bit_len / 64;
+        if used < buf.len() && buf[used] >> (bit_len % 64) != 0 {
+            return Err(RawPartsError::PaddingSet { word: used });
+        }
+        if let Some(i) = buf.iter().skip(used + 1).position(|&w| w != 0) {
+            return Err(RawPartsError::PaddingSet { word: used + 1 + i });
+        }
+        buf.truncate(BitArray::words_for(bit_len));
+        let arr = BitArray { bit_sz: bit_len, data: buf.into() };
+        arr.check_invariants();
+        Ok(arr)
+    }
+
+    /// Gives up the storage and the length, the inverse of
+    /// from_raw_parts.  The buffer is bit_len.div_ceil(64) words.
+    pub fn into_raw_parts(mut self) -> (Vec<u64>, usize) {
+        let bit_len = self.bit_sz;
+        // taken rather than moved out, BitArray has a Drop with paranoid
+        self.bit_sz = 0;
+        (std::mem::take(&mut self.data).into_vec(), bit_len)
+    }
+
+    /// from_raw_parts for a buffer of bytes, copied into words.  buf may
+    /// be longer than needed, but the bits past bit_len must all be zero.
+    pub fn from_raw_bytes(buf: Vec<u8>, bit_len: usize) -> Result<BitArray, RawPartsError> {
+        let bytes = bit_len.div_ceil(8);
+        if buf.len() < bytes {
+            return Err(RawPartsError::TooFewBytes { bit_len, bytes: buf.len() });
+        }
+        let used = bit_len / 8;
+        if used < buf.len() && buf[used] >> (bit_len % 8) != 0 {
+            return Err(RawPartsError::BytePaddingSet { byte: used });
+        }
+        if let Some(i) = buf.iter().skip(used + 1).position(|&b| b != 0) {
+            return Err(RawPartsError::BytePaddingSet { byte: used + 1 + i });
+        }
+        let words = buf[.. bytes].chunks(8).map(|chunk| {
+            let mut word = [0; 8];
+            word[.. chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        });
+        let arr = BitArray { bit_sz: bit_len, data: words.collect::<Vec<u64>>().into() };
+        arr.check_invariants();
+        Ok(arr)
+    }
+
+    /// into_raw_parts as bytes, the inverse of from_raw_bytes: exactly
+    /// bit_len.div_ceil(8) of them.
+    pub fn into_raw_bytes(self) -> (Vec<u8>, usize) {
+        let (words, bit_len) = self.into_raw_parts();
+        (words.iter().flat_map(|w| w.to_le_bytes()).take(bit_len.div_ceil(8)).collect(), bit_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut ba = BitArray::new(1000);
+        ba.randfill();
+        let copy = ba.clone();
+        let (buf, len) = ba.into_raw_parts();
+        assert_eq!(len, 1000);
+        let ptr = buf.as_ptr();
+        let back = BitArray::from_raw_parts(buf, len).unwrap();
+        assert_eq!(back, copy);
+        assert_eq!(back.into_raw_parts().0.as_ptr(), ptr);
+
+        let ba = BitArray::from_raw_parts(vec![0b0110, 0, 0], 4).unwrap();
+        assert_eq!(ba.show(), "0110");
+        // the spare words go, their room stays
+        assert_eq!(ba.capacity(), 3 * 64);
+        assert_eq!(ba.into_raw_parts().0, vec![0b0110]);
+        assert_eq!(BitArray::from_raw_parts(vec![], 0).unwrap().get_bit_sz(), 0);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(BitArray::from_raw_parts(vec![0; 2], 129), Err(RawPartsError::TooShort { bit_len: 129, words: 2 }));
+        assert_eq!(BitArray::from_raw_parts(vec![0b0001_0000], 4), Err(RawPartsError::PaddingSet { word: 0 }));
+        assert_eq!(BitArray::from_raw_parts(vec![u64::MAX, 0, 1], 64), Err(RawPartsError::PaddingSet { word: 2 }));
+        assert_eq!(RawPartsError::TooShort { bit_len: 129, words: 2 }.to_string(), "129 bits don't fit in 2 words");
+        assert_eq!(BitArray::from_raw_bytes(vec![0; 2], 17), Err(RawPartsError::TooFewBytes { bit_len: 17, bytes: 2 }));
+        assert_eq!(BitArray::from_raw_bytes(vec![0, 0b10], 9), Err(RawPartsError::BytePaddingSet { byte: 1 }));
+        assert_eq!(BitArray::from_raw_bytes(vec![0xff, 0, 1], 8), Err(RawPartsError::BytePaddingSet { byte: 2 }));
+        assert_eq!(RawPartsError::BytePaddingSet { byte: 2 }.to_string(), "bits past the end are set in byte 2");
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut ba = BitArray::new(1000);
+        ba.randfill();
+        let (bytes, len) = ba.clone().into_raw_bytes();
+        assert_eq!((bytes.len(), len), (125, 1000));
+        assert!((0 .. 125).all(|i| bytes[i] == ba.byte(i)));
+        assert_eq!(BitArray::from_raw_bytes(bytes, len).unwrap(), ba);
+
+        let ba = BitArray::from_raw_bytes(vec![0b0110, 0, 0], 4).unwrap();
+        assert_eq!(ba.show(), "0110");
+        assert_eq!(ba.into_raw_bytes(), (vec![0b0110], 4));
+        let ba = BitArray::from_raw_bytes(vec![0x01, 0x80, 0, 0, 0, 0, 0, 0, 0x03], 66).unwrap();
+        assert_eq!(ba.count_ones(), 4);
+        assert!(ba.get(0) && ba.get(15) && ba.get(64) && ba.get(65));
+        assert_eq!(BitArray::from_raw_bytes(vec![], 0).unwrap().into_raw_bytes(), (vec![], 0));
+    }
+}
@@ -0,0 +1,314 @@
+// The rotation algorithms side by side, for benchmarking one against
+// another.  rotate picks its own path (see plan.rs); rotate_with runs the
+// one asked for whatever the arguments.
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "harness")]
+use serde::{Deserialize, Serialize};
+
+use super::moves::SCRATCH_BITS;
+use super::{Allocator, BitArray};
+
+/// The algorithms BitArray::rotate_with can be told to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "harness", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum RotateStrategy {
+    /// Shift the range left one bit, left_amount times: O(n * amount).
+    Naive,
+    /// Reverse the two parts, then the whole range.
+    Reverse,
+    /// Move each of the gcd(n, amount) cycles of the permutation along
+    /// from its leader, so every bit is read and written once.
+    CycleLeader,
+    /// Gries and Mills' block swap: swap the shorter part with the end of
+    /// the longer one it belongs at, and repeat on what is left.  Blocks
+    /// move 64 bits at a time.
+    BlockSwap,
+    /// What rotate does for long ranges: block swaps until the shorter
+    /// part fits in a scratch buffer, then one pass shifting the rest past
+    /// it, whole words at a time in the middle.
+    WordMoves,
+    /// For ranges bigger than the last level cache: block swaps, each a
+    /// pair of streams through memory, until what is left fits in a
+    /// ROTATE_TILE_BYTES tile, then the three reversals, which stay in
+    /// cache.  A short part is shifted past as in WordMoves.
+    Blocked,
+}
+
+const NAMES: [(RotateStrategy, &str); 6] = [
+    (RotateStrategy::Naive, "naive"),
+    (RotateStrategy::Reverse, "reverse"),
+    (RotateStrategy::CycleLeader, "cycle-leader"),
+    (RotateStrategy::BlockSwap, "block-swap"),
+    (RotateStrategy::WordMoves, "word-moves"),
+    (RotateStrategy::Blocked, "blocked"),
+];
+
+impl RotateStrategy {
+    /// The strategy's name in a config file or on the command line, the
+    /// variant's name in kebab case.
+    pub fn name(self) -> &'static str {
+        NAMES.iter().find(|(s, _)| *s == self).unwrap().1
+    }
+
+    /// Every strategy's name, in the order they are declared.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        NAMES.iter().map(|(_, name)| *name)
+    }
+}
+
+impl fmt::Display for RotateStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RotateStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RotateStrategy, String> {
+        NAMES.iter().find(|(_, name)| *name == s).map(|(strategy, _)| *strategy)
+            .ok_or_else(|| format!("unknown strategy {:?}, expected one of {}", s,
+                                   RotateStrategy::names().collect::<Vec<_>>().join(", ")))
+    }
+}
+
+/// The size of the tile RotateStrategy::Blocked finishes a rotation in,
+/// small enough to stay in a typical L2 cache with room to spare.
+pub const ROTATE_TILE_BYTES: usize = 1 << 18;
+
+/// The greatest common divisor, by Euclid.
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// rotate(bit_offset, bit_length, bit_right_amount), carried out by
+    /// strategy.  The result is the same for every strategy.
+    pub fn rotate_with(&mut self,
+                       strategy: RotateStrategy,
+                       bit_offset: usize,
+                       bit_length: usize,
+                       bit_right_amount: isize) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        if bit_length == 0 {
+            return;
+        }
+        let right = BitArray::modulo(bit_right_amount, bit_length);
+        let left = (bit_length - right) % bit_length;
+        if left == 0 {
+            return;
+        }
+        match strategy {
+            RotateStrategy::Naive => self.rotate_left_naive(bit_offset, bit_length, left),
+            RotateStrategy::Reverse => self.rotate_left_by_reversal(bit_offset, bit_length, left),
+            RotateStrategy::CycleLeader => self.rotate_left_by_cycles(bit_offset, bit_length, left),
+            RotateStrategy::BlockSwap => self.rotate_left_by_block_swaps(bit_offset, bit_length, left),
+            RotateStrategy::WordMoves => self.rotate_left_by_word_moves(bit_offset, bit_length, left, |_, _| {}),
+            RotateStrategy::Blocked => {
+                self.rotate_left_by_tiles(bit_offset, bit_length, left, 8 * ROTATE_TILE_BYTES)
+            }
+        }
+    }
+
+    /// Rotates left by following the cycles of i -> i + bit_left_amount
+    /// (mod bit_length).  There are gcd(bit_length, bit_left_amount) of
+    /// them and cycle c is the one through c; each is walked once, every
+    /// bit taking the value of the one bit_left_amount after it.
+    fn rotate_left_by_cycles(&mut self,
+                             bit_offset: usize,
+                             bit_length: usize,
+                             bit_left_amount: usize) {
+        for leader in 0 .. gcd(bit_length, bit_left_amount) {
+            let first = self.get(bit_offset + leader);
+            let mut i = leader;
+            loop {
+                let next = (i + bit_left_amount) % bit_length;
+                if next == leader {
+                    break;
+                }
+                // i and next are below bit_length, and rotate_with checked
+                // the range is inside the array
+                unsafe { self.set_unchecked(bit_offset + i, self.get_unchecked(bit_offset + next)) };
+                i = next;
+            }
+            self.set(bit_offset + i, first);
+        }
+    }
+
+    /// Rotates left by block swaps.  With a the first bit_left_amount bits
+    /// and b the rest, ab = a b1 b2 with b2 as long as a becomes b2 b1 a by
+    /// one swap, and a is then in place; b2 b1 is rotated the same way.
+    /// The unplaced bits are always [p - i, p + j), i of them before p and
+    /// j after, and the loop ends when the two parts are the same size.
+    fn rotate_left_by_block_swaps(&mut self,
+                                  bit_offset: usize,
+                                  bit_length: usize,
+                                  bit_left_amount: usize) {
+        let p = bit_offset + bit_left_amount;
+        let (mut i, mut j) = (bit_left_amount, bit_length - bit_left_amount);
+        while i != j {
+            if i < j {
+                self.swap_blocks(p - i, p + j - i, i);
+                j -= i;
+            } else {
+                self.swap_blocks(p - i, p, j);
+                i -= j;
+            }
+        }
+        self.swap_blocks(p - i, p, i);
+    }
+
+    /// Rotates left by block swaps, as rotate_left_by_block_swaps, until
+    /// the unplaced bits fit in tile_bits, and then by reversal: a
+    /// reversal runs three times over its range, which is cheap once the
+    /// range is in cache and costly while it is not.  Swaps of a short
+    /// part are one word or less each, so once the shorter part fits in
+    /// the word moves' scratch buffer the rest is shifted past it instead.
+    fn rotate_left_by_tiles(&mut self,
+                            bit_offset: usize,
+                            bit_length: usize,
+                            bit_left_amount: usize,
+                            tile_bits: usize) {
+        let p = bit_offset + bit_left_amount;
+        let (mut i, mut j) = (bit_left_amount, bit_length - bit_left_amount);
+        while i + j > tile_bits && usize::min(i, j) > SCRATCH_BITS {
+            if i == j {
+                self.swap_blocks(p - i, p, i);
+                return;
+            } else if i < j {
+                self.swap_blocks(p - i, p + j - i, i);
+                j -= i;
+            } else {
+                self.swap_blocks(p - i, p, j);
+                i -= j;
+            }
+        }
+        if i + j <= tile_bits {
+            self.rotate_left_by_reversal(p - i, i + j, i);
+        } else {
+            self.rotate_left_by_word_moves(p - i, i + j, i, |_, _| {});
+        }
+    }
+
+    /// Swaps the bit_length bits at a with those at b; the two ranges
+    /// must not overlap.
+    pub(crate) fn swap_blocks(&mut self, a: usize, b: usize, bit_length: usize) {
+        let mut done = 0;
+        while done < bit_length {
+            let width = usize::min(64, bit_length - done);
+            let (x, y) = (self.read_bits(a + done, width), self.read_bits(b + done, width));
+            self.write_bits(a + done, width, y);
+            self.write_bits(b + done, width, x);
+            done += width;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::alloc_count::allocations;
+
+    const ALL: [RotateStrategy; 6] = [RotateStrategy::Naive, RotateStrategy::Reverse, RotateStrategy::CycleLeader,
+                                      RotateStrategy::BlockSwap, RotateStrategy::WordMoves, RotateStrategy::Blocked];
+
+    #[test]
+    fn test_names() {
+        for &strategy in &ALL {
+            assert_eq!(strategy.name().parse(), Ok(strategy));
+        }
+        assert_eq!(RotateStrategy::CycleLeader.to_string(), "cycle-leader");
+        assert_eq!("fast".parse::<RotateStrategy>().unwrap_err(),
+                   "unknown strategy \"fast\", expected one of naive, reverse, cycle-leader, block-swap, \
+                    word-moves, blocked");
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(gcd(12, 8), 4);
+        assert_eq!(gcd(7, 3), 1);
+        assert_eq!(gcd(5, 0), 5);
+    }
+
+    #[test]
+    fn test_strategies_agree_with_rotate() {
+        let mut ba = BitArray::new(300);
+        ba.randfill();
+        for &(offset, length, amount) in &[(0, 300, 2), (5, 290, -149), (17, 100, 60), (64, 64, 32),
+                                           (3, 7, isize::MIN), (0, 1, 5), (9, 0, 3), (10, 12, 12)] {
+            let mut want = ba.clone();
+            want.rotate(offset, length, amount);
+            for &strategy in &ALL {
+                let mut got = ba.clone();
+                got.rotate_with(strategy, offset, length, amount);
+                assert_eq!(got, want, "{:?} {} {} {}", strategy, offset, length, amount);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cycle_leader() {
+        // 12 bits left by 8 is four cycles of three
+        let mut ba = BitArray::from_str("101100111000");
+        ba.rotate_with(RotateStrategy::CycleLeader, 0, 12, -8);
+        assert_eq!(ba.show(), "001110001011");
+    }
+
+    #[test]
+    fn test_block_swap() {
+        let mut ba = BitArray::from_str("101100111000");
+        ba.rotate_with(RotateStrategy::BlockSwap, 0, 12, -8);
+        assert_eq!(ba.show(), "001110001011");
+        // blocks of more than a word, at unaligned offsets
+        let mut ba = BitArray::new(1000);
+        ba.randfill();
+        let mut want = ba.clone();
+        want.rotate(3, 990, -337);
+        ba.rotate_with(RotateStrategy::BlockSwap, 3, 990, -337);
+        assert_eq!(ba, want);
+    }
+
+    #[test]
+    fn test_blocked() {
+        let mut ba = BitArray::new(20_000);
+        ba.randfill();
+        // the last three swap blocks first, the very last until the parts
+        // are equal
+        for &(offset, length, left, tile) in &[(0, 5000, 1700, 256), (3, 4990, 2495, 256), (7, 4000, 3999, 100),
+                                               (64, 4096, 2048, 512), (1, 4000, 1000, 4000),
+                                               (0, 5000, 3, 256), (5, 4990, 4989, 256),
+                                               (0, 20_000, 9000, 1000), (9, 19_990, 13_001, 3000),
+                                               (0, 20_000, 10_000, 256)] {
+            let mut want = ba.clone();
+            want.rotate_with(RotateStrategy::Reverse, offset, length, -(left as isize));
+            let mut got = ba.clone();
+            got.rotate_left_by_tiles(offset, length, left, tile);
+            assert_eq!(got, want, "{} {} {} {}", offset, length, left, tile);
+        }
+    }
+
+    #[test]
+    fn test_rotate_does_not_allocate() {
+        let mut ba = BitArray::new(40_000);
+        ba.randfill();
+        // the count does see allocations
+        assert_eq!(allocations(|| drop(ba.clone())), 1);
+        // every path: nothing to do, in a register, and word moves with
+        // and without block swaps, at aligned and unaligned offsets
+        for &(offset, length, amount) in &[(5, 0, 3), (0, 20, 20), (3, 20, -1), (3, 20, 7), (0, 64, -1),
+                                           (7, 1000, 333), (64, 4096, -64), (1, 39_990, 17_001),
+                                           (0, 40_000, -4097)] {
+            let path = ba.plan_rotate(offset, length, amount).path;
+            assert_eq!(allocations(|| ba.rotate(offset, length, amount)), 0,
+                       "{} {} {} ({:?})", offset, length, amount, path);
+        }
+        assert_eq!(allocations(|| ba.reverse_range(3, 39_000)), 0);
+    }
+}
@@ -0,0 +1,252 @@
+// Runs of equal bits, found a 64 bit word at a time, and conversion
+// between arrays and lists of intervals.
+use std::ops::Range;
+
+use super::BitArray;
+
+impl BitArray {
+    /// The first index >= from whose bit is val, or bit_sz if there is
+    /// none.  Skips whole words that hold no such bit.
+    pub(crate) fn next_with(&self, from: usize, val: bool) -> usize {
+        let mut pos = from;
+        while pos < self.bit_sz {
+            let width = usize::min(64, self.bit_sz - pos);
+            let mut word = self.read_bits(pos, width);
+            if !val {
+                word = !word & (u64::MAX >> (64 - width));
+            }
+            if word != 0 {
+                return pos + word.trailing_zeros() as usize;
+            }
+            pos += width;
+        }
+        self.bit_sz
+    }
+
+    /// Iterates over the maximal runs of equal bits as (value, start, len),
+    /// covering the whole array in order.
+    pub fn runs(&self) -> Runs<'_> {
+        Runs { arr: self, pos: 0 }
+    }
+
+    /// The maximal intervals of set bits, in increasing order.
+    pub fn to_ranges(&self) -> Vec<Range<usize>> {
+        self.runs().filter(|r| r.0).map(|(_, start, len)| start .. start + len).collect()
+    }
+
+    /// An array of bit_sz bits with exactly the bits in the given intervals
+    /// set.  The intervals may overlap and come in any order.
+    pub fn from_ranges<I>(bit_sz: usize, intervals: I) -> BitArray
+    where I: IntoIterator<Item = Range<usize>> {
+        let mut ba = BitArray::new(bit_sz);
+        for r in intervals {
+            assert!(r.start <= r.end, "BitArray::from_ranges gets backwards range {:?}", r);
+            ba.fill_range(r.start, r.end - r.start, true);
+        }
+        ba
+    }
+
+    /// Iterates over the maximal runs of clear bits as (start, len), in
+    /// increasing order.
+    pub fn zero_runs(&self) -> ZeroRuns<'_> {
+        ZeroRuns { arr: self, pos: 0 }
+    }
+
+    /// The start of the first run of at least min_len clear bits, for
+    /// finding contiguous free space in an allocation map.
+    pub fn find_zero_run(&self, min_len: usize) -> Option<usize> {
+        if min_len == 0 {
+            return Some(0);
+        }
+        let mut pos = 0;
+        loop {
+            let start = self.next_with(pos, false);
+            if min_len > self.bit_sz - start {
+                return None;
+            }
+            let end = self.next_with(start, true);
+            if end - start >= min_len {
+                return Some(start);
+            }
+            pos = end;
+        }
+    }
+
+    /// Finds the first run of len clear bits, sets it, and returns its
+    /// start; None, with nothing changed, if there is no such run.  The
+    /// primitive for a single-threaded block or slot allocator; see
+    /// allocator::BitmapAllocator for a concurrent one.
+    pub fn alloc_run(&mut self, len: usize) -> Option<usize> {
+        let start = self.find_zero_run(len)?;
+        self.fill_range(start, len, true);
+        Some(start)
+    }
+
+    /// Clears a run returned by alloc_run.  Panics unless every bit of it
+    /// is set, which catches double frees and wrong lengths.
+    pub fn free_run(&mut self, start: usize, len: usize) {
+        assert_eq!(self.count_ones_range(start, len), len,
+                   "BitArray::free_run of [{}, {}) which isn't all allocated", start, start + len);
+        self.fill_range(start, len, false);
+    }
+}
+
+/// Iterator over the runs of clear bits, see BitArray::zero_runs.
+#[derive(Debug, Clone)]
+pub struct ZeroRuns<'a> {
+    arr: &'a BitArray,
+    pos: usize,
+}
+
+impl<'a> Iterator for ZeroRuns<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let start = self.arr.next_with(self.pos, false);
+        if start == self.arr.bit_sz {
+            return None;
+        }
+        let end = self.arr.next_with(start, true);
+        self.pos = end;
+        Some((start, end - start))
+    }
+}
+
+/// Iterator over the runs of equal bits, see BitArray::runs.
+#[derive(Debug, Clone)]
+pub struct Runs<'a> {
+    arr: &'a BitArray,
+    pos: usize,
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = (bool, usize, usize);
+
+    fn next(&mut self) -> Option<(bool, usize, usize)> {
+        if self.pos >= self.arr.bit_sz {
+            return None;
+        }
+        let start = self.pos;
+        let val = self.arr.get(start);
+        self.pos = self.arr.next_with(start, !val);
+        Some((val, start, self.pos - start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_runs() {
+        let ba = BitArray::from_str("0011000100");
+        assert_eq!(ba.zero_runs().collect::<Vec<_>>(), vec![(0, 2), (3, 3), (8, 2)]);
+        assert_eq!(BitArray::new(0).zero_runs().next(), None);
+
+        let mut ba = BitArray::new(300);
+        ba.fill(true);
+        ba.fill_range(60, 140, false);
+        ba.fill_range(299, 1, false);
+        assert_eq!(ba.zero_runs().collect::<Vec<_>>(), vec![(60, 140), (299, 1)]);
+        ba.fill(false);
+        assert_eq!(ba.zero_runs().collect::<Vec<_>>(), vec![(0, 300)]);
+    }
+
+    #[test]
+    fn test_zero_runs_match_scan() {
+        let mut ba = BitArray::new(1000);
+        ba.randfill();
+        let mut expected = vec![];
+        let mut i = 0;
+        while i < 1000 {
+            if ba.get(i) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < 1000 && !ba.get(i) {
+                i += 1;
+            }
+            expected.push((start, i - start));
+        }
+        assert_eq!(ba.zero_runs().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_find_zero_run() {
+        let ba = BitArray::from_str("0011000100");
+        assert_eq!(ba.find_zero_run(0), Some(0));
+        assert_eq!(ba.find_zero_run(2), Some(0));
+        assert_eq!(ba.find_zero_run(3), Some(3));
+        assert_eq!(ba.find_zero_run(4), None);
+
+        let mut ba = BitArray::new(500);
+        ba.fill(true);
+        ba.fill_range(100, 10, false);
+        ba.fill_range(400, 100, false);
+        assert_eq!(ba.find_zero_run(10), Some(100));
+        assert_eq!(ba.find_zero_run(11), Some(400));
+        assert_eq!(ba.find_zero_run(101), None);
+    }
+
+    #[test]
+    fn test_huge_zero_run() {
+        let mut ba = BitArray::new(100);
+        ba.set(0, true);
+        assert_eq!(ba.find_zero_run(usize::MAX), None);
+        assert_eq!(ba.alloc_run(usize::MAX), None);
+        assert_eq!(BitArray::new(0).find_zero_run(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_alloc_run() {
+        let mut ba = BitArray::new(100);
+        assert_eq!(ba.alloc_run(10), Some(0));
+        assert_eq!(ba.alloc_run(30), Some(10));
+        assert_eq!(ba.alloc_run(70), None);
+        assert_eq!(ba.alloc_run(60), Some(40));
+        assert_eq!(ba.count_ones(), 100);
+        ba.free_run(10, 30);
+        assert_eq!(ba.alloc_run(5), Some(10));
+        assert_eq!(ba.alloc_run(25), Some(15));
+        assert_eq!(ba.alloc_run(1), None);
+        assert_eq!(ba.alloc_run(0), Some(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't all allocated")]
+    fn test_double_free_run() {
+        let mut ba = BitArray::new(100);
+        let start = ba.alloc_run(10).unwrap();
+        ba.free_run(start, 10);
+        ba.free_run(start, 10);
+    }
+
+    #[test]
+    fn test_runs() {
+        let ba = BitArray::from_str("0011000100");
+        assert_eq!(ba.runs().collect::<Vec<_>>(),
+                   vec![(false, 0, 2), (true, 2, 1), (false, 3, 3), (true, 6, 2), (false, 8, 2)]);
+        assert_eq!(BitArray::new(0).runs().next(), None);
+
+        let mut ba = BitArray::new(777);
+        ba.randfill();
+        let runs: Vec<_> = ba.runs().collect();
+        assert_eq!(runs.iter().map(|r| r.2).sum::<usize>(), 777);
+        assert!(runs.windows(2).all(|w| w[0].0 != w[1].0 && w[0].1 + w[0].2 == w[1].1));
+        assert!(runs.iter().all(|&(v, s, l)| (s .. s + l).all(|i| ba.get(i) == v)));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let ba = BitArray::from_str("0011000100");
+        assert_eq!(ba.to_ranges(), vec![2 .. 3, 6 .. 8]);
+        assert_eq!(BitArray::from_ranges(10, ba.to_ranges()), ba);
+        assert_eq!(BitArray::from_ranges(10, vec![6 .. 8, 2 .. 3, 7 .. 7]), ba);
+        assert_eq!(BitArray::from_ranges(200, vec![0 .. 100, 50 .. 150]).to_ranges(), vec![0 .. 150]);
+
+        let mut ba = BitArray::new(1000);
+        ba.randfill();
+        assert_eq!(BitArray::from_ranges(1000, ba.to_ranges()), ba);
+    }
+}
@@ -0,0 +1,66 @@
+// Random samples of the set bits, for spot checks and load balancing over
+// membership bitmaps too large to list.
+use rand::Rng;
+
+use super::BitArray;
+
+impl BitArray {
+    /// k indices of set bits chosen uniformly at random without
+    /// replacement, or all of them if fewer than k are set.  One pass
+    /// over the ones with reservoir sampling, so memory is O(k) however
+    /// many bits are set.  The indices come in no particular order.
+    pub fn sample_ones<R: Rng + ?Sized>(&self, k: usize, rng: &mut R) -> Vec<usize> {
+        let mut reservoir = Vec::with_capacity(k);
+        for (seen, i) in self.iter_ones().enumerate() {
+            if seen < k {
+                reservoir.push(i);
+            } else {
+                let j = rng.gen_range(0, seen + 1);
+                if j < k {
+                    reservoir[j] = i;
+                }
+            }
+        }
+        reservoir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample_ones() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(9);
+        let ba = BitArray::from_str("0110100010");
+        let mut all = ba.sample_ones(10, &mut rng);
+        all.sort_unstable();
+        assert_eq!(all, vec![1, 5, 7, 8]);
+        assert!(ba.sample_ones(0, &mut rng).is_empty());
+        for i in ba.sample_ones(2, &mut rng) {
+            assert!(ba.get(i));
+        }
+    }
+
+    #[test]
+    fn test_uniform() {
+        // each of 20 ones should be picked in about 3/20 of the samples
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4);
+        let mut ba = BitArray::new(1000);
+        for i in 0 .. 20 {
+            ba.set(50 * i + 7, true);
+        }
+        let mut hits = [0usize; 20];
+        for _ in 0 .. 20_000 {
+            let sample = ba.sample_ones(3, &mut rng);
+            assert_eq!(sample.len(), 3);
+            for i in sample {
+                hits[(i - 7) / 50] += 1;
+            }
+        }
+        for &h in &hits {
+            assert!((2700 .. 3300).contains(&h), "{:?}", hits);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Readable strings of bits: show() with the bits split into groups, and
+// only part of the array if asked, for failure messages about arrays too
+// long to eyeball.
+use std::fmt;
+use std::ops::Range;
+
+use super::BitArray;
+
+/// Controls the layout of BitArray::show_with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShowOptions {
+    /// Number of bits between separators, 0 for no grouping.
+    pub group_bits: usize,
+
+    /// What goes between groups.
+    pub separator: char,
+
+    /// The half-open range of bits to show, None for the whole array.
+    pub range: Option<Range<usize>>,
+}
+
+impl Default for ShowOptions {
+    fn default() -> ShowOptions {
+        ShowOptions { group_bits: 0, separator: '_', range: None }
+    }
+}
+
+impl BitArray {
+    /// Like show(), highest index on the left, laid out as opts asks.
+    /// Groups are counted from the start of the range, so the leftmost
+    /// one is the short one when the range doesn't divide evenly.
+    ///
+    /// Example:
+    /// BitArray::from_str("1111000010010110").show_with(&ShowOptions {
+    ///     group_bits: 4, range: Some(2 .. 14), ..Default::default() })
+    /// gives "1100_0010_0101"
+    pub fn show_with(&self, opts: &ShowOptions) -> String {
+        let range = opts.range.clone().unwrap_or(0 .. self.bit_sz);
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+        let mut out = String::with_capacity(2 * range.len());
+        for i in range.clone().rev() {
+            // range.end <= bit_sz, asserted above
+            out.push(if unsafe { self.get_unchecked(i) } { '1' } else { '0' });
+            let from_start = i - range.start;
+            if opts.group_bits > 0 && from_start > 0 && from_start.is_multiple_of(opts.group_bits) {
+                out.push(opts.separator);
+            }
+        }
+        out
+    }
+
+    /// A Display wrapper printing the array in groups of group_bits bits
+    /// with separator between them, e.g. format!("{}", ba.grouped(8, '_')).
+    pub fn grouped(&self, group_bits: usize, separator: char) -> Grouped<'_> {
+        Grouped {
+            arr: self,
+            opts: ShowOptions { group_bits, separator, range: None },
+        }
+    }
+}
+
+/// Displays a BitArray with ShowOptions, see BitArray::grouped.
+#[derive(Debug, Clone)]
+pub struct Grouped<'a> {
+    arr: &'a BitArray,
+    opts: ShowOptions,
+}
+
+impl<'a> Grouped<'a> {
+    /// Shows only the bits in range.
+    pub fn window(mut self, range: Range<usize>) -> Grouped<'a> {
+        self.opts.range = Some(range);
+        self
+    }
+}
+
+impl<'a> fmt::Display for Grouped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.arr.show_with(&self.opts))
+    }
+}
+
+/// The same string as show(), padded and aligned as the format asks.
+impl fmt::Display for BitArray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.show_with(&ShowOptions::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_show_with() {
+        let ba = BitArray::from_str("1111000010010110");
+        assert_eq!(ba.show_with(&ShowOptions::default()), ba.show());
+        let opts = ShowOptions { group_bits: 4, range: Some(2 .. 14), ..Default::default() };
+        assert_eq!(ba.show_with(&opts), "1100_0010_0101");
+        let opts = ShowOptions { group_bits: 3, separator: ' ', range: None };
+        assert_eq!(ba.show_with(&opts), "1 111 000 010 010 110");
+        let opts = ShowOptions { range: Some(5 .. 5), ..Default::default() };
+        assert_eq!(ba.show_with(&opts), "");
+    }
+
+    #[test]
+    fn test_display() {
+        let ba = BitArray::from_str("1111000010010110");
+        assert_eq!(ba.to_string(), "1111000010010110");
+        assert_eq!(format!("{:>18}", BitArray::from_str("101")), "               101");
+        assert_eq!(ba.grouped(8, '_').to_string(), "11110000_10010110");
+        assert_eq!(ba.grouped(4, ' ').window(0 .. 6).to_string(), "01 0110");
+    }
+}
@@ -0,0 +1,123 @@
+// Vectorized kernels for x86_64, behind the simd feature.  Reversing a
+// range is mostly bit-reversing words: x86 has no instruction for that,
+// but SSSE3's pshufb does it for sixteen bytes at once, as a table lookup
+// per nibble followed by a byte shuffle.  reverse_range, and through it
+// reverse and rotate_with(RotateStrategy::Reverse, ..), moves 256 bits
+// from each end of the range per step this way; the bits left in the
+// middle go the scalar way.  rotate itself moves words and never
+// reverses, so it gains nothing here.  Without SSSE3 at run time, or in
+// CHECKED builds, nothing here runs.
+use std::arch::x86_64::*;
+
+use super::{Allocator, BitArray};
+
+/// The bits swapped from each end of the range per step.
+const BLOCK_WORDS: usize = 4;
+
+/// Whether the vector kernels can run on this CPU.
+pub(crate) fn available() -> bool {
+    !super::CHECKED && is_x86_feature_detected!("ssse3")
+}
+
+/// Bit-reverses each word in place, with SSSE3 when it's available.
+pub(crate) fn reverse_word_bits(words: &mut [u64]) {
+    if available() {
+        unsafe { reverse_word_bits_ssse3(words) }
+    } else {
+        for w in words {
+            *w = w.reverse_bits();
+        }
+    }
+}
+
+#[target_feature(enable = "ssse3")]
+unsafe fn reverse_word_bits_ssse3(words: &mut [u64]) {
+    // the reversal of each nibble, and the byte order of each 64 bit lane
+    // reversed
+    let nibbles = _mm_setr_epi8(0x0, 0x8, 0x4, 0xc, 0x2, 0xa, 0x6, 0xe, 0x1, 0x9, 0x5, 0xd, 0x3, 0xb, 0x7, 0xf);
+    let bswap = _mm_setr_epi8(7, 6, 5, 4, 3, 2, 1, 0, 15, 14, 13, 12, 11, 10, 9, 8);
+    let low = _mm_set1_epi8(0x0f);
+    let mut pairs = words.chunks_exact_mut(2);
+    for pair in &mut pairs {
+        let p = pair.as_mut_ptr() as *mut __m128i;
+        // the slice is two words long, and loadu/storeu take any alignment
+        let v = _mm_loadu_si128(p);
+        let lo = _mm_and_si128(v, low);
+        let hi = _mm_and_si128(_mm_srli_epi16(v, 4), low);
+        // each byte becomes its reversed low nibble above its reversed
+        // high nibble; the table entries are below 16, so the 16 bit shift
+        // never carries into the next byte
+        let bytes = _mm_or_si128(_mm_slli_epi16(_mm_shuffle_epi8(nibbles, lo), 4),
+                                 _mm_shuffle_epi8(nibbles, hi));
+        _mm_storeu_si128(p, _mm_shuffle_epi8(bytes, bswap));
+    }
+    for w in pairs.into_remainder() {
+        *w = w.reverse_bits();
+    }
+}
+
+impl<A: Allocator + Clone> BitArray<A> {
+    /// Reverses the ends of [i, j) into each other a block at a time, for
+    /// as long as two blocks fit, and returns the middle still to do.
+    pub(crate) fn reverse_ends_simd(&mut self, mut i: usize, mut j: usize) -> (usize, usize) {
+        let block = 64 * BLOCK_WORDS;
+        let mut words = [0u64; 2 * BLOCK_WORDS];
+        while j - i >= 2 * block {
+            // the right block, words in reverse order, then the left, so
+            // that after reversing each word's bits they are the new left
+            // and right blocks
+            for k in 0 .. BLOCK_WORDS {
+                words[BLOCK_WORDS - 1 - k] = self.read_bits(j - block + 64 * k, 64);
+                words[2 * BLOCK_WORDS - 1 - k] = self.read_bits(i + 64 * k, 64);
+            }
+            reverse_word_bits(&mut words);
+            for k in 0 .. BLOCK_WORDS {
+                self.write_bits(i + 64 * k, 64, words[k]);
+                self.write_bits(j - block + 64 * k, 64, words[BLOCK_WORDS + k]);
+            }
+            i += block;
+            j -= block;
+        }
+        (i, j)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_reverse_word_bits() {
+        let mut rng = rand::thread_rng();
+        for n in 0 .. 9 {
+            let mut words: Vec<u64> = (0 .. n).map(|_| rng.gen()).collect();
+            let want: Vec<u64> = words.iter().map(|w| w.reverse_bits()).collect();
+            reverse_word_bits(&mut words);
+            assert_eq!(words, want);
+        }
+        let mut one = [1u64, 0x8000_0000_0000_00f0];
+        reverse_word_bits(&mut one);
+        assert_eq!(one, [1 << 63, 0x0f00_0000_0000_0001]);
+    }
+
+    #[test]
+    fn test_matches_scalar_reversal() {
+        let mut ba = BitArray::new(5000);
+        ba.randfill();
+        for &(offset, length) in &[(0, 5000), (3, 4990), (64, 512), (100, 1023), (7, 511)] {
+            let mut want = ba.clone();
+            let (mut i, mut j) = (offset, offset + length - 1);
+            while i < j {
+                let (a, b) = (want.get(i), want.get(j));
+                want.set(i, b);
+                want.set(j, a);
+                i += 1;
+                j -= 1;
+            }
+            let mut got = ba.clone();
+            got.reverse_range(offset, length);
+            assert_eq!(got, want, "{} {}", offset, length);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// Borrowed views of a range of bits, and chunk-at-a-time processing built
+// on them.
+use std::ops::Range;
+
+use super::iter::IterOnes;
+use super::BitArray;
+
+/// A read-only view of the bits [offset, offset + len) of a BitArray.
+/// Indices into the slice are relative to its start.
+#[derive(Debug, Clone, Copy)]
+pub struct BitSlice<'a> {
+    arr: &'a BitArray,
+    offset: usize,
+    len: usize,
+}
+
+/// A mutable view of the bits [offset, offset + len) of a BitArray.
+#[derive(Debug)]
+pub struct BitSliceMut<'a> {
+    arr: &'a mut BitArray,
+    offset: usize,
+    len: usize,
+}
+
+impl<'a> BitSlice<'a> {
+    /// Where the slice starts in the underlying array.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len);
+        self.arr.get(self.offset + i)
+    }
+
+    /// Like BitArray::read_bits, relative to the start of the slice.
+    pub fn read_bits(&self, bit_offset: usize, bit_width: usize) -> u64 {
+        assert!(bit_offset + bit_width <= self.len);
+        self.arr.read_bits(self.offset + bit_offset, bit_width)
+    }
+
+    /// The indices, relative to the slice, of its set bits.
+    pub fn iter_ones(&self) -> IterOnes<'a> {
+        IterOnes::new(self.arr, self.offset, self.offset + self.len)
+    }
+
+    /// The bytes holding the slice, when it starts on a byte boundary and
+    /// covers whole bytes.  Always None on big-endian targets, where the
+    /// words don't hold the bytes in order.
+    pub fn aligned_bytes(&self) -> Option<&'a [u8]> {
+        if !self.offset.is_multiple_of(8) || !self.len.is_multiple_of(8) {
+            return None;
+        }
+        #[cfg(target_endian = "little")]
+        return Some(&self.arr.as_bytes()[self.offset / 8 .. (self.offset + self.len) / 8]);
+        #[cfg(target_endian = "big")]
+        return None;
+    }
+
+    /// The words holding the slice, when it starts on a word boundary and
+    /// covers whole words.
+    pub fn aligned_words(&self) -> Option<&'a [u64]> {
+        if !self.offset.is_multiple_of(64) || !self.len.is_multiple_of(64) {
+            return None;
+        }
+        Some(&self.arr.data[self.offset / 64 .. (self.offset + self.len) / 64])
+    }
+
+    /// Copies the slice out into its own array.
+    pub fn to_bitarray(self) -> BitArray {
+        let mut out = BitArray::new(self.len);
+        let mut done = 0;
+        while done < self.len {
+            let width = usize::min(64, self.len - done);
+            out.write_bits(done, width, self.read_bits(done, width));
+            done += width;
+        }
+        out
+    }
+}
+
+impl<'a> BitSliceMut<'a> {
+    /// Where the slice starts in the underlying array.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len);
+        self.arr.get(self.offset + i)
+    }
+
+    pub fn set(&mut self, i: usize, val: bool) {
+        assert!(i < self.len);
+        self.arr.set(self.offset + i, val)
+    }
+
+    /// Like BitArray::read_bits, relative to the start of the slice.
+    pub fn read_bits(&self, bit_offset: usize, bit_width: usize) -> u64 {
+        assert!(bit_offset + bit_width <= self.len);
+        self.arr.read_bits(self.offset + bit_offset, bit_width)
+    }
+
+    /// Like BitArray::write_bits, relative to the start of the slice.
+    pub fn write_bits(&mut self, bit_offset: usize, bit_width: usize, value: u64) {
+        assert!(bit_offset + bit_width <= self.len);
+        self.arr.write_bits(self.offset + bit_offset, bit_width, value)
+    }
+
+    /// The bytes holding the slice, when it starts on a byte boundary and
+    /// covers whole bytes.  Writing through them is the fast path.  As
+    /// with aligned_bytes, always None on big-endian targets.
+    pub fn aligned_bytes_mut(&mut self) -> Option<&mut [u8]> {
+        if !self.offset.is_multiple_of(8) || !self.len.is_multiple_of(8) {
+            return None;
+        }
+        #[cfg(target_endian = "little")]
+        return Some(&mut self.arr.as_bytes_mut()[self.offset / 8 .. (self.offset + self.len) / 8]);
+        #[cfg(target_endian = "big")]
+        return None;
+    }
+
+    /// The words holding the slice, when it starts on a word boundary and
+    /// covers whole words; the fastest way through a chunk.
+    pub fn aligned_words_mut(&mut self) -> Option<&mut [u64]> {
+        if !self.offset.is_multiple_of(64) || !self.len.is_multiple_of(64) {
+            return None;
+        }
+        Some(&mut self.arr.data[self.offset / 64 .. (self.offset + self.len) / 64])
+    }
+
+    pub fn as_slice(&self) -> BitSlice<'_> {
+        BitSlice { arr: self.arr, offset: self.offset, len: self.len }
+    }
+}
+
+/// With the paranoid feature, checks the slice still fits its array and
+/// that writes through aligned_bytes_mut or aligned_words_mut left the
+/// array intact.
+#[cfg(feature = "paranoid")]
+impl<'a> Drop for BitSliceMut<'a> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            assert!(self.offset + self.len <= self.arr.bit_sz,
+                    "slice [{}, {}) outlived its {} bit array", self.offset,
+                    self.offset + self.len, self.arr.bit_sz);
+            self.arr.assert_invariants();
+        }
+    }
+}
+
+impl BitArray {
+    /// A read-only view of a range of bits.
+    pub fn slice(&self, range: Range<usize>) -> BitSlice<'_> {
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+        BitSlice { arr: self, offset: range.start, len: range.end - range.start }
+    }
+
+    /// A mutable view of a range of bits.
+    pub fn slice_mut(&mut self, range: Range<usize>) -> BitSliceMut<'_> {
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+        BitSliceMut { arr: self, offset: range.start, len: range.end - range.start }
+    }
+
+    /// Walks the array in consecutive windows of chunk_bits bits, rounded
+    /// up to whole words, and hands each to f in order.  Every window
+    /// starts on a word boundary and all but a short last one cover whole
+    /// words, so f can use aligned_words_mut instead of going bit by bit.
+    pub fn process_chunks<F>(&mut self, chunk_bits: usize, mut f: F)
+    where F: FnMut(&mut BitSliceMut) {
+        assert!(chunk_bits > 0);
+        let chunk_bits = chunk_bits.next_multiple_of(64);
+        let mut offset = 0;
+        while offset < self.bit_sz {
+            let len = usize::min(chunk_bits, self.bit_sz - offset);
+            f(&mut BitSliceMut { arr: self, offset, len });
+            offset += len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice() {
+        let ba = BitArray::from_str("1111000010010110");
+        let s = ba.slice(4 .. 12);
+        assert_eq!(s.len(), 8);
+        assert!(s.get(0));
+        assert!(!s.get(1));
+        assert_eq!(s.read_bits(0, 8), 0b00001001);
+        assert_eq!(s.to_bitarray(), BitArray::from_str("00001001"));
+        assert!(s.aligned_bytes().is_none());
+        assert_eq!(s.iter_ones().collect::<Vec<_>>(), vec![0, 3]);
+        #[cfg(target_endian = "little")]
+        assert_eq!(ba.slice(8 .. 16).aligned_bytes(), Some(&[0xf0u8][..]));
+    }
+
+    #[test]
+    fn test_slice_mut() {
+        let mut ba = BitArray::new(20);
+        {
+            let mut s = ba.slice_mut(5 .. 15);
+            s.set(0, true);
+            s.write_bits(6, 4, 0b1011);
+            assert_eq!(s.as_slice().read_bits(0, 10), 0b1011000001);
+        }
+        assert_eq!(ba.show(), "00000101100000100000");
+    }
+
+    #[test]
+    fn test_process_chunks() {
+        let mut ba = BitArray::new(300);
+        let mut seen = vec![];
+        ba.process_chunks(100, |chunk| {
+            seen.push((chunk.offset(), chunk.len()));
+            match chunk.aligned_words_mut() {
+                Some(words) => {
+                    for w in words {
+                        *w = u64::MAX;
+                    }
+                }
+                None => {
+                    for i in 0 .. chunk.len() {
+                        chunk.set(i, i % 2 == 0);
+                    }
+                }
+            }
+        });
+        // 100 bits round up to two words
+        assert_eq!(seen, vec![(0, 128), (128, 128), (256, 44)]);
+        assert_eq!(ba.count_ones_range(0, 256), 256);
+        assert_eq!(ba.read_bits(250, 10), 0b0101_111111);
+        assert_eq!(ba.slice(64 .. 192).aligned_words(), Some(&[u64::MAX, u64::MAX][..]));
+        assert!(ba.slice(64 .. 100).aligned_words().is_none());
+    }
+}
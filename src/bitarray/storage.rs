@@ -0,0 +1,254 @@
+// The words behind a BitArray.  Programs that make thousands of tiny
+// arrays (flags, masks, one byte of a protocol header) would otherwise pay
+// an allocation for each, so up to INLINE_WORDS words live in the struct
+// itself, SmallVec style, and only longer arrays go to the heap.  An array
+// that outgrows the inline words moves to the heap and stays there, as
+// does one built from a caller's Vec (in_buffer, from_raw_parts, the
+// builder), so that buffer is never copied or dropped behind their back.
+// Everything else sees a slice of words.
+//
+// The heap words come from the allocator A.  With the allocator_api
+// feature, which needs nightly, that is any std::alloc::Allocator, so an
+// arena can back a batch of arrays; without it A is always the stand-in
+// Global below and every array uses the global allocator.
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+#[cfg(feature = "allocator_api")]
+use std::alloc::{handle_alloc_error, Layout};
+#[cfg(feature = "allocator_api")]
+pub use std::alloc::{Allocator, Global};
+#[cfg(not(feature = "allocator_api"))]
+pub use self::stand_in::{Allocator, Global};
+
+/// BitArray's allocator parameter without the allocator_api feature.
+#[cfg(not(feature = "allocator_api"))]
+mod stand_in {
+    /// Implemented by Global only; the allocator_api feature replaces
+    /// this with std::alloc::Allocator.
+    pub trait Allocator {}
+
+    /// The global allocator.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct Global;
+
+    impl Allocator for Global {}
+}
+
+/// Words kept inline, 128 bits.
+pub(crate) const INLINE_WORDS: usize = 2;
+
+#[derive(Clone)]
+pub(crate) enum Words<A: Allocator + Clone = Global> {
+    /// The first len of words are in use; the rest are zero.  alloc is
+    /// kept for when the words move to the heap.
+    Inline { len: usize, words: [u64; INLINE_WORDS], alloc: A },
+    #[cfg(feature = "allocator_api")]
+    Heap(Vec<u64, A>),
+    #[cfg(not(feature = "allocator_api"))]
+    Heap(Vec<u64>),
+}
+
+impl<A: Allocator + Clone> Words<A> {
+    /// n zero words, inline if they fit and otherwise from alloc.
+    pub(crate) fn zeroed_in(n: usize, alloc: A) -> Words<A> {
+        if n <= INLINE_WORDS {
+            Words::Inline { len: n, words: [0; INLINE_WORDS], alloc }
+        } else {
+            Words::heap(&[], n, alloc)
+        }
+    }
+
+    /// The heap words, first then zeros up to n in all.  The zeros come
+    /// from the allocator already zeroed, as vec![0; n] gets them, so a
+    /// big buffer is a fresh mapping whose pages are only faulted in when
+    /// they are first touched.
+    fn heap(first: &[u64], n: usize, alloc: A) -> Words<A> {
+        #[cfg(feature = "allocator_api")]
+        let mut v = {
+            let layout = Layout::array::<u64>(n).expect("capacity overflow");
+            let ptr = alloc.allocate_zeroed(layout).unwrap_or_else(|_| handle_alloc_error(layout));
+            // allocated by alloc for exactly n words, all of them zero
+            unsafe { Vec::from_raw_parts_in(ptr.as_ptr() as *mut u64, n, n, alloc) }
+        };
+        #[cfg(not(feature = "allocator_api"))]
+        let mut v = {
+            // the stand-in Global, which is the global allocator
+            drop(alloc);
+            vec![0; n]
+        };
+        v[.. first.len()].copy_from_slice(first);
+        Words::Heap(v)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_inline(&self) -> bool {
+        matches!(self, Words::Inline { .. })
+    }
+
+    /// The allocator the words come from, or would if they moved to the
+    /// heap.
+    #[cfg(feature = "allocator_api")]
+    pub(crate) fn allocator(&self) -> &A {
+        match self {
+            Words::Inline { alloc, .. } => alloc,
+            Words::Heap(v) => v.allocator(),
+        }
+    }
+
+    /// The words this holds without allocating.
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            Words::Inline { .. } => INLINE_WORDS,
+            Words::Heap(v) => v.capacity(),
+        }
+    }
+
+    /// Vec::resize with zeros, moving to the heap past INLINE_WORDS.
+    pub(crate) fn resize(&mut self, n: usize) {
+        match self {
+            Words::Inline { len, words, .. } if n <= INLINE_WORDS => {
+                for w in &mut words[n ..] {
+                    *w = 0;
+                }
+                *len = n;
+            }
+            Words::Inline { len, words, alloc } => *self = Words::heap(&words[.. *len], n, alloc.clone()),
+            Words::Heap(v) => v.resize(n, 0),
+        }
+    }
+}
+
+impl Words {
+    /// n zero words, inline if they fit.
+    pub(crate) fn zeroed(n: usize) -> Words {
+        Words::zeroed_in(n, Global)
+    }
+
+    /// The words as a Vec, allocating one for inline words.
+    pub(crate) fn into_vec(self) -> Vec<u64> {
+        match self {
+            Words::Inline { len, words, .. } => words[.. len].to_vec(),
+            Words::Heap(v) => v,
+        }
+    }
+}
+
+impl Default for Words {
+    fn default() -> Words {
+        Words::zeroed(0)
+    }
+}
+
+impl From<Vec<u64>> for Words {
+    fn from(v: Vec<u64>) -> Words {
+        Words::Heap(v)
+    }
+}
+
+impl<A: Allocator + Clone> Deref for Words<A> {
+    type Target = [u64];
+
+    fn deref(&self) -> &[u64] {
+        match self {
+            Words::Inline { len, words, .. } => &words[.. *len],
+            Words::Heap(v) => v,
+        }
+    }
+}
+
+impl<A: Allocator + Clone> DerefMut for Words<A> {
+    fn deref_mut(&mut self) -> &mut [u64] {
+        match self {
+            Words::Inline { len, words, .. } => &mut words[.. *len],
+            Words::Heap(v) => v,
+        }
+    }
+}
+
+impl<'a, A: Allocator + Clone> IntoIterator for &'a Words<A> {
+    type Item = &'a u64;
+    type IntoIter = std::slice::Iter<'a, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, A: Allocator + Clone> IntoIterator for &'a mut Words<A> {
+    type Item = &'a mut u64;
+    type IntoIter = std::slice::IterMut<'a, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// The words, whichever way they are stored.
+impl<A: Allocator + Clone> PartialEq for Words<A> {
+    fn eq(&self, other: &Words<A>) -> bool {
+        **self == **other
+    }
+}
+
+impl<A: Allocator + Clone> fmt::Debug for Words<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_up_to_two_words() {
+        for n in 0 ..= INLINE_WORDS {
+            assert!(Words::zeroed(n).is_inline());
+            assert_eq!(Words::zeroed(n).len(), n);
+        }
+        assert!(!Words::zeroed(INLINE_WORDS + 1).is_inline());
+        assert!(!Words::from(vec![0]).is_inline());
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut w = Words::zeroed(2);
+        w[1] = 7;
+        w.resize(1);
+        assert_eq!(*w, [0]);
+        // a word given back comes back zero
+        w.resize(2);
+        assert_eq!(*w, [0, 0]);
+        w[1] = 7;
+        w.resize(5);
+        assert!(!w.is_inline());
+        assert_eq!(*w, [0, 7, 0, 0, 0]);
+        w.resize(1);
+        assert!(!w.is_inline());
+        assert_eq!(w, Words::zeroed(1));
+        assert_eq!(format!("{:?}", w), "[0]");
+        assert_eq!(Words::zeroed(2).into_vec(), vec![0, 0]);
+    }
+
+    /// Resident pages of this process, from /proc/self/statm.
+    #[cfg(target_os = "linux")]
+    fn resident_bytes() -> usize {
+        let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+        4096 * statm.split_whitespace().nth(1).unwrap().parse::<usize>().unwrap()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)] // no /proc under Miri
+    fn test_zeroed_is_not_touched() {
+        // writing the zeros would make all 1GB resident, while a zeroed
+        // allocation only maps it; other tests running meanwhile use far
+        // less than the slack
+        let before = resident_bytes();
+        let w = Words::zeroed((1 << 30) / 8);
+        assert_eq!((w[0], w[w.len() - 1]), (0, 0));
+        let grown = resident_bytes().saturating_sub(before);
+        assert!(grown < 256 << 20, "made {} bytes resident", grown);
+    }
+}
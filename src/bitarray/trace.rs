@@ -0,0 +1,160 @@
+// A rotate that reports every intermediate state of the array, for
+// following the algorithm by hand and for finding the step where a broken
+// one goes wrong.
+use std::io::{self, Write};
+
+use super::moves::WordMove;
+use super::{BitArray, RotatePath};
+
+/// One step of a traced rotation: the array just after it.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceStep<'a> {
+    /// Counted from 1; step 0 is the starting array.
+    pub step: usize,
+    pub label: &'a str,
+    pub array: &'a BitArray,
+}
+
+impl BitArray {
+    /// Does the same rotation as rotate(), by the same path, and calls
+    /// on_step with the array before it starts and after each step, for
+    /// the first max_steps steps only; the rotation itself always
+    /// finishes.  Returns the number of steps taken, which may be more
+    /// than were reported.
+    pub fn rotate_traced<F>(&mut self,
+                            bit_offset: usize,
+                            bit_length: usize,
+                            bit_right_amount: isize,
+                            max_steps: usize,
+                            mut on_step: F) -> usize
+    where F: FnMut(TraceStep) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        on_step(TraceStep { step: 0, label: "start", array: self });
+        if bit_length == 0 {
+            return 0;
+        }
+        let right = BitArray::modulo(bit_right_amount, bit_length);
+        let left = (bit_length - right) % bit_length;
+        let end = bit_offset + bit_length;
+        let mut step = 0;
+        let mut report = |array: &BitArray, label: String| {
+            step += 1;
+            if step <= max_steps {
+                on_step(TraceStep { step, label: &label, array });
+            }
+        };
+        match BitArray::rotate_path(bit_length, left) {
+            RotatePath::Identity => {}
+            RotatePath::Register => {
+                self.rotate_left_in_register(bit_offset, bit_length, left);
+                report(self, format!("rotate [{}, {}) left by {} in a register", bit_offset, end, left));
+            }
+            #[cfg(feature = "rayon")]
+            RotatePath::ParallelReversal => {
+                let mid = bit_offset + left;
+                self.reverse_range(bit_offset, left);
+                report(self, format!("reverse [{}, {})", bit_offset, mid));
+                self.reverse_range(mid, bit_length - left);
+                report(self, format!("reverse [{}, {})", mid, end));
+                self.reverse_range(bit_offset, bit_length);
+                report(self, format!("reverse [{}, {})", bit_offset, end));
+            }
+            RotatePath::WordMoves => {
+                self.rotate_left_by_word_moves(bit_offset, bit_length, left, |array, step| {
+                    let label = match step {
+                        WordMove::Swap { a, b, len } => {
+                            format!("swap [{}, {}) with [{}, {})", a, a + len, b, b + len)
+                        }
+                        WordMove::ShiftDown { start, end, by } => {
+                            format!("save [{}, {}), shift [{}, {}) down by {}", start, start + by,
+                                    start + by, end, by)
+                        }
+                        WordMove::ShiftUp { start, end, by } => {
+                            format!("save [{}, {}), shift [{}, {}) up by {}", end - by, end, start,
+                                    end - by, by)
+                        }
+                        WordMove::Restore { start, len } => {
+                            format!("put the saved bits at [{}, {})", start, start + len)
+                        }
+                    };
+                    report(array, label);
+                });
+            }
+        }
+        step
+    }
+
+    /// rotate_traced writing one line per step to out, the array as show()
+    /// prints it followed by what the step did, and a last line saying how
+    /// many steps went unreported past max_steps.
+    pub fn rotate_trace_to<W: Write>(&mut self,
+                                     bit_offset: usize,
+                                     bit_length: usize,
+                                     bit_right_amount: isize,
+                                     max_steps: usize,
+                                     out: &mut W) -> io::Result<()> {
+        let mut result = Ok(());
+        let steps = self.rotate_traced(bit_offset, bit_length, bit_right_amount, max_steps, |s| {
+            if result.is_ok() {
+                result = writeln!(out, "{:>4} {} {}", s.step, s.array, s.label);
+            }
+        });
+        result?;
+        if steps > max_steps {
+            writeln!(out, "... {} more steps", steps - max_steps)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_traced() {
+        let start = BitArray::from_str("10010110");
+        let mut ba = start.clone();
+        let mut states = vec![];
+        let steps = ba.rotate_traced(2, 5, 2, 10, |s| states.push((s.step, s.label.to_string(), s.array.show())));
+        // right by 2 of 5 is left by 3, in one go
+        assert_eq!(steps, 1);
+        assert_eq!(states, vec![(0, "start".to_string(), "10010110".to_string()),
+                                (1, "rotate [2, 7) left by 3 in a register".to_string(), "11010010".to_string())]);
+        let mut want = start.clone();
+        want.rotate(2, 5, 2);
+        assert_eq!(ba, want);
+
+        let mut ba = start.clone();
+        assert_eq!(ba.rotate_traced(2, 5, -1, 10, |_| {}), 1);
+        assert_eq!(ba.rotate_traced(2, 5, 5, 10, |_| {}), 0);
+    }
+
+    #[test]
+    fn test_rotate_traced_word_moves() {
+        let mut ba = BitArray::new(300);
+        ba.randfill();
+        let mut want = ba.clone();
+        want.rotate(10, 280, -3);
+        let mut labels = vec![];
+        assert_eq!(ba.rotate_traced(10, 280, -3, 10, |s| labels.push(s.label.to_string())), 2);
+        assert_eq!(labels, vec!["start", "save [10, 13), shift [13, 290) down by 3",
+                                "put the saved bits at [287, 290)"]);
+        assert_eq!(ba, want);
+    }
+
+    #[test]
+    fn test_rotate_trace_to() {
+        let mut ba = BitArray::from_str("10010110");
+        let mut out = vec![];
+        ba.rotate_trace_to(0, 8, 5, 2, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   concat!("   0 10010110 start\n",
+                           "   1 11010010 rotate [0, 8) left by 3 in a register\n"));
+        assert_eq!(ba.show(), "11010010");
+        let mut out = vec![];
+        ba.rotate_trace_to(0, 8, 5, 0, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!("   0 11010010 start\n",
+                                                            "... 1 more steps\n"));
+    }
+}
@@ -0,0 +1,83 @@
+// Word-at-a-time access to a range of bits, for custom kernels that
+// would otherwise need the raw buffer.  Words are the 64-bit aligned
+// chunks of the array, word k holding bits [64k, 64k + 64) with bit 64k
+// lowest, so a kernel can combine words of different arrays by index.
+// The words at the ends of the range are partial; the code here masks
+// them, and the kernel never sees or changes a bit outside the range.
+use std::ops::Range;
+
+use super::BitArray;
+
+/// The bits [lo, hi) of word, as a mask.
+fn mask(lo: usize, hi: usize) -> u64 {
+    (u64::MAX >> (64 - (hi - lo))) << lo
+}
+
+impl BitArray {
+    /// Calls f(k, word) for each word k overlapping range, in order.  The
+    /// bits of word outside range are zero.
+    pub fn for_each_word<F: FnMut(usize, u64)>(&self, range: Range<usize>, mut f: F) {
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+        if range.is_empty() {
+            return;
+        }
+        for k in range.start / 64 ..= (range.end - 1) / 64 {
+            let lo = usize::max(range.start, 64 * k);
+            let hi = usize::min(range.end, 64 * k + 64);
+            f(k, self.read_bits(lo, hi - lo) << (lo - 64 * k));
+        }
+    }
+
+    /// Replaces each word k overlapping range with f(k, word), where word
+    /// is as for_each_word passes it.  Only the bits of the result inside
+    /// range are stored.
+    pub fn map_words_in_place<F: FnMut(usize, u64) -> u64>(&mut self, range: Range<usize>, mut f: F) {
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+        if range.is_empty() {
+            return;
+        }
+        for k in range.start / 64 ..= (range.end - 1) / 64 {
+            let (lo, hi) = (usize::max(range.start, 64 * k), usize::min(range.end, 64 * k + 64));
+            let (lo_bit, hi_bit) = (lo - 64 * k, hi - 64 * k);
+            let word = self.read_bits(lo, hi - lo) << lo_bit;
+            let out = f(k, word) & mask(lo_bit, hi_bit);
+            self.write_bits(lo, hi - lo, out >> lo_bit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_word() {
+        let mut ba = BitArray::new(200);
+        ba.fill(true);
+        let mut words = vec![];
+        ba.for_each_word(60 .. 140, |k, w| words.push((k, w)));
+        assert_eq!(words, vec![(0, 0xf << 60), (1, u64::MAX), (2, 0xfff)]);
+        let mut ones = 0;
+        ba.for_each_word(0 .. 200, |_, w| ones += w.count_ones());
+        assert_eq!(ones, 200);
+        ba.for_each_word(5 .. 5, |_, _| panic!("empty range"));
+    }
+
+    #[test]
+    fn test_map_words_in_place() {
+        let mut ba = BitArray::new(200);
+        // not every kernel leaves the outside bits alone
+        ba.map_words_in_place(60 .. 140, |_, w| !w);
+        assert_eq!(ba.to_ranges(), vec![60 .. 140]);
+        let other = ba.clone();
+        ba.map_words_in_place(0 .. 200, |k, w| {
+            let mut theirs = 0;
+            other.for_each_word(64 * k .. usize::min(64 * k + 64, 200), |_, o| theirs = o);
+            w ^ theirs
+        });
+        assert_eq!(ba.count_ones(), 0);
+        let mut small = BitArray::from_str("10010110");
+        small.map_words_in_place(2 .. 6, |k, w| { assert_eq!((k, w), (0, 0b010100)); 0b111100 });
+        assert_eq!(small.show(), "10111110");
+    }
+}
@@ -0,0 +1,205 @@
+// Declarative register maps and packet headers on top of BitArray.
+//
+// The bitfield! macro turns a list of named fields (offset and width in
+// bits) into a struct wrapping a BitArray with one getter and one setter
+// per field, so the read_bits/write_bits calls are written exactly once.
+
+/// Conversion between a field's Rust type and the raw bits stored in the
+/// array.  Implemented for bool and the unsigned integers; implement it for
+/// an enum to get typed enum fields.
+pub trait BitfieldValue: Sized {
+    /// Builds the value from the low bits of bits.
+    fn from_bits(bits: u64) -> Self;
+
+    /// The raw bits to store, which must fit in the field's width.
+    fn to_bits(self) -> u64;
+}
+
+impl BitfieldValue for bool {
+    fn from_bits(bits: u64) -> bool {
+        bits != 0
+    }
+
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+}
+
+macro_rules! impl_bitfield_value {
+    ($($t:ty),*) => {
+        $(impl BitfieldValue for $t {
+            fn from_bits(bits: u64) -> $t {
+                bits as $t
+            }
+
+            fn to_bits(self) -> u64 {
+                self as u64
+            }
+        })*
+    };
+}
+
+impl_bitfield_value!(u8, u16, u32, u64, usize);
+
+/// Declares a struct wrapping a fixed size BitArray with typed accessors.
+///
+/// Each field is written `getter, setter: Type [offset, width];` and is
+/// stored with read_bits/write_bits, that is bit offset holds the least
+/// significant bit of the field.  Type is anything implementing
+/// BitfieldValue.
+///
+/// Example:
+/// ```
+/// use bitarray::bitfield;
+///
+/// bitfield! {
+///     pub struct Status(16) {
+///         ready, set_ready: bool [0, 1];
+///         mode, set_mode: u8 [1, 3];
+///         count, set_count: u16 [4, 12];
+///     }
+/// }
+///
+/// let mut status = Status::new();
+/// status.set_mode(5);
+/// status.set_count(1000);
+/// assert_eq!(status.mode(), 5);
+/// assert_eq!(status.count(), 1000);
+/// assert!(!status.ready());
+/// ```
+#[macro_export]
+macro_rules! bitfield {
+    ($(#[$meta:meta])*
+     $vis:vis struct $name:ident($bit_sz:expr) {
+         $($(#[$field_meta:meta])*
+           $getter:ident, $setter:ident : $ty:ty [$offset:expr, $width:expr];)*
+     }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        $vis struct $name {
+            bits: $crate::bitarray::BitArray,
+        }
+
+        impl $name {
+            /// The number of bits in the underlying array.
+            pub const BIT_SZ: usize = $bit_sz;
+
+            /// All fields zero.
+            pub fn new() -> $name {
+                $name { bits: $crate::bitarray::BitArray::new($bit_sz) }
+            }
+
+            /// Wraps an existing array, which must be BIT_SZ bits long.
+            pub fn from_bitarray(bits: $crate::bitarray::BitArray) -> $name {
+                assert_eq!(bits.get_bit_sz(), $bit_sz);
+                $name { bits }
+            }
+
+            pub fn as_bitarray(&self) -> &$crate::bitarray::BitArray {
+                &self.bits
+            }
+
+            pub fn into_bitarray(self) -> $crate::bitarray::BitArray {
+                self.bits
+            }
+
+            $(
+                $(#[$field_meta])*
+                pub fn $getter(&self) -> $ty {
+                    <$ty as $crate::bitfield::BitfieldValue>::from_bits(
+                        self.bits.read_bits($offset, $width))
+                }
+
+                pub fn $setter(&mut self, value: $ty) {
+                    self.bits.write_bits($offset, $width,
+                                         $crate::bitfield::BitfieldValue::to_bits(value));
+                }
+            )*
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name::new()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitarray::BitArray;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Mode {
+        Off,
+        Read,
+        Write,
+        ReadWrite,
+    }
+
+    impl BitfieldValue for Mode {
+        fn from_bits(bits: u64) -> Mode {
+            match bits {
+                0 => Mode::Off,
+                1 => Mode::Read,
+                2 => Mode::Write,
+                3 => Mode::ReadWrite,
+                _ => panic!("bad mode {}", bits),
+            }
+        }
+
+        fn to_bits(self) -> u64 {
+            self as u64
+        }
+    }
+
+    bitfield! {
+        /// A made up control register.
+        struct Control(40) {
+            enabled, set_enabled: bool [0, 1];
+            mode, set_mode: Mode [1, 2];
+            /// Crosses a byte boundary.
+            divider, set_divider: u16 [5, 10];
+            address, set_address: u32 [15, 25];
+        }
+    }
+
+    #[test]
+    fn test_fields_are_independent() {
+        let mut reg = Control::new();
+        reg.set_enabled(true);
+        reg.set_mode(Mode::Write);
+        reg.set_divider(0x2ab);
+        reg.set_address(0x1ff_ffff);
+        assert!(reg.enabled());
+        assert_eq!(reg.mode(), Mode::Write);
+        assert_eq!(reg.divider(), 0x2ab);
+        assert_eq!(reg.address(), 0x1ff_ffff);
+
+        reg.set_mode(Mode::ReadWrite);
+        reg.set_address(0);
+        assert!(reg.enabled());
+        assert_eq!(reg.mode(), Mode::ReadWrite);
+        assert_eq!(reg.divider(), 0x2ab);
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut reg = Control::default();
+        reg.set_mode(Mode::Read);
+        reg.set_divider(1);
+        assert_eq!(reg.as_bitarray().read_bits(0, 16), 0b100010);
+
+        let reg = Control::from_bitarray(BitArray::from_str(&"1".repeat(40)));
+        assert_eq!(reg.address(), 0x1ff_ffff);
+        assert_eq!(reg.into_bitarray().get_bit_sz(), Control::BIT_SZ);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_value_too_wide() {
+        let mut reg = Control::new();
+        reg.set_divider(1 << 10);
+    }
+}
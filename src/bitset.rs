@@ -0,0 +1,223 @@
+// The classic bit set interface over usize elements, growing as needed,
+// in the spirit of the old std::collections::BitSet.
+use std::fmt;
+use std::iter::FromIterator;
+
+use crate::bitarray::BitArray;
+
+/// A copy of bits grown to n bits (n >= the current size).
+fn resized(bits: &BitArray, n: usize) -> BitArray {
+    let mut out = BitArray::new(n);
+    let mut done = 0;
+    while done < bits.get_bit_sz() {
+        let width = usize::min(64, bits.get_bit_sz() - done);
+        out.write_bits(done, width, bits.read_bits(done, width));
+        done += width;
+    }
+    out
+}
+
+/// A set of small non-negative integers, element i being bit i of a
+/// BitArray.  Inserting past the end grows the array (doubling, at least
+/// 64 bits); everything past the end is simply absent.
+#[derive(Clone)]
+pub struct BitSet {
+    bits: BitArray,
+}
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        BitSet::with_capacity(0)
+    }
+
+    /// A set that holds 0 .. capacity without growing.
+    pub fn with_capacity(capacity: usize) -> BitSet {
+        BitSet { bits: BitArray::new(capacity) }
+    }
+
+    pub fn from_bitarray(bits: BitArray) -> BitSet {
+        BitSet { bits }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bits.get_bit_sz()
+    }
+
+    /// Grows the array to hold at least n elements.
+    fn reserve_to(&mut self, n: usize) {
+        if n > self.capacity() {
+            let grown = usize::max(n, usize::max(64, self.capacity() * 2));
+            self.bits = resized(&self.bits, grown);
+        }
+    }
+
+    /// Adds value, returning whether it was newly added.
+    pub fn insert(&mut self, value: usize) -> bool {
+        self.reserve_to(value + 1);
+        let was = self.bits.get(value);
+        self.bits.set(value, true);
+        !was
+    }
+
+    /// Removes value, returning whether it was present.
+    pub fn remove(&mut self, value: usize) -> bool {
+        if !self.contains(value) {
+            return false;
+        }
+        self.bits.set(value, false);
+        true
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        value < self.capacity() && self.bits.get(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(false)
+    }
+
+    /// The elements in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bits.iter_ones()
+    }
+
+    /// Grows self to at least other's capacity and returns other's bits
+    /// at exactly self's, ready for the whole-array operators.
+    fn with_matching(&mut self, other: &BitSet) -> BitArray {
+        self.reserve_to(other.capacity());
+        resized(&other.bits, self.capacity())
+    }
+
+    /// Adds every element of other.
+    pub fn union_with(&mut self, other: &BitSet) {
+        let theirs = self.with_matching(other);
+        self.bits |= &theirs;
+    }
+
+    /// Keeps only the elements also in other.
+    pub fn intersect_with(&mut self, other: &BitSet) {
+        let theirs = self.with_matching(other);
+        self.bits &= &theirs;
+    }
+
+    /// Removes every element of other.
+    pub fn difference_with(&mut self, other: &BitSet) {
+        let mut theirs = self.with_matching(other);
+        theirs.invert();
+        self.bits &= &theirs;
+    }
+
+    /// Keeps the elements in exactly one of the two sets.
+    pub fn symmetric_difference_with(&mut self, other: &BitSet) {
+        let theirs = self.with_matching(other);
+        self.bits ^= &theirs;
+    }
+
+    pub fn is_subset(&self, other: &BitSet) -> bool {
+        self.iter().all(|v| other.contains(v))
+    }
+
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+}
+
+impl Default for BitSet {
+    fn default() -> BitSet {
+        BitSet::new()
+    }
+}
+
+impl PartialEq for BitSet {
+    /// Sets are equal when they hold the same elements, whatever their
+    /// capacities.
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+impl Eq for BitSet {}
+
+impl fmt::Debug for BitSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> BitSet {
+        let mut set = BitSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<usize> for BitSet {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for v in iter {
+            self.insert(v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_insert_grows() {
+        let mut s = BitSet::new();
+        assert!(!s.contains(1000));
+        assert!(s.insert(1000));
+        assert!(!s.insert(1000));
+        assert!(s.insert(3));
+        assert!(s.capacity() >= 1001);
+        assert_eq!(s.iter().collect::<Vec<_>>(), vec![3, 1000]);
+        assert!(s.remove(3));
+        assert!(!s.remove(3) && !s.remove(5000));
+        assert_eq!(s.len(), 1);
+        assert_eq!(format!("{:?}", s), "{1000}");
+    }
+
+    #[test]
+    fn test_set_ops_match_btreeset() {
+        let a: BitSet = vec![1, 5, 64, 200].into_iter().collect();
+        let b: BitSet = vec![5, 70, 200, 1000].into_iter().collect();
+        let ta: BTreeSet<usize> = a.iter().collect();
+        let tb: BTreeSet<usize> = b.iter().collect();
+
+        let mut u = a.clone();
+        u.union_with(&b);
+        assert!(u.iter().eq(ta.union(&tb).cloned()));
+        let mut i = a.clone();
+        i.intersect_with(&b);
+        assert!(i.iter().eq(ta.intersection(&tb).cloned()));
+        let mut d = b.clone();
+        d.difference_with(&a);
+        assert!(d.iter().eq(tb.difference(&ta).cloned()));
+        let mut x = a.clone();
+        x.symmetric_difference_with(&b);
+        assert!(x.iter().eq(ta.symmetric_difference(&tb).cloned()));
+
+        assert!(i.is_subset(&a) && i.is_subset(&b) && !a.is_subset(&b));
+    }
+
+    #[test]
+    fn test_eq_ignores_capacity() {
+        let mut a = BitSet::with_capacity(10_000);
+        a.insert(7);
+        let b: BitSet = vec![7].into_iter().collect();
+        assert_eq!(a, b);
+        a.clear();
+        assert!(a.is_empty());
+        assert_eq!(a, BitSet::new());
+    }
+}
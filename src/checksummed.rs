@@ -0,0 +1,111 @@
+// A BitArray that keeps a checksum of every block of its storage, so a
+// long-lived bitmap can be checked for silent corruption (a bad DIMM, a
+// stray write through unsafe code, a file changed under an mmap) before
+// something acts on it.
+use std::fmt;
+
+use crate::bitarray::BitArray;
+use crate::observed::{Change, Observed, Observer};
+
+/// The bits covered by each checksum.
+pub const BLOCK_BITS: usize = 4096;
+
+/// The blocks whose contents no longer match their checksums.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumError {
+    /// Block indices, ascending; block i holds bits
+    /// [i * BLOCK_BITS, (i + 1) * BLOCK_BITS).
+    pub blocks: Vec<usize>,
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "checksum mismatch in {} blocks, the first at bit {}",
+               self.blocks.len(), self.blocks[0] * BLOCK_BITS)
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+/// Block checksums of an Observed array, rechecksumming just the blocks
+/// each change touched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSums {
+    sums: Vec<u64>,
+}
+
+/// A BitArray whose BLOCK_BITS blocks can be checked against the sums
+/// taken when they were last changed through it.
+pub type ChecksummedBitArray = Observed<BlockSums>;
+
+/// FNV-1a over the 64-bit words of block, which is cheap and catches any
+/// single flipped bit.
+fn block_sum(bits: &BitArray, block: usize) -> u64 {
+    let start = block * BLOCK_BITS;
+    let end = usize::min(start + BLOCK_BITS, bits.get_bit_sz());
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for word_start in (start .. end).step_by(64) {
+        let word = bits.read_bits(word_start, usize::min(64, end - word_start));
+        hash = (hash ^ word).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+impl Observer for BlockSums {
+    /// Checksums bits as they are now.
+    fn start(bits: &BitArray) -> BlockSums {
+        BlockSums { sums: (0 .. bits.get_bit_sz().div_ceil(BLOCK_BITS)).map(|b| block_sum(bits, b)).collect() }
+    }
+
+    fn after(&mut self, bits: &BitArray, change: &Change) {
+        let span = change.span(bits.get_bit_sz());
+        if !span.is_empty() {
+            for block in span.start / BLOCK_BITS ..= (span.end - 1) / BLOCK_BITS {
+                self.sums[block] = block_sum(bits, block);
+            }
+        }
+    }
+}
+
+impl Observed<BlockSums> {
+    /// Recomputes every checksum and compares; O(n).
+    pub fn verify(&self) -> Result<(), ChecksumError> {
+        let sums = &self.observer.sums;
+        let blocks: Vec<usize> = (0 .. sums.len()).filter(|&b| block_sum(&self.bits, b) != sums[b]).collect();
+        if blocks.is_empty() { Ok(()) } else { Err(ChecksumError { blocks }) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations_keep_sums() {
+        let mut c = ChecksummedBitArray::new(3 * BLOCK_BITS + 100);
+        c.set(5, true);
+        c.fill_range(BLOCK_BITS - 10, 20, true);
+        c.write_bits(3 * BLOCK_BITS + 40, 60, 0xabc);
+        c.rotate(100, 2 * BLOCK_BITS, 7);
+        assert_eq!(c.verify(), Ok(()));
+        c.invert();
+        c.fill(true);
+        assert_eq!(c.verify(), Ok(()));
+        assert_eq!(ChecksummedBitArray::new(0).verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let mut c = ChecksummedBitArray::new(3 * BLOCK_BITS + 100);
+        c.fill_range(0, 200, true);
+        // writes that bypass the wrapper, as corruption would
+        c.bits.set(BLOCK_BITS + 1, true);
+        c.bits.set(3 * BLOCK_BITS + 99, true);
+        let e = c.verify().unwrap_err();
+        assert_eq!(e.blocks, vec![1, 3]);
+        assert_eq!(e.to_string(), "checksum mismatch in 2 blocks, the first at bit 4096");
+        c.bits.set(BLOCK_BITS + 1, false);
+        c.bits.set(3 * BLOCK_BITS + 99, false);
+        assert_eq!(c.verify(), Ok(()));
+    }
+}
@@ -0,0 +1,276 @@
+// Defaults for the harness from an everybit.toml, so a course machine or
+// a CI job is set up once instead of in every command line.  The file is
+// looked for in the working directory, then in the user's config
+// directory; environment variables (see ENV_VARS) win over it, and flags
+// given on the command line win over both.
+//
+//     seed = 42
+//     format = "json"
+//     calibrate = true
+//     warmup = 3
+//     strategy = "word-moves"
+//
+//     [tiers]         # seconds per rotation for -s, -m and -l
+//     small = 0.02
+//     large = 2.0
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bitarray::RotateStrategy;
+use crate::perf::{Calibration, TierLimits};
+
+/// The file name looked for.
+pub const CONFIG_FILE: &str = "everybit.toml";
+
+/// Where `everybit calibrate` keeps its measurements, in the user's
+/// config directory.
+pub const CALIBRATION_FILE: &str = "everybit-calibration.json";
+
+/// The environment variables Config::apply_env reads, and what they set.
+/// EVERYBIT_CONFIG is read by the binary, not apply_env, since it picks
+/// the file.
+pub const ENV_VARS: &[(&str, &str)] = &[
+    ("EVERYBIT_CONFIG", "the config file, instead of looking for everybit.toml"),
+    ("EVERYBIT_SEED", "the seed, as --seed"),
+    ("EVERYBIT_FORMAT", "text or json, as --format"),
+    ("EVERYBIT_CALIBRATE", "1 to calibrate the tiers, as --calibrate; 0 not to"),
+    ("EVERYBIT_WARMUP", "untimed rotations per tier, as --warmup"),
+    ("EVERYBIT_STRATEGY", "the rotation algorithm of the perf tests, as --strategy"),
+    ("EVERYBIT_TIME_LIMIT", "the time limit of all three tiers, in seconds"),
+    ("EVERYBIT_SMALL_LIMIT", "the time limit of -s, tiers.small in everybit.toml"),
+    ("EVERYBIT_MEDIUM_LIMIT", "the time limit of -m, tiers.medium"),
+    ("EVERYBIT_LARGE_LIMIT", "the time limit of -l, tiers.large"),
+];
+
+/// How the harness prints results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// The report the original harness printed.
+    Text,
+    /// One JSON object per run, for scripts.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format {:?}, expected text or json", s)),
+        }
+    }
+}
+
+/// Everything an everybit.toml can set; a key left out keeps its default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tiers: TierLimits,
+    /// The seed for the perf tests and --oracle, random if None.
+    pub seed: Option<u64>,
+    pub format: Option<Format>,
+    /// Scale the tier limits to this machine's speed, see perf::calibrate.
+    pub calibrate: bool,
+    /// Untimed rotations before each timed one in the perf tests.
+    pub warmup: usize,
+    /// The rotation algorithm the perf tests time, rotate's own choice
+    /// if None.
+    pub strategy: Option<RotateStrategy>,
+}
+
+/// A config file that could not be read or parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub msg: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.msg)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn from_toml(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|e| e.message().to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let error = |msg: String| ConfigError { path: path.to_path_buf(), msg };
+        let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        Config::from_toml(&text).map_err(error)
+    }
+
+    /// Overrides settings with the ENV_VARS that var returns a value for;
+    /// var is std::env::var(..).ok() outside of tests.
+    pub fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<(), String> {
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value.trim().parse().map_err(|_| format!("{}: can't parse {:?}", name, value))
+        }
+        if let Some(v) = var("EVERYBIT_SEED") {
+            self.seed = Some(parse("EVERYBIT_SEED", &v)?);
+        }
+        if let Some(v) = var("EVERYBIT_FORMAT") {
+            self.format = Some(v.parse().map_err(|e| format!("EVERYBIT_FORMAT: {}", e))?);
+        }
+        if let Some(v) = var("EVERYBIT_CALIBRATE") {
+            self.calibrate = match v.trim() {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                _ => return Err(format!("EVERYBIT_CALIBRATE: can't parse {:?}", v)),
+            };
+        }
+        if let Some(v) = var("EVERYBIT_WARMUP") {
+            self.warmup = parse("EVERYBIT_WARMUP", &v)?;
+        }
+        if let Some(v) = var("EVERYBIT_STRATEGY") {
+            self.strategy = Some(v.trim().parse().map_err(|e| format!("EVERYBIT_STRATEGY: {}", e))?);
+        }
+        if let Some(v) = var("EVERYBIT_TIME_LIMIT") {
+            let limit = parse("EVERYBIT_TIME_LIMIT", &v)?;
+            self.tiers = TierLimits { small: limit, medium: limit, large: limit };
+        }
+        if let Some(v) = var("EVERYBIT_SMALL_LIMIT") {
+            self.tiers.small = parse("EVERYBIT_SMALL_LIMIT", &v)?;
+        }
+        if let Some(v) = var("EVERYBIT_MEDIUM_LIMIT") {
+            self.tiers.medium = parse("EVERYBIT_MEDIUM_LIMIT", &v)?;
+        }
+        if let Some(v) = var("EVERYBIT_LARGE_LIMIT") {
+            self.tiers.large = parse("EVERYBIT_LARGE_LIMIT", &v)?;
+        }
+        Ok(())
+    }
+
+    /// Where the file is looked for, in order: the working directory,
+    /// then $XDG_CONFIG_HOME or ~/.config.
+    pub fn search_paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from(CONFIG_FILE)];
+        if let Some(dir) = config_dir() {
+            paths.push(dir.join(CONFIG_FILE));
+        }
+        paths
+    }
+
+    /// The first config file in search_paths that exists, loaded, or the
+    /// defaults if there is none.
+    pub fn find() -> Result<(Option<PathBuf>, Config), ConfigError> {
+        match Config::search_paths().into_iter().find(|p| p.is_file()) {
+            Some(path) => Config::load(&path).map(|c| (Some(path), c)),
+            None => Ok((None, Config::default())),
+        }
+    }
+}
+
+/// $XDG_CONFIG_HOME, or ~/.config.
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+}
+
+/// The path of CALIBRATION_FILE, None if there is no config directory.
+pub fn calibration_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CALIBRATION_FILE))
+}
+
+pub fn save_calibration(path: &Path, calibration: &Calibration) -> Result<(), ConfigError> {
+    let error = |msg: String| ConfigError { path: path.to_path_buf(), msg };
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| error(e.to_string()))?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(calibration).unwrap()).map_err(|e| error(e.to_string()))
+}
+
+/// The saved calibration, Ok(None) if there isn't one.
+pub fn load_calibration(path: &Path) -> Result<Option<Calibration>, ConfigError> {
+    let error = |msg: String| ConfigError { path: path.to_path_buf(), msg };
+    match std::fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).map(Some).map_err(|e| error(e.to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(error(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml("seed = 42\nformat = \"json\"\nstrategy = \"block-swap\"\n\
+                                        [tiers]\nlarge = 2.5\n").unwrap();
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.strategy, Some(RotateStrategy::BlockSwap));
+        assert_eq!(config.format, Some(Format::Json));
+        assert_eq!(config.tiers, TierLimits { large: 2.5, ..TierLimits::default() });
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_bad_config() {
+        assert!(Config::from_toml("sed = 1").unwrap_err().contains("unknown field"));
+        assert!(Config::from_toml("format = \"xml\"").is_err());
+        assert!(Config::from_toml("strategy = \"WordMoves\"").is_err());
+        assert!(Config::from_toml("[tiers]\nhuge = 3.0").is_err());
+        let e = Config::load(Path::new("/nonexistent/everybit.toml")).unwrap_err();
+        assert!(e.to_string().starts_with("/nonexistent/everybit.toml: "));
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+        };
+        let mut config = Config::from_toml("seed = 1\nformat = \"json\"").unwrap();
+        config.apply_env(env(&[("EVERYBIT_SEED", "99"), ("EVERYBIT_TIME_LIMIT", "0.5"),
+                               ("EVERYBIT_LARGE_LIMIT", "3")])).unwrap();
+        assert_eq!(config.seed, Some(99));
+        assert_eq!(config.format, Some(Format::Json));
+        assert_eq!(config.tiers, TierLimits { small: 0.5, medium: 0.5, large: 3.0 });
+        assert!(!config.calibrate);
+        config.apply_env(env(&[("EVERYBIT_CALIBRATE", "1"), ("EVERYBIT_WARMUP", "4"),
+                               ("EVERYBIT_STRATEGY", "naive")])).unwrap();
+        assert!(config.calibrate);
+        assert_eq!(config.warmup, 4);
+        assert_eq!(config.strategy, Some(RotateStrategy::Naive));
+
+        let e = config.apply_env(env(&[("EVERYBIT_SEED", "-1")])).unwrap_err();
+        assert_eq!(e, "EVERYBIT_SEED: can't parse \"-1\"");
+        assert!(config.apply_env(env(&[("EVERYBIT_FORMAT", "xml")])).is_err());
+        let e = config.apply_env(env(&[("EVERYBIT_STRATEGY", "fast")])).unwrap_err();
+        assert!(e.starts_with("EVERYBIT_STRATEGY: unknown strategy \"fast\""));
+        assert!(config.apply_env(env(&[])).is_ok());
+    }
+
+    #[test]
+    fn test_save_calibration() {
+        let path = std::env::temp_dir().join(format!("everybit-{}", std::process::id())).join(CALIBRATION_FILE);
+        assert_eq!(load_calibration(&path), Ok(None));
+        let c = Calibration {
+            factor: 0.5,
+            gets_per_sec: 1e8,
+            sets_per_sec: 5e7,
+            rotate_bits_per_sec: 1e6,
+            copy_bytes_per_sec: 1e10,
+        };
+        save_calibration(&path, &c).unwrap();
+        let loaded = load_calibration(&path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded, Ok(Some(c)));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert!("JSON".parse::<Format>().is_err());
+    }
+}
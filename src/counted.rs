@@ -0,0 +1,115 @@
+// A BitArray that keeps its number of ones up to date as it is changed,
+// so count_ones is free for code that checks density after every small
+// mutation.
+use crate::bitarray::BitArray;
+use crate::observed::{Change, Observed, Observer};
+
+/// The ones count of an Observed array.  Most changes are counted by
+/// what they overwrote, found before they are made; rotate moves bits
+/// without changing how many are set, and invert turns the ones count
+/// into the zeros count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ones {
+    ones: usize,
+    /// The ones a change is about to overwrite.
+    overwritten: usize,
+}
+
+/// A BitArray with count_ones in O(1).
+pub type CountedBitArray = Observed<Ones>;
+
+impl Observer for Ones {
+    /// Counts the ones of bits once, then keeps track.
+    fn start(bits: &BitArray) -> Ones {
+        Ones { ones: bits.count_ones(), overwritten: 0 }
+    }
+
+    fn before(&mut self, bits: &BitArray, change: &Change) {
+        self.overwritten = match *change {
+            Change::Set { bit_index, .. } => bits.get(bit_index) as usize,
+            // O(bit_length / 64)
+            Change::FillRange { bit_offset, bit_length, .. } => bits.count_ones_range(bit_offset, bit_length),
+            Change::WriteBits { bit_offset, bit_width, .. } => {
+                bits.read_bits(bit_offset, bit_width).count_ones() as usize
+            }
+            Change::Fill { .. } | Change::Invert | Change::Rotate { .. } => 0,
+        };
+    }
+
+    fn after(&mut self, bits: &BitArray, change: &Change) {
+        let written = match *change {
+            Change::Set { val, .. } => val as usize,
+            Change::FillRange { bit_length, val, .. } => if val { bit_length } else { 0 },
+            Change::WriteBits { value, .. } => value.count_ones() as usize,
+            Change::Fill { val } => {
+                self.ones = if val { bits.get_bit_sz() } else { 0 };
+                return;
+            }
+            Change::Invert => {
+                self.ones = bits.get_bit_sz() - self.ones;
+                return;
+            }
+            Change::Rotate { .. } => return,
+        };
+        self.ones = self.ones - self.overwritten + written;
+    }
+}
+
+impl Observed<Ones> {
+    /// The number of ones, in O(1).
+    pub fn count_ones(&self) -> usize {
+        self.observer.ones
+    }
+
+    /// The fraction of bits that are one, 0 for an empty array.
+    pub fn density(&self) -> f64 {
+        if self.bits.get_bit_sz() == 0 { 0.0 } else { self.observer.ones as f64 / self.bits.get_bit_sz() as f64 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let mut c = CountedBitArray::from_bitarray(BitArray::from_str("10010110"));
+        assert_eq!(c.count_ones(), 4);
+        c.set(0, true);
+        c.set(0, true);
+        c.set(7, false);
+        assert_eq!(c.count_ones(), 4);
+        c.rotate(0, 8, 3);
+        c.fill_range(2, 4, true);
+        c.write_bits(0, 2, 0b10);
+        assert_eq!(c.count_ones(), c.bits().count_ones());
+        c.invert();
+        assert_eq!(c.count_ones(), c.bits().count_ones());
+        assert_eq!(c.density(), c.count_ones() as f64 / 8.0);
+        c.fill(true);
+        assert_eq!(c.count_ones(), 8);
+        assert_eq!(CountedBitArray::new(0).density(), 0.0);
+    }
+
+    #[test]
+    fn test_random_mutations() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut c = CountedBitArray::new(300);
+        for _ in 0 .. 2000 {
+            let i = rng.gen_range(0, 300);
+            let len = rng.gen_range(0, 300 - i + 1);
+            match rng.gen_range(0, 4) {
+                0 => c.set(i, rng.gen()),
+                1 => c.fill_range(i, len, rng.gen()),
+                2 => {
+                    let width = usize::min(len, 64);
+                    let value = if width == 0 { 0 } else { rng.gen::<u64>() >> (64 - width) };
+                    c.write_bits(i, width, value)
+                }
+                _ => c.invert(),
+            }
+            assert_eq!(c.count_ones(), c.bits().count_ones());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// A Bloom filter that supports removal: every slot is a small saturating
+// counter in a PackedIntVec instead of a single bit.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::packed::PackedIntVec;
+
+/// Counter width used by CountingBloomFilter::new.  Four bits overflow
+/// with negligible probability for filters sized by CountingBloomFilter::optimal.
+pub const DEFAULT_COUNTER_BITS: usize = 4;
+
+/// A counting Bloom filter over any Hash type.
+///
+/// Inserting bumps num_hashes counters and removing drops them again, so
+/// an item can be taken out without rebuilding the filter.  A counter that
+/// reaches its maximum sticks there: it can no longer tell how many items
+/// share it, so removals leave it alone rather than risk a false negative.
+/// Only remove items that were inserted; removing anything else can make
+/// the filter forget items it holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountingBloomFilter {
+    counters: PackedIntVec,
+    num_hashes: usize,
+}
+
+impl CountingBloomFilter {
+    /// num_counters counters of DEFAULT_COUNTER_BITS bits, each item
+    /// setting num_hashes of them.
+    pub fn new(num_counters: usize, num_hashes: usize) -> CountingBloomFilter {
+        CountingBloomFilter::with_counter_bits(num_counters, num_hashes, DEFAULT_COUNTER_BITS)
+    }
+
+    pub fn with_counter_bits(num_counters: usize,
+                             num_hashes: usize,
+                             counter_bits: usize) -> CountingBloomFilter {
+        assert!(num_counters > 0 && num_hashes > 0);
+        CountingBloomFilter { counters: PackedIntVec::zeros(counter_bits, num_counters), num_hashes }
+    }
+
+    /// A filter sized so that holding expected_items items gives a false
+    /// positive rate of about fp_rate.
+    pub fn optimal(expected_items: usize, fp_rate: f64) -> CountingBloomFilter {
+        assert!(fp_rate > 0.0 && fp_rate < 1.0);
+        let ln2 = std::f64::consts::LN_2;
+        let n = usize::max(1, expected_items) as f64;
+        let m = (-n * fp_rate.ln() / (ln2 * ln2)).ceil();
+        let k = (m / n * ln2).round();
+        CountingBloomFilter::new(m as usize, usize::max(1, k as usize))
+    }
+
+    pub fn num_counters(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    /// The counters the item maps to, by double hashing: h1 + i * h2.
+    fn slots<T: Hash + ?Sized>(&self, item: &T) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h = hasher.finish();
+        let (h1, h2) = (h & 0xffff_ffff, (h >> 32) | 1);
+        let m = self.counters.len() as u64;
+        (0 .. self.num_hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let max = self.counters.max_value();
+        for s in self.slots(item) {
+            let c = self.counters.get(s);
+            if c < max {
+                self.counters.set(s, c + 1);
+            }
+        }
+    }
+
+    /// Removes one copy of an inserted item.  Returns false, changing
+    /// nothing, if the item is definitely not in the filter.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        let max = self.counters.max_value();
+        for s in self.slots(item) {
+            let c = self.counters.get(s);
+            if c < max {
+                self.counters.set(s, c - 1);
+            }
+        }
+        true
+    }
+
+    /// false means the item is definitely absent; true means it is
+    /// probably present.
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.slots(item).all(|s| self.counters.get(s) > 0)
+    }
+
+    /// An upper bound on how many times the item was inserted (the
+    /// smallest of its counters).
+    pub fn estimate_count<T: Hash + ?Sized>(&self, item: &T) -> u64 {
+        self.slots(item).map(|s| self.counters.get(s)).min().unwrap()
+    }
+
+    /// Empties the filter.
+    pub fn clear(&mut self) {
+        for s in 0 .. self.counters.len() {
+            self.counters.set(s, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut f = CountingBloomFilter::new(1000, 4);
+        f.insert("apple");
+        f.insert("pear");
+        f.insert("apple");
+        assert!(f.contains("apple") && f.contains("pear"));
+        assert_eq!(f.estimate_count("apple"), 2);
+        assert!(f.remove("apple"));
+        assert!(f.contains("apple"));
+        assert!(f.remove("apple"));
+        assert!(!f.contains("apple"));
+        assert!(!f.remove("apple"));
+        assert!(f.contains("pear"));
+        f.clear();
+        assert!(!f.contains("pear"));
+    }
+
+    #[test]
+    fn test_no_false_negatives_after_removals() {
+        let mut f = CountingBloomFilter::optimal(1000, 0.01);
+        for i in 0 .. 1000 {
+            f.insert(&i);
+        }
+        for i in (0 .. 1000).step_by(2) {
+            assert!(f.remove(&i));
+        }
+        assert!((1 .. 1000).step_by(2).all(|i| f.contains(&i)));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut f = CountingBloomFilter::optimal(1000, 0.01);
+        for i in 0 .. 1000 {
+            f.insert(&i);
+        }
+        let fp = (1000 .. 11000).filter(|i| f.contains(i)).count();
+        assert!(fp < 300, "{} false positives in 10000", fp);
+    }
+
+    #[test]
+    fn test_saturation_sticks() {
+        let mut f = CountingBloomFilter::with_counter_bits(64, 1, 2);
+        for _ in 0 .. 5 {
+            f.insert(&7u32);
+        }
+        assert_eq!(f.estimate_count(&7u32), 3);
+        for _ in 0 .. 5 {
+            assert!(f.remove(&7u32));
+        }
+        assert!(f.contains(&7u32));
+    }
+}
@@ -0,0 +1,103 @@
+// Differential testing against bitvec's BitVec, a second oracle that
+// shares nothing with this crate.  The Vec<bool> model in oracle.rs is
+// simple enough to trust, but it is written by the same hands as the code
+// it checks; bitvec is not.  Behind the bitvec feature.
+use bitvec::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bitarray::BitArray;
+use crate::journal::Op;
+use crate::oracle::{random_op, Divergence};
+
+/// The same bit order as BitArray: bit i is bit i % 8 of byte i / 8.
+pub type Reference = BitVec<u8, Lsb0>;
+
+pub fn to_bitvec(ba: &BitArray) -> Reference {
+    ba.iter().collect()
+}
+
+/// Does op to bv with bitvec's own methods.
+pub fn apply(bv: &mut Reference, op: &Op) {
+    match *op {
+        Op::Set { bit_index, val } => bv.set(bit_index, val),
+        Op::Fill { val } => bv.fill(val),
+        Op::FillRange { bit_offset, bit_length, val } => {
+            bv[bit_offset .. bit_offset + bit_length].fill(val)
+        }
+        Op::Rotate { bit_offset, bit_length, bit_right_amount } => {
+            if bit_length > 0 {
+                let k = (bit_right_amount as i128).rem_euclid(bit_length as i128) as usize;
+                bv[bit_offset .. bit_offset + bit_length].rotate_right(k);
+            }
+        }
+    }
+}
+
+/// The first bit where ba and bv disagree, as in Model::diverges_at.
+/// count_ones is compared too, which BitArray does a word at a time.
+pub fn diverges_at(ba: &BitArray, bv: &Reference) -> Option<usize> {
+    let n = usize::min(bv.len(), ba.get_bit_sz());
+    if let Some(i) = (0 .. n).find(|&i| ba.get(i) != bv[i]) {
+        return Some(i);
+    }
+    if bv.len() != ba.get_bit_sz() || ba.count_ones() != bv.count_ones() {
+        return Some(n);
+    }
+    None
+}
+
+/// Does ops to a copy of start and to a BitVec of it, comparing the two
+/// after every operation.
+pub fn check_ops(start: &BitArray, ops: &[Op]) -> Result<BitArray, Divergence> {
+    let mut ba = start.clone();
+    let mut bv = to_bitvec(start);
+    for (step, op) in ops.iter().enumerate() {
+        op.apply(&mut ba);
+        apply(&mut bv, op);
+        if let Some(bit_index) = diverges_at(&ba, &bv) {
+            return Err(Divergence { step, op: op.clone(), bit_index });
+        }
+    }
+    Ok(ba)
+}
+
+/// oracle::run_random with BitVec as the oracle.
+pub fn run_random(bit_sz: usize, steps: usize, seed: u64) -> Result<(), Divergence> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut start = BitArray::new(bit_sz);
+    start.randfill_seeded(rng.gen());
+    let ops: Vec<Op> = (0 .. steps).map(|_| random_op(&mut rng, bit_sz)).collect();
+    check_ops(&start, &ops).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::ORACLE_SIZES;
+
+    #[test]
+    fn test_same_layout() {
+        let ba = BitArray::from_str("1111000010010110");
+        assert_eq!(to_bitvec(&ba).as_raw_slice(), &[0x96, 0xf0]);
+    }
+
+    #[test]
+    fn test_agrees_with_model() {
+        let start = BitArray::from_str("10010110");
+        let op = Op::Rotate { bit_offset: 2, bit_length: 5, bit_right_amount: 2 };
+        let mut bv = to_bitvec(&start);
+        apply(&mut bv, &op);
+        assert_eq!(bv, to_bitvec(&BitArray::from_str("11010010")));
+        assert_eq!(check_ops(&start, &[op]).unwrap().show(), "11010010");
+    }
+
+    #[test]
+    fn test_random() {
+        for &bit_sz in ORACLE_SIZES {
+            if let Err(d) = run_random(bit_sz, 300, 468 + bit_sz as u64) {
+                panic!("{} bits: {}", bit_sz, d);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// A double-ended queue of bits: a ring buffer over a BitArray, for
+// sliding windows that push bits in at one end and drop them at the other.
+use crate::bitarray::{BitArray, BitSlice};
+
+/// A growable ring buffer of bits.  Element i lives at bit
+/// (head + i) % capacity of the buffer, so pushes and pops at either end
+/// are O(1) and never move the other bits.
+#[derive(Debug, Clone)]
+pub struct BitDeque {
+    bits: BitArray,
+    head: usize,
+    len: usize,
+}
+
+impl BitDeque {
+    pub fn new() -> BitDeque {
+        BitDeque::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> BitDeque {
+        BitDeque { bits: BitArray::new(capacity), head: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bits.get_bit_sz()
+    }
+
+    fn physical(&self, i: usize) -> usize {
+        (self.head + i) % self.capacity()
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len);
+        self.bits.get(self.physical(i))
+    }
+
+    pub fn set(&mut self, i: usize, val: bool) {
+        assert!(i < self.len);
+        let p = self.physical(i);
+        self.bits.set(p, val)
+    }
+
+    /// Doubles the buffer (minimum 64 bits), unwrapping the elements to
+    /// the start of the new one.
+    fn grow(&mut self) {
+        let new_capacity = usize::max(64, self.capacity() * 2);
+        let mut bits = BitArray::new(new_capacity);
+        let (a, b) = self.as_slices();
+        let mut done = 0;
+        for s in [a, b].iter() {
+            let mut k = 0;
+            while k < s.len() {
+                let width = usize::min(64, s.len() - k);
+                bits.write_bits(done, width, s.read_bits(k, width));
+                done += width;
+                k += width;
+            }
+        }
+        self.bits = bits;
+        self.head = 0;
+    }
+
+    pub fn push_back(&mut self, val: bool) {
+        if self.len == self.capacity() {
+            self.grow();
+        }
+        self.len += 1;
+        self.set(self.len - 1, val);
+    }
+
+    pub fn push_front(&mut self, val: bool) {
+        if self.len == self.capacity() {
+            self.grow();
+        }
+        self.head = (self.head + self.capacity() - 1) % self.capacity();
+        self.len += 1;
+        self.set(0, val);
+    }
+
+    pub fn pop_back(&mut self) -> Option<bool> {
+        if self.len == 0 {
+            return None;
+        }
+        let val = self.get(self.len - 1);
+        self.len -= 1;
+        Some(val)
+    }
+
+    pub fn pop_front(&mut self) -> Option<bool> {
+        if self.len == 0 {
+            return None;
+        }
+        let val = self.get(0);
+        self.head = (self.head + 1) % self.capacity();
+        self.len -= 1;
+        Some(val)
+    }
+
+    pub fn front(&self) -> Option<bool> {
+        if self.len == 0 { None } else { Some(self.get(0)) }
+    }
+
+    pub fn back(&self) -> Option<bool> {
+        if self.len == 0 { None } else { Some(self.get(self.len - 1)) }
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// The elements as two slices of the buffer, front part first; the
+    /// second is empty unless the elements wrap around its end.
+    pub fn as_slices(&self) -> (BitSlice<'_>, BitSlice<'_>) {
+        let cap = self.capacity();
+        if self.head + self.len <= cap {
+            (self.bits.slice(self.head .. self.head + self.len), self.bits.slice(0 .. 0))
+        } else {
+            (self.bits.slice(self.head .. cap), self.bits.slice(0 .. self.head + self.len - cap))
+        }
+    }
+
+    /// Rotates the buffer so the elements are one contiguous slice.
+    pub fn make_contiguous(&mut self) -> BitSlice<'_> {
+        if self.head != 0 {
+            let cap = self.capacity();
+            self.bits.rotate(0, cap, -(self.head as isize));
+            self.head = 0;
+        }
+        self.bits.slice(0 .. self.len)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = bool> + '_ {
+        (0 .. self.len).map(move |i| self.get(i))
+    }
+}
+
+impl Default for BitDeque {
+    fn default() -> BitDeque {
+        BitDeque::new()
+    }
+}
+
+impl PartialEq for BitDeque {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+impl Eq for BitDeque {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_push_pop() {
+        let mut d = BitDeque::new();
+        assert_eq!(d.pop_front(), None);
+        d.push_back(true);
+        d.push_back(false);
+        d.push_front(false);
+        assert_eq!(d.iter().collect::<Vec<_>>(), vec![false, true, false]);
+        assert_eq!((d.front(), d.back()), (Some(false), Some(false)));
+        assert_eq!(d.pop_front(), Some(false));
+        assert_eq!(d.pop_back(), Some(false));
+        assert_eq!(d.pop_back(), Some(true));
+        assert!(d.is_empty());
+    }
+
+    #[test]
+    fn test_matches_vecdeque() {
+        let mut d = BitDeque::new();
+        let mut v = VecDeque::new();
+        for i in 0 .. 2000usize {
+            let val = (i * 7) % 3 == 0;
+            match i % 5 {
+                0 | 1 => { d.push_back(val); v.push_back(val); }
+                2 => { d.push_front(val); v.push_front(val); }
+                3 => assert_eq!(d.pop_front(), v.pop_front()),
+                _ => assert_eq!(d.pop_back(), v.pop_back()),
+            }
+        }
+        assert_eq!(d.len(), v.len());
+        assert!(d.iter().eq(v.iter().cloned()));
+    }
+
+    #[test]
+    fn test_slices() {
+        // a sliding window that wraps around the buffer
+        let mut d = BitDeque::with_capacity(64);
+        for i in 0 .. 100 {
+            d.push_back(i % 2 == 0);
+            if d.len() > 50 {
+                d.pop_front();
+            }
+        }
+        let (a, b) = d.as_slices();
+        assert_eq!(a.len() + b.len(), 50);
+        assert!(!b.is_empty());
+        let expected: Vec<bool> = d.iter().collect();
+        let s = d.make_contiguous();
+        assert_eq!(s.len(), 50);
+        assert!((0 .. 50).all(|i| s.get(i) == expected[i]));
+        assert_eq!(d.as_slices().1.len(), 0);
+    }
+}
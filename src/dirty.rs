@@ -0,0 +1,124 @@
+// A BitArray that remembers which 64-bit words have changed since the
+// last checkpoint, so code keeping a copy of a huge bitmap elsewhere (a
+// replica, a file, a GPU buffer) can send only the changed regions.
+use std::ops::Range;
+
+use crate::bitarray::BitArray;
+use crate::observed::{Change, Observed, Observer};
+
+/// The granularity of the tracking, in bits.
+pub const WORD_BITS: usize = 64;
+
+/// The words of an Observed array changed since the last checkpoint, a
+/// bit per word.  A change marks every word its span overlaps, whether
+/// or not the bits there really differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyWords {
+    // bit i is set when word i changed since the last take_dirty_ranges
+    dirty: BitArray,
+}
+
+/// A BitArray that can say which of its words changed since it last
+/// asked.
+pub type DirtyBitArray = Observed<DirtyWords>;
+
+fn word_count(bit_sz: usize) -> usize {
+    bit_sz.div_ceil(WORD_BITS)
+}
+
+impl Observer for DirtyWords {
+    /// Starts tracking bits, with nothing dirty yet.
+    fn start(bits: &BitArray) -> DirtyWords {
+        DirtyWords { dirty: BitArray::new(word_count(bits.get_bit_sz())) }
+    }
+
+    fn after(&mut self, bits: &BitArray, change: &Change) {
+        let span = change.span(bits.get_bit_sz());
+        if !span.is_empty() {
+            let first = span.start / WORD_BITS;
+            let last = (span.end - 1) / WORD_BITS;
+            self.dirty.fill_range(first, last - first + 1, true);
+        }
+    }
+}
+
+impl Observed<DirtyWords> {
+    /// The number of words; the last one is short unless the size is a
+    /// multiple of WORD_BITS.
+    pub fn word_count(&self) -> usize {
+        self.observer.dirty.get_bit_sz()
+    }
+
+    /// Word index, bit index * WORD_BITS of the array in its lowest bit.
+    pub fn word(&self, index: usize) -> u64 {
+        let start = index * WORD_BITS;
+        self.bits.read_bits(start, usize::min(WORD_BITS, self.bits.get_bit_sz() - start))
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.observer.dirty.count_ones() > 0
+    }
+
+    /// The changed words since the last call, or since tracking began, as
+    /// sorted, non-adjacent ranges of word indices; then marks everything
+    /// clean.
+    pub fn take_dirty_ranges(&mut self) -> Vec<Range<usize>> {
+        let dirty = &mut self.observer.dirty;
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for word in (0 .. dirty.get_bit_sz()).filter(|&w| dirty.get(w)) {
+            match ranges.last_mut() {
+                Some(last) if last.end == word => last.end += 1,
+                _ => ranges.push(word .. word + 1),
+            }
+        }
+        dirty.fill(false);
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_dirty_ranges() {
+        let mut d = DirtyBitArray::new(1000);
+        assert_eq!(d.word_count(), 16);
+        assert!(!d.is_dirty());
+        d.set(3, true);
+        d.set(70, true);
+        d.fill_range(300, 200, true);
+        d.write_bits(990, 10, 0x3ff);
+        assert!(d.is_dirty());
+        assert_eq!(d.take_dirty_ranges(), vec![0 .. 2, 4 .. 8, 15 .. 16]);
+        assert!(d.take_dirty_ranges().is_empty());
+        d.rotate(100, 64, 1);
+        assert_eq!(d.take_dirty_ranges(), vec![1 .. 3]);
+        d.invert();
+        assert_eq!(d.take_dirty_ranges(), vec![0 .. 16]);
+        d.fill_range(5, 0, false);
+        assert!(!d.is_dirty());
+    }
+
+    #[test]
+    fn test_sync() {
+        // a replica that only ever receives the dirty words ends up equal
+        let mut d = DirtyBitArray::from_bitarray(BitArray::from_str("1011"));
+        let mut replica = d.bits().clone();
+        let mut big = DirtyBitArray::new(200);
+        let mut big_replica = big.bits().clone();
+        d.set(1, false);
+        big.set(150, true);
+        big.fill_range(10, 20, true);
+        for (src, dst) in [(&mut d, &mut replica), (&mut big, &mut big_replica)] {
+            for range in src.take_dirty_ranges() {
+                for w in range {
+                    let start = w * WORD_BITS;
+                    let width = usize::min(WORD_BITS, dst.get_bit_sz() - start);
+                    dst.write_bits(start, width, src.word(w));
+                }
+            }
+            assert_eq!(&*dst, src.bits());
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// DNA sequences packed two bits per base on top of BitArray.
+//
+// The codes are chosen so that complementing a base is flipping both of
+// its bits (A = 00 <-> T = 11, C = 01 <-> G = 10), which turns complement
+// into a whole-array invert.
+use std::fmt;
+
+use crate::bitarray::BitArray;
+
+/// A single nucleotide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    A = 0b00,
+    C = 0b01,
+    G = 0b10,
+    T = 0b11,
+}
+
+impl Base {
+    pub fn from_char(c: char) -> Option<Base> {
+        match c {
+            'A' | 'a' => Some(Base::A),
+            'C' | 'c' => Some(Base::C),
+            'G' | 'g' => Some(Base::G),
+            'T' | 't' => Some(Base::T),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Base::A => 'A',
+            Base::C => 'C',
+            Base::G => 'G',
+            Base::T => 'T',
+        }
+    }
+
+    fn from_code(code: u64) -> Base {
+        match code {
+            0b00 => Base::A,
+            0b01 => Base::C,
+            0b10 => Base::G,
+            _ => Base::T,
+        }
+    }
+
+    pub fn complement(self) -> Base {
+        Base::from_code(self as u64 ^ 0b11)
+    }
+}
+
+/// Returned when a sequence contains something other than A, C, G or T.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidBase {
+    /// Character position of the offending character.
+    pub index: usize,
+    pub found: char,
+}
+
+impl fmt::Display for InvalidBase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid base {:?} at position {}", self.found, self.index)
+    }
+}
+
+impl std::error::Error for InvalidBase {}
+
+/// A DNA sequence, base i stored in bits [2i, 2i + 2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnaSeq {
+    bits: BitArray,
+}
+
+impl DnaSeq {
+    /// Packs a string of A/C/G/T (either case).
+    pub fn encode(seq: &str) -> Result<DnaSeq, InvalidBase> {
+        let mut bits = BitArray::new(seq.chars().count() * 2);
+        for (i, c) in seq.chars().enumerate() {
+            let base = Base::from_char(c).ok_or(InvalidBase { index: i, found: c })?;
+            bits.write_bits(2 * i, 2, base as u64);
+        }
+        Ok(DnaSeq { bits })
+    }
+
+    /// Unpacks the sequence back into upper case letters.
+    pub fn decode(&self) -> String {
+        (0 .. self.len()).map(|i| self.get(i).to_char()).collect()
+    }
+
+    /// The number of bases.
+    pub fn len(&self) -> usize {
+        self.bits.get_bit_sz() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> Base {
+        Base::from_code(self.bits.read_bits(2 * i, 2))
+    }
+
+    pub fn set(&mut self, i: usize, base: Base) {
+        self.bits.write_bits(2 * i, 2, base as u64)
+    }
+
+    /// The complement of every base, in place.
+    pub fn complement(&mut self) {
+        self.bits.invert();
+    }
+
+    /// Reverses the order of the bases and complements them, in place.
+    pub fn reverse_complement(&mut self) {
+        // Reversing all the bits reverses the bases but also swaps the two
+        // bits inside each base, so swap every pair back a word at a time
+        // before complementing.
+        let n = self.bits.get_bit_sz();
+        self.bits.reverse_range(0, n);
+        let mut offset = 0;
+        while offset < n {
+            let width = usize::min(64, n - offset);
+            let w = self.bits.read_bits(offset, width);
+            let swapped = ((w >> 1) & 0x5555_5555_5555_5555) | ((w & 0x5555_5555_5555_5555) << 1);
+            self.bits.write_bits(offset, width, swapped);
+            offset += width;
+        }
+        self.complement();
+    }
+
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+}
+
+impl fmt::Display for DnaSeq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.decode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let seq = DnaSeq::encode("GATTACAcgt").unwrap();
+        assert_eq!(seq.len(), 10);
+        assert_eq!(seq.get(0), Base::G);
+        assert_eq!(seq.decode(), "GATTACACGT");
+        assert_eq!(seq.as_bitarray().read_bits(0, 6), 0b11_00_10);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(DnaSeq::encode("ACNT"), Err(InvalidBase { index: 2, found: 'N' }));
+    }
+
+    #[test]
+    fn test_complement() {
+        let mut seq = DnaSeq::encode("GATTACA").unwrap();
+        seq.complement();
+        assert_eq!(seq.decode(), "CTAATGT");
+        assert_eq!(Base::C.complement(), Base::G);
+    }
+
+    #[test]
+    fn test_reverse_complement() {
+        let mut seq = DnaSeq::encode("GATTACA").unwrap();
+        seq.reverse_complement();
+        assert_eq!(seq.decode(), "TGTAATC");
+
+        // long enough to go through several words
+        let s: String = "ACGGTCATTGCA".repeat(20);
+        let expected: String = s.chars().rev().map(|c| Base::from_char(c).unwrap()
+                                                   .complement().to_char()).collect();
+        let mut seq = DnaSeq::encode(&s).unwrap();
+        seq.reverse_complement();
+        assert_eq!(seq.decode(), expected);
+        seq.reverse_complement();
+        assert_eq!(seq.decode(), s);
+    }
+}
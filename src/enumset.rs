@@ -0,0 +1,238 @@
+// Sets of the variants of a field-less enum, one bit per variant, so flag
+// sets are typed instead of raw bit indices.
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+
+use crate::bitarray::BitArray;
+
+/// An enum whose variants can be numbered 0 .. COUNT.  Use the bit_enum!
+/// macro rather than implementing it by hand.
+pub trait BitEnum: Copy {
+    /// The number of variants.
+    const COUNT: usize;
+
+    /// The variant's number, below COUNT.
+    fn to_index(self) -> usize;
+
+    /// The variant numbered index.  Panics if index >= COUNT.
+    fn from_index(index: usize) -> Self;
+}
+
+/// Declares a field-less enum and implements BitEnum for it, numbering the
+/// variants in declaration order.
+///
+/// Example:
+/// ```
+/// use bitarray::bit_enum;
+/// use bitarray::enumset::EnumBitSet;
+///
+/// bit_enum! {
+///     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///     pub enum Perm { Read, Write, Exec }
+/// }
+///
+/// let mut perms = EnumBitSet::new();
+/// perms.insert(Perm::Read);
+/// perms.insert(Perm::Exec);
+/// assert!(perms.contains(Perm::Exec));
+/// assert_eq!(perms.iter().collect::<Vec<_>>(), vec![Perm::Read, Perm::Exec]);
+/// ```
+#[macro_export]
+macro_rules! bit_enum {
+    ($(#[$meta:meta])*
+     $vis:vis enum $name:ident { $($(#[$variant_meta:meta])* $variant:ident),* $(,)? }) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant),*
+        }
+
+        impl $crate::enumset::BitEnum for $name {
+            const COUNT: usize = [$($name::$variant),*].len();
+
+            fn to_index(self) -> usize {
+                self as usize
+            }
+
+            fn from_index(index: usize) -> $name {
+                [$($name::$variant),*][index]
+            }
+        }
+    };
+}
+
+/// A set of variants of E, stored as an E::COUNT bit BitArray.
+pub struct EnumBitSet<E: BitEnum> {
+    bits: BitArray,
+    _marker: PhantomData<E>,
+}
+
+impl<E: BitEnum> EnumBitSet<E> {
+    /// The empty set.
+    pub fn new() -> EnumBitSet<E> {
+        EnumBitSet { bits: BitArray::new(E::COUNT), _marker: PhantomData }
+    }
+
+    /// The set of every variant.
+    pub fn all() -> EnumBitSet<E> {
+        let mut set = EnumBitSet::new();
+        set.bits.fill(true);
+        set
+    }
+
+    /// Adds e, returning whether it was newly added.
+    pub fn insert(&mut self, e: E) -> bool {
+        let i = e.to_index();
+        let was = self.bits.get(i);
+        self.bits.set(i, true);
+        !was
+    }
+
+    /// Removes e, returning whether it was there.
+    pub fn remove(&mut self, e: E) -> bool {
+        let i = e.to_index();
+        let was = self.bits.get(i);
+        self.bits.set(i, false);
+        was
+    }
+
+    pub fn contains(&self, e: E) -> bool {
+        self.bits.get(e.to_index())
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(false)
+    }
+
+    /// The members, in declaration order.
+    pub fn iter(&self) -> impl Iterator<Item = E> + '_ {
+        self.bits.iter_ones().map(E::from_index)
+    }
+
+    pub fn union(&self, other: &EnumBitSet<E>) -> EnumBitSet<E> {
+        let mut out = self.clone();
+        out.bits |= &other.bits;
+        out
+    }
+
+    pub fn intersection(&self, other: &EnumBitSet<E>) -> EnumBitSet<E> {
+        let mut out = self.clone();
+        out.bits &= &other.bits;
+        out
+    }
+
+    /// The members of self that are not in other.
+    pub fn difference(&self, other: &EnumBitSet<E>) -> EnumBitSet<E> {
+        let mut out = other.clone();
+        out.bits.invert();
+        out.bits &= &self.bits;
+        out
+    }
+
+    pub fn is_subset(&self, other: &EnumBitSet<E>) -> bool {
+        self.difference(other).is_empty()
+    }
+
+    /// The underlying bits, bit i for the variant numbered i.
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+}
+
+// Written out rather than derived, which would demand E: Clone etc. even
+// though no E is stored.
+impl<E: BitEnum> Clone for EnumBitSet<E> {
+    fn clone(&self) -> EnumBitSet<E> {
+        EnumBitSet { bits: self.bits.clone(), _marker: PhantomData }
+    }
+}
+
+impl<E: BitEnum> Default for EnumBitSet<E> {
+    fn default() -> EnumBitSet<E> {
+        EnumBitSet::new()
+    }
+}
+
+impl<E: BitEnum> PartialEq for EnumBitSet<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+impl<E: BitEnum> Eq for EnumBitSet<E> {}
+
+impl<E: BitEnum + fmt::Debug> fmt::Debug for EnumBitSet<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<E: BitEnum> FromIterator<E> for EnumBitSet<E> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> EnumBitSet<E> {
+        let mut set = EnumBitSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<E: BitEnum> Extend<E> for EnumBitSet<E> {
+    fn extend<I: IntoIterator<Item = E>>(&mut self, iter: I) {
+        for e in iter {
+            self.insert(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    bit_enum! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Day { Mon, Tue, Wed, Thu, Fri, Sat, Sun }
+    }
+
+    #[test]
+    fn test_bit_enum() {
+        assert_eq!(Day::COUNT, 7);
+        assert_eq!(Day::Wed.to_index(), 2);
+        assert_eq!(Day::from_index(6), Day::Sun);
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut set = EnumBitSet::new();
+        assert!(set.is_empty());
+        assert!(set.insert(Day::Tue));
+        assert!(!set.insert(Day::Tue));
+        set.insert(Day::Sun);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(Day::Sun) && !set.contains(Day::Mon));
+        assert!(set.remove(Day::Tue));
+        assert!(!set.remove(Day::Tue));
+        assert_eq!(format!("{:?}", set), "{Sun}");
+        set.clear();
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_set_ops() {
+        let weekend: EnumBitSet<Day> = [Day::Sat, Day::Sun].iter().cloned().collect();
+        let busy: EnumBitSet<Day> = vec![Day::Mon, Day::Sat].into_iter().collect();
+        let all = EnumBitSet::<Day>::all();
+        assert_eq!(all.len(), 7);
+        assert_eq!(weekend.union(&busy).iter().collect::<Vec<_>>(),
+                   vec![Day::Mon, Day::Sat, Day::Sun]);
+        assert_eq!(weekend.intersection(&busy).iter().collect::<Vec<_>>(), vec![Day::Sat]);
+        assert_eq!(weekend.difference(&busy).iter().collect::<Vec<_>>(), vec![Day::Sun]);
+        assert!(weekend.is_subset(&all) && !weekend.is_subset(&busy));
+        assert_eq!(all.difference(&all), EnumBitSet::default());
+    }
+}
@@ -0,0 +1,152 @@
+// Exhaustive checking of rotate on small arrays (`everybit exhaustive`):
+// every size up to a bound, every value of that size, every subarray and
+// every rotation amount from one full turn left to one full turn right,
+// against a reference computed with shifts on a u64.  Off-by-one bugs at
+// the ends of a range or a byte have nowhere to hide.
+//
+// A Counterexample holds three arrays, each with its words inline, which
+// clippy finds too big for an Err; but there is at most one, at the end of
+// a search, so it is returned as it is rather than boxed.
+#![allow(clippy::result_large_err)]
+use std::fmt;
+
+use crate::bitarray::BitArray;
+
+/// The largest max_bits check_rotations accepts; 2^32 values per size is
+/// already far beyond patience.
+pub const MAX_EXHAUSTIVE_BITS: usize = 32;
+
+/// A rotation where the implementation and the reference disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    pub before: BitArray,
+    pub bit_offset: usize,
+    pub bit_length: usize,
+    pub bit_right_amount: isize,
+    pub expected: BitArray,
+    pub got: BitArray,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rotate({}, {}, {}): expected {} got {}", self.before.show(),
+               self.bit_offset, self.bit_length, self.bit_right_amount,
+               self.expected.show(), self.got.show())
+    }
+}
+
+impl std::error::Error for Counterexample {}
+
+fn low_mask(width: usize) -> u64 {
+    if width == 64 { u64::MAX } else { (1 << width) - 1 }
+}
+
+/// The reference: value (an array of at most 32 bits) with the field
+/// [bit_offset, bit_offset + bit_length) rotated right by amount.
+pub fn rotate_reference(value: u64, bit_offset: usize, bit_length: usize, amount: isize) -> u64 {
+    assert!(bit_offset + bit_length <= MAX_EXHAUSTIVE_BITS);
+    if bit_length == 0 {
+        return value;
+    }
+    let mask = low_mask(bit_length);
+    let field = (value >> bit_offset) & mask;
+    let k = (amount as i128).rem_euclid(bit_length as i128) as usize;
+    let rotated = if k == 0 { field } else { ((field << k) | (field >> (bit_length - k))) & mask };
+    (value & !(mask << bit_offset)) | (rotated << bit_offset)
+}
+
+/// The amounts tried for a subarray of bit_length bits.
+fn amounts(bit_length: usize) -> impl Iterator<Item = isize> {
+    let len = bit_length as isize;
+    (-len ..= len).chain(vec![isize::MIN, isize::MAX])
+}
+
+/// Checks rotate against rotate_reference on every case of bit_sz bits
+/// and returns how many there were.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(rotate), err(Display)))]
+pub fn check_size_with<F>(bit_sz: usize, rotate: F) -> Result<u64, Counterexample>
+where F: Fn(&mut BitArray, usize, usize, isize) {
+    assert!(bit_sz <= MAX_EXHAUSTIVE_BITS);
+    let mut cases = 0;
+    let mut ba = BitArray::new(bit_sz);
+    for value in 0 .. 1u64 << bit_sz {
+        for bit_offset in 0 ..= bit_sz {
+            for bit_length in 0 ..= bit_sz - bit_offset {
+                for amount in amounts(bit_length) {
+                    ba.write_bits(0, bit_sz, value);
+                    rotate(&mut ba, bit_offset, bit_length, amount);
+                    let expected = rotate_reference(value, bit_offset, bit_length, amount);
+                    if ba.read_bits(0, bit_sz) != expected {
+                        let mut before = BitArray::new(bit_sz);
+                        before.write_bits(0, bit_sz, value);
+                        let mut want = BitArray::new(bit_sz);
+                        want.write_bits(0, bit_sz, expected);
+                        return Err(Counterexample {
+                            before,
+                            bit_offset,
+                            bit_length,
+                            bit_right_amount: amount,
+                            expected: want,
+                            got: ba,
+                        });
+                    }
+                    cases += 1;
+                }
+            }
+        }
+    }
+    Ok(cases)
+}
+
+/// check_size_with for every size from 0 to max_bits, calling done with
+/// each size and its number of cases as it is finished.
+pub fn check_rotations_with<F, D>(max_bits: usize, rotate: F, mut done: D) -> Result<u64, Counterexample>
+where F: Fn(&mut BitArray, usize, usize, isize), D: FnMut(usize, u64) {
+    let mut total = 0;
+    for bit_sz in 0 ..= max_bits {
+        let cases = check_size_with(bit_sz, &rotate)?;
+        done(bit_sz, cases);
+        total += cases;
+    }
+    Ok(total)
+}
+
+/// Checks BitArray::rotate on every case up to max_bits bits.
+pub fn check_rotations(max_bits: usize) -> Result<u64, Counterexample> {
+    check_rotations_with(max_bits, |ba, o, l, a| ba.rotate(o, l, a), |_, _| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference() {
+        assert_eq!(rotate_reference(0b10010110, 0, 8, 1), 0b00101101);
+        assert_eq!(rotate_reference(0b10010110, 0, 8, -1), 0b01001011);
+        assert_eq!(rotate_reference(0b10010110, 2, 5, 2), 0b11010010);
+        assert_eq!(rotate_reference(0b10010110, 2, 5, -3), 0b11010010);
+        assert_eq!(rotate_reference(0b10010110, 3, 0, 9), 0b10010110);
+    }
+
+    #[test]
+    fn test_check_rotations() {
+        let mut sizes = vec![];
+        let total = check_rotations_with(8, |ba, o, l, a| ba.rotate(o, l, a),
+                                         |n, _| sizes.push(n)).unwrap();
+        assert_eq!(sizes, (0 ..= 8).collect::<Vec<_>>());
+        // the empty array has one empty subarray, rotated by 0 and the extremes
+        assert_eq!(check_size_with(0, |ba, o, l, a| ba.rotate(o, l, a)), Ok(3));
+        assert!(total > 1 << 16);
+    }
+
+    #[test]
+    fn test_finds_off_by_one() {
+        // a rotate that forgets the last bit of the subarray
+        let buggy = |ba: &mut BitArray, o: usize, l: usize, a: isize| {
+            ba.rotate(o, l.saturating_sub(1), a)
+        };
+        let c = check_rotations_with(4, buggy, |_, _| ()).unwrap_err();
+        assert_eq!(c.to_string(), "01 rotate(0, 2, -1): expected 10 got 01");
+    }
+}
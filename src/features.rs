@@ -0,0 +1,212 @@
+// What `everybit features` prints: the CPU features that matter to the
+// kernels, as detected at run time, the target features the binary was
+// built with, and which implementation each operation uses as a result.
+// Attach it to a performance bug report; two machines that disagree on
+// the dispatch lines are running different code.
+use std::fmt;
+
+#[cfg(feature = "harness")]
+use serde::Serialize;
+
+/// The CPU's support for each feature, None where it can't exist on
+/// this architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "harness", derive(Serialize))]
+pub struct CpuFeatures {
+    pub popcnt: Option<bool>,
+    /// SSSE3, which has the byte shuffle the simd feature reverses with.
+    pub ssse3: Option<bool>,
+    /// BMI2, which has PEXT and PDEP.
+    pub bmi2: Option<bool>,
+    pub avx2: Option<bool>,
+    pub neon: Option<bool>,
+}
+
+impl CpuFeatures {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn detect() -> CpuFeatures {
+        CpuFeatures {
+            popcnt: Some(is_x86_feature_detected!("popcnt")),
+            ssse3: Some(is_x86_feature_detected!("ssse3")),
+            bmi2: Some(is_x86_feature_detected!("bmi2")),
+            avx2: Some(is_x86_feature_detected!("avx2")),
+            neon: None,
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn detect() -> CpuFeatures {
+        CpuFeatures {
+            popcnt: None,
+            ssse3: None,
+            bmi2: None,
+            avx2: None,
+            neon: Some(std::arch::is_aarch64_feature_detected!("neon")),
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn detect() -> CpuFeatures {
+        CpuFeatures { popcnt: None, ssse3: None, bmi2: None, avx2: None, neon: None }
+    }
+}
+
+/// The implementation one operation dispatches to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "harness", derive(Serialize))]
+pub struct Kernel {
+    pub op: String,
+    pub implementation: String,
+}
+
+/// Everything `everybit features` reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "harness", derive(Serialize))]
+pub struct FeatureReport {
+    pub cpu: CpuFeatures,
+    /// The target features the binary was compiled with, of those the
+    /// kernels can use.
+    pub target_features: Vec<String>,
+    /// The cargo features the binary was built with.
+    pub cargo_features: Vec<String>,
+    pub kernels: Vec<Kernel>,
+}
+
+/// The kernels cpu and this build lead to.
+fn kernels(cpu: &CpuFeatures) -> Vec<Kernel> {
+    let kernel = |op: &str, implementation: String| Kernel { op: op.to_string(), implementation };
+    let popcount = if cfg!(target_feature = "popcnt") {
+        "POPCNT instruction".to_string()
+    } else if cfg!(target_arch = "aarch64") {
+        "NEON CNT instruction".to_string()
+    } else if cpu.popcnt == Some(true) {
+        "software popcount (the CPU has POPCNT: build with -C target-cpu=native)".to_string()
+    } else {
+        "software popcount".to_string()
+    };
+    let vector = if cfg!(target_feature = "avx2") {
+        "AVX2"
+    } else if cfg!(target_feature = "neon") {
+        "NEON"
+    } else if cfg!(target_feature = "sse2") {
+        "SSE2"
+    } else {
+        "scalar"
+    };
+    let threads = if cfg!(feature = "rayon") { ", on all cores for large arrays" } else { "" };
+    let long_threads = if cfg!(feature = "rayon") { ", on all cores for very long ranges" } else { "" };
+    let bulk = format!("word loop, compiled to {} code{}", vector, threads);
+    let gather = if cfg!(target_arch = "x86_64") && cpu.bmi2 == Some(true) {
+        if crate::bitarray::CHECKED {
+            "a loop over the mask's ones (BMI2 is there, but CHECKED builds don't use it)"
+        } else {
+            "PEXT/PDEP instructions"
+        }
+    } else {
+        "a loop over the mask's ones"
+    };
+    let simd = cfg!(all(feature = "simd", target_arch = "x86_64"));
+    let reverse = if simd && !crate::bitarray::CHECKED && cpu.ssse3 == Some(true) {
+        "SSSE3 shuffles, 256 bits from each end at a time"
+    } else if simd && cpu.ssse3 == Some(true) {
+        "64 bits from each end at a time (SSSE3 is there, but CHECKED builds don't use it)"
+    } else if cpu.ssse3 == Some(true) {
+        "64 bits from each end at a time (the CPU has SSSE3: build with --features simd)"
+    } else {
+        "64 bits from each end at a time"
+    };
+    vec![
+        kernel("count_ones", popcount),
+        kernel("and/or/xor/invert/fill", bulk),
+        kernel("read_bits/write_bits", "one or two word shifts".to_string()),
+        kernel("reverse_range", format!("{}{}", reverse, long_threads)),
+        kernel("extract/deposit", gather.to_string()),
+        kernel("rotate", format!("a u128 rotate up to 128 bits, block swaps and word shifts past that{}; \
+                                  see --explain", long_threads)),
+    ]
+}
+
+/// The report for this machine and binary.
+pub fn report() -> FeatureReport {
+    let cpu = CpuFeatures::detect();
+    let mut target_features = vec![];
+    macro_rules! target_features {
+        ($($f:tt),*) => { $(if cfg!(target_feature = $f) { target_features.push($f.to_string()); })* };
+    }
+    target_features!("sse2", "ssse3", "popcnt", "avx2", "bmi2", "neon");
+    let mut cargo_features = vec![];
+    macro_rules! cargo_features {
+        ($($f:tt),*) => { $(if cfg!(feature = $f) { cargo_features.push($f.to_string()); })* };
+    }
+    cargo_features!("cli", "harness", "affinity", "rayon", "simd", "counters", "tracing", "paranoid",
+                    "checked", "hugepages", "dna", "bitvec", "proptest", "quickcheck");
+    FeatureReport { kernels: kernels(&cpu), cpu, target_features, cargo_features }
+}
+
+impl fmt::Display for FeatureReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |have: Option<bool>| match have {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "n/a",
+        };
+        let list = |v: &[String]| if v.is_empty() { "none".to_string() } else { v.join(", ") };
+        writeln!(f, "CPU features (detected at run time):")?;
+        writeln!(f, "  {:<18}{}", "POPCNT", yes_no(self.cpu.popcnt))?;
+        writeln!(f, "  {:<18}{}", "SSSE3", yes_no(self.cpu.ssse3))?;
+        writeln!(f, "  {:<18}{}", "PEXT/PDEP (BMI2)", yes_no(self.cpu.bmi2))?;
+        writeln!(f, "  {:<18}{}", "AVX2", yes_no(self.cpu.avx2))?;
+        writeln!(f, "  {:<18}{}", "NEON", yes_no(self.cpu.neon))?;
+        writeln!(f, "compiled for target features: {}", list(&self.target_features))?;
+        writeln!(f, "cargo features: {}", list(&self.cargo_features))?;
+        writeln!(f, "dispatch:")?;
+        let width = self.kernels.iter().map(|k| k.op.len()).max().unwrap_or(0);
+        for k in &self.kernels {
+            writeln!(f, "  {:<width$}  {}", k.op, k.implementation, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let r = report();
+        assert_eq!(r.cpu, CpuFeatures::detect());
+        assert_eq!(r.kernels.len(), 6);
+        let text = r.to_string();
+        assert!(text.starts_with("CPU features (detected at run time):\n  POPCNT "));
+        assert!(text.contains("\ndispatch:\n  count_ones "));
+        #[cfg(target_arch = "x86_64")]
+        assert!(r.cpu.popcnt.is_some() && r.cpu.neon.is_none());
+    }
+
+    #[test]
+    fn test_kernels() {
+        let cpu = CpuFeatures { popcnt: Some(true), ssse3: Some(true), bmi2: Some(true), avx2: Some(true),
+                                neon: None };
+        let ops: Vec<String> = kernels(&cpu).into_iter().map(|k| k.op).collect();
+        assert_eq!(ops, vec!["count_ones", "and/or/xor/invert/fill", "read_bits/write_bits", "reverse_range",
+                             "extract/deposit", "rotate"]);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(kernels(&cpu)[4].implementation == "PEXT/PDEP instructions", !crate::bitarray::CHECKED);
+        let want = if cfg!(all(feature = "simd", target_arch = "x86_64")) && !crate::bitarray::CHECKED {
+            "SSSE3 shuffles"
+        } else {
+            "64 bits from each end"
+        };
+        assert!(kernels(&cpu)[3].implementation.starts_with(want));
+        let none = CpuFeatures { popcnt: None, ssse3: None, bmi2: None, avx2: None, neon: None };
+        assert_eq!(kernels(&none)[2].implementation, "one or two word shifts");
+        assert_eq!(kernels(&none)[3].implementation,
+                   if cfg!(feature = "rayon") {
+                       "64 bits from each end at a time, on all cores for very long ranges"
+                   } else {
+                       "64 bits from each end at a time"
+                   });
+        assert_eq!(kernels(&none)[4].implementation, "a loop over the mask's ones");
+    }
+}
@@ -0,0 +1,190 @@
+// Hamming codes over BitArrays: every 4 data bits become a 7 bit
+// codeword that survives any single flipped bit, or with the extended
+// code an 8 bit codeword that also detects (but can't fix) two flips.
+use std::fmt;
+
+use crate::bitarray::BitArray;
+
+/// Bit i of a codeword is Hamming position i + 1: parity bits sit at the
+/// power of two positions 1, 2 and 4, data at 3, 5, 6 and 7, and the
+/// extended code's overall parity is bit 7.
+const DATA_BITS: [usize; 4] = [2, 4, 5, 6];
+
+/// The 7 bit codeword of each 4 bit nibble.
+fn codeword(nibble: u64) -> u64 {
+    let d = |k: usize| (nibble >> k) & 1;
+    let p1 = d(0) ^ d(1) ^ d(3);
+    let p2 = d(0) ^ d(2) ^ d(3);
+    let p4 = d(1) ^ d(2) ^ d(3);
+    p1 | p2 << 1 | d(0) << 2 | p4 << 3 | d(1) << 4 | d(2) << 5 | d(3) << 6
+}
+
+/// The xor of the positions of the set bits of a 7 bit codeword: 0 for
+/// a valid one, otherwise the position of a single flipped bit.
+fn syndrome(word: u64) -> usize {
+    (0 .. 7).filter(|&i| (word >> i) & 1 == 1).fold(0, |s, i| s ^ (i + 1))
+}
+
+fn data_of(word: u64) -> u64 {
+    DATA_BITS.iter().enumerate().fold(0, |n, (k, &i)| n | ((word >> i) & 1) << k)
+}
+
+/// Which Hamming code to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    /// Hamming(7,4): corrects any single error per codeword.
+    Hamming74,
+    /// Extended Hamming(8,4): corrects single and detects double errors.
+    Extended84,
+}
+
+/// A codeword with two flipped bits, which the extended code detects but
+/// can't correct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uncorrectable {
+    /// Index of the bad codeword.
+    pub block: usize,
+}
+
+impl fmt::Display for Uncorrectable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "uncorrectable error in codeword {}", self.block)
+    }
+}
+
+impl std::error::Error for Uncorrectable {}
+
+/// The result of a successful decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub data: BitArray,
+    /// How many codewords had a bit corrected.
+    pub corrected: usize,
+}
+
+impl Code {
+    /// Bits per codeword.
+    pub fn block_bits(self) -> usize {
+        match self {
+            Code::Hamming74 => 7,
+            Code::Extended84 => 8,
+        }
+    }
+
+    /// The size of the encoding of data_bits bits; the last nibble is
+    /// zero padded.
+    pub fn encoded_len(self, data_bits: usize) -> usize {
+        data_bits.div_ceil(4) * self.block_bits()
+    }
+
+    pub fn encode(self, data: &BitArray) -> BitArray {
+        let n = data.get_bit_sz();
+        let block = self.block_bits();
+        let mut out = BitArray::new(self.encoded_len(n));
+        for b in 0 .. n.div_ceil(4) {
+            let mut word = codeword(data.read_bits(4 * b, usize::min(4, n - 4 * b)));
+            if self == Code::Extended84 {
+                word |= ((word.count_ones() & 1) as u64) << 7;
+            }
+            out.write_bits(b * block, block, word);
+        }
+        out
+    }
+
+    /// Recovers data_bits bits of data from code, correcting one flipped
+    /// bit per codeword.  With Extended84, a codeword with two flips is an
+    /// error rather than being "corrected" into the wrong data; Hamming74
+    /// can't tell and silently miscorrects.
+    pub fn decode(self, code: &BitArray, data_bits: usize) -> Result<Decoded, Uncorrectable> {
+        assert_eq!(code.get_bit_sz(), self.encoded_len(data_bits));
+        let block = self.block_bits();
+        let mut data = BitArray::new(data_bits);
+        let mut corrected = 0;
+        for b in 0 .. data_bits.div_ceil(4) {
+            let raw = code.read_bits(b * block, block);
+            let mut word = raw & 0x7f;
+            let s = syndrome(word);
+            match self {
+                Code::Hamming74 => {
+                    if s != 0 {
+                        word ^= 1 << (s - 1);
+                        corrected += 1;
+                    }
+                }
+                Code::Extended84 => {
+                    let parity_ok = raw.count_ones().is_multiple_of(2);
+                    match (s, parity_ok) {
+                        (0, true) => {}
+                        // the overall parity bit itself flipped
+                        (0, false) => corrected += 1,
+                        (s, false) => {
+                            word ^= 1 << (s - 1);
+                            corrected += 1;
+                        }
+                        (_, true) => return Err(Uncorrectable { block: b }),
+                    }
+                }
+            }
+            let width = usize::min(4, data_bits - 4 * b);
+            data.write_bits(4 * b, width, data_of(word) & ((1 << width) - 1));
+        }
+        Ok(Decoded { data, corrected })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codewords_are_valid() {
+        for nibble in 0 .. 16 {
+            let word = codeword(nibble);
+            assert_eq!(syndrome(word), 0);
+            assert_eq!(data_of(word), nibble);
+        }
+        // the classic example: data 1011 (d1 = 1) encodes as 0110011
+        assert_eq!(codeword(0b1101), 0b1100110);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut data = BitArray::new(103);
+        data.randfill();
+        for &code in &[Code::Hamming74, Code::Extended84] {
+            let enc = code.encode(&data);
+            assert_eq!(enc.get_bit_sz(), 26 * code.block_bits());
+            let dec = code.decode(&enc, 103).unwrap();
+            assert_eq!(dec, Decoded { data: data.clone(), corrected: 0 });
+        }
+    }
+
+    #[test]
+    fn test_corrects_single_errors() {
+        let data = BitArray::from_str("1011001110001111");
+        for &code in &[Code::Hamming74, Code::Extended84] {
+            let enc = code.encode(&data);
+            for i in 0 .. enc.get_bit_sz() {
+                let mut bad = enc.clone();
+                bad.set(i, !bad.get(i));
+                let dec = code.decode(&bad, 16).unwrap();
+                assert_eq!(dec.data, data);
+                assert_eq!(dec.corrected, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_detects_double_errors() {
+        let data = BitArray::from_str("10110011");
+        let enc = Code::Extended84.encode(&data);
+        for i in 8 .. 16 {
+            for j in i + 1 .. 16 {
+                let mut bad = enc.clone();
+                bad.set(i, !bad.get(i));
+                bad.set(j, !bad.get(j));
+                assert_eq!(Code::Extended84.decode(&bad, 8), Err(Uncorrectable { block: 1 }));
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// A bit array with summary levels on top, so the first set or clear bit
+// is found by walking down from a single word instead of scanning.
+use crate::bitarray::BitArray;
+
+/// A bit array plus two trees of summary bits.  Bit w of has_one[0] says
+/// whether word w of the array holds a one, and bit w of has_one[k + 1]
+/// whether word w of has_one[k] is non-zero; has_zero works the same for
+/// clear bits.  Levels are added until one fits in a single word, so a
+/// billion bit array has four and find_first_one/find_first_zero read five
+/// words.  set keeps the summaries up to date, touching one word per
+/// level at most.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HierBitArray {
+    bits: BitArray,
+    has_one: Vec<BitArray>,
+    has_zero: Vec<BitArray>,
+}
+
+/// The width of word w of a level, and the word itself.
+fn word(level: &BitArray, w: usize) -> (usize, u64) {
+    let width = usize::min(64, level.get_bit_sz() - w * 64);
+    (width, level.read_bits(w * 64, width))
+}
+
+fn mask(width: usize) -> u64 {
+    u64::MAX >> (64 - width)
+}
+
+impl HierBitArray {
+    /// bit_sz clear bits.
+    pub fn new(bit_sz: usize) -> HierBitArray {
+        HierBitArray::from_bitarray(BitArray::new(bit_sz))
+    }
+
+    pub fn from_bitarray(bits: BitArray) -> HierBitArray {
+        let mut has_one: Vec<BitArray> = vec![];
+        let mut has_zero: Vec<BitArray> = vec![];
+        let mut words = bits.get_bit_sz().div_ceil(64);
+        while words > 0 {
+            let (mut ones, mut zeros) = (BitArray::new(words), BitArray::new(words));
+            for w in 0 .. words {
+                let (one, zero) = match (has_one.last(), has_zero.last()) {
+                    (Some(o), Some(z)) => (word(o, w).1 != 0, word(z, w).1 != 0),
+                    _ => {
+                        let (width, x) = word(&bits, w);
+                        (x != 0, x != mask(width))
+                    }
+                };
+                ones.set(w, one);
+                zeros.set(w, zero);
+            }
+            has_one.push(ones);
+            has_zero.push(zeros);
+            if words <= 64 {
+                break;
+            }
+            words = words.div_ceil(64);
+        }
+        HierBitArray { bits, has_one, has_zero }
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bits.get_bit_sz()
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        self.bits.get(bit_index)
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        self.bits.set(bit_index, val);
+        let mut w = bit_index / 64;
+        let (width, x) = word(&self.bits, w);
+        let (mut one, mut zero) = (x != 0, x != mask(width));
+        for k in 0 .. self.has_one.len() {
+            if self.has_one[k].get(w) == one && self.has_zero[k].get(w) == zero {
+                // nothing changes further up either
+                return;
+            }
+            self.has_one[k].set(w, one);
+            self.has_zero[k].set(w, zero);
+            w /= 64;
+            if k + 1 < self.has_one.len() {
+                one = word(&self.has_one[k], w).1 != 0;
+                zero = word(&self.has_zero[k], w).1 != 0;
+            }
+        }
+    }
+
+    /// Walks a summary tree down to the first leaf word it flags, and
+    /// returns that word's index.
+    fn first_flagged(levels: &[BitArray]) -> Option<usize> {
+        let top = levels.last()?;
+        let (_, x) = word(top, 0);
+        if x == 0 {
+            return None;
+        }
+        let mut w = x.trailing_zeros() as usize;
+        for level in levels.iter().rev().skip(1) {
+            w = w * 64 + word(level, w).1.trailing_zeros() as usize;
+        }
+        Some(w)
+    }
+
+    /// The index of the lowest set bit.
+    pub fn find_first_one(&self) -> Option<usize> {
+        let w = HierBitArray::first_flagged(&self.has_one)?;
+        Some(w * 64 + word(&self.bits, w).1.trailing_zeros() as usize)
+    }
+
+    /// The index of the lowest clear bit.
+    pub fn find_first_zero(&self) -> Option<usize> {
+        let w = HierBitArray::first_flagged(&self.has_zero)?;
+        Some(w * 64 + (!word(&self.bits, w).1).trailing_zeros() as usize)
+    }
+
+    /// The number of summary levels.
+    pub fn levels(&self) -> usize {
+        self.has_one.len()
+    }
+
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+
+    pub fn into_bitarray(self) -> BitArray {
+        self.bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(HierBitArray::new(0).levels(), 0);
+        assert_eq!(HierBitArray::new(64 * 64).levels(), 1);
+        assert_eq!(HierBitArray::new(64 * 64 + 1).levels(), 2);
+        assert_eq!(HierBitArray::new(0).find_first_one(), None);
+        assert_eq!(HierBitArray::new(0).find_first_zero(), None);
+    }
+
+    #[test]
+    fn test_find_first() {
+        let n = 64 * 64 * 64 + 100;
+        let mut h = HierBitArray::new(n);
+        assert_eq!(h.levels(), 3);
+        assert_eq!(h.find_first_one(), None);
+        assert_eq!(h.find_first_zero(), Some(0));
+        h.set(n - 1, true);
+        assert_eq!(h.find_first_one(), Some(n - 1));
+        h.set(70_000, true);
+        assert_eq!(h.find_first_one(), Some(70_000));
+        h.set(70_000, false);
+        assert_eq!(h.find_first_one(), Some(n - 1));
+    }
+
+    #[test]
+    fn test_fill_up_as_an_allocator() {
+        let n = 64 * 64 + 30;
+        let mut h = HierBitArray::new(n);
+        for i in 0 .. n {
+            assert_eq!(h.find_first_zero(), Some(i));
+            h.set(i, true);
+        }
+        assert_eq!(h.find_first_zero(), None);
+        h.set(4000, false);
+        h.set(17, false);
+        assert_eq!(h.find_first_zero(), Some(17));
+        h.set(17, true);
+        assert_eq!(h.find_first_zero(), Some(4000));
+    }
+
+    #[test]
+    fn test_matches_rebuild() {
+        let mut ba = BitArray::new(20_000);
+        ba.randfill();
+        let mut h = HierBitArray::from_bitarray(ba.clone());
+        for i in (0 .. 20_000).step_by(7) {
+            h.set(i, i % 3 == 0);
+            ba.set(i, i % 3 == 0);
+        }
+        assert_eq!(h, HierBitArray::from_bitarray(ba.clone()));
+        assert_eq!(h.find_first_one(), ba.iter_ones().next());
+        assert_eq!(h.find_first_zero(), (0 .. 20_000).find(|&i| !ba.get(i)));
+    }
+}
@@ -0,0 +1,233 @@
+// A BitArray that records every mutation, so they can be undone, redone,
+// or replayed onto a fresh array to reproduce a run exactly.
+use std::fmt;
+
+use crate::bitarray::BitArray;
+
+/// A recorded mutation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Set { bit_index: usize, val: bool },
+    Fill { val: bool },
+    FillRange { bit_offset: usize, bit_length: usize, val: bool },
+    Rotate { bit_offset: usize, bit_length: usize, bit_right_amount: isize },
+}
+
+impl Op {
+    /// Performs the operation on ba.
+    pub fn apply(&self, ba: &mut BitArray) {
+        match *self {
+            Op::Set { bit_index, val } => ba.set(bit_index, val),
+            Op::Fill { val } => ba.fill(val),
+            Op::FillRange { bit_offset, bit_length, val } => ba.fill_range(bit_offset, bit_length, val),
+            Op::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                ba.rotate(bit_offset, bit_length, bit_right_amount)
+            }
+        }
+    }
+
+    /// The bits [offset, offset + len) the operation can overwrite, or
+    /// None for rotations, which are undone by rotating back.
+    fn clobbers(&self, ba: &BitArray) -> Option<(usize, usize)> {
+        match *self {
+            Op::Set { bit_index, .. } => Some((bit_index, 1)),
+            Op::Fill { .. } => Some((0, ba.get_bit_sz())),
+            Op::FillRange { bit_offset, bit_length, .. } => Some((bit_offset, bit_length)),
+            Op::Rotate { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Set { bit_index, val } => write!(f, "set {} {}", bit_index, *val as u8),
+            Op::Fill { val } => write!(f, "fill {}", *val as u8),
+            Op::FillRange { bit_offset, bit_length, val } => {
+                write!(f, "fill_range {} {} {}", bit_offset, bit_length, *val as u8)
+            }
+            Op::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                write!(f, "rotate {} {} {}", bit_offset, bit_length, bit_right_amount)
+            }
+        }
+    }
+}
+
+/// Applies ops in order to a new array of bit_sz zeros.
+pub fn replay(bit_sz: usize, ops: &[Op]) -> BitArray {
+    let mut ba = BitArray::new(bit_sz);
+    for op in ops {
+        op.apply(&mut ba);
+    }
+    ba
+}
+
+/// A done operation and what it needs to be undone.
+#[derive(Debug, Clone)]
+struct Entry {
+    op: Op,
+    /// The bits the operation overwrote, if it overwrites any.
+    before: Option<BitArray>,
+}
+
+/// Writes src into ba at bit_offset.
+fn restore(ba: &mut BitArray, bit_offset: usize, src: &BitArray) {
+    let mut done = 0;
+    while done < src.get_bit_sz() {
+        let width = usize::min(64, src.get_bit_sz() - done);
+        ba.write_bits(bit_offset + done, width, src.read_bits(done, width));
+        done += width;
+    }
+}
+
+/// A BitArray with an undo/redo history.  Reads go through bits();
+/// mutations go through the methods here, which log them.  Doing a new
+/// operation after an undo discards the redo history, as editors do.
+#[derive(Debug, Clone)]
+pub struct JournaledBitArray {
+    bits: BitArray,
+    done: Vec<Entry>,
+    undone: Vec<Op>,
+}
+
+impl JournaledBitArray {
+    pub fn new(bit_sz: usize) -> JournaledBitArray {
+        JournaledBitArray::from_bitarray(BitArray::new(bit_sz))
+    }
+
+    /// Starts journaling an existing array.  Replaying the log reproduces
+    /// the current state only if this started out all zeros.
+    pub fn from_bitarray(bits: BitArray) -> JournaledBitArray {
+        JournaledBitArray { bits, done: vec![], undone: vec![] }
+    }
+
+    pub fn bits(&self) -> &BitArray {
+        &self.bits
+    }
+
+    pub fn into_bitarray(self) -> BitArray {
+        self.bits
+    }
+
+    fn run(&mut self, op: Op) {
+        let before = op.clobbers(&self.bits)
+            .map(|(offset, len)| self.bits.slice(offset .. offset + len).to_bitarray());
+        op.apply(&mut self.bits);
+        self.done.push(Entry { op, before });
+    }
+
+    /// Performs op, recording it and clearing the redo history.
+    pub fn apply(&mut self, op: Op) {
+        self.undone.clear();
+        self.run(op);
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        self.apply(Op::Set { bit_index, val })
+    }
+
+    pub fn fill(&mut self, val: bool) {
+        self.apply(Op::Fill { val })
+    }
+
+    pub fn fill_range(&mut self, bit_offset: usize, bit_length: usize, val: bool) {
+        self.apply(Op::FillRange { bit_offset, bit_length, val })
+    }
+
+    pub fn rotate(&mut self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) {
+        self.apply(Op::Rotate { bit_offset, bit_length, bit_right_amount })
+    }
+
+    /// Reverts the latest operation.  Returns false if there is none.
+    pub fn undo(&mut self) -> bool {
+        let entry = match self.done.pop() {
+            Some(e) => e,
+            None => return false,
+        };
+        match (&entry.op, &entry.before) {
+            (Op::Rotate { bit_offset, bit_length, bit_right_amount }, _) => {
+                self.bits.rotate(*bit_offset, *bit_length, -*bit_right_amount)
+            }
+            (op, Some(before)) => {
+                let (offset, _) = op.clobbers(&self.bits).unwrap();
+                restore(&mut self.bits, offset, before);
+            }
+            (_, None) => unreachable!(),
+        }
+        self.undone.push(entry.op);
+        true
+    }
+
+    /// Performs the latest undone operation again.  Returns false if there
+    /// is none.
+    pub fn redo(&mut self) -> bool {
+        match self.undone.pop() {
+            Some(op) => {
+                self.run(op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The operations currently applied, oldest first.
+    pub fn log(&self) -> Vec<Op> {
+        self.done.iter().map(|e| e.op.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut j = JournaledBitArray::from_bitarray(BitArray::from_str("10010110"));
+        j.set(0, true);
+        j.rotate(0, 8, 3);
+        assert_eq!(j.bits().show(), "10111100");
+        j.fill_range(2, 3, false);
+        let after = j.bits().clone();
+        assert!(j.undo());
+        assert_eq!(j.bits().show(), "10111100");
+        assert!(j.undo());
+        assert!(j.undo());
+        assert_eq!(j.bits().show(), "10010110");
+        assert!(!j.undo());
+        assert!(j.redo() && j.redo() && j.redo());
+        assert!(!j.redo());
+        assert_eq!(j.bits(), &after);
+    }
+
+    #[test]
+    fn test_new_op_drops_redo() {
+        let mut j = JournaledBitArray::new(10);
+        j.set(1, true);
+        j.undo();
+        j.fill(true);
+        assert!(!j.redo());
+        assert_eq!(j.log(), vec![Op::Fill { val: true }]);
+        j.undo();
+        assert_eq!(j.bits().count_ones(), 0);
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut j = JournaledBitArray::new(300);
+        for i in 0 .. 50 {
+            j.set(i * 5, true);
+        }
+        j.rotate(10, 250, -17);
+        j.fill_range(100, 30, true);
+        j.rotate(0, 300, 99);
+        j.undo();
+        assert_eq!(&replay(300, &j.log()), j.bits());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Op::Set { bit_index: 4, val: true }.to_string(), "set 4 1");
+        assert_eq!(Op::Rotate { bit_offset: 0, bit_length: 8, bit_right_amount: -2 }.to_string(),
+                   "rotate 0 8 -2");
+    }
+}
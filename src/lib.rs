@@ -1,9 +1,90 @@
+//! Bit arrays with fast subarray rotation, ported from project 1 of MIT
+//! 6.172, and a collection of structures built on them.
+//!
+//! The core type is [`bitarray::BitArray`], also exported at the crate
+//! root:
+//!
+//! ```
+//! use bitarray::BitArray;
+//!
+//! let mut ba = BitArray::from_str("10010110");
+//! ba.rotate(0, 8, 1);
+//! assert_eq!(ba.show(), "00101101");
+//! ```
+//!
+//! Bit 0 is the rightmost character of from_str and show.  The other
+//! modules fall into three groups:
+//!
+//! - sets, maps and indexes over bits: [`bitset`], [`enumset`],
+//!   [`typedset`], [`rank`], [`hier`], [`adaptive`], [`query`],
+//!   [`counting_bloom`], [`matrix`], [`packed`], [`bitfield`](mod@bitfield)
+//! - other shapes of storage: [`rope`], [`persistent`], [`journal`],
+//!   [`counted`], [`dirty`] and [`checksummed`] (all on [`observed`]),
+//!   [`deque`], [`pool`], [`shared`], [`sharded`], [`atomic`],
+//!   [`allocator`], [`hamming`], and behind feature `mmap` a file-backed array for more bits than RAM,
+//!   and [`stream`] for rotating bits in a file a chunk at a time
+//! - testing tools, used by the everybit binary and usable from other
+//!   crates' tests: [`testfile`], [`oracle`], [`model_test`],
+//!   [`exhaustive`], the timed tiers in [`perf`], [`affinity`] for
+//!   pinning them and [`features`] for what they run on, and behind
+//!   features `crosscheck` (bitvec), `strategy` (proptest), `arbitrary`
+//!   (quickcheck), and `config`, `results` and `summary` (harness)
+//!
+//! The everybit command line is behind the default cli feature; depend on
+//! the crate with `default-features = false` to leave out clap.  The
+//! allocator_api feature, for `BitArray::new_in`, needs a nightly compiler.
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+pub mod adaptive;
+pub mod affinity;
+pub mod allocator;
+#[cfg(feature = "quickcheck")]
+pub mod arbitrary;
+pub mod atomic;
 pub mod bitarray;
+pub mod bitfield;
+pub mod bitset;
+pub mod checksummed;
+pub mod counted;
+#[cfg(feature = "harness")]
+pub mod config;
+pub mod counting_bloom;
+#[cfg(feature = "bitvec")]
+pub mod crosscheck;
+pub mod deque;
+pub mod dirty;
+#[cfg(feature = "dna")]
+pub mod dna;
+pub mod enumset;
+pub mod exhaustive;
+pub mod features;
+pub mod hamming;
+pub mod hier;
+pub mod journal;
+pub mod matrix;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap;
+pub mod model_test;
+pub mod observed;
+pub mod oracle;
+pub mod packed;
+pub mod perf;
+pub mod persistent;
+pub mod pool;
+pub mod query;
+pub mod rank;
+#[cfg(feature = "harness")]
+pub mod results;
+pub mod rope;
+pub mod sharded;
+pub mod shared;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+pub mod stream;
+#[cfg(feature = "harness")]
+pub mod summary;
+mod sync;
+pub mod testfile;
+pub mod typedset;
+pub mod viz;
 
-
-
-
-
-
-
-
+pub use crate::bitarray::BitArray;
@@ -1,7 +1,4 @@
-#![allow(unused_variables)]
-#![allow(dead_code)]
-
-/**
+/*
  * Copyright (c) 2019 MIT License by Derek Rhodes (porting to Rust)
  * Copyright (c) 2012 MIT License by 6.172 Staff
  *
@@ -26,38 +23,35 @@
 
 // This test harness which allows both functional testing (i.e., testing for
 // correctness) and performance testing (i.e., testing for speed).
+//
+// It is only the command line: everything it runs lives in the library,
+// so depending on the crate (with default-features = false) does not
+// bring in clap.
 
-// We need _POSIX_C_SOURCE >= 2 to use getopt.
-// #define _POSIX_C_SOURCE 200112L
-
-// #include <stdio.h>
-// #include <stdlib.h>
-
-// #include <unistd.h>
-// #include "./tests.h"
-
-// argument parsing
-// https://www.youtube.com/watch?v=_Kifa_BZ6WQ
-// extern crate clap;
-
-extern crate rand;
-
-    // maybe we can do better than this!
-
-mod bitarray;
-
-
-use clap::{App, Arg};
-use crate::bitarray::BitArray;
-
+use bitarray::bitarray::{BitArray, RotateStrategy};
+use bitarray::config::{self, Config, Format};
+#[cfg(feature = "bitvec")]
+use bitarray::crosscheck;
+use bitarray::viz::{self, Algorithm};
+use bitarray::perf::{self, Size, TimingOptions};
+use bitarray::results::{self, Results};
+use bitarray::summary::{self, Machine, Summary};
+use bitarray::{affinity, exhaustive, features, model_test, oracle, stream, testfile};
+use clap::{App, Arg, SubCommand};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 fn main() {
-    BitArray::new(234);
-    
+    let mut env_help = String::from("ENVIRONMENT (overridden by flags, override everybit.toml):\n");
+    for (name, what) in config::ENV_VARS {
+        env_help.push_str(&format!("    {:<24}{}\n", name, what));
+    }
     let matches = App::new("everybit")
         .version("1.0")
         .author("human being")        
         .about("project #1 for rust fast and safe")
+        .after_help(env_help.as_str())
         .arg(Arg::with_name("small")
              .short("s")
              .help("runs the short rotation performance test."))
@@ -67,8 +61,554 @@ fn main() {
         .arg(Arg::with_name("large")
              .short("l")
              .help("runs the large rotation performance test."))
+        .arg(Arg::with_name("calibrate")
+             .long("calibrate")
+             .help("with -s, -m or -l, scales the time limit to this machine's speed, \
+                    as saved by calibrate or measured now."))
+        .arg(Arg::with_name("pin-core")
+             .long("pin-core")
+             .value_name("N")
+             .global(true)
+             .help("runs on core N only, for steadier timings."))
+        .arg(Arg::with_name("warmup")
+             .long("warmup")
+             .value_name("N")
+             .help("with -s, -m or -l, does N untimed rotations before each timed one (default 0)."))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .value_name("FORMAT")
+             .possible_values(&["text", "json"])
+             .help("how -s, -m and -l print their results (default text)."))
+        .arg(Arg::with_name("strategy")
+             .long("strategy")
+             .value_name("STRATEGY")
+             .possible_values(&RotateStrategy::names().collect::<Vec<_>>())
+             .help("with -s, -m or -l, times this rotation algorithm instead of rotate's own choice."))
+        .arg(Arg::with_name("config")
+             .long("config")
+             .value_name("FILE")
+             .help("reads defaults from FILE instead of everybit.toml."))
+        .arg(Arg::with_name("testfile")
+             .short("t")
+             .value_name("FILE")
+             .help("runs the functional tests in FILE, e.g. tests/default."))
+        .arg(Arg::with_name("test")
+             .short("n")
+             .value_name("N")
+             .requires("testfile")
+             .help("with -t, runs only test N."))
+        .arg(Arg::with_name("isolate")
+             .long("isolate")
+             .requires("testfile")
+             .help("with -t, runs each test in its own process, so a crash fails only that test."))
+        .arg(Arg::with_name("oracle")
+             .long("oracle")
+             .value_name("STEPS")
+             .help("checks STEPS random operations per size against a Vec<bool> model."))
+        .arg(Arg::with_name("seed")
+             .long("seed")
+             .value_name("SEED")
+             .help("with --oracle, -s, -m or -l, the seed to reproduce a run (default random)."))
+        .arg(Arg::with_name("against")
+             .long("against")
+             .value_name("ORACLE")
+             .possible_values(&["model", "bitvec"])
+             .requires("oracle")
+             .help("with --oracle, what to compare with (default model); bitvec needs the bitvec feature."))
+        .arg(Arg::with_name("visualize")
+             .long("visualize")
+             .value_name("ALGORITHM")
+             .possible_values(&["naive", "reversal"])
+             .requires("rotation")
+             .help("writes an SVG animation of a rotation to stdout."))
+        .arg(Arg::with_name("trace")
+             .long("trace")
+             .value_name("MAX_STEPS")
+             .requires("rotation")
+             .conflicts_with("visualize")
+             .help("prints the array after each step of a rotation, for the first MAX_STEPS steps."))
+        .arg(Arg::with_name("explain")
+             .long("explain")
+             .requires("rotation")
+             .help("says how rotate would carry out a rotation of --bits, and why."))
+        .arg(Arg::with_name("bits")
+             .long("bits")
+             .value_name("BITSTRING")
+             .default_value("10010110")
+             .help("the array to visualize, trace or explain, bit 0 is the last character."))
+        .arg(Arg::with_name("rotation")
+             .long("rotation")
+             .value_names(&["OFFSET", "LENGTH", "AMOUNT"])
+             .allow_hyphen_values(true)
+             .help("the rotation to visualize, trace or explain, as in BitArray::rotate."))
+        .subcommand(SubCommand::with_name("exhaustive")
+                    .about("checks rotate on every array, subarray and amount up to a size.")
+                    .arg(Arg::with_name("max-bits")
+                         .long("max-bits")
+                         .value_name("N")
+                         .default_value("12")
+                         .help("the largest array to check, at most 32.")))
+        .subcommand(SubCommand::with_name("model-test")
+                    .about("runs random action sequences against a Vec<bool> model, shrinking failures.")
+                    .arg(Arg::with_name("runs")
+                         .long("runs")
+                         .value_name("N")
+                         .default_value("100")
+                         .help("the number of random sequences to try."))
+                    .arg(Arg::with_name("steps")
+                         .long("steps")
+                         .value_name("N")
+                         .default_value("100")
+                         .help("the number of actions in each sequence."))
+                    .arg(Arg::with_name("seed")
+                         .long("seed")
+                         .value_name("SEED")
+                         .help("the seed to reproduce a run (default random)."))
+                    .arg(Arg::with_name("file")
+                         .long("file")
+                         .value_name("FILE")
+                         .conflicts_with_all(&["runs", "steps", "seed"])
+                         .help("runs (and shrinks) the actions in FILE instead.")))
+        .subcommand(SubCommand::with_name("compare-results")
+                    .about("compares two benchmark result files, failing if any case got slower.")
+                    .arg(Arg::with_name("old").required(true).value_name("OLD.json"))
+                    .arg(Arg::with_name("new").required(true).value_name("NEW.json"))
+                    .arg(Arg::with_name("threshold")
+                         .long("threshold")
+                         .value_name("PERCENT")
+                         .default_value("5")
+                         .help("how much slower a case may get before it counts as a regression.")))
+        .subcommand(SubCommand::with_name("calibrate")
+                    .about("measures this machine's get, set, rotate and memory speed and saves them for --calibrate.")
+                    .arg(Arg::with_name("out")
+                         .long("out")
+                         .value_name("FILE")
+                         .help("where to save them (default everybit-calibration.json in ~/.config).")))
+        .subcommand(SubCommand::with_name("features")
+                    .about("prints the CPU features detected and which implementation each operation uses.")
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .value_name("FORMAT")
+                         .possible_values(&["text", "json"])
+                         .help("text (the default) or json, for attaching to a bug report.")))
+        .subcommand(SubCommand::with_name("summary")
+                    .about("writes the latest Criterion results and harness result files, with the machine, \
+                            to NAME.md and NAME.json.")
+                    .arg(Arg::with_name("results")
+                         .multiple(true)
+                         .value_name("RESULTS.json")
+                         .help("result files from -s, -m or -l with --format json."))
+                    .arg(Arg::with_name("criterion")
+                         .long("criterion")
+                         .value_name("DIR")
+                         .default_value("target/criterion")
+                         .help("Criterion's output directory, skipped if it doesn't exist."))
+                    .arg(Arg::with_name("out")
+                         .long("out")
+                         .value_name("NAME")
+                         .default_value("summary")
+                         .help("the files to write, without extension.")))
+        .subcommand(SubCommand::with_name("rotate-file")
+                    .about("rotates bits of a file in place a chunk at a time, for data bigger than memory.")
+                    .arg(Arg::with_name("file").required(true).value_name("FILE"))
+                    .arg(Arg::with_name("rotation")
+                         .long("rotation")
+                         .value_names(&["OFFSET", "LENGTH", "AMOUNT"])
+                         .allow_hyphen_values(true)
+                         .required(true)
+                         .help("as in BitArray::rotate; bit 0 is the low bit of the file's first byte."))
+                    .arg(Arg::with_name("chunk")
+                         .long("chunk")
+                         .value_name("BYTES")
+                         .help("how much to read from each end of a reversal at a time (default 1 MiB).")))
         .get_matches();
 
+    // flags, then EVERYBIT_* variables, then the config file
+    let config_path = matches.value_of("config").map(String::from)
+        .or_else(|| std::env::var("EVERYBIT_CONFIG").ok());
+    let config = match &config_path {
+        Some(path) => Config::load(path.as_ref()),
+        None => Config::find().map(|(_, config)| config),
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = config.apply_env(|name| std::env::var(name).ok()) {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    let seed = matches.value_of("seed").map(|s| s.parse().expect("SEED must be a number"))
+        .or(config.seed)
+        .unwrap_or_else(rand::random);
+
+    if let Some(m) = matches.subcommand_matches("exhaustive") {
+        let max_bits = m.value_of("max-bits").unwrap().parse().expect("--max-bits must be a number");
+        std::process::exit(run_exhaustive(max_bits));
+    }
+
+    if let Some(m) = matches.subcommand_matches("model-test") {
+        std::process::exit(run_model_test(m));
+    }
+
+    if let Some(m) = matches.subcommand_matches("compare-results") {
+        let threshold = m.value_of("threshold").unwrap().parse().expect("PERCENT must be a number");
+        std::process::exit(run_compare_results(m.value_of("old").unwrap(), m.value_of("new").unwrap(),
+                                               threshold));
+    }
+
+    if let Some(m) = matches.subcommand_matches("calibrate") {
+        pin_core(m);
+        std::process::exit(run_calibrate(m.value_of("out")));
+    }
+
+    if let Some(m) = matches.subcommand_matches("features") {
+        let report = features::report();
+        match m.value_of("format").map(|f| f.parse().unwrap()).or(config.format).unwrap_or(Format::Text) {
+            Format::Text => print!("{}", report),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+        }
+        std::process::exit(0);
+    }
+
+    if let Some(m) = matches.subcommand_matches("summary") {
+        let harness: Vec<&str> = m.values_of("results").map_or(vec![], |v| v.collect());
+        std::process::exit(run_summary(m.value_of("criterion").unwrap(), &harness, m.value_of("out").unwrap()));
+    }
+
+    if let Some(m) = matches.subcommand_matches("rotate-file") {
+        let chunk_bytes = m.value_of("chunk").map_or(stream::STREAM_CHUNK_BYTES,
+                                                     |n| n.parse().expect("BYTES must be a number"));
+        std::process::exit(run_rotate_file(m.value_of("file").unwrap(), rotation_arg(m), chunk_bytes));
+    }
+
+    if let Some(path) = matches.value_of("testfile") {
+        let only = matches.value_of("test").map(|n| n.parse().expect("N must be a number"));
+        std::process::exit(run_test_file(path, only, matches.is_present("isolate")));
+    }
+
+    if let Some(steps) = matches.value_of("oracle") {
+        let steps = steps.parse().expect("STEPS must be a number");
+        std::process::exit(run_oracle(steps, seed, matches.value_of("against").unwrap_or("model")));
+    }
+
+    let sizes = [("small", Size::Small), ("medium", Size::Medium), ("large", Size::Large)];
+    if let Some(&(_, size)) = sizes.iter().find(|(flag, _)| matches.is_present(flag)) {
+        pin_core(&matches);
+        let format = matches.value_of("format").map(|f| f.parse().unwrap())
+            .or(config.format)
+            .unwrap_or(Format::Text);
+        let calibration = if matches.is_present("calibrate") || config.calibrate { machine_factor() } else { 1.0 };
+        let warmup = matches.value_of("warmup").map_or(config.warmup, |n| n.parse().expect("--warmup N must be a number"));
+        let strategy = matches.value_of("strategy").map(|s| s.parse().unwrap()).or(config.strategy);
+        let opts = TimingOptions { calibration, warmup, strategy };
+        let run = perf::timed_rotation_with(size, config.tiers.get(size), seed, &opts);
+        match format {
+            Format::Text => print!("{}", run),
+            Format::Json => println!("{}", serde_json::to_string(&run).unwrap()),
+        }
+        std::process::exit(0);
+    }
+
+    if let Some(name) = matches.value_of("visualize") {
+        let algorithm = Algorithm::from_name(name).unwrap();
+        let ba = BitArray::from_str(matches.value_of("bits").unwrap());
+        if ba.get_bit_sz() > viz::MAX_VIZ_BITS {
+            eprintln!("--visualize takes at most {} --bits, not {}", viz::MAX_VIZ_BITS, ba.get_bit_sz());
+            std::process::exit(2);
+        }
+        let (bit_offset, bit_length, bit_right_amount) = rotation_arg(&matches);
+        check_rotation(&ba, bit_offset, bit_length);
+        print!("{}", viz::rotation_svg(&ba, bit_offset, bit_length, bit_right_amount, algorithm));
+    }
+
+    if matches.is_present("explain") {
+        let ba = BitArray::from_str(matches.value_of("bits").unwrap());
+        let (bit_offset, bit_length, bit_right_amount) = rotation_arg(&matches);
+        check_rotation(&ba, bit_offset, bit_length);
+        print!("{}", ba.plan_rotate(bit_offset, bit_length, bit_right_amount));
+    }
+
+    if let Some(max_steps) = matches.value_of("trace") {
+        let max_steps = max_steps.parse().unwrap_or_else(|_| {
+            eprintln!("--trace MAX_STEPS must be a number, not {:?}", max_steps);
+            std::process::exit(2);
+        });
+        let mut ba = BitArray::from_str(matches.value_of("bits").unwrap());
+        let (bit_offset, bit_length, bit_right_amount) = rotation_arg(&matches);
+        check_rotation(&ba, bit_offset, bit_length);
+        let stdout = std::io::stdout();
+        ba.rotate_trace_to(bit_offset, bit_length, bit_right_amount, max_steps, &mut stdout.lock())
+            .expect("writing to stdout");
+    }
+}
+
+/// The OFFSET LENGTH AMOUNT of --rotation, exiting with a usage error if
+/// one is not a number.
+fn rotation_arg(matches: &clap::ArgMatches) -> (usize, usize, isize) {
+    let rotation: Vec<&str> = matches.values_of("rotation").unwrap().collect();
+    fn number<T: std::str::FromStr>(name: &str, arg: &str) -> T {
+        arg.parse().unwrap_or_else(|_| {
+            eprintln!("--rotation {} must be a number, not {:?}", name, arg);
+            std::process::exit(2);
+        })
+    }
+    (number("OFFSET", rotation[0]), number("LENGTH", rotation[1]), number("AMOUNT", rotation[2]))
+}
+
+/// Exits with a usage error unless the --rotation range lies inside ba, as
+/// rotate requires.
+fn check_rotation(ba: &BitArray, bit_offset: usize, bit_length: usize) {
+    if bit_offset.checked_add(bit_length).is_none_or(|end| end > ba.get_bit_sz()) {
+        eprintln!("--rotation {} {} is outside the {} bits of --bits", bit_offset, bit_length, ba.get_bit_sz());
+        std::process::exit(2);
+    }
+}
+
+/// Runs the exhaustive subcommand and returns the exit status.
+fn run_exhaustive(max_bits: usize) -> i32 {
+    if max_bits > exhaustive::MAX_EXHAUSTIVE_BITS {
+        eprintln!("--max-bits is at most {}", exhaustive::MAX_EXHAUSTIVE_BITS);
+        return 2;
+    }
+    let report = |bit_sz, cases| println!("{:2} bits: {} cases ok", bit_sz, cases);
+    match exhaustive::check_rotations_with(max_bits, |ba, o, l, a| ba.rotate(o, l, a), report) {
+        Ok(total) => {
+            println!("all {} cases ok", total);
+            0
+        }
+        Err(c) => {
+            println!("counterexample: {}", c);
+            1
+        }
+    }
+}
+
+/// Runs the model-test subcommand and returns the exit status.
+fn run_model_test(m: &clap::ArgMatches) -> i32 {
+    // failures are reported below, the default hook would print every
+    // panic the shrinker provokes
+    std::panic::set_hook(Box::new(|_| {}));
+    let found = match m.value_of("file") {
+        Some(path) => {
+            let actions = match std::fs::read_to_string(path).map_err(|e| e.to_string())
+                .and_then(|text| model_test::parse_actions(&text)) {
+                Ok(actions) => actions,
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return 2;
+                }
+            };
+            model_test::run(&actions).err().map(|_| {
+                let shrunk = model_test::shrink(&actions, |a| model_test::run(a).is_err());
+                let failure = model_test::run(&shrunk).unwrap_err();
+                (shrunk, failure)
+            })
+        }
+        None => {
+            let runs = m.value_of("runs").unwrap().parse().expect("--runs must be a number");
+            let steps = m.value_of("steps").unwrap().parse().expect("--steps must be a number");
+            let seed = m.value_of("seed")
+                .map_or_else(rand::random, |s| s.parse().expect("SEED must be a number"));
+            println!("model-test: {} runs of {} steps, seed {}", runs, steps, seed);
+            model_test::search(runs, steps, seed)
+        }
+    };
+    match found {
+        None => {
+            println!("no failures");
+            0
+        }
+        Some((actions, failure)) => {
+            println!("{}", failure);
+            println!("# minimal failing sequence, rerun with --file");
+            for action in &actions {
+                println!("{}", action);
+            }
+            1
+        }
+    }
+}
+
+/// Runs --oracle over every size in ORACLE_SIZES and returns the exit
+/// status.
+fn run_oracle(steps: usize, seed: u64, against: &str) -> i32 {
+    let run: fn(usize, usize, u64) -> Result<(), oracle::Divergence> = match against {
+        #[cfg(feature = "bitvec")]
+        "bitvec" => crosscheck::run_random,
+        "model" => oracle::run_random,
+        _ => {
+            eprintln!("--against {} needs the {} feature", against, against);
+            return 2;
+        }
+    };
+    println!("oracle: {} steps per size against {}, seed {}", steps, against, seed);
+    let mut failed = 0;
+    for &bit_sz in oracle::ORACLE_SIZES {
+        if let Err(d) = run(bit_sz, steps, seed) {
+            println!("{} bits: {}", bit_sz, d);
+            failed += 1;
+        }
+    }
+    println!("{} of {} sizes diverged", failed, oracle::ORACLE_SIZES.len());
+    if failed == 0 { 0 } else { 1 }
+}
+
+/// Runs the compare-results subcommand and returns the exit status.
+fn run_compare_results(old: &str, new: &str, threshold: f64) -> i32 {
+    let (old, new) = match (Results::load(old.as_ref()), Results::load(new.as_ref())) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let comparison = results::compare(&old, &new, threshold);
+    print!("{}", comparison);
+    if comparison.regressions().next().is_none() { 0 } else { 1 }
+}
+
+/// Does --pin-core, and warns if the core's clock may change under the
+/// timing.
+fn pin_core(matches: &clap::ArgMatches) {
+    if let Some(core) = matches.value_of("pin-core") {
+        let core = core.parse().expect("--pin-core N must be a number");
+        if let Err(e) = affinity::pin_to_core(core) {
+            eprintln!("--pin-core {}: {}, running unpinned", core, e);
+        }
+        if let Some(warning) = affinity::governor_warning(core) {
+            eprintln!("{}", warning);
+        }
+    }
+}
+
+/// The speed factor saved by calibrate, or measured now if there is none.
+fn machine_factor() -> f64 {
+    let saved = config::calibration_path().map(|path| config::load_calibration(&path));
+    match saved {
+        Some(Ok(Some(c))) => c.factor,
+        Some(Err(e)) => {
+            eprintln!("{}, calibrating again", e);
+            perf::calibrate()
+        }
+        _ => perf::calibrate(),
+    }
+}
+
+/// Runs the calibrate subcommand and returns the exit status.
+fn run_calibrate(out: Option<&str>) -> i32 {
+    let path = match out.map(PathBuf::from).or_else(config::calibration_path) {
+        Some(path) => path,
+        None => {
+            eprintln!("no config directory, give --out");
+            return 2;
+        }
+    };
+    let calibration = perf::measure_calibration();
+    print!("{}", calibration);
+    match config::save_calibration(&path, &calibration) {
+        Ok(()) => {
+            println!("saved to {}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+/// Runs the summary subcommand and returns the exit status.
+fn run_summary(criterion_dir: &str, harness: &[&str], out: &str) -> i32 {
+    let criterion = if Path::new(criterion_dir).is_dir() {
+        summary::criterion_benchmarks(criterion_dir.as_ref())
+    } else {
+        Ok(vec![])
+    };
+    let loaded: Result<Vec<Results>, String> = harness.iter().map(|p| Results::load(p.as_ref())).collect();
+    let (criterion, loaded) = match (criterion, loaded) {
+        (Ok(c), Ok(l)) => (c, l),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let summary = Summary::new(Machine::current(), criterion, &loaded);
+    for (ext, text) in &[("md", summary.to_markdown()), ("json", summary.to_json())] {
+        let path = format!("{}.{}", out, ext);
+        if let Err(e) = std::fs::write(&path, text) {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+        println!("wrote {}", path);
+    }
+    0
+}
+
+/// Runs the rotate-file subcommand and returns the exit status.
+fn run_rotate_file(path: &str, (bit_offset, bit_length, bit_right_amount): (usize, usize, isize),
+                   chunk_bytes: usize) -> i32 {
+    if chunk_bytes == 0 {
+        eprintln!("--chunk must be at least 1");
+        return 2;
+    }
+    let start = Instant::now();
+    let result = OpenOptions::new().read(true).write(true).open(path).and_then(|mut f| {
+        stream::rotate_stream_chunked(&mut f, bit_offset, bit_length, bit_right_amount, chunk_bytes)?;
+        f.sync_all()
+    });
+    match result {
+        Ok(()) => {
+            println!("rotated {} bits in {:.3}s", bit_length, start.elapsed().as_secs_f64());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            2
+        }
+    }
+}
+
+/// Runs a test file for -t and returns the exit status.
+fn run_test_file(path: &str, only: Option<usize>, isolate: bool) -> i32 {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
+    let tests = match testfile::parse(&text) {
+        Ok(tests) => tests,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
+    let (ran, failures) = if isolate {
+        let exe = match std::env::current_exe() {
+            Ok(exe) => exe,
+            Err(e) => {
+                eprintln!("--isolate: {}", e);
+                return 2;
+            }
+        };
+        testfile::run_isolated(&tests, only, |n| {
+            std::process::Command::new(&exe).arg("-t").arg(path).arg("-n").arg(n.to_string()).output()
+        })
+    } else {
+        // panics are caught and reported as failures below
+        std::panic::set_hook(Box::new(|_| {}));
+        testfile::run_all(&tests, only)
+    };
+    for failure in &failures {
+        println!("{}", failure);
+    }
+    println!("ran {} tests, {} failed", ran, failures.len());
+    if failures.is_empty() { 0 } else { 1 }
 }
     
     // //int retval = EXIT_SUCCESS;
@@ -0,0 +1,236 @@
+// A two dimensional matrix of bits, stored row after row in a BitArray.
+use crate::bitarray::{BitArray, BitSlice, BitSliceMut};
+
+mod gf2;
+mod graph;
+mod zorder;
+pub use self::zorder::{morton_decode, morton_encode, ZOrderMatrix};
+
+/// A rows x cols matrix of bits.  Element (r, c) is bit r * cols + c of
+/// the underlying array, so each row is a contiguous BitSlice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitMatrix {
+    rows: usize,
+    cols: usize,
+    bits: BitArray,
+}
+
+/// dst[dst_off ..][.. len] = f(dst, src[src_off ..][.. len]), a 64 bit
+/// word at a time.  The row operations of gf2 and graph are built on it.
+fn combine_bits<F>(dst: &mut BitArray, dst_off: usize, src: &BitArray, src_off: usize, len: usize,
+                   f: F)
+where F: Fn(u64, u64) -> u64 {
+    let mut done = 0;
+    while done < len {
+        let width = usize::min(64, len - done);
+        let x = f(dst.read_bits(dst_off + done, width), src.read_bits(src_off + done, width));
+        dst.write_bits(dst_off + done, width, x);
+        done += width;
+    }
+}
+
+/// Transposes a 64x64 block in place: bit c of a[r] ends up as bit r of
+/// a[c].  Swaps ever smaller off-diagonal sub-blocks, 32x32 down to 1x1.
+fn transpose_64(a: &mut [u64; 64]) {
+    let mut j = 32;
+    let mut m: u64 = 0x0000_0000_ffff_ffff;
+    while j != 0 {
+        let mut k = 0;
+        while k < 64 {
+            let t = ((a[k] >> j) ^ a[k + j]) & m;
+            a[k] ^= t << j;
+            a[k + j] ^= t;
+            k = (k + j + 1) & !j;
+        }
+        j >>= 1;
+        m ^= m << j;
+    }
+}
+
+impl BitMatrix {
+    /// A rows x cols matrix of zeros.
+    pub fn new(rows: usize, cols: usize) -> BitMatrix {
+        BitMatrix { rows, cols, bits: BitArray::new(rows * cols) }
+    }
+
+    /// The n x n identity matrix.
+    pub fn identity(n: usize) -> BitMatrix {
+        let mut m = BitMatrix::new(n, n);
+        for i in 0 .. n {
+            m.set(i, i, true);
+        }
+        m
+    }
+
+    /// Builds a matrix from strings of '0' and '1', one per row.  Unlike
+    /// BitArray::from_str the leftmost character is column 0, so the
+    /// strings read like the matrix.
+    pub fn from_rows(rows: &[&str]) -> BitMatrix {
+        let cols = rows.first().map_or(0, |r| r.len());
+        let mut m = BitMatrix::new(rows.len(), cols);
+        for (r, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), cols, "BitMatrix::from_rows rows differ in length");
+            for (c, b) in row.chars().enumerate() {
+                m.set(r, c, match b {
+                    '0' => false,
+                    '1' => true,
+                    _ => panic!("BitMatrix::from_rows gets bad input {}", b),
+                });
+            }
+        }
+        m
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    fn index(&self, r: usize, c: usize) -> usize {
+        assert!(r < self.rows && c < self.cols);
+        r * self.cols + c
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> bool {
+        self.bits.get(self.index(r, c))
+    }
+
+    pub fn set(&mut self, r: usize, c: usize, val: bool) {
+        let i = self.index(r, c);
+        self.bits.set(i, val)
+    }
+
+    pub fn row(&self, r: usize) -> BitSlice<'_> {
+        assert!(r < self.rows);
+        self.bits.slice(r * self.cols .. (r + 1) * self.cols)
+    }
+
+    pub fn row_mut(&mut self, r: usize) -> BitSliceMut<'_> {
+        assert!(r < self.rows);
+        self.bits.slice_mut(r * self.cols .. (r + 1) * self.cols)
+    }
+
+    /// A view of column c.  Its bits are cols apart in memory so it can't
+    /// be a BitSlice; use transpose().row(c) when that is needed.
+    pub fn col(&self, c: usize) -> Column<'_> {
+        assert!(c < self.cols);
+        Column { matrix: self, c }
+    }
+
+    /// The cols x rows transpose, built 64x64 blocks at a time: each block
+    /// is loaded as 64 row words, transposed in registers and stored as 64
+    /// row words of the result.
+    pub fn transpose(&self) -> BitMatrix {
+        let mut t = BitMatrix::new(self.cols, self.rows);
+        let mut block = [0u64; 64];
+        for br in (0 .. self.rows).step_by(64) {
+            let h = usize::min(64, self.rows - br);
+            for bc in (0 .. self.cols).step_by(64) {
+                let w = usize::min(64, self.cols - bc);
+                for (i, word) in block.iter_mut().enumerate() {
+                    *word = if i < h { self.bits.read_bits((br + i) * self.cols + bc, w) } else { 0 };
+                }
+                transpose_64(&mut block);
+                for (j, &word) in block.iter().take(w).enumerate() {
+                    t.bits.write_bits((bc + j) * t.cols + br, h, word);
+                }
+            }
+        }
+        t
+    }
+
+    /// The bits, row after row.
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+}
+
+/// A column of a BitMatrix, see BitMatrix::col.
+#[derive(Debug, Clone, Copy)]
+pub struct Column<'a> {
+    matrix: &'a BitMatrix,
+    c: usize,
+}
+
+impl<'a> Column<'a> {
+    pub fn len(&self) -> usize {
+        self.matrix.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matrix.rows == 0
+    }
+
+    /// Bit r of the column, that is element (r, c) of the matrix.
+    pub fn get(&self, r: usize) -> bool {
+        self.matrix.get(r, self.c)
+    }
+
+    pub fn to_bitarray(self) -> BitArray {
+        let mut ba = BitArray::new(self.len());
+        for r in 0 .. self.len() {
+            ba.set(r, self.get(r));
+        }
+        ba
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(rows: usize, cols: usize) -> BitMatrix {
+        let mut m = BitMatrix::new(rows, cols);
+        let mut ba = BitArray::new(rows * cols);
+        ba.randfill();
+        m.bits = ba;
+        m
+    }
+
+    #[test]
+    fn test_get_set_rows_cols() {
+        let mut m = BitMatrix::from_rows(&["100",
+                                           "011"]);
+        assert!(m.get(0, 0) && !m.get(0, 1) && m.get(1, 2));
+        m.set(0, 2, true);
+        assert_eq!(m.row(0).to_bitarray().show(), "101");
+        assert_eq!(m.col(2).to_bitarray().show(), "11");
+        assert_eq!(m.col(0).len(), 2);
+        m.row_mut(1).set(0, true);
+        assert!(m.get(1, 0));
+    }
+
+    #[test]
+    fn test_transpose_64() {
+        let mut a = [0u64; 64];
+        for (r, word) in a.iter_mut().enumerate() {
+            *word = (r as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+        let orig = a;
+        transpose_64(&mut a);
+        for (r, &row) in orig.iter().enumerate() {
+            for (c, &col) in a.iter().enumerate() {
+                assert_eq!((col >> r) & 1, (row >> c) & 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transpose() {
+        for &(rows, cols) in &[(0, 5), (1, 1), (3, 70), (64, 64), (130, 77)] {
+            let m = random(rows, cols);
+            let t = m.transpose();
+            assert_eq!((t.rows(), t.cols()), (cols, rows));
+            for r in 0 .. rows {
+                for c in 0 .. cols {
+                    assert_eq!(t.get(c, r), m.get(r, c));
+                }
+            }
+            assert_eq!(t.transpose(), m);
+        }
+        assert_eq!(BitMatrix::identity(100).transpose(), BitMatrix::identity(100));
+    }
+}
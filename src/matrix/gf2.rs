@@ -0,0 +1,183 @@
+// Linear algebra over GF(2), where adding is xor and multiplying is and.
+// Rows are combined 64 bits at a time with read_bits/write_bits.
+use super::{combine_bits, BitMatrix};
+use crate::bitarray::BitArray;
+
+fn xor_bits(dst: &mut BitArray, dst_off: usize, src: &BitArray, src_off: usize, len: usize) {
+    combine_bits(dst, dst_off, src, src_off, len, |a, b| a ^ b)
+}
+
+impl BitMatrix {
+    /// Adds (xors) row src into row dst.
+    pub fn xor_row(&mut self, src: usize, dst: usize) {
+        assert!(src < self.rows && dst < self.rows);
+        let row = self.row(src).to_bitarray();
+        let cols = self.cols;
+        xor_bits(&mut self.bits, dst * cols, &row, 0, cols);
+    }
+
+    pub fn swap_rows(&mut self, a: usize, b: usize) {
+        assert!(a < self.rows && b < self.rows);
+        if a == b {
+            return;
+        }
+        // xoring a ^ b into both rows swaps them
+        let mut diff = self.row(a).to_bitarray();
+        diff ^= &self.row(b).to_bitarray();
+        let cols = self.cols;
+        xor_bits(&mut self.bits, a * cols, &diff, 0, cols);
+        xor_bits(&mut self.bits, b * cols, &diff, 0, cols);
+    }
+
+    /// The product self * other.  Row i of the result is the xor of the
+    /// rows of other picked out by the ones in row i of self.
+    pub fn mul(&self, other: &BitMatrix) -> BitMatrix {
+        assert_eq!(self.cols, other.rows, "BitMatrix::mul of mismatched shapes");
+        let mut out = BitMatrix::new(self.rows, other.cols);
+        for i in 0 .. self.rows {
+            for j in (0 .. self.cols).filter(|&j| self.get(i, j)) {
+                xor_bits(&mut out.bits, i * other.cols, &other.bits, j * other.cols, other.cols);
+            }
+        }
+        out
+    }
+
+    /// The product self * v for a column vector v of cols bits.
+    pub fn mul_vec(&self, v: &BitArray) -> BitArray {
+        assert_eq!(v.get_bit_sz(), self.cols, "BitMatrix::mul_vec of mismatched shapes");
+        let mut out = BitArray::new(self.rows);
+        for i in 0 .. self.rows {
+            let mut parity = 0;
+            let mut done = 0;
+            while done < self.cols {
+                let width = usize::min(64, self.cols - done);
+                parity ^= (self.bits.read_bits(i * self.cols + done, width)
+                           & v.read_bits(done, width)).count_ones() & 1;
+                done += width;
+            }
+            out.set(i, parity == 1);
+        }
+        out
+    }
+
+    /// Gaussian elimination to reduced row echelon form, in place.  Returns
+    /// the pivot column of each non-zero row, so its length is the rank.
+    pub fn row_reduce(&mut self) -> Vec<usize> {
+        let mut pivots = vec![];
+        for c in 0 .. self.cols {
+            let r = pivots.len();
+            if r == self.rows {
+                break;
+            }
+            let p = match (r .. self.rows).find(|&i| self.get(i, c)) {
+                Some(p) => p,
+                None => continue,
+            };
+            self.swap_rows(p, r);
+            for i in 0 .. self.rows {
+                if i != r && self.get(i, c) {
+                    self.xor_row(r, i);
+                }
+            }
+            pivots.push(c);
+        }
+        pivots
+    }
+
+    pub fn rank(&self) -> usize {
+        self.clone().row_reduce().len()
+    }
+
+    /// A basis of the nullspace: vectors x of cols bits with self * x = 0.
+    /// There is one per free (non-pivot) column of the reduced matrix.
+    pub fn nullspace(&self) -> Vec<BitArray> {
+        let mut m = self.clone();
+        let pivots = m.row_reduce();
+        let mut basis = vec![];
+        let mut next_pivot = 0;
+        for f in 0 .. self.cols {
+            if next_pivot < pivots.len() && pivots[next_pivot] == f {
+                next_pivot += 1;
+                continue;
+            }
+            let mut x = BitArray::new(self.cols);
+            x.set(f, true);
+            for (r, &p) in pivots.iter().enumerate() {
+                if m.get(r, f) {
+                    x.set(p, true);
+                }
+            }
+            basis.push(x);
+        }
+        basis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(rows: usize, cols: usize, seed: u64) -> BitMatrix {
+        let mut m = BitMatrix::new(rows, cols);
+        m.bits.randfill_seeded(seed);
+        m
+    }
+
+    fn naive_mul(a: &BitMatrix, b: &BitMatrix) -> BitMatrix {
+        let mut out = BitMatrix::new(a.rows(), b.cols());
+        for i in 0 .. a.rows() {
+            for j in 0 .. b.cols() {
+                let dot = (0 .. a.cols()).filter(|&k| a.get(i, k) && b.get(k, j)).count();
+                out.set(i, j, dot % 2 == 1);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_mul() {
+        let a = random(9, 70, 1);
+        let b = random(70, 13, 2);
+        assert_eq!(a.mul(&b), naive_mul(&a, &b));
+        assert_eq!(a.mul(&BitMatrix::identity(70)), a);
+
+        let mut v = BitArray::new(70);
+        v.randfill_seeded(3);
+        let mut col = BitMatrix::new(70, 1);
+        for i in 0 .. 70 {
+            col.set(i, 0, v.get(i));
+        }
+        assert_eq!(a.mul_vec(&v), a.mul(&col).col(0).to_bitarray());
+    }
+
+    #[test]
+    fn test_row_reduce_and_rank() {
+        let mut m = BitMatrix::from_rows(&["110",
+                                           "011",
+                                           "101"]);
+        assert_eq!(m.rank(), 2);
+        assert_eq!(m.row_reduce(), vec![0, 1]);
+        assert_eq!(m, BitMatrix::from_rows(&["101",
+                                             "011",
+                                             "000"]));
+        assert_eq!(BitMatrix::identity(100).rank(), 100);
+        assert_eq!(BitMatrix::new(5, 7).rank(), 0);
+    }
+
+    #[test]
+    fn test_nullspace() {
+        let m = BitMatrix::from_rows(&["110",
+                                       "011",
+                                       "101"]);
+        let basis = m.nullspace();
+        assert_eq!(basis.len(), 1);
+        assert_eq!(basis[0].show(), "111");
+
+        let m = random(20, 90, 4);
+        let basis = m.nullspace();
+        assert_eq!(basis.len(), 90 - m.rank());
+        for x in &basis {
+            assert_eq!(m.mul_vec(x).count_ones(), 0);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// Using a square BitMatrix as the adjacency matrix of a directed graph:
+// row v holds the out-edges of vertex v, so whole frontiers of a search
+// are expanded with word-wide ors instead of one edge at a time.
+use super::{combine_bits, BitMatrix};
+use crate::bitarray::{BitArray, IterOnes};
+
+impl BitMatrix {
+    fn assert_square(&self) {
+        assert_eq!(self.rows, self.cols, "BitMatrix graph operations need a square matrix");
+    }
+
+    /// Adds the edge from -> to.
+    pub fn add_edge(&mut self, from: usize, to: usize) {
+        self.set(from, to, true)
+    }
+
+    pub fn has_edge(&self, from: usize, to: usize) -> bool {
+        self.get(from, to)
+    }
+
+    /// The vertices v has an edge to, in increasing order.
+    pub fn neighbors(&self, v: usize) -> IterOnes<'_> {
+        self.row(v).iter_ones()
+    }
+
+    /// One step of a bitmap BFS.  Returns the next frontier, the vertices
+    /// reachable by one edge from frontier that are not yet in visited,
+    /// and adds them to visited.
+    pub fn bfs_step(&self, frontier: &BitArray, visited: &mut BitArray) -> BitArray {
+        self.assert_square();
+        assert!(frontier.get_bit_sz() == self.rows && visited.get_bit_sz() == self.rows);
+        let n = self.cols;
+        let mut next = BitArray::new(n);
+        for v in frontier.iter_ones() {
+            combine_bits(&mut next, 0, &self.bits, v * n, n, |a, b| a | b);
+        }
+        combine_bits(&mut next, 0, visited, 0, n, |a, b| a & !b);
+        *visited |= &next;
+        next
+    }
+
+    /// BFS distances from source, None for vertices it can't reach.
+    pub fn bfs(&self, source: usize) -> Vec<Option<usize>> {
+        let mut dist = vec![None; self.rows];
+        let mut visited = BitArray::new(self.rows);
+        let mut frontier = BitArray::new(self.rows);
+        visited.set(source, true);
+        frontier.set(source, true);
+        let mut depth = 0;
+        while frontier.count_ones() > 0 {
+            for v in frontier.iter_ones() {
+                dist[v] = Some(depth);
+            }
+            frontier = self.bfs_step(&frontier, &mut visited);
+            depth += 1;
+        }
+        dist
+    }
+
+    /// Every vertex reachable from source, source included.
+    pub fn reachable_from(&self, source: usize) -> BitArray {
+        let mut visited = BitArray::new(self.rows);
+        let mut frontier = BitArray::new(self.rows);
+        visited.set(source, true);
+        frontier.set(source, true);
+        while frontier.count_ones() > 0 {
+            frontier = self.bfs_step(&frontier, &mut visited);
+        }
+        visited
+    }
+
+    /// The transitive closure: (u, v) is set when there is a path of one or
+    /// more edges from u to v.  Warshall's algorithm, with each inner loop
+    /// a word-wide or of two rows, so O(n^3 / 64).
+    pub fn transitive_closure(&self) -> BitMatrix {
+        self.assert_square();
+        let n = self.rows;
+        let mut c = self.clone();
+        for k in 0 .. n {
+            let row_k = c.row(k).to_bitarray();
+            for i in 0 .. n {
+                if c.get(i, k) {
+                    combine_bits(&mut c.bits, i * n, &row_k, 0, n, |a, b| a | b);
+                }
+            }
+        }
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0 -> 1 -> 2 -> 0, 2 -> 3, and 4 on its own with a self loop
+    fn example() -> BitMatrix {
+        let mut g = BitMatrix::new(5, 5);
+        for &(u, v) in &[(0, 1), (1, 2), (2, 0), (2, 3), (4, 4)] {
+            g.add_edge(u, v);
+        }
+        g
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let g = example();
+        assert_eq!(g.neighbors(2).collect::<Vec<_>>(), vec![0, 3]);
+        assert_eq!(g.neighbors(3).next(), None);
+        assert!(g.has_edge(4, 4) && !g.has_edge(3, 2));
+    }
+
+    #[test]
+    fn test_bfs() {
+        let g = example();
+        assert_eq!(g.bfs(0), vec![Some(0), Some(1), Some(2), Some(3), None]);
+        assert_eq!(g.bfs(3), vec![None, None, None, Some(0), None]);
+        assert_eq!(g.reachable_from(1).iter_ones().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_bfs_step() {
+        let g = example();
+        let mut visited = BitArray::from_str("00011");
+        let frontier = BitArray::from_str("00010");
+        assert_eq!(g.bfs_step(&frontier, &mut visited).show(), "00100");
+        assert_eq!(visited.show(), "00111");
+    }
+
+    #[test]
+    fn test_transitive_closure() {
+        let c = example().transitive_closure();
+        for u in 0 .. 3 {
+            assert_eq!(c.neighbors(u).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        }
+        assert_eq!(c.neighbors(3).next(), None);
+        assert_eq!(c.neighbors(4).collect::<Vec<_>>(), vec![4]);
+
+        // a path of 100 vertices closes to the strict upper triangle
+        let mut path = BitMatrix::new(100, 100);
+        for v in 0 .. 99 {
+            path.add_edge(v, v + 1);
+        }
+        let c = path.transitive_closure();
+        assert!((0 .. 100).all(|u| c.neighbors(u).eq(u + 1 .. 100)));
+    }
+}
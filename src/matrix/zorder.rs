@@ -0,0 +1,255 @@
+// A bit matrix stored along the Z (Morton) curve instead of row by row.
+// Every aligned 2^k x 2^k square is then a contiguous run of 4^k bits, so
+// rectangle operations break into a few big range operations and nearby
+// cells are close in memory in both directions.
+use std::ops::Range;
+
+use super::BitMatrix;
+use crate::bitarray::BitArray;
+
+/// Spreads the low 32 bits of x out to the even bit positions.
+fn spread(x: u64) -> u64 {
+    let mut x = x & 0xffff_ffff;
+    x = (x | x << 16) & 0x0000_ffff_0000_ffff;
+    x = (x | x << 8) & 0x00ff_00ff_00ff_00ff;
+    x = (x | x << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | x << 2) & 0x3333_3333_3333_3333;
+    (x | x << 1) & 0x5555_5555_5555_5555
+}
+
+/// Gathers the even bit positions of x back into the low 32 bits.
+fn compact(x: u64) -> u64 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | x >> 1) & 0x3333_3333_3333_3333;
+    x = (x | x >> 2) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | x >> 4) & 0x00ff_00ff_00ff_00ff;
+    x = (x | x >> 8) & 0x0000_ffff_0000_ffff;
+    (x | x >> 16) & 0xffff_ffff
+}
+
+/// The position of (r, c) along the Z curve: the bits of c and r
+/// interleaved, c in the even bits.
+pub fn morton_encode(r: usize, c: usize) -> usize {
+    (spread(c as u64) | spread(r as u64) << 1) as usize
+}
+
+/// The (r, c) at position z along the Z curve.
+pub fn morton_decode(z: usize) -> (usize, usize) {
+    (compact(z as u64 >> 1) as usize, compact(z as u64) as usize)
+}
+
+/// A rows x cols bit matrix in Z order.  The matrix is cut along its
+/// longer side into power of two squares as big as its shorter side
+/// rounds up to, and the curve runs through each square in turn, so a
+/// long thin matrix costs no more than about four times its cells.  The
+/// cells outside the matrix are always clear.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZOrderMatrix {
+    rows: usize,
+    cols: usize,
+    /// The side of each square.
+    side: usize,
+    bits: BitArray,
+}
+
+impl ZOrderMatrix {
+    pub fn new(rows: usize, cols: usize) -> ZOrderMatrix {
+        let side = usize::max(1, usize::min(rows, cols)).next_power_of_two();
+        let squares = if rows == 0 || cols == 0 { 0 } else { usize::max(rows, cols).div_ceil(side) };
+        ZOrderMatrix { rows, cols, side, bits: BitArray::new(squares * side * side) }
+    }
+
+    pub fn from_matrix(m: &BitMatrix) -> ZOrderMatrix {
+        let mut z = ZOrderMatrix::new(m.rows(), m.cols());
+        for r in 0 .. m.rows() {
+            for c in m.row(r).iter_ones() {
+                z.set(r, c, true);
+            }
+        }
+        z
+    }
+
+    pub fn to_matrix(&self) -> BitMatrix {
+        let mut m = BitMatrix::new(self.rows, self.cols);
+        for z in self.bits.iter_ones() {
+            let (r, c) = self.cell(z);
+            m.set(r, c, true);
+        }
+        m
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Whether the squares run down the rows rather than across the
+    /// columns.
+    fn tall(&self) -> bool {
+        self.rows > self.cols
+    }
+
+    /// The row and column where square k starts.
+    fn square_origin(&self, k: usize) -> (usize, usize) {
+        if self.tall() { (k * self.side, 0) } else { (0, k * self.side) }
+    }
+
+    /// The position of (r, c) in bits.
+    fn index(&self, r: usize, c: usize) -> usize {
+        let k = if self.tall() { r / self.side } else { c / self.side };
+        k * self.side * self.side + morton_encode(r % self.side, c % self.side)
+    }
+
+    /// The (r, c) at position z in bits.
+    fn cell(&self, z: usize) -> (usize, usize) {
+        let area = self.side * self.side;
+        let (r0, c0) = self.square_origin(z / area);
+        let (r, c) = morton_decode(z % area);
+        (r0 + r, c0 + c)
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> bool {
+        assert!(r < self.rows && c < self.cols);
+        self.bits.get(self.index(r, c))
+    }
+
+    pub fn set(&mut self, r: usize, c: usize, val: bool) {
+        assert!(r < self.rows && c < self.cols);
+        self.bits.set(self.index(r, c), val)
+    }
+
+    /// Calls f(z_start, len) for a set of disjoint Z curve runs that
+    /// exactly cover the rectangle, by splitting each square it touches
+    /// into quadrants until each piece is inside or outside.
+    fn cover<F>(&self, rows: &Range<usize>, cols: &Range<usize>, f: &mut F)
+    where F: FnMut(usize, usize) {
+        assert!(rows.end <= self.rows && cols.end <= self.cols);
+        if rows.start >= rows.end || cols.start >= cols.end {
+            return;
+        }
+        let along = if self.tall() { rows } else { cols };
+        for k in along.start / self.side ..= (along.end - 1) / self.side {
+            let (r0, c0) = self.square_origin(k);
+            self.cover_quad(r0, c0, self.side, rows, cols, f)
+        }
+    }
+
+    fn cover_quad<F>(&self, r0: usize, c0: usize, size: usize,
+                     rows: &Range<usize>, cols: &Range<usize>, f: &mut F)
+    where F: FnMut(usize, usize) {
+        if r0 >= rows.end || r0 + size <= rows.start || c0 >= cols.end || c0 + size <= cols.start {
+            return;
+        }
+        if rows.start <= r0 && r0 + size <= rows.end && cols.start <= c0 && c0 + size <= cols.end {
+            // the piece is aligned, so its run starts at its corner
+            return f(self.index(r0, c0), size * size);
+        }
+        let h = size / 2;
+        self.cover_quad(r0, c0, h, rows, cols, f);
+        self.cover_quad(r0, c0 + h, h, rows, cols, f);
+        self.cover_quad(r0 + h, c0, h, rows, cols, f);
+        self.cover_quad(r0 + h, c0 + h, h, rows, cols, f);
+    }
+
+    /// The number of set cells in the rectangle rows x cols.
+    pub fn count_ones_rect(&self, rows: Range<usize>, cols: Range<usize>) -> usize {
+        let mut ones = 0;
+        self.cover(&rows, &cols, &mut |z, len| ones += self.bits.count_ones_range(z, len));
+        ones
+    }
+
+    /// Sets every cell in the rectangle rows x cols to val.
+    pub fn fill_rect(&mut self, rows: Range<usize>, cols: Range<usize>, val: bool) {
+        let mut runs = vec![];
+        self.cover(&rows, &cols, &mut |z, len| runs.push((z, len)));
+        for (z, len) in runs {
+            self.bits.fill_range(z, len, val);
+        }
+    }
+
+    /// The bits in Z order, square after square.
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_morton() {
+        assert_eq!(morton_encode(0, 0), 0);
+        assert_eq!(morton_encode(0, 1), 1);
+        assert_eq!(morton_encode(1, 0), 2);
+        assert_eq!(morton_encode(1, 1), 3);
+        assert_eq!(morton_encode(2, 3), 0b1101);
+        for &(r, c) in &[(0, 0), (5, 9), (1000, 3), (123_456, 654_321)] {
+            assert_eq!(morton_decode(morton_encode(r, c)), (r, c));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut m = BitMatrix::new(37, 20);
+        for r in 0 .. 37 {
+            for c in 0 .. 20 {
+                m.set(r, c, (r * 31 + c * 7) % 5 == 0);
+            }
+        }
+        let z = ZOrderMatrix::from_matrix(&m);
+        assert_eq!(z.to_matrix(), m);
+        assert_eq!(z.get(5, 5), m.get(5, 5));
+        // two 32 x 32 squares, one above the other
+        assert_eq!(z.as_bitarray().get_bit_sz(), 2 * 32 * 32);
+    }
+
+    #[test]
+    fn test_rects() {
+        let mut z = ZOrderMatrix::new(100, 70);
+        z.fill_rect(10 .. 50, 3 .. 61, true);
+        assert_eq!(z.count_ones_rect(0 .. 100, 0 .. 70), 40 * 58);
+        assert_eq!(z.count_ones_rect(0 .. 11, 0 .. 4), 1);
+        assert_eq!(z.count_ones_rect(49 .. 100, 60 .. 70), 1);
+        assert!(z.get(10, 3) && !z.get(9, 3) && !z.get(10, 61));
+        z.fill_rect(20 .. 30, 0 .. 70, false);
+        assert_eq!(z.count_ones_rect(0 .. 100, 0 .. 70), 30 * 58);
+        assert_eq!(z.count_ones_rect(5 .. 5, 0 .. 70), 0);
+
+        let m = z.to_matrix();
+        let naive = (12 .. 77).map(|r| (1 .. 66).filter(|&c| m.get(r, c)).count()).sum::<usize>();
+        assert_eq!(z.count_ones_rect(12 .. 77, 1 .. 66), naive);
+    }
+
+    #[test]
+    fn test_thin() {
+        // a square as big as the long side would be 2^40 bits
+        let mut z = ZOrderMatrix::new(1, 1 << 20);
+        assert_eq!(z.as_bitarray().get_bit_sz(), 1 << 20);
+        z.set(0, (1 << 20) - 1, true);
+        z.fill_rect(0 .. 1, 1000 .. 3000, true);
+        assert_eq!(z.count_ones_rect(0 .. 1, 0 .. 1 << 20), 2001);
+
+        for &(rows, cols) in &[(3, 41), (41, 3), (5, 5), (0, 9), (9, 0), (17, 64)] {
+            let mut m = BitMatrix::new(rows, cols);
+            for r in 0 .. rows {
+                for c in 0 .. cols {
+                    m.set(r, c, (r * 13 + c * 5) % 3 == 0);
+                }
+            }
+            let mut z = ZOrderMatrix::from_matrix(&m);
+            assert_eq!(z.to_matrix(), m);
+            let ones = z.count_ones_rect(0 .. rows, 0 .. cols);
+            assert!(z.as_bitarray().get_bit_sz() <= 4 * usize::max(1, rows * cols));
+            if rows > 2 && cols > 2 {
+                let naive = (1 .. rows - 1).map(|r| (2 .. cols).filter(|&c| m.get(r, c)).count()).sum::<usize>();
+                assert_eq!(z.count_ones_rect(1 .. rows - 1, 2 .. cols), naive);
+                z.fill_rect(1 .. rows - 1, 2 .. cols, false);
+                assert_eq!(z.count_ones_rect(0 .. rows, 0 .. cols), ones - naive);
+            }
+        }
+    }
+}
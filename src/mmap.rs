@@ -0,0 +1,257 @@
+// A bit array whose words live in a memory-mapped file instead of on the
+// heap, for arrays bigger than RAM: the kernel pages words in as they are
+// touched and writes dirty pages back when it likes, or at once on flush.
+// Unix only, behind the mmap feature.
+//
+// The file is the words and nothing else, 8 * ceil(bit_sz / 64) bytes in
+// the machine's byte order, bit i in bit i % 64 of word i / 64 as in
+// BitArray.  The size in bits is not stored, so open is told it.  A new
+// file is created sparse, and the zeros cost nothing until they are
+// written.
+//
+// Rotation is by three reversals, each of which streams through its range
+// from both ends at once, which is the access pattern the page cache
+// handles best; the word moves rotate uses in memory jump about more.
+// The reversals and the counting are BitArray's own kernels run over the
+// mapped words.
+//
+// The words are only as stable as the file: a mapping is shared with
+// everything else that has the file open, so create and open are unsafe,
+// and their callers promise nothing else touches the file meanwhile.
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+use crate::bitarray::{kernels, BitArray};
+
+/// A bit array backed by a file mapped into memory, see the module
+/// comment.  Changes reach the file when the kernel writes the pages back,
+/// at the latest when the array is dropped and unmapped; flush forces
+/// them out and waits.
+#[derive(Debug)]
+pub struct BitArrayMmap {
+    bit_sz: usize,
+    /// The start of the mapping, dangling when there are no words.
+    ptr: NonNull<u64>,
+    words: usize,
+}
+
+// The mapping is owned by the array, like a Vec's buffer, and only
+// reached through it.
+unsafe impl Send for BitArrayMmap {}
+unsafe impl Sync for BitArrayMmap {}
+
+impl BitArrayMmap {
+    /// A zeroed array of bit_sz bits in a new file at path, replacing
+    /// whatever was there.
+    ///
+    /// # Safety
+    ///
+    /// Until the array is dropped, nothing else, in this process or
+    /// another, may write to, truncate or map the file.  Writes would
+    /// change the words under the array's references, which is undefined
+    /// behaviour, and a truncated file raises SIGBUS at the next touch of
+    /// a word past its end.
+    pub unsafe fn create<P: AsRef<Path>>(path: P, bit_sz: usize) -> io::Result<BitArrayMmap> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let words = BitArray::words_for(bit_sz);
+        file.set_len(8 * words as u64)?;
+        BitArrayMmap::map(file, bit_sz, words)
+    }
+
+    /// The array of bit_sz bits in the file at path, which must be exactly
+    /// the size create would make it, with no bits set past bit_sz.  The
+    /// file is not changed until the array is.
+    ///
+    /// # Safety
+    ///
+    /// As for create: nothing else may write to, truncate or map the file
+    /// until the array is dropped.
+    pub unsafe fn open<P: AsRef<Path>>(path: P, bit_sz: usize) -> io::Result<BitArrayMmap> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let words = BitArray::words_for(bit_sz);
+        let len = file.metadata()?.len();
+        if len != 8 * words as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("{} bits need a {} byte file, not {}", bit_sz, 8 * words, len)));
+        }
+        let arr = BitArrayMmap::map(file, bit_sz, words)?;
+        if !bit_sz.is_multiple_of(64) && arr.words()[words - 1] >> (bit_sz % 64) != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bits set past bit {}", bit_sz)));
+        }
+        Ok(arr)
+    }
+
+    fn map(file: File, bit_sz: usize, words: usize) -> io::Result<BitArrayMmap> {
+        // mmap refuses an empty mapping
+        if words == 0 {
+            return Ok(BitArrayMmap { bit_sz, ptr: NonNull::dangling(), words });
+        }
+        let p = unsafe {
+            libc::mmap(ptr::null_mut(), 8 * words, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED,
+                       file.as_raw_fd(), 0)
+        };
+        if p == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // a mapping is page aligned, so aligned for u64, and it keeps the
+        // file open after file is closed
+        Ok(BitArrayMmap { bit_sz, ptr: NonNull::new(p as *mut u64).unwrap(), words })
+    }
+
+    fn words(&self) -> &[u64] {
+        // the mapping is words long and lives as long as self
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.words) }
+    }
+
+    fn words_mut(&mut self) -> &mut [u64] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.words) }
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bit_sz
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        assert!(bit_index < self.bit_sz);
+        self.words()[bit_index / 64] & (1 << (bit_index % 64)) != 0
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        assert!(bit_index < self.bit_sz);
+        let mask = 1 << (bit_index % 64);
+        let word = &mut self.words_mut()[bit_index / 64];
+        if val {
+            *word |= mask
+        } else {
+            *word &= !mask
+        }
+    }
+
+    /// As BitArray::rotate: rotates [bit_offset, bit_offset + bit_length)
+    /// right by bit_right_amount, left if it is negative.
+    pub fn rotate(&mut self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        if bit_length == 0 {
+            return;
+        }
+        let right = BitArray::modulo(bit_right_amount, bit_length);
+        let left = (bit_length - right) % bit_length;
+        if left == 0 {
+            return;
+        }
+        let (start, mid, end) = (bit_offset, bit_offset + left, bit_offset + bit_length);
+        let words = self.words_mut();
+        kernels::reverse_by_words(words, start, mid);
+        kernels::reverse_by_words(words, mid, end);
+        kernels::reverse_by_words(words, start, end);
+    }
+
+    /// The number of bits set to one, a word at a time.  The bits past
+    /// bit_sz are always zero.
+    pub fn count_ones(&self) -> usize {
+        kernels::count_ones(self.words())
+    }
+
+    /// Writes every change so far to the file and waits for the writes to
+    /// finish.
+    pub fn flush(&self) -> io::Result<()> {
+        if self.words == 0 {
+            return Ok(());
+        }
+        let ret = unsafe { libc::msync(self.ptr.as_ptr() as *mut libc::c_void, 8 * self.words, libc::MS_SYNC) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BitArrayMmap {
+    fn drop(&mut self) {
+        if self.words > 0 {
+            unsafe { libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, 8 * self.words) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Each test has a file of its own, which nothing else touches, so the
+    // unsafe create and open calls keep their contract.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("everybit-mmap-{}-{}", std::process::id(), name))
+    }
+
+    /// The same bits in a BitArray.
+    fn to_bitarray(arr: &BitArrayMmap) -> BitArray {
+        let mut ba = BitArray::new(arr.get_bit_sz());
+        for i in 0 .. arr.get_bit_sz() {
+            ba.set(i, arr.get(i));
+        }
+        ba
+    }
+
+    #[test]
+    fn test_matches_bitarray() {
+        let path = temp_path("match");
+        let mut arr = unsafe { BitArrayMmap::create(&path, 1000) }.unwrap();
+        let mut ba = BitArray::new(1000);
+        ba.randfill_seeded(7);
+        for i in 0 .. 1000 {
+            arr.set(i, ba.get(i));
+        }
+        assert_eq!(arr.count_ones(), ba.count_ones());
+        for &(offset, length, amount) in &[(0, 1000, 1), (3, 990, -337), (64, 128, 64), (7, 8, 3), (5, 0, 2),
+                                           (1, 999, isize::MIN)] {
+            arr.rotate(offset, length, amount);
+            ba.rotate(offset, length, amount);
+            assert_eq!(to_bitarray(&arr), ba, "{} {} {}", offset, length, amount);
+        }
+        drop(arr);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_flush_and_reopen() {
+        let path = temp_path("reopen");
+        let mut arr = unsafe { BitArrayMmap::create(&path, 100) }.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 16);
+        arr.set(0, true);
+        arr.set(99, true);
+        arr.rotate(0, 100, 1);
+        arr.set(80, true);
+        arr.flush().unwrap();
+        drop(arr);
+        let arr = unsafe { BitArrayMmap::open(&path, 100) }.unwrap();
+        assert_eq!((arr.get(1), arr.get(0), arr.get(80), arr.count_ones()), (true, true, true, 3));
+        drop(arr);
+        // bit 80 is padding to a 65 bit array, and open leaves it be
+        let before = std::fs::read(&path).unwrap();
+        let err = unsafe { BitArrayMmap::open(&path, 65) }.unwrap_err();
+        assert_eq!(err.to_string(), "bits set past bit 65");
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert_eq!(unsafe { BitArrayMmap::open(&path, 81) }.unwrap().count_ones(), 3);
+        let err = unsafe { BitArrayMmap::open(&path, 200) }.unwrap_err();
+        assert_eq!(err.to_string(), "200 bits need a 32 byte file, not 16");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_empty() {
+        let path = temp_path("empty");
+        let mut arr = unsafe { BitArrayMmap::create(&path, 0) }.unwrap();
+        arr.rotate(0, 0, 5);
+        assert_eq!(arr.count_ones(), 0);
+        arr.flush().unwrap();
+        drop(arr);
+        assert_eq!(unsafe { BitArrayMmap::open(&path, 0) }.unwrap().get_bit_sz(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
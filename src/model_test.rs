@@ -0,0 +1,478 @@
+// A state machine test driver (`everybit model-test`).  A test case is a
+// sequence of actions done both to a BitArray and to a Vec<bool> model,
+// starting from an empty array; the two are compared after every action.
+// When a sequence fails, panics included, it is shrunk to a minimal one
+// that still fails, so a bug that needs a resize, then a rotate, then a
+// slice to show up is reported as just those three actions.
+//
+// Sequences come from a seeded generator or from a file with one action
+// per line, written the way Action displays:
+//
+//     resize 100
+//     write_bits 0 64 12345
+//     rotate 3 90 -7
+//     slice 2 50
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bitarray::BitArray;
+
+/// The largest array the generator resizes to.
+pub const MAX_MODEL_BITS: usize = 300;
+
+/// One step of a test case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Resize { bit_sz: usize },
+    Set { bit_index: usize, val: bool },
+    FillRange { bit_offset: usize, bit_length: usize, val: bool },
+    WriteBits { bit_offset: usize, bit_width: usize, value: u64 },
+    Rotate { bit_offset: usize, bit_length: usize, bit_right_amount: isize },
+    Reverse { bit_offset: usize, bit_length: usize },
+    Invert,
+    /// Replace the array with a copy of [bit_offset, bit_offset + bit_length).
+    Slice { bit_offset: usize, bit_length: usize },
+}
+
+fn fits(offset: usize, len: usize, bit_sz: usize) -> bool {
+    offset.checked_add(len).is_some_and(|end| end <= bit_sz)
+}
+
+impl Action {
+    /// Whether the action is valid on an array of bit_sz bits.  Invalid
+    /// actions are skipped, which keeps a sequence meaningful when the
+    /// shrinker deletes the resize that made room for them.
+    pub fn applies_to(&self, bit_sz: usize) -> bool {
+        match *self {
+            Action::Resize { .. } | Action::Invert => true,
+            Action::Set { bit_index, .. } => bit_index < bit_sz,
+            Action::FillRange { bit_offset, bit_length, .. }
+            | Action::Reverse { bit_offset, bit_length }
+            | Action::Slice { bit_offset, bit_length } => fits(bit_offset, bit_length, bit_sz),
+            Action::WriteBits { bit_offset, bit_width, .. } => {
+                bit_width <= 64 && fits(bit_offset, bit_width, bit_sz)
+            }
+            Action::Rotate { bit_offset, bit_length, .. } => fits(bit_offset, bit_length, bit_sz),
+        }
+    }
+
+    /// Does the action to the real array.
+    pub fn apply(&self, ba: &mut BitArray) {
+        match *self {
+            Action::Resize { bit_sz } => ba.resize(bit_sz),
+            Action::Set { bit_index, val } => ba.set(bit_index, val),
+            Action::FillRange { bit_offset, bit_length, val } => ba.fill_range(bit_offset, bit_length, val),
+            Action::WriteBits { bit_offset, bit_width, value } => ba.write_bits(bit_offset, bit_width, value),
+            Action::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                ba.rotate(bit_offset, bit_length, bit_right_amount)
+            }
+            Action::Reverse { bit_offset, bit_length } => ba.reverse_range(bit_offset, bit_length),
+            Action::Invert => ba.invert(),
+            Action::Slice { bit_offset, bit_length } => {
+                *ba = ba.slice(bit_offset .. bit_offset + bit_length).to_bitarray()
+            }
+        }
+    }
+
+    /// Does the action to the model, one bool at a time.
+    pub fn apply_model(&self, model: &mut Vec<bool>) {
+        match *self {
+            Action::Resize { bit_sz } => model.resize(bit_sz, false),
+            Action::Set { bit_index, val } => model[bit_index] = val,
+            Action::FillRange { bit_offset, bit_length, val } => {
+                for b in &mut model[bit_offset .. bit_offset + bit_length] {
+                    *b = val;
+                }
+            }
+            Action::WriteBits { bit_offset, bit_width, value } => {
+                for i in 0 .. bit_width {
+                    model[bit_offset + i] = (value >> i) & 1 == 1;
+                }
+            }
+            Action::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                let old = model.clone();
+                for j in 0 .. bit_length {
+                    let to = (j as i128 + bit_right_amount as i128).rem_euclid(bit_length as i128);
+                    model[bit_offset + to as usize] = old[bit_offset + j];
+                }
+            }
+            Action::Reverse { bit_offset, bit_length } => {
+                model[bit_offset .. bit_offset + bit_length].reverse()
+            }
+            Action::Invert => {
+                for b in model.iter_mut() {
+                    *b = !*b;
+                }
+            }
+            Action::Slice { bit_offset, bit_length } => {
+                *model = model[bit_offset .. bit_offset + bit_length].to_vec()
+            }
+        }
+    }
+
+    /// Simpler versions of the action, for the shrinker: smaller sizes,
+    /// shorter ranges and smaller amounts.
+    fn simpler(&self) -> Vec<Action> {
+        let smaller = |n: usize| -> Vec<usize> {
+            let mut v = vec![0, n / 2, n.saturating_sub(1)];
+            v.retain(|&m| m < n);
+            v.dedup();
+            v
+        };
+        match *self {
+            Action::Resize { bit_sz } => {
+                smaller(bit_sz).into_iter().map(|bit_sz| Action::Resize { bit_sz }).collect()
+            }
+            Action::Set { bit_index, val } => {
+                smaller(bit_index).into_iter().map(|bit_index| Action::Set { bit_index, val }).collect()
+            }
+            Action::FillRange { bit_offset, bit_length, val } => smaller(bit_length).into_iter()
+                .map(|bit_length| Action::FillRange { bit_offset, bit_length, val }).collect(),
+            Action::WriteBits { bit_offset, bit_width, value } => {
+                let mut v: Vec<Action> = smaller(bit_width).into_iter()
+                    .map(|bit_width| Action::WriteBits { bit_offset, bit_width, value }).collect();
+                if value != 0 {
+                    v.push(Action::WriteBits { bit_offset, bit_width, value: value & (value - 1) });
+                }
+                v
+            }
+            Action::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                let mut v: Vec<Action> = smaller(bit_length).into_iter()
+                    .map(|bit_length| Action::Rotate { bit_offset, bit_length, bit_right_amount })
+                    .collect();
+                v.extend(smaller(bit_offset).into_iter()
+                    .map(|bit_offset| Action::Rotate { bit_offset, bit_length, bit_right_amount }));
+                let a = bit_right_amount;
+                for &bit_right_amount in &[0, a / 2, a - a.signum()] {
+                    if bit_right_amount != a {
+                        v.push(Action::Rotate { bit_offset, bit_length, bit_right_amount });
+                    }
+                }
+                v
+            }
+            Action::Reverse { bit_offset, bit_length } => smaller(bit_length).into_iter()
+                .map(|bit_length| Action::Reverse { bit_offset, bit_length }).collect(),
+            Action::Invert => vec![],
+            Action::Slice { bit_offset, bit_length } => smaller(bit_offset).into_iter()
+                .map(|bit_offset| Action::Slice { bit_offset, bit_length }).collect(),
+        }
+    }
+
+    /// A random action valid on an array of bit_sz bits.
+    pub fn random<R: Rng>(rng: &mut R, bit_sz: usize) -> Action {
+        if bit_sz == 0 || rng.gen_range(0, 12) == 0 {
+            return Action::Resize { bit_sz: rng.gen_range(0, MAX_MODEL_BITS + 1) };
+        }
+        let (bit_offset, bit_length) = random_range(rng, bit_sz);
+        match rng.gen_range(0, 7) {
+            0 => Action::Set { bit_index: rng.gen_range(0, bit_sz), val: rng.gen() },
+            1 => Action::FillRange { bit_offset, bit_length, val: rng.gen() },
+            2 => {
+                let bit_width = usize::min(bit_length, 64);
+                Action::WriteBits { bit_offset, bit_width, value: rng.gen::<u64>() & low_mask(bit_width) }
+            }
+            3 => {
+                let (bit_offset, bit_length) = random_range(rng, bit_sz);
+                let reach = 2 * bit_length as isize + 1;
+                Action::Rotate { bit_offset, bit_length, bit_right_amount: rng.gen_range(-reach, reach + 1) }
+            }
+            4 => Action::Reverse { bit_offset, bit_length },
+            5 => Action::Invert,
+            _ => Action::Slice { bit_offset, bit_length },
+        }
+    }
+}
+
+/// A random range of an array of bit_sz bits, possibly empty.
+fn random_range<R: Rng>(rng: &mut R, bit_sz: usize) -> (usize, usize) {
+    let offset = rng.gen_range(0, bit_sz + 1);
+    (offset, rng.gen_range(0, bit_sz - offset + 1))
+}
+
+fn low_mask(width: usize) -> u64 {
+    if width == 64 { u64::MAX } else { (1 << width) - 1 }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Resize { bit_sz } => write!(f, "resize {}", bit_sz),
+            Action::Set { bit_index, val } => write!(f, "set {} {}", bit_index, val as u8),
+            Action::FillRange { bit_offset, bit_length, val } => {
+                write!(f, "fill_range {} {} {}", bit_offset, bit_length, val as u8)
+            }
+            Action::WriteBits { bit_offset, bit_width, value } => {
+                write!(f, "write_bits {} {} {}", bit_offset, bit_width, value)
+            }
+            Action::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                write!(f, "rotate {} {} {}", bit_offset, bit_length, bit_right_amount)
+            }
+            Action::Reverse { bit_offset, bit_length } => write!(f, "reverse {} {}", bit_offset, bit_length),
+            Action::Invert => write!(f, "invert"),
+            Action::Slice { bit_offset, bit_length } => write!(f, "slice {} {}", bit_offset, bit_length),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Action, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let arg = |i: usize| -> Result<&str, String> {
+            words.get(i + 1).copied().ok_or(format!("{:?} is missing argument {}", s, i + 1))
+        };
+        let num = |i: usize| -> Result<usize, String> {
+            arg(i)?.parse().map_err(|_| format!("bad number {:?}", words[i + 1]))
+        };
+        let bit = |i: usize| -> Result<bool, String> {
+            match arg(i)? {
+                "0" => Ok(false),
+                "1" => Ok(true),
+                w => Err(format!("bad bit {:?}", w)),
+            }
+        };
+        let (action, argc) = match words.first().copied().unwrap_or("") {
+            "resize" => (Action::Resize { bit_sz: num(0)? }, 1),
+            "set" => (Action::Set { bit_index: num(0)?, val: bit(1)? }, 2),
+            "fill_range" => (Action::FillRange { bit_offset: num(0)?, bit_length: num(1)?, val: bit(2)? }, 3),
+            "write_bits" => {
+                let value = arg(2)?.parse().map_err(|_| format!("bad value {:?}", words[3]))?;
+                (Action::WriteBits { bit_offset: num(0)?, bit_width: num(1)?, value }, 3)
+            }
+            "rotate" => {
+                let amount = arg(2)?.parse().map_err(|_| format!("bad amount {:?}", words[3]))?;
+                (Action::Rotate { bit_offset: num(0)?, bit_length: num(1)?, bit_right_amount: amount }, 3)
+            }
+            "reverse" => (Action::Reverse { bit_offset: num(0)?, bit_length: num(1)? }, 2),
+            "invert" => (Action::Invert, 0),
+            "slice" => (Action::Slice { bit_offset: num(0)?, bit_length: num(1)? }, 2),
+            w => return Err(format!("unknown action {:?}", w)),
+        };
+        if words.len() != argc + 1 {
+            return Err(format!("{:?} takes {} argument(s)", words[0], argc));
+        }
+        Ok(action)
+    }
+}
+
+/// Parses a file of actions, one per line.  Blank lines and lines
+/// starting with # are skipped; errors carry the (1 based) line number.
+pub fn parse_actions(text: &str) -> Result<Vec<Action>, String> {
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| line.parse().map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// How a sequence of actions went wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// Index of the failing action in the sequence.
+    pub step: usize,
+    pub action: Action,
+    /// A mismatch with the model, or the panic message.
+    pub msg: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {} ({}): {}", self.step, self.action, self.msg)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+fn compare(ba: &BitArray, model: &[bool]) -> Result<(), String> {
+    if ba.get_bit_sz() != model.len() {
+        return Err(format!("size is {}, model has {}", ba.get_bit_sz(), model.len()));
+    }
+    if let Some(i) = (0 .. model.len()).find(|&i| ba.get(i) != model[i]) {
+        return Err(format!("bit {} is {}, model has {}", i, ba.get(i) as u8, model[i] as u8));
+    }
+    let ones = model.iter().filter(|&&b| b).count();
+    if ba.count_ones() != ones {
+        return Err(format!("count_ones is {}, model has {}", ba.count_ones(), ones));
+    }
+    Ok(())
+}
+
+/// The message of a caught panic, as "panicked: ...".
+pub(crate) fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(s) => format!("panicked: {}", s),
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(s) => format!("panicked: {}", s),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+/// Runs the actions from an empty array, skipping the ones that do not
+/// apply, and checks the array against the model after each.
+pub fn run(actions: &[Action]) -> Result<(), Failure> {
+    let mut ba = BitArray::new(0);
+    let mut model: Vec<bool> = vec![];
+    for (step, action) in actions.iter().enumerate() {
+        if !action.applies_to(model.len()) {
+            continue;
+        }
+        let fail = |msg: String| Failure { step, action: action.clone(), msg };
+        panic::catch_unwind(AssertUnwindSafe(|| action.apply(&mut ba)))
+            .map_err(|payload| fail(panic_message(payload)))?;
+        action.apply_model(&mut model);
+        compare(&ba, &model).map_err(fail)?;
+    }
+    Ok(())
+}
+
+/// A random sequence of steps actions, starting with a resize.
+pub fn random_actions<R: Rng>(rng: &mut R, steps: usize) -> Vec<Action> {
+    let mut bit_sz = 0;
+    (0 .. steps).map(|_| {
+        let action = Action::random(rng, bit_sz);
+        let mut model_sz = vec![false; bit_sz];
+        action.apply_model(&mut model_sz);
+        bit_sz = model_sz.len();
+        action
+    }).collect()
+}
+
+/// Shrinks a sequence for which fails is true to a smaller one for which
+/// it is still true: first by deleting runs of actions, halving the run
+/// length down to single actions, then by simplifying actions one at a
+/// time, until neither makes progress.
+pub fn shrink<F: Fn(&[Action]) -> bool>(actions: &[Action], fails: F) -> Vec<Action> {
+    let mut cur = actions.to_vec();
+    loop {
+        let mut progress = false;
+        let mut chunk = usize::max(cur.len() / 2, 1);
+        while chunk > 0 {
+            let mut i = 0;
+            while i < cur.len() {
+                let mut candidate = cur.clone();
+                candidate.drain(i .. usize::min(i + chunk, cur.len()));
+                if fails(&candidate) {
+                    cur = candidate;
+                    progress = true;
+                } else {
+                    i += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        for i in 0 .. cur.len() {
+            for simpler in cur[i].simpler() {
+                let mut candidate = cur.clone();
+                candidate[i] = simpler;
+                if fails(&candidate) {
+                    cur = candidate;
+                    progress = true;
+                    break;
+                }
+            }
+        }
+        if !progress {
+            return cur;
+        }
+    }
+}
+
+/// Runs random sequences from seed until one fails, and returns that
+/// one shrunk along with its failure.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "info"))]
+pub fn search(runs: usize, steps: usize, seed: u64) -> Option<(Vec<Action>, Failure)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0 .. runs {
+        let actions = random_actions(&mut rng, steps);
+        if run(&actions).is_err() {
+            let shrunk = shrink(&actions, |a| run(a).is_err());
+            let failure = run(&shrunk).unwrap_err();
+            return Some((shrunk, failure));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let text = "# a comment\nresize 100\n\nwrite_bits 0 64 12345\nrotate 3 90 -7\nslice 2 50\ninvert\n";
+        let actions = parse_actions(text).unwrap();
+        assert_eq!(actions.len(), 5);
+        assert_eq!(actions[2], Action::Rotate { bit_offset: 3, bit_length: 90, bit_right_amount: -7 });
+        for a in &actions {
+            assert_eq!(&a.to_string().parse::<Action>().unwrap(), a);
+        }
+        assert_eq!(parse_actions("resize 1\nset 0 2").unwrap_err(), "line 2: bad bit \"2\"");
+        assert!(parse_actions("invert 3").is_err());
+        assert!(parse_actions("spin 3").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let actions = parse_actions("resize 100\nwrite_bits 60 8 255\nrotate 3 90 -7\nslice 2 50\n\
+                                     resize 70\nreverse 0 70\nset 69 1\nfill_range 10 10 1").unwrap();
+        assert_eq!(run(&actions), Ok(()));
+        // out of range actions are skipped
+        assert_eq!(run(&parse_actions("set 5 1\nresize 3\nslice 2 9").unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_random_sequences_pass() {
+        let mut rng = StdRng::seed_from_u64(463);
+        for _ in 0 .. 20 {
+            let actions = random_actions(&mut rng, 50);
+            if let Err(f) = run(&actions) {
+                panic!("{}", f);
+            }
+        }
+        assert_eq!(search(5, 50, 1), None);
+    }
+
+    #[test]
+    fn test_shrink() {
+        // stands in for a bug that needs an array of 10 or more bits and
+        // then a rotation of at least 3 of them
+        let fails = |actions: &[Action]| {
+            let mut big = false;
+            actions.iter().any(|a| match *a {
+                Action::Resize { bit_sz } => {
+                    big = bit_sz >= 10;
+                    false
+                }
+                Action::Rotate { bit_length, .. } => big && bit_length >= 3,
+                _ => false,
+            })
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        let actions = loop {
+            let actions = random_actions(&mut rng, 40);
+            if fails(&actions) {
+                break actions;
+            }
+        };
+        let shrunk = shrink(&actions, fails);
+        assert_eq!(shrunk, vec![
+            Action::Resize { bit_sz: 10 },
+            Action::Rotate { bit_offset: 0, bit_length: 3, bit_right_amount: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let actions = vec![Action::Resize { bit_sz: 8 }, Action::WriteBits { bit_offset: 0, bit_width: 65, value: 0 }];
+        // applies_to rules this out, so call apply directly
+        let mut ba = BitArray::new(8);
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| actions[1].apply(&mut ba))).unwrap_err();
+        assert!(panic_message(payload).starts_with("panicked: "));
+        assert_eq!(run(&actions), Ok(()));
+    }
+}
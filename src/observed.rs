@@ -0,0 +1,169 @@
+// A BitArray whose every mutation is reported to an observer, which keeps
+// something derived from the bits in step with them: a ones count, block
+// checksums, dirty words.  Reads need no reporting and go through bits();
+// the mutations live here once, so each observer only says what a change
+// does to its own state.
+use std::ops::Range;
+
+use crate::bitarray::BitArray;
+
+/// A mutation of an Observed array, with the arguments it was called with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Set { bit_index: usize, val: bool },
+    Fill { val: bool },
+    Invert,
+    FillRange { bit_offset: usize, bit_length: usize, val: bool },
+    WriteBits { bit_offset: usize, bit_width: usize, value: u64 },
+    Rotate { bit_offset: usize, bit_length: usize, bit_right_amount: isize },
+}
+
+impl Change {
+    /// The bits the change may alter, in an array of bit_sz bits.
+    pub fn span(&self, bit_sz: usize) -> Range<usize> {
+        match *self {
+            Change::Set { bit_index, .. } => bit_index .. bit_index + 1,
+            Change::Fill { .. } | Change::Invert => 0 .. bit_sz,
+            Change::FillRange { bit_offset, bit_length, .. } | Change::Rotate { bit_offset, bit_length, .. } => {
+                bit_offset .. bit_offset + bit_length
+            }
+            Change::WriteBits { bit_offset, bit_width, .. } => bit_offset .. bit_offset + bit_width,
+        }
+    }
+}
+
+/// State kept in step with the bits of an Observed array.
+pub trait Observer {
+    /// The state for bits as they are when observing starts.
+    fn start(bits: &BitArray) -> Self;
+
+    /// Called with the bits as they are just before change is made.
+    fn before(&mut self, _bits: &BitArray, _change: &Change) {}
+
+    /// Called with the bits as change left them.
+    fn after(&mut self, bits: &BitArray, change: &Change);
+}
+
+/// A BitArray and an observer told of every change to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observed<O: Observer> {
+    pub(crate) bits: BitArray,
+    pub(crate) observer: O,
+}
+
+impl<O: Observer> Observed<O> {
+    /// An all-zero array of bit_sz bits.
+    pub fn new(bit_sz: usize) -> Observed<O> {
+        Observed::from_bitarray(BitArray::new(bit_sz))
+    }
+
+    pub fn from_bitarray(bits: BitArray) -> Observed<O> {
+        let observer = O::start(&bits);
+        Observed { bits, observer }
+    }
+
+    pub fn bits(&self) -> &BitArray {
+        &self.bits
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn into_bitarray(self) -> BitArray {
+        self.bits
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bits.get_bit_sz()
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        self.bits.get(bit_index)
+    }
+
+    /// Makes change to the bits between the observer's before and after.
+    fn apply(&mut self, change: Change) {
+        self.observer.before(&self.bits, &change);
+        match change {
+            Change::Set { bit_index, val } => self.bits.set(bit_index, val),
+            Change::Fill { val } => self.bits.fill(val),
+            Change::Invert => self.bits.invert(),
+            Change::FillRange { bit_offset, bit_length, val } => self.bits.fill_range(bit_offset, bit_length, val),
+            Change::WriteBits { bit_offset, bit_width, value } => self.bits.write_bits(bit_offset, bit_width, value),
+            Change::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                self.bits.rotate(bit_offset, bit_length, bit_right_amount)
+            }
+        }
+        self.observer.after(&self.bits, &change);
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        self.apply(Change::Set { bit_index, val })
+    }
+
+    pub fn fill(&mut self, val: bool) {
+        self.apply(Change::Fill { val })
+    }
+
+    pub fn invert(&mut self) {
+        self.apply(Change::Invert)
+    }
+
+    pub fn fill_range(&mut self, bit_offset: usize, bit_length: usize, val: bool) {
+        self.apply(Change::FillRange { bit_offset, bit_length, val })
+    }
+
+    pub fn write_bits(&mut self, bit_offset: usize, bit_width: usize, value: u64) {
+        self.apply(Change::WriteBits { bit_offset, bit_width, value })
+    }
+
+    pub fn rotate(&mut self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) {
+        self.apply(Change::Rotate { bit_offset, bit_length, bit_right_amount })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every change, with the bits before and after it.
+    #[derive(Debug)]
+    struct Log(Vec<(String, Change, String)>);
+
+    impl Observer for Log {
+        fn start(_bits: &BitArray) -> Log {
+            Log(vec![])
+        }
+
+        fn before(&mut self, bits: &BitArray, change: &Change) {
+            self.0.push((bits.show(), *change, String::new()));
+        }
+
+        fn after(&mut self, bits: &BitArray, _change: &Change) {
+            self.0.last_mut().unwrap().2 = bits.show();
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_each_change() {
+        let mut o: Observed<Log> = Observed::from_bitarray(BitArray::from_str("0110"));
+        o.set(0, true);
+        o.rotate(0, 4, 1);
+        o.write_bits(1, 2, 0b01);
+        o.fill_range(3, 1, true);
+        o.invert();
+        o.fill(true);
+        let log: Vec<_> = o.observer().0.iter().map(|(b, c, a)| (b.as_str(), *c, a.as_str())).collect();
+        assert_eq!(log, [
+            ("0110", Change::Set { bit_index: 0, val: true }, "0111"),
+            ("0111", Change::Rotate { bit_offset: 0, bit_length: 4, bit_right_amount: 1 }, "1110"),
+            ("1110", Change::WriteBits { bit_offset: 1, bit_width: 2, value: 0b01 }, "1010"),
+            ("1010", Change::FillRange { bit_offset: 3, bit_length: 1, val: true }, "1010"),
+            ("1010", Change::Invert, "0101"),
+            ("0101", Change::Fill { val: true }, "1111"),
+        ]);
+        assert_eq!(Change::Invert.span(4), 0 .. 4);
+        assert_eq!(Change::WriteBits { bit_offset: 1, bit_width: 2, value: 0 }.span(4), 1 .. 3);
+    }
+}
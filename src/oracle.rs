@@ -0,0 +1,191 @@
+// Differential testing against a Vec<bool>: every operation is done both
+// on a BitArray and, the slow obvious way, on a model, and the two are
+// compared after each step.  Long random sequences of small operations
+// are good at finding the word boundary bugs that creep in as the real
+// implementation gets cleverer (`everybit --oracle 10000`).
+use std::fmt;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bitarray::BitArray;
+use crate::journal::Op;
+
+/// Array sizes the harness mode tries, chosen to straddle byte and word
+/// boundaries.
+pub const ORACLE_SIZES: &[usize] = &[1, 2, 7, 8, 9, 63, 64, 65, 127, 128, 129, 200, 1000];
+
+/// The reference implementation: one bool per bit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    bits: Vec<bool>,
+}
+
+impl Model {
+    pub fn from_bitarray(ba: &BitArray) -> Model {
+        Model { bits: ba.iter().collect() }
+    }
+
+    pub fn bits(&self) -> &[bool] {
+        &self.bits
+    }
+
+    /// Does op to the model, with no cleverness at all.
+    pub fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::Set { bit_index, val } => self.bits[bit_index] = val,
+            Op::Fill { val } => {
+                for b in &mut self.bits {
+                    *b = val;
+                }
+            }
+            Op::FillRange { bit_offset, bit_length, val } => {
+                for b in &mut self.bits[bit_offset .. bit_offset + bit_length] {
+                    *b = val;
+                }
+            }
+            Op::Rotate { bit_offset, bit_length, bit_right_amount } => {
+                let old = self.bits.clone();
+                for j in 0 .. bit_length {
+                    let to = (j as i128 + bit_right_amount as i128).rem_euclid(bit_length as i128);
+                    self.bits[bit_offset + to as usize] = old[bit_offset + j];
+                }
+            }
+        }
+    }
+
+    /// The first bit where ba disagrees with the model, or None if they
+    /// are the same.  A size mismatch counts as disagreeing at the end of
+    /// the shorter one.
+    pub fn diverges_at(&self, ba: &BitArray) -> Option<usize> {
+        let n = usize::min(self.bits.len(), ba.get_bit_sz());
+        (0 .. n).find(|&i| ba.get(i) != self.bits[i])
+            .or(if self.bits.len() == ba.get_bit_sz() { None } else { Some(n) })
+    }
+}
+
+/// Where a BitArray first stopped agreeing with the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the operation after which they differed.
+    pub step: usize,
+    pub op: Op,
+    /// The first differing bit.
+    pub bit_index: usize,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "after step {} ({}) bit {} differs from the model", self.step, self.op,
+               self.bit_index)
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Does ops to a copy of start and to a model of it, comparing the two
+/// after every operation.
+pub fn check_ops(start: &BitArray, ops: &[Op]) -> Result<BitArray, Divergence> {
+    let mut ba = start.clone();
+    let mut model = Model::from_bitarray(start);
+    for (step, op) in ops.iter().enumerate() {
+        op.apply(&mut ba);
+        model.apply(op);
+        if let Some(bit_index) = model.diverges_at(&ba) {
+            return Err(Divergence { step, op: op.clone(), bit_index });
+        }
+    }
+    Ok(ba)
+}
+
+/// A random operation that is valid on an array of bit_sz bits, biased
+/// toward short ranges and ranges touching the ends of the array.
+pub fn random_op<R: Rng>(rng: &mut R, bit_sz: usize) -> Op {
+    assert!(bit_sz > 0);
+    let range = |rng: &mut R| {
+        let offset = match rng.gen_range(0, 4) {
+            0 => 0,
+            _ => rng.gen_range(0, bit_sz + 1),
+        };
+        let room = bit_sz - offset;
+        let len = match rng.gen_range(0, 4) {
+            0 => room,
+            1 => rng.gen_range(0, usize::min(room, 3) + 1),
+            _ => rng.gen_range(0, room + 1),
+        };
+        (offset, len)
+    };
+    match rng.gen_range(0, 8) {
+        0 ..= 2 => Op::Set { bit_index: rng.gen_range(0, bit_sz), val: rng.gen() },
+        3 => Op::Fill { val: rng.gen() },
+        4 => {
+            let (bit_offset, bit_length) = range(rng);
+            Op::FillRange { bit_offset, bit_length, val: rng.gen() }
+        }
+        _ => {
+            let (bit_offset, bit_length) = range(rng);
+            let reach = 2 * bit_length as isize + 1;
+            Op::Rotate { bit_offset, bit_length, bit_right_amount: rng.gen_range(-reach, reach + 1) }
+        }
+    }
+}
+
+/// Checks steps random operations on a random array of bit_sz bits, all
+/// drawn from seed so a failure can be reproduced.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "info", err(Display)))]
+pub fn run_random(bit_sz: usize, steps: usize, seed: u64) -> Result<(), Divergence> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut start = BitArray::new(bit_sz);
+    start.randfill_seeded(rng.gen());
+    let ops: Vec<Op> = (0 .. steps).map(|_| random_op(&mut rng, bit_sz)).collect();
+    check_ops(&start, &ops).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model() {
+        let ba = BitArray::from_str("10010110");
+        let mut model = Model::from_bitarray(&ba);
+        model.apply(&Op::Rotate { bit_offset: 0, bit_length: 8, bit_right_amount: 1 });
+        assert_eq!(model, Model::from_bitarray(&BitArray::from_str("00101101")));
+        model.apply(&Op::FillRange { bit_offset: 4, bit_length: 4, val: true });
+        assert_eq!(model.diverges_at(&BitArray::from_str("11111101")), None);
+        assert_eq!(model.diverges_at(&BitArray::from_str("11101101")), Some(4));
+        assert_eq!(model.diverges_at(&BitArray::from_str("1111101")), Some(7));
+    }
+
+    #[test]
+    fn test_check_ops() {
+        let ops = vec![
+            Op::Set { bit_index: 3, val: true },
+            Op::Rotate { bit_offset: 1, bit_length: 60, bit_right_amount: -7 },
+            Op::FillRange { bit_offset: 30, bit_length: 40, val: true },
+        ];
+        let ba = check_ops(&BitArray::new(70), &ops).unwrap();
+        assert_eq!(ba.count_ones(), 40);
+    }
+
+    #[test]
+    fn test_random() {
+        for &bit_sz in ORACLE_SIZES {
+            if let Err(d) = run_random(bit_sz, 300, bit_sz as u64) {
+                panic!("{} bits: {}", bit_sz, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_catches_divergence() {
+        // a model that has been told the wrong thing stands in for a bug
+        let ba = BitArray::from_str("0110");
+        let mut model = Model::from_bitarray(&ba);
+        model.apply(&Op::Set { bit_index: 0, val: true });
+        assert_eq!(model.diverges_at(&ba), Some(0));
+        let op = Op::Set { bit_index: 2, val: true };
+        let d = Divergence { step: 4, op, bit_index: 2 };
+        assert_eq!(d.to_string(), "after step 4 (set 2 1) bit 2 differs from the model");
+    }
+}
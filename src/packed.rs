@@ -0,0 +1,224 @@
+// A vector of fixed width unsigned integers packed end to end in a
+// BitArray, e.g. 3 bit values take 3 bits each instead of a whole byte.
+use crate::bitarray::BitArray;
+
+/// A growable vector of unsigned integers that are each bit_width bits
+/// wide.  Element i occupies bits [i * bit_width, (i + 1) * bit_width) of
+/// the underlying array, least significant bit first.
+#[derive(Debug, Clone)]
+pub struct PackedIntVec {
+    bits: BitArray,
+    bit_width: usize,
+    len: usize,
+}
+
+impl PackedIntVec {
+    /// An empty vector of bit_width bit integers, 1 <= bit_width <= 64.
+    pub fn new(bit_width: usize) -> PackedIntVec {
+        PackedIntVec::with_capacity(bit_width, 0)
+    }
+
+    /// An empty vector with room for capacity elements before it has to
+    /// reallocate.
+    pub fn with_capacity(bit_width: usize, capacity: usize) -> PackedIntVec {
+        assert!((1 ..= 64).contains(&bit_width));
+        PackedIntVec { bits: BitArray::new(capacity * bit_width), bit_width, len: 0 }
+    }
+
+    /// A vector of len zeros.
+    pub fn zeros(bit_width: usize, len: usize) -> PackedIntVec {
+        let mut v = PackedIntVec::with_capacity(bit_width, len);
+        v.len = len;
+        v
+    }
+
+    pub fn bit_width(&self) -> usize {
+        self.bit_width
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bits.get_bit_sz() / self.bit_width
+    }
+
+    /// The largest value that fits in an element.
+    pub fn max_value(&self) -> u64 {
+        u64::MAX >> (64 - self.bit_width)
+    }
+
+    pub fn get(&self, i: usize) -> u64 {
+        assert!(i < self.len);
+        self.bits.read_bits(i * self.bit_width, self.bit_width)
+    }
+
+    /// Sets element i, value must fit in bit_width bits.
+    pub fn set(&mut self, i: usize, value: u64) {
+        assert!(i < self.len);
+        self.bits.write_bits(i * self.bit_width, self.bit_width, value)
+    }
+
+    /// Appends a value, doubling the capacity when full.  A value that
+    /// does not fit in bit_width bits panics before the vector changes.
+    pub fn push(&mut self, value: u64) {
+        assert!(value <= self.max_value());
+        if self.len == self.capacity() {
+            self.grow();
+        }
+        self.len += 1;
+        self.set(self.len - 1, value);
+    }
+
+    pub fn pop(&mut self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = self.get(self.len - 1);
+        self.bits.write_bits((self.len - 1) * self.bit_width, self.bit_width, 0);
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn grow(&mut self) {
+        let new_capacity = usize::max(4, self.capacity() * 2);
+        let mut bits = BitArray::new(new_capacity * self.bit_width);
+        for i in 0 .. self.len {
+            let offset = i * self.bit_width;
+            bits.write_bits(offset, self.bit_width, self.bits.read_bits(offset, self.bit_width));
+        }
+        self.bits = bits;
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { vec: self, i: 0 }
+    }
+
+    /// The underlying bits, including any unused capacity at the end.
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+}
+
+impl PartialEq for PackedIntVec {
+    fn eq(&self, other: &Self) -> bool {
+        self.bit_width == other.bit_width && self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+impl Eq for PackedIntVec {}
+
+/// Iterator over the elements of a PackedIntVec.
+pub struct Iter<'a> {
+    vec: &'a PackedIntVec,
+    i: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.i == self.vec.len {
+            return None;
+        }
+        self.i += 1;
+        Some(self.vec.get(self.i - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.vec.len - self.i;
+        (left, Some(left))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl<'a> IntoIterator for &'a PackedIntVec {
+    type Item = u64;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_push_get() {
+        let mut rng = rand::thread_rng();
+        for &width in &[1, 3, 7, 8, 13, 33, 64] {
+            let mut v = PackedIntVec::new(width);
+            let mut expected = vec![];
+            for _ in 0 .. 200 {
+                let x = rng.gen::<u64>() & v.max_value();
+                v.push(x);
+                expected.push(x);
+            }
+            assert_eq!(v.len(), 200);
+            assert_eq!(v.iter().collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_set_leaves_neighbours() {
+        let mut v = PackedIntVec::zeros(5, 10);
+        v.set(3, 31);
+        v.set(4, 17);
+        v.set(3, 2);
+        assert_eq!(v.iter().collect::<Vec<_>>(), vec![0, 0, 0, 2, 17, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut v = PackedIntVec::new(4);
+        v.push(9);
+        v.push(15);
+        assert_eq!(v.pop(), Some(15));
+        v.push(1);
+        assert_eq!(v.pop(), Some(1));
+        assert_eq!(v.pop(), Some(9));
+        assert_eq!(v.pop(), None);
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_eq_ignores_capacity() {
+        let mut a = PackedIntVec::with_capacity(6, 100);
+        let mut b = PackedIntVec::new(6);
+        for x in 0 .. 20 {
+            a.push(x);
+            b.push(x);
+        }
+        assert_eq!(a, b);
+        b.set(19, 0);
+        assert!(a != b);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_value_too_wide() {
+        let mut v = PackedIntVec::new(3);
+        v.push(8);
+    }
+
+    #[test]
+    fn test_value_too_wide_leaves_vector() {
+        let mut v = PackedIntVec::new(3);
+        v.push(5);
+        let before = v.clone();
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| v.push(8))).is_err());
+        assert_eq!((v.len(), v.capacity()), (1, 4));
+        assert_eq!(v, before);
+        v.push(7);
+        assert_eq!(v.iter().collect::<Vec<_>>(), vec![5, 7]);
+    }
+}
@@ -0,0 +1,414 @@
+// The rotation performance test behind -s, -m and -l, after timed_rotation
+// in the original 6.172 harness: rotate ever bigger random arrays until
+// one rotation takes longer than the time limit, and report the last tier
+// that made it.  Only speed is measured here, -t checks correctness.
+//
+// With calibration the limits are scaled by how fast the machine is, so a
+// tier means about the same rotate on a laptop as on a fast server.  The
+// calibration loop never touches BitArray, so a faster rotate is not
+// scaled away.
+use std::fmt;
+use std::time::Instant;
+
+#[cfg(feature = "harness")]
+use serde::{Deserialize, Serialize};
+
+use crate::bitarray::{BitArray, RotateStrategy};
+use crate::pool::BitArrayPool;
+
+/// The size of tier 0; each tier is TIER_GROWTH times the one before.
+pub const STARTING_BITS: usize = 1000;
+pub const TIER_GROWTH: f64 = 1.2;
+
+/// The last tier tried, a couple of gigabits, so a very fast rotate
+/// still finishes.
+pub const MAX_TIER: usize = 80;
+
+/// How long calibrate runs its loop.
+pub const CALIBRATION_SECONDS: f64 = 0.05;
+
+/// The calibration loop's speed on the machine the default limits were
+/// chosen on, in words per second (release build).
+pub const REFERENCE_WORDS_PER_SEC: f64 = 1e9;
+
+/// The array size of a tier.
+pub fn tier_bits(tier: usize) -> usize {
+    (STARTING_BITS as f64 * TIER_GROWTH.powi(tier as i32)) as usize
+}
+
+/// The three tests, named as the flags are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "harness", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum Size {
+    Small,
+    Medium,
+    Large,
+}
+
+impl Size {
+    pub fn name(self) -> &'static str {
+        match self {
+            Size::Small => "small",
+            Size::Medium => "medium",
+            Size::Large => "large",
+        }
+    }
+}
+
+/// The time limit in seconds for each Size; the original harness used
+/// 0.01, 0.1 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "harness", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct TierLimits {
+    pub small: f64,
+    pub medium: f64,
+    pub large: f64,
+}
+
+impl Default for TierLimits {
+    fn default() -> TierLimits {
+        TierLimits { small: 0.01, medium: 0.1, large: 1.0 }
+    }
+}
+
+impl TierLimits {
+    pub fn get(&self, size: Size) -> f64 {
+        match size {
+            Size::Small => self.small,
+            Size::Medium => self.medium,
+            Size::Large => self.large,
+        }
+    }
+}
+
+/// How much faster, in percent, a rotation after warmup has to be than
+/// the first one for the warmup to count as having mattered.
+pub const WARMUP_MATERIAL_PERCENT: f64 = 10.0;
+
+/// The time one tier's rotation took.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "harness", derive(Serialize, Deserialize))]
+pub struct TierTime {
+    pub tier: usize,
+    pub bit_sz: usize,
+    pub seconds: f64,
+    /// The first, untimed in the results, warmup rotation, if there was
+    /// warmup.
+    #[cfg_attr(feature = "harness", serde(default))]
+    pub cold_seconds: Option<f64>,
+}
+
+/// Controls timed_rotation_with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingOptions {
+    /// This machine's speed relative to the reference one, see
+    /// calibrate; the time limit is divided by it.
+    pub calibration: f64,
+    /// Rotations done before the timed one at each tier, to warm the
+    /// caches and let the clock ramp up.
+    pub warmup: usize,
+    /// Rotate with rotate_with this strategy, or with rotate if None.
+    pub strategy: Option<RotateStrategy>,
+}
+
+impl Default for TimingOptions {
+    fn default() -> TimingOptions {
+        TimingOptions { calibration: 1.0, warmup: 0, strategy: None }
+    }
+}
+
+/// The outcome of timed_rotation.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "harness", derive(Serialize, Deserialize))]
+pub struct TimedRun {
+    pub size: Size,
+    pub time_limit: f64,
+    pub seed: u64,
+    /// Every tier tried, the last one over the limit unless MAX_TIER was
+    /// reached first.
+    pub tiers: Vec<TierTime>,
+    /// The last tier within the limit, None if not even tier 0 was.
+    pub completed: Option<usize>,
+    /// How many times faster than the reference machine this one is;
+    /// time_limit was divided by it.  1 without calibration.
+    #[cfg_attr(feature = "harness", serde(default = "no_calibration"))]
+    pub calibration: f64,
+    /// Untimed rotations before each timed one.
+    #[cfg_attr(feature = "harness", serde(default))]
+    pub warmup: usize,
+    /// The strategy every rotation was done with, None for rotate's own.
+    #[cfg_attr(feature = "harness", serde(default))]
+    pub strategy: Option<RotateStrategy>,
+}
+
+impl TimedRun {
+    /// How much slower, in percent, the first warmup rotation was than the
+    /// timed one, at the tier where that was most; None without warmup.
+    pub fn warmup_effect(&self) -> Option<f64> {
+        self.tiers.iter()
+            .filter_map(|t| t.cold_seconds.map(|cold| 100.0 * (cold - t.seconds) / t.seconds))
+            .fold(None, |max: Option<f64>, p| Some(max.map_or(p, |m| m.max(p))))
+    }
+}
+
+#[cfg(feature = "harness")]
+fn no_calibration() -> f64 {
+    1.0
+}
+
+/// A machine's baseline speeds, from `everybit calibrate`.  factor is
+/// what calibrate returns; the rest are for comparing results from
+/// different hardware by hand.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "harness", derive(Serialize, Deserialize))]
+pub struct Calibration {
+    pub factor: f64,
+    pub gets_per_sec: f64,
+    pub sets_per_sec: f64,
+    /// Subarray bits rotated per second, by a third of their length.
+    pub rotate_bits_per_sec: f64,
+    /// Bytes copied per second between two buffers bigger than cache.
+    pub copy_bytes_per_sec: f64,
+}
+
+/// Runs f until CALIBRATION_SECONDS have passed and returns how many
+/// times it ran per second.
+fn per_sec<F: FnMut()>(mut f: F) -> f64 {
+    let mut runs = 0u64;
+    let start = Instant::now();
+    while start.elapsed().as_secs_f64() < CALIBRATION_SECONDS {
+        f();
+        runs += 1;
+    }
+    runs as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Measures everything in a Calibration, about a quarter of a second.
+pub fn measure_calibration() -> Calibration {
+    const BITS: usize = 1 << 16;
+    const ROTATE_BITS: usize = 4096;
+    const COPY_BYTES: usize = 32 << 20;
+    let mut ba = BitArray::new(BITS);
+    ba.randfill_seeded(1);
+    let gets_per_sec = BITS as f64 * per_sec(|| {
+        let ones = (0 .. BITS).filter(|&i| ba.get(i)).count();
+        std::hint::black_box(ones);
+    });
+    let sets_per_sec = BITS as f64 * per_sec(|| {
+        for i in 0 .. BITS {
+            ba.set(i, i % 3 == 0);
+        }
+        std::hint::black_box(&ba);
+    });
+    let mut small = BitArray::new(ROTATE_BITS);
+    small.randfill_seeded(2);
+    let (bit_offset, bit_length, amount) = (0, ROTATE_BITS, -((ROTATE_BITS / 3) as isize));
+    let rotate_bits_per_sec = bit_length as f64 * per_sec(|| small.rotate(bit_offset, bit_length, amount));
+    let from = vec![1u8; COPY_BYTES];
+    let mut to = vec![0u8; COPY_BYTES];
+    let copy_bytes_per_sec = COPY_BYTES as f64 * per_sec(|| {
+        to.copy_from_slice(std::hint::black_box(&from));
+        std::hint::black_box(&to);
+    });
+    Calibration { factor: calibrate(), gets_per_sec, sets_per_sec, rotate_bits_per_sec, copy_bytes_per_sec }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "factor:  {:.2}x the reference machine", self.factor)?;
+        writeln!(f, "get:     {:.3e} per second", self.gets_per_sec)?;
+        writeln!(f, "set:     {:.3e} per second", self.sets_per_sec)?;
+        writeln!(f, "rotate:  {:.3e} bits per second", self.rotate_bits_per_sec)?;
+        writeln!(f, "memory:  {:.2} GB/s copied", self.copy_bytes_per_sec / 1e9)
+    }
+}
+
+/// One pass of the calibration loop: a dependent chain of shifts and
+/// xors over a quarter megabyte, cheap enough to be memory and ALU
+/// bound and nothing else.
+fn calibration_pass(words: &mut [u64], mut x: u64) -> u64 {
+    for w in words.iter_mut() {
+        x = x.rotate_left(7) ^ *w ^ 0x9e37_79b9_7f4a_7c15;
+        *w = x;
+    }
+    x
+}
+
+/// Times the calibration loop for CALIBRATION_SECONDS and returns this
+/// machine's speed relative to the reference machine.
+pub fn calibrate() -> f64 {
+    let mut words = vec![1u64; 1 << 15];
+    let mut x = 0;
+    let mut passes = 0u64;
+    let start = Instant::now();
+    while start.elapsed().as_secs_f64() < CALIBRATION_SECONDS {
+        x = calibration_pass(std::hint::black_box(&mut words), x);
+        passes += 1;
+    }
+    std::hint::black_box(x);
+    let rate = (passes * words.len() as u64) as f64 / start.elapsed().as_secs_f64();
+    rate / REFERENCE_WORDS_PER_SEC
+}
+
+/// The rotation done at every tier: the middle half of the array, left
+/// by a third of its length.
+fn tier_rotation(bit_sz: usize) -> (usize, usize, isize) {
+    let bit_length = bit_sz / 2;
+    (bit_sz / 4, bit_length, -((bit_length / 3) as isize))
+}
+
+/// Times one rotation per tier, starting at tier 0, until one takes more
+/// than time_limit seconds.  The arrays are filled from seed.
+pub fn timed_rotation(size: Size, time_limit: f64, seed: u64) -> TimedRun {
+    timed_rotation_with(size, time_limit, seed, &TimingOptions::default())
+}
+
+/// timed_rotation with calibration, warmup and strategy as opts asks.
+pub fn timed_rotation_with(size: Size, time_limit: f64, seed: u64, opts: &TimingOptions) -> TimedRun {
+    assert!(opts.calibration > 0.0);
+    let time_limit = time_limit / opts.calibration;
+    let mut run = TimedRun {
+        size,
+        time_limit,
+        seed,
+        tiers: vec![],
+        completed: None,
+        calibration: opts.calibration,
+        warmup: opts.warmup,
+        strategy: opts.strategy,
+    };
+    // tiers whose arrays round up to the same power of two words share
+    // one buffer, given back to the pool at the end of each tier
+    let pool = BitArrayPool::new();
+    for tier in 0 ..= MAX_TIER {
+        let bit_sz = tier_bits(tier);
+        let mut ba = pool.get(bit_sz);
+        ba.randfill_seeded(seed ^ tier as u64);
+        let (bit_offset, bit_length, bit_right_amount) = tier_rotation(bit_sz);
+        let timed = |ba: &mut BitArray| {
+            let start = Instant::now();
+            match opts.strategy {
+                Some(strategy) => ba.rotate_with(strategy, bit_offset, bit_length, bit_right_amount),
+                None => ba.rotate(bit_offset, bit_length, bit_right_amount),
+            }
+            start.elapsed().as_secs_f64()
+        };
+        let mut cold_seconds = None;
+        for _ in 0 .. opts.warmup {
+            let seconds = timed(&mut ba);
+            cold_seconds.get_or_insert(seconds);
+        }
+        let seconds = timed(&mut ba);
+        run.tiers.push(TierTime { tier, bit_sz, seconds, cold_seconds });
+        if seconds > time_limit {
+            break;
+        }
+        run.completed = Some(tier);
+    }
+    run
+}
+
+/// The report the original harness printed, with the time of each tier.
+impl fmt::Display for TimedRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "---- RESULTS ----")?;
+        writeln!(f, "{} test, {:.4}s per rotation, seed {}", self.size.name(), self.time_limit, self.seed)?;
+        if self.calibration != 1.0 {
+            writeln!(f, "calibrated: this machine is {:.2}x the reference", self.calibration)?;
+        }
+        if let Some(strategy) = self.strategy {
+            writeln!(f, "strategy: {}", strategy)?;
+        }
+        for t in &self.tiers {
+            write!(f, "tier {:2}: {:>10} bits in {:.6}s", t.tier, t.bit_sz, t.seconds)?;
+            match t.cold_seconds {
+                Some(cold) => writeln!(f, " ({:.6}s cold)", cold)?,
+                None => writeln!(f)?,
+            }
+        }
+        if let Some(effect) = self.warmup_effect() {
+            let verdict = if effect > WARMUP_MATERIAL_PERCENT { "it mattered" } else { "it made no real difference" };
+            writeln!(f, "warmup: {} rotations per tier; cold was at most {:.1}% slower, {}",
+                     self.warmup, effect, verdict)?;
+        }
+        match self.completed {
+            Some(tier) => writeln!(f, "Succesfully completed tier: {}", tier)?,
+            None => writeln!(f, "Did not complete tier 0")?,
+        }
+        writeln!(f, "---- END RESULTS ----")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tier_bits() {
+        assert_eq!(tier_bits(0), 1000);
+        assert_eq!(tier_bits(1), 1200);
+        assert!(tier_bits(MAX_TIER) > 1 << 30);
+        let (bit_offset, bit_length, amount) = tier_rotation(1000);
+        assert_eq!((bit_offset, bit_length, amount), (250, 500, -166));
+    }
+
+    #[test]
+    fn test_timed_rotation() {
+        let run = timed_rotation(Size::Small, 0.001, 1);
+        // every tier but the last made the limit, and completed says so
+        let (last, made_it) = run.tiers.split_last().unwrap();
+        assert!(made_it.iter().all(|t| t.seconds <= 0.001));
+        assert!(last.seconds > 0.001 || last.tier == MAX_TIER);
+        let completed = if last.seconds > 0.001 { last.tier.checked_sub(1) } else { Some(last.tier) };
+        assert_eq!(run.completed, completed);
+        assert_eq!(run.tiers.iter().map(|t| t.tier).collect::<Vec<_>>(), (0 ..= last.tier).collect::<Vec<_>>());
+        assert_eq!(run.strategy, None);
+        let text = run.to_string();
+        assert!(text.starts_with("---- RESULTS ----\nsmall test, 0.0010s per rotation, seed 1\ntier  0:"));
+        assert!(text.ends_with("---- END RESULTS ----\n"));
+    }
+
+    #[test]
+    fn test_measure_calibration() {
+        let c = measure_calibration();
+        for rate in &[c.factor, c.gets_per_sec, c.sets_per_sec, c.rotate_bits_per_sec, c.copy_bytes_per_sec] {
+            assert!(*rate > 0.0 && rate.is_finite());
+        }
+        assert!(c.to_string().starts_with("factor:  "));
+    }
+
+    #[test]
+    fn test_warmup() {
+        let run = timed_rotation_with(Size::Small, 0.001, 1, &TimingOptions { warmup: 2, ..Default::default() });
+        assert_eq!(run.warmup, 2);
+        assert!(run.tiers.iter().all(|t| t.cold_seconds.is_some()));
+        assert!(run.warmup_effect().is_some());
+        assert!(run.to_string().contains("\nwarmup: 2 rotations per tier; cold was at most "));
+        assert_eq!(timed_rotation(Size::Small, 0.001, 1).warmup_effect(), None);
+
+        let tier = |seconds, cold| TierTime { tier: 0, bit_sz: 1000, seconds, cold_seconds: Some(cold) };
+        let mut run = timed_rotation(Size::Small, 0.0, 1);
+        run.tiers = vec![tier(1.0, 1.05), tier(2.0, 3.0)];
+        assert_eq!(run.warmup_effect(), Some(50.0));
+    }
+
+    #[test]
+    fn test_calibration() {
+        let factor = calibrate();
+        assert!(factor > 0.0 && factor.is_finite());
+        let opts = TimingOptions { calibration: 2.0, ..Default::default() };
+        let run = timed_rotation_with(Size::Small, 0.004, 1, &opts);
+        assert_eq!(run.time_limit, 0.002);
+        assert!(run.to_string().contains("\ncalibrated: this machine is 2.00x the reference\n"));
+    }
+
+    #[test]
+    fn test_strategy() {
+        let opts = TimingOptions { strategy: Some(RotateStrategy::Naive), ..Default::default() };
+        let run = timed_rotation_with(Size::Small, 0.001, 1, &opts);
+        assert_eq!(run.strategy, Some(RotateStrategy::Naive));
+        assert!(run.to_string().contains("\nstrategy: naive\n"));
+        // one bit at a time is too slow for the biggest tier by far
+        assert!(run.completed < Some(MAX_TIER));
+    }
+}
@@ -0,0 +1,251 @@
+// An immutable bit array where every update returns a new version.  The
+// bits live in a tree of fixed size chunks behind Arcs, so a new version
+// copies only the chunks (and tree nodes above them) that it changes and
+// shares everything else with the version it came from.
+use std::sync::Arc;
+
+use crate::bitarray::BitArray;
+
+/// 64 bit words per leaf chunk.
+pub const LEAF_WORDS: usize = 8;
+const LEAF_BITS: usize = LEAF_WORDS * 64;
+
+/// Children per interior node.
+pub const FANOUT: usize = 16;
+
+#[derive(Debug)]
+enum Node {
+    Leaf([u64; LEAF_WORDS]),
+    Branch(Vec<Arc<Node>>),
+}
+
+/// Bits covered by a node height levels above the leaves.
+fn span(height: usize) -> usize {
+    LEAF_BITS * FANOUT.pow(height as u32)
+}
+
+/// Writes the low width bits of value at bit off of a leaf.
+fn leaf_write(words: &mut [u64; LEAF_WORDS], off: usize, width: usize, value: u64) {
+    for k in 0 .. width {
+        let (w, b) = ((off + k) / 64, (off + k) % 64);
+        words[w] = words[w] & !(1 << b) | ((value >> k) & 1) << b;
+    }
+}
+
+/// A persistent bit array.  Cloning is O(1); set and rotate leave self
+/// alone and return the updated version.  A set copies one leaf and the
+/// nodes on its path, O(log n); a rotate copies the leaves the rotated
+/// range covers.
+#[derive(Debug, Clone)]
+pub struct PersistentBitArray {
+    bit_sz: usize,
+    height: usize,
+    root: Arc<Node>,
+}
+
+impl PersistentBitArray {
+    /// bit_sz clear bits.  All the leaves start out as one shared zero
+    /// chunk, so this takes O(log n) memory until bits are set.
+    pub fn new(bit_sz: usize) -> PersistentBitArray {
+        let mut height = 0;
+        while span(height) < bit_sz {
+            height += 1;
+        }
+        let mut root = Arc::new(Node::Leaf([0; LEAF_WORDS]));
+        for _ in 0 .. height {
+            root = Arc::new(Node::Branch(vec![root; FANOUT]));
+        }
+        PersistentBitArray { bit_sz, height, root }
+    }
+
+    pub fn from_bitarray(ba: &BitArray) -> PersistentBitArray {
+        PersistentBitArray::new(ba.get_bit_sz()).write_range(0, ba)
+    }
+
+    pub fn to_bitarray(&self) -> BitArray {
+        self.read_range(0, self.bit_sz)
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bit_sz
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        assert!(bit_index < self.bit_sz);
+        let mut node = &self.root;
+        let mut i = bit_index;
+        for h in (0 .. self.height).rev() {
+            match &**node {
+                Node::Branch(children) => {
+                    node = &children[i / span(h)];
+                    i %= span(h);
+                }
+                Node::Leaf(_) => unreachable!(),
+            }
+        }
+        match &**node {
+            Node::Leaf(words) => (words[i / 64] >> (i % 64)) & 1 == 1,
+            Node::Branch(_) => unreachable!(),
+        }
+    }
+
+    /// A new version with bit bit_index set to val.
+    pub fn set(&self, bit_index: usize, val: bool) -> PersistentBitArray {
+        assert!(bit_index < self.bit_sz);
+        let mut bit = BitArray::new(1);
+        bit.set(0, val);
+        self.write_range(bit_index, &bit)
+    }
+
+    /// A new version with the range rotated as BitArray::rotate does.
+    pub fn rotate(&self, bit_offset: usize, bit_length: usize,
+                  bit_right_amount: isize) -> PersistentBitArray {
+        let mut range = self.read_range(bit_offset, bit_length);
+        range.rotate(0, bit_length, bit_right_amount);
+        self.write_range(bit_offset, &range)
+    }
+
+    /// Copies the bits [bit_offset, bit_offset + bit_length) out.
+    pub fn read_range(&self, bit_offset: usize, bit_length: usize) -> BitArray {
+        assert!(bit_offset + bit_length <= self.bit_sz);
+        let mut out = BitArray::new(bit_length);
+        if bit_length > 0 {
+            read_node(&self.root, self.height, 0, bit_offset, &mut out);
+        }
+        out
+    }
+
+    /// A new version with the bits of src written at bit_offset.
+    pub fn write_range(&self, bit_offset: usize, src: &BitArray) -> PersistentBitArray {
+        assert!(bit_offset + src.get_bit_sz() <= self.bit_sz);
+        if src.get_bit_sz() == 0 {
+            return self.clone();
+        }
+        let root = write_node(&self.root, self.height, 0, bit_offset, src);
+        PersistentBitArray { bit_sz: self.bit_sz, height: self.height, root }
+    }
+
+    /// Whether two versions are the very same tree.
+    pub fn ptr_eq(&self, other: &PersistentBitArray) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+}
+
+/// node covers [start, start + span(height)); copies the part of it that
+/// overlaps [offset, offset + out's size) into out.
+fn read_node(node: &Node, height: usize, start: usize, offset: usize, out: &mut BitArray) {
+    let (lo, hi) = (usize::max(start, offset),
+                    usize::min(start + span(height), offset + out.get_bit_sz()));
+    if lo >= hi {
+        return;
+    }
+    match node {
+        Node::Leaf(words) => {
+            for pos in lo .. hi {
+                let i = pos - start;
+                if (words[i / 64] >> (i % 64)) & 1 == 1 {
+                    out.set(pos - offset, true);
+                }
+            }
+        }
+        Node::Branch(children) => {
+            for (k, c) in children.iter().enumerate() {
+                read_node(c, height - 1, start + k * span(height - 1), offset, out);
+            }
+        }
+    }
+}
+
+/// node covers [start, start + span(height)); returns it with src written
+/// at bit offset, copying only the parts that overlap.
+fn write_node(node: &Arc<Node>, height: usize, start: usize, offset: usize,
+              src: &BitArray) -> Arc<Node> {
+    let (lo, hi) = (usize::max(start, offset),
+                    usize::min(start + span(height), offset + src.get_bit_sz()));
+    if lo >= hi {
+        return Arc::clone(node);
+    }
+    match &**node {
+        Node::Leaf(words) => {
+            let mut words = *words;
+            let mut pos = lo;
+            while pos < hi {
+                let width = usize::min(64, hi - pos);
+                leaf_write(&mut words, pos - start, width, src.read_bits(pos - offset, width));
+                pos += width;
+            }
+            Arc::new(Node::Leaf(words))
+        }
+        Node::Branch(children) => Arc::new(Node::Branch(
+            children.iter().enumerate()
+                .map(|(k, c)| write_node(c, height - 1, start + k * span(height - 1), offset, src))
+                .collect())),
+    }
+}
+
+impl PartialEq for PersistentBitArray {
+    fn eq(&self, other: &Self) -> bool {
+        self.bit_sz == other.bit_sz && (self.ptr_eq(other) || self.to_bitarray() == other.to_bitarray())
+    }
+}
+impl Eq for PersistentBitArray {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(node: &Arc<Node>, out: &mut Vec<*const Node>) {
+        match &**node {
+            Node::Leaf(_) => out.push(Arc::as_ptr(node)),
+            Node::Branch(children) => children.iter().for_each(|c| leaves(c, out)),
+        }
+    }
+
+    /// How many leaf positions of a and b hold the very same chunk.
+    fn shared_leaves(a: &PersistentBitArray, b: &PersistentBitArray) -> usize {
+        let (mut la, mut lb) = (vec![], vec![]);
+        leaves(&a.root, &mut la);
+        leaves(&b.root, &mut lb);
+        la.iter().zip(&lb).filter(|(x, y)| x == y).count()
+    }
+
+    #[test]
+    fn test_set_keeps_old_version() {
+        let v0 = PersistentBitArray::new(10_000);
+        let v1 = v0.set(5000, true);
+        let v2 = v1.set(3, true);
+        assert!(!v0.get(5000));
+        assert!(v1.get(5000) && !v1.get(3));
+        assert!(v2.get(5000) && v2.get(3));
+        assert_eq!(v2.to_bitarray().count_ones(), 2);
+        assert!(v1 != v0);
+        assert_eq!(v1.clone(), v1);
+    }
+
+    #[test]
+    fn test_structural_sharing() {
+        let mut ba = BitArray::new(100_000);
+        ba.randfill();
+        let v0 = PersistentBitArray::from_bitarray(&ba);
+        let total = 100_000usize.div_ceil(LEAF_BITS);
+        let v1 = v0.set(70_000, !v0.get(70_000));
+        assert!(shared_leaves(&v0, &v1) >= total - 1);
+        // rotating 2000 bits touches at most 5 leaves
+        let v2 = v1.rotate(1000, 2000, 37);
+        assert!(shared_leaves(&v1, &v2) >= total - 5);
+    }
+
+    #[test]
+    fn test_matches_bitarray() {
+        let mut ba = BitArray::new(3000);
+        ba.randfill();
+        let mut p = PersistentBitArray::from_bitarray(&ba);
+        assert_eq!(p.to_bitarray(), ba);
+        for &(off, len, amt) in &[(0, 3000, 1), (5, 1000, -77), (1499, 1, 5), (600, 1800, 900)] {
+            ba.rotate(off, len, amt);
+            p = p.rotate(off, len, amt);
+            assert_eq!(p.to_bitarray(), ba);
+        }
+        assert_eq!(p.read_range(100, 200), ba.slice(100 .. 300).to_bitarray());
+    }
+}
@@ -0,0 +1,209 @@
+// Recycling of BitArray buffers for workloads that make and drop many
+// small arrays, e.g. one bitmap per request, so the steady state never
+// reaches the allocator.
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::bitarray::BitArray;
+
+/// Buffers handed out per slab refill of an empty size class.
+pub const SLAB_ARRAYS: usize = 64;
+
+/// The most a slab refill allocates, so classes of big buffers are
+/// refilled with fewer of them, down to one.
+pub const SLAB_BYTES: usize = 1 << 20;
+
+/// Free buffers kept per size class; any more are really freed.
+pub const MAX_FREE_PER_CLASS: usize = 4096;
+
+/// The size class of an array of bit_sz bits: buffers of class c hold
+/// 2^c words, enough for any array whose buffer needs at most that.
+fn class_of(bit_sz: usize) -> usize {
+    BitArray::words_for(bit_sz).next_power_of_two().trailing_zeros() as usize
+}
+
+/// The buffers in a slab of size class c, whose buffers are 8 << c bytes.
+fn slab_arrays(c: usize) -> usize {
+    ((SLAB_BYTES / 8) >> c).clamp(1, SLAB_ARRAYS)
+}
+
+/// Counters for checking the pool is doing its job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Buffers allocated fresh, in slab refills.
+    pub allocated: usize,
+    /// Arrays handed out on a recycled buffer.
+    pub reused: usize,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// free[c] holds free buffers of size class c.
+    free: Vec<Vec<Vec<u64>>>,
+    stats: PoolStats,
+}
+
+/// A pool of array buffers grouped into power of two size classes.
+/// get hands out a PooledBitArray, which puts its buffer back in the pool
+/// when dropped.  When a class runs dry it is refilled with a slab of
+/// SLAB_ARRAYS fresh buffers (or SLAB_BYTES' worth, if that is fewer) at
+/// once, so a cold pool refills once per slab rather than once per get.
+/// Each buffer is still an allocation of its own, since a BitArray owns
+/// its buffer and frees it alone.  The pool is Sync and can be shared by
+/// threads.
+#[derive(Debug, Default)]
+pub struct BitArrayPool {
+    inner: Mutex<Inner>,
+}
+
+impl BitArrayPool {
+    pub fn new() -> BitArrayPool {
+        BitArrayPool::default()
+    }
+
+    /// A zeroed array of bit_sz bits on a pooled buffer.
+    pub fn get(&self, bit_sz: usize) -> PooledBitArray<'_> {
+        let c = class_of(bit_sz);
+        let buf = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.free.len() <= c {
+                inner.free.resize_with(c + 1, Vec::new);
+            }
+            if inner.free[c].is_empty() {
+                let n = slab_arrays(c);
+                inner.free[c] = (0 .. n).map(|_| Vec::with_capacity(1 << c)).collect();
+                inner.stats.allocated += n;
+            } else {
+                inner.stats.reused += 1;
+            }
+            inner.free[c].pop().unwrap()
+        };
+        PooledBitArray { pool: self, arr: Some(BitArray::in_buffer(buf, bit_sz)) }
+    }
+
+    /// Takes ownership of an array's buffer for later reuse.
+    pub fn recycle(&self, ba: BitArray) {
+        let buf = ba.into_buffer();
+        if !buf.capacity().is_power_of_two() {
+            return;
+        }
+        let c = buf.capacity().trailing_zeros() as usize;
+        let mut inner = self.inner.lock().unwrap();
+        if inner.free.len() <= c {
+            inner.free.resize_with(c + 1, Vec::new);
+        }
+        if inner.free[c].len() < MAX_FREE_PER_CLASS {
+            inner.free[c].push(buf);
+        }
+    }
+
+    /// Free buffers currently held, over all size classes.
+    pub fn free_buffers(&self) -> usize {
+        self.inner.lock().unwrap().free.iter().map(|f| f.len()).sum()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.inner.lock().unwrap().stats
+    }
+}
+
+/// A BitArray borrowed from a BitArrayPool; derefs to the array and gives
+/// the buffer back on drop.
+#[derive(Debug)]
+pub struct PooledBitArray<'a> {
+    pool: &'a BitArrayPool,
+    arr: Option<BitArray>,
+}
+
+impl<'a> PooledBitArray<'a> {
+    /// Keeps the array, taking its buffer out of the pool for good.
+    pub fn detach(mut self) -> BitArray {
+        self.arr.take().unwrap()
+    }
+}
+
+impl<'a> Deref for PooledBitArray<'a> {
+    type Target = BitArray;
+
+    fn deref(&self) -> &BitArray {
+        self.arr.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for PooledBitArray<'a> {
+    fn deref_mut(&mut self) -> &mut BitArray {
+        self.arr.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for PooledBitArray<'a> {
+    fn drop(&mut self) {
+        if let Some(arr) = self.arr.take() {
+            self.pool.recycle(arr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_of() {
+        assert_eq!(class_of(0), 0);
+        assert_eq!(class_of(64), 0);
+        assert_eq!(class_of(65), 1);
+        assert_eq!(class_of(1000), 4);
+    }
+
+    #[test]
+    fn test_slab_arrays() {
+        assert_eq!(slab_arrays(0), SLAB_ARRAYS);
+        assert_eq!(slab_arrays(11), SLAB_ARRAYS);
+        assert_eq!(slab_arrays(12), SLAB_ARRAYS / 2);
+        assert_eq!(slab_arrays(17), 1);
+        assert_eq!(slab_arrays(40), 1);
+        // a big array gets a slab of one
+        let pool = BitArrayPool::new();
+        drop(pool.get(1 << 24));
+        drop(pool.get(1 << 24));
+        assert_eq!(pool.stats(), PoolStats { allocated: 1, reused: 1 });
+    }
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BitArrayPool::new();
+        for _ in 0 .. 1000 {
+            let mut ba = pool.get(500);
+            assert_eq!(ba.count_ones(), 0);
+            ba.fill(true);
+            ba.set(499, false);
+        }
+        assert_eq!(pool.stats(), PoolStats { allocated: SLAB_ARRAYS, reused: 999 });
+        assert_eq!(pool.free_buffers(), SLAB_ARRAYS);
+    }
+
+    #[test]
+    fn test_recycled_arrays_come_back_zeroed() {
+        let pool = BitArrayPool::new();
+        let mut a = pool.get(100);
+        a.fill(true);
+        drop(a);
+        // a smaller array of the same class gets the dirty buffer
+        let b = pool.get(70);
+        assert_eq!(b.get_bit_sz(), 70);
+        assert_eq!(*b, BitArray::new(70));
+    }
+
+    #[test]
+    fn test_detach() {
+        let pool = BitArrayPool::new();
+        let mut a = pool.get(16);
+        a.set(3, true);
+        let kept = a.detach();
+        assert!(kept.get(3));
+        assert_eq!(pool.free_buffers(), SLAB_ARRAYS - 1);
+        pool.recycle(kept);
+        assert_eq!(pool.free_buffers(), SLAB_ARRAYS);
+    }
+}
@@ -0,0 +1,285 @@
+// Boolean queries over a bitmap index: a set of named columns, each a
+// BitArray with one bit per row.  A query like a AND (b OR NOT c) is
+// evaluated in a single pass, 64 rows at a time, reading each column's
+// word once and never materialising the intermediate results.
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+
+use crate::bitarray::BitArray;
+
+/// A boolean expression over column names.  Build one with col and the
+/// &, | and ! operators, or parse it from text with Expr::parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Col(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// The expression that selects the rows set in the named column.
+pub fn col(name: &str) -> Expr {
+    Expr::Col(name.to_string())
+}
+
+impl BitAnd for Expr {
+    type Output = Expr;
+
+    fn bitand(self, other: Expr) -> Expr {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+}
+
+impl BitOr for Expr {
+    type Output = Expr;
+
+    fn bitor(self, other: Expr) -> Expr {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+}
+
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+
+/// Why a query couldn't be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// The query names a column the index doesn't have.
+    UnknownColumn(String),
+    /// The query text is malformed; the message says where.
+    Parse(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::UnknownColumn(name) => write!(f, "unknown column {:?}", name),
+            QueryError::Parse(msg) => write!(f, "bad query: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Splits query text into words and parentheses.
+fn tokenize(text: &str) -> Vec<String> {
+    text.replace('(', " ( ").replace(')', " ) ").split_whitespace().map(String::from).collect()
+}
+
+/// Recursive descent over the tokens, lowest precedence first:
+///     or  := and ("OR" and)*
+///     and := not ("AND" not)*
+///     not := "NOT" not | "(" or ")" | column
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_is(&self, word: &str) -> bool {
+        self.tokens.get(self.pos).is_some_and(|t| t.eq_ignore_ascii_case(word))
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut e = self.and()?;
+        while self.peek_is("OR") {
+            self.pos += 1;
+            e = e | self.and()?;
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut e = self.not()?;
+        while self.peek_is("AND") {
+            self.pos += 1;
+            e = e & self.not()?;
+        }
+        Ok(e)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        let tok = match self.tokens.get(self.pos) {
+            Some(t) => t.clone(),
+            None => return Err(QueryError::Parse("unexpected end of query".to_string())),
+        };
+        self.pos += 1;
+        if tok.eq_ignore_ascii_case("NOT") {
+            return Ok(!self.not()?);
+        }
+        if tok == "(" {
+            let e = self.or()?;
+            if !self.peek_is(")") {
+                return Err(QueryError::Parse(format!("missing ) at token {}", self.pos)));
+            }
+            self.pos += 1;
+            return Ok(e);
+        }
+        if tok == ")" || tok.eq_ignore_ascii_case("AND") || tok.eq_ignore_ascii_case("OR") {
+            return Err(QueryError::Parse(format!("unexpected {} at token {}", tok, self.pos - 1)));
+        }
+        Ok(Expr::Col(tok))
+    }
+}
+
+impl Expr {
+    /// Parses text like "a AND (b OR NOT c)".  AND binds tighter than OR,
+    /// the keywords are case insensitive and any other word is a column.
+    pub fn parse(text: &str) -> Result<Expr, QueryError> {
+        let mut p = Parser { tokens: tokenize(text), pos: 0 };
+        let e = p.or()?;
+        if p.pos != p.tokens.len() {
+            return Err(QueryError::Parse(format!("unexpected {} at token {}",
+                                                 p.tokens[p.pos], p.pos)));
+        }
+        Ok(e)
+    }
+}
+
+/// An expression with its columns looked up, ready to evaluate.
+enum Plan<'a> {
+    Col(&'a BitArray),
+    Not(Box<Plan<'a>>),
+    And(Box<Plan<'a>>, Box<Plan<'a>>),
+    Or(Box<Plan<'a>>, Box<Plan<'a>>),
+}
+
+impl<'a> Plan<'a> {
+    /// The value of the expression for rows [pos, pos + width).
+    fn eval_word(&self, pos: usize, width: usize) -> u64 {
+        match self {
+            Plan::Col(bits) => bits.read_bits(pos, width),
+            Plan::Not(e) => !e.eval_word(pos, width) & (u64::MAX >> (64 - width)),
+            Plan::And(a, b) => a.eval_word(pos, width) & b.eval_word(pos, width),
+            Plan::Or(a, b) => a.eval_word(pos, width) | b.eval_word(pos, width),
+        }
+    }
+}
+
+/// Named columns of the same number of rows.
+#[derive(Debug, Clone, Default)]
+pub struct BitmapIndex {
+    rows: usize,
+    columns: HashMap<String, BitArray>,
+}
+
+impl BitmapIndex {
+    pub fn new(rows: usize) -> BitmapIndex {
+        BitmapIndex { rows, columns: HashMap::new() }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Adds or replaces a column, which must have one bit per row.
+    pub fn add_column(&mut self, name: &str, bits: BitArray) {
+        assert_eq!(bits.get_bit_sz(), self.rows, "BitmapIndex column {:?} has the wrong size", name);
+        self.columns.insert(name.to_string(), bits);
+    }
+
+    pub fn column(&self, name: &str) -> Option<&BitArray> {
+        self.columns.get(name)
+    }
+
+    fn plan(&self, e: &Expr) -> Result<Plan<'_>, QueryError> {
+        Ok(match e {
+            Expr::Col(name) => Plan::Col(self.columns.get(name)
+                                         .ok_or_else(|| QueryError::UnknownColumn(name.clone()))?),
+            Expr::Not(a) => Plan::Not(Box::new(self.plan(a)?)),
+            Expr::And(a, b) => Plan::And(Box::new(self.plan(a)?), Box::new(self.plan(b)?)),
+            Expr::Or(a, b) => Plan::Or(Box::new(self.plan(a)?), Box::new(self.plan(b)?)),
+        })
+    }
+
+    /// The bitmap of the rows matching e.  Iterate the selected rows with
+    /// iter_ones on the result.
+    pub fn eval(&self, e: &Expr) -> Result<BitArray, QueryError> {
+        let plan = self.plan(e)?;
+        let mut out = BitArray::new(self.rows);
+        let mut pos = 0;
+        while pos < self.rows {
+            let width = usize::min(64, self.rows - pos);
+            out.write_bits(pos, width, plan.eval_word(pos, width));
+            pos += width;
+        }
+        Ok(out)
+    }
+
+    /// Parses and evaluates a query in one go.
+    pub fn query(&self, text: &str) -> Result<BitArray, QueryError> {
+        self.eval(&Expr::parse(text)?)
+    }
+
+    /// The number of rows matching e, without building the result bitmap.
+    pub fn count(&self, e: &Expr) -> Result<usize, QueryError> {
+        let plan = self.plan(e)?;
+        let mut ones = 0;
+        let mut pos = 0;
+        while pos < self.rows {
+            let width = usize::min(64, self.rows - pos);
+            ones += plan.eval_word(pos, width).count_ones() as usize;
+            pos += width;
+        }
+        Ok(ones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> BitmapIndex {
+        let mut index = BitmapIndex::new(8);
+        index.add_column("a", BitArray::from_str("11110000"));
+        index.add_column("b", BitArray::from_str("11001100"));
+        index.add_column("c", BitArray::from_str("10101010"));
+        index
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Expr::parse("a AND (b OR NOT c)").unwrap(), col("a") & (col("b") | !col("c")));
+        assert_eq!(Expr::parse("a or b and c").unwrap(), col("a") | (col("b") & col("c")));
+        assert_eq!(Expr::parse("not not a").unwrap(), !!col("a"));
+        assert!(matches!(Expr::parse("a AND"), Err(QueryError::Parse(_))));
+        assert!(matches!(Expr::parse("(a OR b"), Err(QueryError::Parse(_))));
+        assert!(matches!(Expr::parse("a b"), Err(QueryError::Parse(_))));
+    }
+
+    #[test]
+    fn test_eval() {
+        let index = example();
+        let r = index.query("a AND (b OR NOT c)").unwrap();
+        assert_eq!(r.show(), "11010000");
+        assert_eq!(r.iter_ones().collect::<Vec<_>>(), vec![4, 6, 7]);
+        assert_eq!(index.count(&(col("b") | col("c"))).unwrap(), 6);
+        assert_eq!(index.query("d"), Err(QueryError::UnknownColumn("d".to_string())));
+    }
+
+    #[test]
+    fn test_eval_matches_bitwise_ops() {
+        let n = 1000;
+        let mut index = BitmapIndex::new(n);
+        let cols: Vec<BitArray> = (0 .. 3).map(|k| {
+            let mut ba = BitArray::new(n);
+            ba.randfill_seeded(k);
+            ba
+        }).collect();
+        for (name, bits) in ["a", "b", "c"].iter().zip(&cols) {
+            index.add_column(name, bits.clone());
+        }
+        let mut expected = cols[2].clone();
+        expected.invert();
+        expected |= &cols[1];
+        expected &= &cols[0];
+        assert_eq!(index.query("a AND (b OR NOT c)").unwrap(), expected);
+        assert_eq!(index.count(&Expr::parse("NOT a").unwrap()).unwrap(), n - cols[0].count_ones());
+    }
+}
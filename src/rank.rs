@@ -0,0 +1,105 @@
+// Constant time rank queries (how many ones come before bit i) using a
+// table of running counts, one per block of bits.
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::bitarray::BitArray;
+
+/// Bits per block of the running count table.
+pub const RANK_BLOCK_BITS: usize = 512;
+
+/// A rank index over a borrowed BitArray.  The borrow keeps the array from
+/// changing underneath the table; build a new index after mutating it.
+#[derive(Debug, Clone)]
+pub struct RankIndex<'a> {
+    bits: &'a BitArray,
+    /// blocks[k] is the number of ones in [0, k * RANK_BLOCK_BITS).
+    blocks: Vec<usize>,
+}
+
+fn count_block(bits: &BitArray, k: usize) -> usize {
+    let start = k * RANK_BLOCK_BITS;
+    bits.count_ones_range(start, usize::min(RANK_BLOCK_BITS, bits.get_bit_sz() - start))
+}
+
+impl<'a> RankIndex<'a> {
+    /// Builds the table, counting the blocks on all cores with the rayon
+    /// feature.
+    pub fn new(bits: &'a BitArray) -> RankIndex<'a> {
+        let num_blocks = bits.get_bit_sz().div_ceil(RANK_BLOCK_BITS);
+
+        #[cfg(feature = "rayon")]
+        let counts: Vec<usize> = (0 .. num_blocks).into_par_iter()
+            .map(|k| count_block(bits, k))
+            .collect();
+        #[cfg(not(feature = "rayon"))]
+        let counts: Vec<usize> = (0 .. num_blocks).map(|k| count_block(bits, k)).collect();
+
+        // turn the per block counts into an exclusive prefix sum
+        let mut blocks = Vec::with_capacity(num_blocks + 1);
+        let mut total = 0;
+        blocks.push(0);
+        for c in counts {
+            total += c;
+            blocks.push(total);
+        }
+        RankIndex { bits, blocks }
+    }
+
+    /// The number of ones in [0, bit_index), bit_index <= the array size.
+    pub fn rank(&self, bit_index: usize) -> usize {
+        assert!(bit_index <= self.bits.get_bit_sz());
+        let k = bit_index / RANK_BLOCK_BITS;
+        let start = k * RANK_BLOCK_BITS;
+        self.blocks[k] + self.bits.count_ones_range(start, bit_index - start)
+    }
+
+    /// The number of zeros in [0, bit_index).
+    pub fn rank_zeros(&self, bit_index: usize) -> usize {
+        bit_index - self.rank(bit_index)
+    }
+
+    /// The total number of ones in the array.
+    pub fn count_ones(&self) -> usize {
+        *self.blocks.last().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_small() {
+        let ba = BitArray::from_str("1111000010010110");
+        let index = RankIndex::new(&ba);
+        let expected = [0, 0, 1, 2, 2, 3, 3, 3, 4, 4, 4, 4, 4, 5, 6, 7, 8];
+        for (i, &r) in expected.iter().enumerate() {
+            assert_eq!(index.rank(i), r);
+        }
+        assert_eq!(index.rank_zeros(16), 8);
+        assert_eq!(index.count_ones(), 8);
+    }
+
+    #[test]
+    fn test_rank_matches_scan() {
+        let n = 10 * RANK_BLOCK_BITS + 77;
+        let mut ba = BitArray::new(n);
+        ba.randfill();
+        let index = RankIndex::new(&ba);
+        let mut ones = 0;
+        for i in 0 ..= n {
+            assert_eq!(index.rank(i), ones);
+            if i < n && ba.get(i) {
+                ones += 1;
+            }
+        }
+        assert_eq!(index.count_ones(), ba.count_ones());
+    }
+
+    #[test]
+    fn test_rank_empty() {
+        let ba = BitArray::new(0);
+        assert_eq!(RankIndex::new(&ba).rank(0), 0);
+    }
+}
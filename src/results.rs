@@ -0,0 +1,183 @@
+// Benchmark result files and the comparison behind
+// `everybit compare-results old.json new.json`, for failing a CI job
+// when a commit makes something slower.  A result file is either
+//
+//     {"benchmarks": [{"name": "rotate/1000", "seconds": 0.0021}, ...]}
+//
+// or what `everybit -s --format json` prints, whose tiers become
+// benchmarks named small/tier0, small/tier1 and so on.
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::perf::TimedRun;
+
+/// One timed case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Benchmark {
+    pub name: String,
+    pub seconds: f64,
+}
+
+/// The benchmarks of one result file, in file order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Results {
+    pub benchmarks: Vec<Benchmark>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ResultFile {
+    Results(Results),
+    Timed(TimedRun),
+}
+
+impl From<&TimedRun> for Results {
+    fn from(run: &TimedRun) -> Results {
+        let benchmarks = run.tiers.iter()
+            .map(|t| Benchmark { name: format!("{}/tier{}", run.size.name(), t.tier), seconds: t.seconds })
+            .collect();
+        Results { benchmarks }
+    }
+}
+
+impl Results {
+    pub fn from_json(text: &str) -> Result<Results, String> {
+        match serde_json::from_str(text) {
+            Ok(ResultFile::Results(results)) => Ok(results),
+            Ok(ResultFile::Timed(run)) => Ok(Results::from(&run)),
+            Err(_) => Err("neither a benchmarks list nor a timed run".to_string()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Results, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Results::from_json(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn get(&self, name: &str) -> Option<f64> {
+        self.benchmarks.iter().find(|b| b.name == name).map(|b| b.seconds)
+    }
+}
+
+/// A benchmark present in both files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub name: String,
+    pub old: f64,
+    pub new: f64,
+}
+
+impl Delta {
+    /// The change in percent, positive when new is slower.
+    pub fn percent(&self) -> f64 {
+        if self.old == 0.0 {
+            if self.new == 0.0 { 0.0 } else { f64::INFINITY }
+        } else {
+            100.0 * (self.new - self.old) / self.old
+        }
+    }
+}
+
+/// The benchmarks of two files matched up by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// In the order of the new file.
+    pub deltas: Vec<Delta>,
+    pub only_old: Vec<String>,
+    pub only_new: Vec<String>,
+    /// How many percent slower counts as a regression.
+    pub threshold: f64,
+}
+
+pub fn compare(old: &Results, new: &Results, threshold: f64) -> Comparison {
+    let mut deltas = vec![];
+    let mut only_new = vec![];
+    for b in &new.benchmarks {
+        match old.get(&b.name) {
+            Some(seconds) => deltas.push(Delta { name: b.name.clone(), old: seconds, new: b.seconds }),
+            None => only_new.push(b.name.clone()),
+        }
+    }
+    let only_old = old.benchmarks.iter()
+        .filter(|b| new.get(&b.name).is_none())
+        .map(|b| b.name.clone())
+        .collect();
+    Comparison { deltas, only_old, only_new, threshold }
+}
+
+impl Comparison {
+    pub fn regressions(&self) -> impl Iterator<Item = &Delta> {
+        self.deltas.iter().filter(move |d| d.percent() > self.threshold)
+    }
+}
+
+/// One line per benchmark, regressions marked, then the unmatched names.
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self.deltas.iter().map(|d| &d.name).chain(&self.only_old).chain(&self.only_new);
+        let width = names.map(|name| name.len()).max().unwrap_or(0);
+        for d in &self.deltas {
+            let mark = if d.percent() > self.threshold { "  REGRESSION" } else { "" };
+            writeln!(f, "{:<width$}  {:>12.6}s -> {:>12.6}s  {:>+8.1}%{}",
+                     d.name, d.old, d.new, d.percent(), mark, width = width)?;
+        }
+        for name in &self.only_old {
+            writeln!(f, "{:<width$}  only in the old results", name, width = width)?;
+        }
+        for name in &self.only_new {
+            writeln!(f, "{:<width$}  only in the new results", name, width = width)?;
+        }
+        writeln!(f, "{} regressions over {}%", self.regressions().count(), self.threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf::{Size, TierTime};
+
+    fn results(pairs: &[(&str, f64)]) -> Results {
+        Results {
+            benchmarks: pairs.iter().map(|&(name, seconds)| Benchmark { name: name.to_string(), seconds })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_from_json() {
+        let r = Results::from_json(r#"{"benchmarks": [{"name": "a", "seconds": 1.5}]}"#).unwrap();
+        assert_eq!(r, results(&[("a", 1.5)]));
+        let run = TimedRun {
+            size: Size::Small,
+            time_limit: 0.01,
+            seed: 3,
+            tiers: vec![TierTime { tier: 0, bit_sz: 1000, seconds: 0.002, cold_seconds: None }],
+            completed: Some(0),
+            calibration: 1.0,
+            warmup: 0,
+            strategy: None,
+        };
+        let r = Results::from_json(&serde_json::to_string(&run).unwrap()).unwrap();
+        assert_eq!(r, results(&[("small/tier0", 0.002)]));
+        assert!(Results::from_json("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = results(&[("a", 1.0), ("b", 2.0), ("gone", 1.0)]);
+        let new = results(&[("b", 2.5), ("a", 1.04), ("added", 1.0)]);
+        let c = compare(&old, &new, 5.0);
+        assert_eq!(c.deltas.iter().map(|d| &d.name[..]).collect::<Vec<_>>(), vec!["b", "a"]);
+        assert_eq!(c.only_old, vec!["gone"]);
+        assert_eq!(c.only_new, vec!["added"]);
+        assert_eq!(c.regressions().map(|d| &d.name[..]).collect::<Vec<_>>(), vec!["b"]);
+        assert_eq!(c.to_string(), concat!(
+            "b          2.000000s ->     2.500000s     +25.0%  REGRESSION\n",
+            "a          1.000000s ->     1.040000s      +4.0%\n",
+            "gone   only in the old results\n",
+            "added  only in the new results\n",
+            "1 regressions over 5%\n"));
+    }
+}
@@ -0,0 +1,383 @@
+// A bit array for editing in the middle: a balanced tree of leaf chunks,
+// so inserting, removing and splitting cost O(log n) tree work plus one
+// leaf's worth of copying, instead of shifting the whole tail.
+use std::ops::Range;
+
+use crate::bitarray::BitArray;
+
+/// Leaves are at most this many bits.  New leaves from from_bitarray are
+/// this size; edits can leave smaller ones, which get merged back when two
+/// neighbours fit in one.
+pub const LEAF_BITS: usize = 1 << 12;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf(BitArray),
+    Concat { left: Box<Node>, right: Box<Node>, len: usize, height: usize },
+}
+
+/// Copies src into dst at dst_off.
+fn copy_into(dst: &mut BitArray, dst_off: usize, src: &BitArray) {
+    let mut done = 0;
+    while done < src.get_bit_sz() {
+        let width = usize::min(64, src.get_bit_sz() - done);
+        dst.write_bits(dst_off + done, width, src.read_bits(done, width));
+        done += width;
+    }
+}
+
+impl Node {
+    fn empty() -> Node {
+        Node::Leaf(BitArray::new(0))
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(bits) => bits.get_bit_sz(),
+            Node::Concat { len, .. } => *len,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 0,
+            Node::Concat { height, .. } => *height,
+        }
+    }
+
+    fn concat(left: Node, right: Node) -> Node {
+        let (len, height) = (left.len() + right.len(), 1 + usize::max(left.height(), right.height()));
+        Node::Concat { left: Box::new(left), right: Box::new(right), len, height }
+    }
+
+    fn into_children(self) -> (Node, Node) {
+        match self {
+            Node::Concat { left, right, .. } => (*left, *right),
+            Node::Leaf(_) => unreachable!("a leaf has no children"),
+        }
+    }
+
+    //     t            r
+    //    / \          / \
+    //   a   r   =>   t   c
+    //      / \      / \
+    //     b   c    a   b
+    fn rotate_left(self) -> Node {
+        let (a, r) = self.into_children();
+        let (b, c) = r.into_children();
+        Node::concat(Node::concat(a, b), c)
+    }
+
+    fn rotate_right(self) -> Node {
+        let (l, c) = self.into_children();
+        let (a, b) = l.into_children();
+        Node::concat(a, Node::concat(b, c))
+    }
+
+    /// The concatenation of left and right, rebalanced AVL style so the
+    /// result is at most one level taller than the taller input.
+    fn join(left: Node, right: Node) -> Node {
+        if left.len() == 0 {
+            return right;
+        }
+        if right.len() == 0 {
+            return left;
+        }
+        if let (Node::Leaf(a), Node::Leaf(b)) = (&left, &right) {
+            if a.get_bit_sz() + b.get_bit_sz() <= LEAF_BITS {
+                let mut merged = BitArray::new(a.get_bit_sz() + b.get_bit_sz());
+                copy_into(&mut merged, 0, a);
+                copy_into(&mut merged, a.get_bit_sz(), b);
+                return Node::Leaf(merged);
+            }
+        }
+        if left.height() > right.height() + 1 {
+            Node::join_right(left, right)
+        } else if right.height() > left.height() + 1 {
+            Node::join_left(left, right)
+        } else {
+            Node::concat(left, right)
+        }
+    }
+
+    /// join for a left side more than one level taller: walk down its
+    /// right spine to a subtree of matching height.
+    fn join_right(left: Node, right: Node) -> Node {
+        let (l, c) = left.into_children();
+        if c.height() <= right.height() + 1 {
+            let t = Node::join(c, right);
+            if t.height() <= l.height() + 1 {
+                Node::concat(l, t)
+            } else {
+                Node::concat(l, t.rotate_right()).rotate_left()
+            }
+        } else {
+            let t = Node::join_right(c, right);
+            let needs_rotation = t.height() > l.height() + 1;
+            let joined = Node::concat(l, t);
+            if needs_rotation { joined.rotate_left() } else { joined }
+        }
+    }
+
+    /// The mirror image of join_right.
+    fn join_left(left: Node, right: Node) -> Node {
+        let (c, r) = right.into_children();
+        if c.height() <= left.height() + 1 {
+            let t = Node::join(left, c);
+            if t.height() <= r.height() + 1 {
+                Node::concat(t, r)
+            } else {
+                Node::concat(t.rotate_left(), r).rotate_right()
+            }
+        } else {
+            let t = Node::join_left(left, c);
+            let needs_rotation = t.height() > r.height() + 1;
+            let joined = Node::concat(t, r);
+            if needs_rotation { joined.rotate_right() } else { joined }
+        }
+    }
+
+    /// Splits into [0, at) and [at, len).
+    fn split(self, at: usize) -> (Node, Node) {
+        match self {
+            Node::Leaf(bits) => {
+                let n = bits.get_bit_sz();
+                (Node::Leaf(bits.slice(0 .. at).to_bitarray()),
+                 Node::Leaf(bits.slice(at .. n).to_bitarray()))
+            }
+            Node::Concat { left, right, .. } => {
+                let ll = left.len();
+                if at < ll {
+                    let (a, b) = left.split(at);
+                    (a, Node::join(b, *right))
+                } else if at > ll {
+                    let (a, b) = right.split(at - ll);
+                    (Node::join(*left, a), b)
+                } else {
+                    (*left, *right)
+                }
+            }
+        }
+    }
+
+    /// A balanced tree over bits [start, end) of ba, in LEAF_BITS leaves.
+    fn build(ba: &BitArray, start: usize, end: usize) -> Node {
+        let leaves = (end - start).div_ceil(LEAF_BITS);
+        if leaves <= 1 {
+            return Node::Leaf(ba.slice(start .. end).to_bitarray());
+        }
+        let mid = start + leaves / 2 * LEAF_BITS;
+        Node::concat(Node::build(ba, start, mid), Node::build(ba, mid, end))
+    }
+
+    fn write_to(&self, out: &mut BitArray, offset: usize) {
+        match self {
+            Node::Leaf(bits) => copy_into(out, offset, bits),
+            Node::Concat { left, right, .. } => {
+                left.write_to(out, offset);
+                right.write_to(out, offset + left.len());
+            }
+        }
+    }
+}
+
+/// A rope of bits.  get and set are O(log n); insert, remove, split_off
+/// and append are O(log n) plus the copying of at most a couple of
+/// leaves.  Convert to and from a flat BitArray with from_bitarray and
+/// to_bitarray.
+#[derive(Debug, Clone)]
+pub struct BitRope {
+    root: Node,
+}
+
+impl BitRope {
+    pub fn new() -> BitRope {
+        BitRope { root: Node::empty() }
+    }
+
+    pub fn from_bitarray(ba: &BitArray) -> BitRope {
+        BitRope { root: Node::build(ba, 0, ba.get_bit_sz()) }
+    }
+
+    pub fn to_bitarray(&self) -> BitArray {
+        let mut out = BitArray::new(self.len());
+        self.root.write_to(&mut out, 0);
+        out
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The height of the tree, 0 for a single leaf.
+    pub fn height(&self) -> usize {
+        self.root.height()
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        assert!(bit_index < self.len());
+        let (mut node, mut i) = (&self.root, bit_index);
+        loop {
+            match node {
+                Node::Leaf(bits) => return bits.get(i),
+                Node::Concat { left, right, .. } => {
+                    if i < left.len() {
+                        node = left;
+                    } else {
+                        i -= left.len();
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        assert!(bit_index < self.len());
+        let (mut node, mut i) = (&mut self.root, bit_index);
+        loop {
+            match node {
+                Node::Leaf(bits) => return bits.set(i, val),
+                Node::Concat { left, right, .. } => {
+                    if i < left.len() {
+                        node = left;
+                    } else {
+                        i -= left.len();
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    fn take_root(&mut self) -> Node {
+        std::mem::replace(&mut self.root, Node::empty())
+    }
+
+    /// Inserts the bits of src so they start at bit_index, moving the
+    /// bits from bit_index on up by src's size.
+    pub fn insert(&mut self, bit_index: usize, src: &BitArray) {
+        assert!(bit_index <= self.len());
+        let (a, b) = self.take_root().split(bit_index);
+        self.root = Node::join(Node::join(a, Node::build(src, 0, src.get_bit_sz())), b);
+    }
+
+    /// Removes the bits in range, closing the gap.
+    pub fn remove(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end && range.end <= self.len());
+        let (a, rest) = self.take_root().split(range.start);
+        let (_, c) = rest.split(range.end - range.start);
+        self.root = Node::join(a, c);
+    }
+
+    /// Splits off and returns the bits from at on, keeping [0, at).
+    pub fn split_off(&mut self, at: usize) -> BitRope {
+        assert!(at <= self.len());
+        let (a, b) = self.take_root().split(at);
+        self.root = a;
+        BitRope { root: b }
+    }
+
+    /// Moves all of other's bits onto the end of self.
+    pub fn append(&mut self, other: BitRope) {
+        self.root = Node::join(self.take_root(), other.root);
+    }
+}
+
+impl Default for BitRope {
+    fn default() -> BitRope {
+        BitRope::new()
+    }
+}
+
+impl PartialEq for BitRope {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_bitarray() == other.to_bitarray()
+    }
+}
+impl Eq for BitRope {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balanced(node: &Node) -> bool {
+        match node {
+            Node::Leaf(_) => true,
+            Node::Concat { left, right, len, height } => {
+                *len == left.len() + right.len()
+                    && *height == 1 + usize::max(left.height(), right.height())
+                    && left.height().abs_diff(right.height()) <= 1
+                    && balanced(left) && balanced(right)
+            }
+        }
+    }
+
+    fn random(n: usize, seed: u64) -> BitArray {
+        let mut ba = BitArray::new(n);
+        ba.randfill_seeded(seed);
+        ba
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ba = random(10 * LEAF_BITS + 77, 1);
+        let rope = BitRope::from_bitarray(&ba);
+        assert!(balanced(&rope.root));
+        assert_eq!(rope.len(), ba.get_bit_sz());
+        assert_eq!(rope.to_bitarray(), ba);
+        assert_eq!(rope.get(5 * LEAF_BITS + 3), ba.get(5 * LEAF_BITS + 3));
+        assert!(BitRope::new().is_empty());
+    }
+
+    #[test]
+    fn test_edits_match_vec() {
+        let mut rope = BitRope::from_bitarray(&random(3 * LEAF_BITS, 2));
+        let mut v: Vec<bool> = rope.to_bitarray().iter().collect();
+        for k in 0 .. 60u64 {
+            let at = (k as usize * 7919) % (v.len() + 1);
+            if k % 3 == 2 && at + 500 <= v.len() {
+                rope.remove(at .. at + 500);
+                v.drain(at .. at + 500);
+            } else {
+                let src = random(1 + (k as usize * 131) % 2000, 100 + k);
+                rope.insert(at, &src);
+                for (j, b) in src.iter().enumerate() {
+                    v.insert(at + j, b);
+                }
+            }
+            assert!(balanced(&rope.root));
+        }
+        rope.set(10, !v[10]);
+        v[10] = !v[10];
+        assert_eq!(rope.to_bitarray().iter().collect::<Vec<_>>(), v);
+    }
+
+    #[test]
+    fn test_split_append() {
+        let ba = random(5 * LEAF_BITS + 9, 3);
+        let mut rope = BitRope::from_bitarray(&ba);
+        let tail = rope.split_off(2 * LEAF_BITS + 100);
+        assert_eq!(rope.to_bitarray(), ba.slice(0 .. 2 * LEAF_BITS + 100).to_bitarray());
+        assert_eq!(tail.len(), ba.get_bit_sz() - rope.len());
+        rope.append(tail);
+        assert_eq!(rope.to_bitarray(), ba);
+        assert!(balanced(&rope.root));
+    }
+
+    #[test]
+    fn test_height_stays_logarithmic() {
+        // many small inserts at the front would make a list of a naive rope
+        let mut rope = BitRope::new();
+        let chunk = random(LEAF_BITS, 4);
+        for _ in 0 .. 256 {
+            rope.insert(0, &chunk);
+        }
+        assert!(rope.height() <= 12, "height {}", rope.height());
+        assert!(balanced(&rope.root));
+    }
+}
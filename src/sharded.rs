@@ -0,0 +1,374 @@
+// A BitArray split into shards, each behind its own RwLock, so threads
+// working on different parts of the array don't wait for each other and
+// range operations still see a consistent view of the bits they cover.
+// Rotations and reversals copy the bits of their range out of the shards,
+// do the work on the copy and write it back, all under the write locks.
+use std::ops::{Deref, Range};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::bitarray::BitArray;
+
+/// Default shard size: 64k bits (8KB) per lock.
+pub const DEFAULT_SHARD_BITS: usize = 1 << 16;
+
+/// A bit array usable from many threads through &self, with BitArray's
+/// operations, ranges given as Range rather than offset and length.
+/// Single bit operations take one shard lock; range operations lock every
+/// shard the range touches, in ascending order so they can't deadlock, and
+/// hold them all until they are done.
+#[derive(Debug)]
+pub struct ShardedBitArray {
+    bit_sz: usize,
+    shard_bits: usize,
+    shards: Vec<RwLock<BitArray>>,
+}
+
+impl ShardedBitArray {
+    pub fn new(bit_sz: usize) -> ShardedBitArray {
+        ShardedBitArray::with_shard_bits(bit_sz, DEFAULT_SHARD_BITS)
+    }
+
+    /// shard_bits bits per lock, which must be a non-zero multiple of 64 so
+    /// shards split on word boundaries.
+    pub fn with_shard_bits(bit_sz: usize, shard_bits: usize) -> ShardedBitArray {
+        assert!(shard_bits > 0 && shard_bits.is_multiple_of(64));
+        let shards = (0 .. bit_sz.div_ceil(shard_bits))
+            .map(|s| RwLock::new(BitArray::new(usize::min(shard_bits, bit_sz - s * shard_bits))))
+            .collect();
+        ShardedBitArray { bit_sz, shard_bits, shards }
+    }
+
+    pub fn from_bitarray(ba: &BitArray, shard_bits: usize) -> ShardedBitArray {
+        let arr = ShardedBitArray::with_shard_bits(ba.get_bit_sz(), shard_bits);
+        for (s, shard) in arr.shards.iter().enumerate() {
+            let mut shard = shard.write().unwrap();
+            let len = shard.get_bit_sz();
+            shard.copy_bits(0, ba, s * shard_bits, len);
+        }
+        arr
+    }
+
+    /// Copies the whole array out, holding every shard's read lock at once.
+    pub fn to_bitarray(&self) -> BitArray {
+        let mut guards = self.read_shards(0 .. self.bit_sz);
+        self.gather(&(0 .. self.bit_sz), &mut guards)
+    }
+
+    pub fn get_bit_sz(&self) -> usize {
+        self.bit_sz
+    }
+
+    pub fn get(&self, bit_index: usize) -> bool {
+        assert!(bit_index < self.bit_sz);
+        let shard = self.shards[bit_index / self.shard_bits].read().unwrap();
+        shard.get(bit_index % self.shard_bits)
+    }
+
+    pub fn set(&self, bit_index: usize, val: bool) {
+        assert!(bit_index < self.bit_sz);
+        let mut shard = self.shards[bit_index / self.shard_bits].write().unwrap();
+        shard.set(bit_index % self.shard_bits, val)
+    }
+
+    /// The bit_width (at most 64) bits from bit_offset on, which may
+    /// straddle two shards, under both their read locks.
+    pub fn read_bits(&self, bit_offset: usize, bit_width: usize) -> u64 {
+        assert!(bit_width <= 64);
+        let range = bit_offset .. bit_offset + bit_width;
+        let mut guards = self.read_shards(range.clone());
+        let (mut value, mut at) = (0, 0);
+        self.split(&range, &mut guards, |shard, offset, len| {
+            value |= shard.read_bits(offset, len) << at;
+            at += len;
+        });
+        value
+    }
+
+    /// Writes the low bit_width bits of value from bit_offset on, the
+    /// inverse of read_bits.
+    pub fn write_bits(&self, bit_offset: usize, bit_width: usize, value: u64) {
+        assert!(bit_width <= 64);
+        assert!(bit_width == 64 || value >> bit_width == 0);
+        let range = bit_offset .. bit_offset + bit_width;
+        let mut guards = self.write_shards(range.clone());
+        let mut at = 0;
+        self.split(&range, &mut guards, |shard, offset, len| {
+            shard.write_bits(offset, len, (value >> at) & (u64::MAX >> (64 - len)));
+            at += len;
+        });
+    }
+
+    /// Calls f with each shard the range touches and the part of the range
+    /// inside it, as (offset, length) relative to the shard.
+    fn split<G, F>(&self, range: &Range<usize>, guards: &mut [G], mut f: F)
+    where F: FnMut(&mut G, usize, usize) {
+        let first = range.start / self.shard_bits;
+        let mut pos = range.start;
+        while pos < range.end {
+            let s = pos / self.shard_bits;
+            let shard_end = usize::min((s + 1) * self.shard_bits, range.end);
+            f(&mut guards[s - first], pos % self.shard_bits, shard_end - pos);
+            pos = shard_end;
+        }
+    }
+
+    fn shard_range(&self, range: &Range<usize>) -> Range<usize> {
+        assert!(range.start <= range.end && range.end <= self.bit_sz);
+        if range.start == range.end {
+            return 0 .. 0;
+        }
+        range.start / self.shard_bits .. (range.end - 1) / self.shard_bits + 1
+    }
+
+    fn read_shards(&self, range: Range<usize>) -> Vec<RwLockReadGuard<'_, BitArray>> {
+        self.shards[self.shard_range(&range)].iter().map(|s| s.read().unwrap()).collect()
+    }
+
+    fn write_shards(&self, range: Range<usize>) -> Vec<RwLockWriteGuard<'_, BitArray>> {
+        self.shards[self.shard_range(&range)].iter().map(|s| s.write().unwrap()).collect()
+    }
+
+    /// A copy of the bits in range out of the locked shards in guards.
+    fn gather<G: Deref<Target = BitArray>>(&self, range: &Range<usize>, guards: &mut [G]) -> BitArray {
+        let mut bits = BitArray::new(range.len());
+        let mut at = 0;
+        self.split(range, guards, |shard, offset, len| {
+            bits.copy_bits(at, shard, offset, len);
+            at += len;
+        });
+        bits
+    }
+
+    /// Calls f with a copy of the bits in range, bit 0 the first of them,
+    /// and writes what it leaves back, all under the write locks of every
+    /// shard the range touches.
+    fn update_range<F: FnOnce(&mut BitArray)>(&self, range: Range<usize>, f: F) {
+        let mut guards = self.write_shards(range.clone());
+        let mut bits = self.gather(&range, &mut guards);
+        f(&mut bits);
+        let mut at = 0;
+        self.split(&range, &mut guards, |shard, offset, len| {
+            shard.copy_bits(offset, &bits, at, len);
+            at += len;
+        });
+    }
+
+    /// Sets every bit in range to val, atomically with respect to the other
+    /// range operations.
+    pub fn fill_range(&self, range: Range<usize>, val: bool) {
+        let mut guards = self.write_shards(range.clone());
+        self.split(&range, &mut guards, |shard, offset, len| shard.fill_range(offset, len, val));
+    }
+
+    pub fn fill(&self, val: bool) {
+        self.fill_range(0 .. self.bit_sz, val)
+    }
+
+    /// The number of ones in range, counted under the read locks of all
+    /// the shards it touches.
+    pub fn count_ones_range(&self, range: Range<usize>) -> usize {
+        let mut guards = self.read_shards(range.clone());
+        let mut ones = 0;
+        self.split(&range, &mut guards, |shard, offset, len| {
+            ones += shard.count_ones_range(offset, len)
+        });
+        ones
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.count_ones_range(0 .. self.bit_sz)
+    }
+
+    pub fn count_zeros(&self) -> usize {
+        self.bit_sz - self.count_ones()
+    }
+
+    /// Whether an odd number of bits are set.
+    pub fn parity(&self) -> bool {
+        let guards = self.read_shards(0 .. self.bit_sz);
+        guards.iter().fold(false, |odd, shard| odd ^ shard.parity())
+    }
+
+    /// Flips every bit, holding every shard's write lock at once.
+    pub fn invert(&self) {
+        for shard in self.write_shards(0 .. self.bit_sz).iter_mut() {
+            shard.invert();
+        }
+    }
+
+    /// BitArray::rotate on the bits in range: right by bit_right_amount,
+    /// left if it is negative.
+    pub fn rotate(&self, range: Range<usize>, bit_right_amount: isize) {
+        self.update_range(range, |bits| {
+            let len = bits.get_bit_sz();
+            bits.rotate(0, len, bit_right_amount)
+        })
+    }
+
+    pub fn rotate_left(&self, range: Range<usize>, bit_left_amount: usize) {
+        self.update_range(range, |bits| {
+            let len = bits.get_bit_sz();
+            bits.rotate_left(0, len, bit_left_amount)
+        })
+    }
+
+    pub fn rotate_right(&self, range: Range<usize>, bit_right_amount: usize) {
+        self.update_range(range, |bits| {
+            let len = bits.get_bit_sz();
+            bits.rotate_right(0, len, bit_right_amount)
+        })
+    }
+
+    /// Reverses the order of the bits in range.
+    pub fn reverse_range(&self, range: Range<usize>) {
+        self.update_range(range, |bits| bits.reverse())
+    }
+
+    pub fn reverse(&self) {
+        self.reverse_range(0 .. self.bit_sz)
+    }
+
+    /// Fills every shard with random bits, holding all their write locks.
+    pub fn randfill(&self) {
+        for shard in self.write_shards(0 .. self.bit_sz).iter_mut() {
+            shard.randfill();
+        }
+    }
+
+    /// The same bits BitArray::randfill_seeded gives an array of this size.
+    pub fn randfill_seeded(&self, seed: u64) {
+        self.update_range(0 .. self.bit_sz, |bits| bits.randfill_seeded(seed))
+    }
+
+    /// As BitArray::show, from a consistent copy of the whole array.
+    pub fn show(&self) -> String {
+        self.to_bitarray().show()
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.to_bitarray().to_hex()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_get_set_across_shards() {
+        let arr = ShardedBitArray::with_shard_bits(1000, 128);
+        arr.set(0, true);
+        arr.set(127, true);
+        arr.set(128, true);
+        arr.set(999, true);
+        assert!(arr.get(127) && arr.get(128) && arr.get(999));
+        assert!(!arr.get(1));
+        assert_eq!(arr.count_ones(), 4);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let arr = ShardedBitArray::with_shard_bits(1000, 64);
+        arr.fill_range(10 .. 300, true);
+        assert_eq!(arr.count_ones(), 290);
+        assert_eq!(arr.count_ones_range(0 .. 64), 54);
+        assert_eq!(arr.count_ones_range(250 .. 1000), 50);
+        assert_eq!(arr.count_ones_range(500 .. 500), 0);
+        arr.fill(true);
+        assert_eq!(arr.count_ones(), 1000);
+    }
+
+    #[test]
+    fn test_bitarray_round_trip() {
+        let mut ba = BitArray::new(777);
+        ba.randfill();
+        let arr = ShardedBitArray::from_bitarray(&ba, 192);
+        assert_eq!(arr.to_bitarray(), ba);
+        assert_eq!(arr.count_ones(), ba.count_ones());
+    }
+
+    #[test]
+    fn test_bits_across_shards() {
+        let arr = ShardedBitArray::with_shard_bits(300, 64);
+        arr.write_bits(60, 10, 0b11_0000_0101);
+        assert_eq!(arr.read_bits(60, 10), 0b11_0000_0101);
+        assert_eq!(arr.read_bits(64, 6), 0b11_0000);
+        arr.write_bits(100, 64, u64::MAX - 1);
+        assert_eq!(arr.read_bits(100, 64), u64::MAX - 1);
+        assert_eq!(arr.read_bits(5, 0), 0);
+        assert_eq!((arr.count_ones(), arr.count_zeros(), arr.parity()), (67, 233, true));
+        arr.invert();
+        assert_eq!((arr.count_ones(), arr.read_bits(60, 10)), (233, 0b00_1111_1010));
+    }
+
+    #[test]
+    fn test_matches_bitarray() {
+        let mut ba = BitArray::new(1000);
+        ba.randfill_seeded(5);
+        let arr = ShardedBitArray::with_shard_bits(1000, 128);
+        arr.randfill_seeded(5);
+        assert_eq!(arr.to_bitarray(), ba);
+        for &(offset, length, amount) in &[(0, 1000, 1), (3, 990, -337), (100, 60, 7), (128, 128, 64), (5, 0, 2),
+                                           (1, 999, isize::MIN)] {
+            arr.rotate(offset .. offset + length, amount);
+            ba.rotate(offset, length, amount);
+            assert_eq!(arr.to_bitarray(), ba, "{} {} {}", offset, length, amount);
+        }
+        arr.rotate_left(10 .. 900, 1234);
+        ba.rotate_left(10, 890, 1234);
+        arr.rotate_right(200 .. 260, 3);
+        ba.rotate_right(200, 60, 3);
+        arr.reverse_range(120 .. 390);
+        ba.reverse_range(120, 270);
+        arr.reverse();
+        ba.reverse();
+        assert_eq!(arr.to_bitarray(), ba);
+        assert_eq!((arr.show(), arr.to_hex()), (ba.show(), ba.to_hex()));
+        assert_eq!(arr.parity(), ba.parity());
+    }
+
+    #[test]
+    fn test_fill_is_atomic_to_counts() {
+        // writers flip the whole array between all zeros and all ones, so a
+        // reader must never see anything in between
+        let arr = Arc::new(ShardedBitArray::with_shard_bits(4096, 256));
+        let writers: Vec<_> = (0 .. 2).map(|t| {
+            let arr = Arc::clone(&arr);
+            thread::spawn(move || {
+                for i in 0 .. 200 {
+                    arr.fill((i + t) % 2 == 0);
+                }
+            })
+        }).collect();
+        for _ in 0 .. 200 {
+            let ones = arr.count_ones();
+            assert!(ones == 0 || ones == 4096, "saw a torn fill: {}", ones);
+        }
+        for w in writers {
+            w.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rotate_is_atomic_to_reads() {
+        // rotating a range moves its ones about but never changes how many
+        // there are, and the bits outside it never move
+        let arr = Arc::new(ShardedBitArray::with_shard_bits(4096, 256));
+        arr.fill_range(0 .. 1000, true);
+        let writer = {
+            let arr = Arc::clone(&arr);
+            thread::spawn(move || {
+                for i in 0 .. 200 {
+                    arr.rotate(100 .. 3900, 37 * i);
+                }
+            })
+        };
+        for _ in 0 .. 200 {
+            assert_eq!(arr.count_ones_range(100 .. 3900), 900);
+            assert_eq!(arr.read_bits(36, 64), u64::MAX);
+        }
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+// A BitArray that many owners can hold at once.  Clones share the bits
+// until one of them writes, at which point that one gets its own copy.
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::bitarray::BitArray;
+
+/// A copy-on-write handle to a BitArray.  Cloning it is O(1); the first
+/// mutation through a clone whose bits are still shared copies them.
+/// Reads go straight through Deref, so every &self BitArray method works.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedBitArray {
+    inner: Arc<BitArray>,
+}
+
+impl SharedBitArray {
+    pub fn new(bit_sz: usize) -> SharedBitArray {
+        SharedBitArray::from(BitArray::new(bit_sz))
+    }
+
+    /// Mutable access to the bits, copying them first if any other handle
+    /// can still see them.
+    pub fn make_mut(&mut self) -> &mut BitArray {
+        Arc::make_mut(&mut self.inner)
+    }
+
+    pub fn set(&mut self, bit_index: usize, val: bool) {
+        self.make_mut().set(bit_index, val)
+    }
+
+    pub fn rotate(&mut self, bit_offset: usize, bit_length: usize, bit_right_amount: isize) {
+        self.make_mut().rotate(bit_offset, bit_length, bit_right_amount)
+    }
+
+    /// Whether another handle currently shares these bits.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
+
+    /// Whether two handles point at the very same bits.
+    pub fn ptr_eq(&self, other: &SharedBitArray) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Takes the bits out, copying only if they are still shared.
+    pub fn into_bitarray(self) -> BitArray {
+        Arc::try_unwrap(self.inner).unwrap_or_else(|arc| (*arc).clone())
+    }
+}
+
+impl From<BitArray> for SharedBitArray {
+    fn from(ba: BitArray) -> SharedBitArray {
+        SharedBitArray { inner: Arc::new(ba) }
+    }
+}
+
+impl Deref for SharedBitArray {
+    type Target = BitArray;
+
+    fn deref(&self) -> &BitArray {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clone_shares() {
+        let a = SharedBitArray::from(BitArray::from_str("10010110"));
+        let b = a.clone();
+        assert!(a.ptr_eq(&b));
+        assert!(a.is_shared());
+        assert!(b.get(1));
+        assert_eq!(b.show(), "10010110");
+    }
+
+    #[test]
+    fn test_write_copies() {
+        let a = SharedBitArray::from(BitArray::from_str("10010110"));
+        let mut b = a.clone();
+        b.set(0, true);
+        assert!(!a.ptr_eq(&b));
+        assert!(!a.is_shared());
+        assert_eq!(a.show(), "10010110");
+        assert_eq!(b.show(), "10010111");
+
+        // b owns its bits now, so further writes don't copy again
+        let before = &*b as *const BitArray;
+        b.rotate(0, 8, 1);
+        assert_eq!(before, &*b as *const BitArray);
+        assert_eq!(b.show(), "00101111");
+    }
+
+    #[test]
+    fn test_into_bitarray() {
+        let a = SharedBitArray::new(12);
+        let b = a.clone();
+        assert_eq!(a.into_bitarray(), BitArray::new(12));
+        assert_eq!(b.into_bitarray(), BitArray::new(12));
+    }
+}
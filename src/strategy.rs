@@ -0,0 +1,101 @@
+// proptest strategies for BitArrays and rotations, so crates that take a
+// BitArray can property test without writing their own generators.
+// Behind the proptest feature.
+use std::ops::Range;
+
+use proptest::arbitrary::Arbitrary;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::bitarray::BitArray;
+
+/// Sizes BitArray's Arbitrary impl picks from.
+pub const DEFAULT_SIZES: Range<usize> = 0 .. 1025;
+
+fn from_bools(bools: Vec<bool>) -> BitArray {
+    let mut ba = BitArray::new(bools.len());
+    for (i, b) in bools.into_iter().enumerate() {
+        ba.set(i, b);
+    }
+    ba
+}
+
+/// Arrays with a size in sizes and each bit set with probability 1/2.
+/// They shrink towards shorter arrays with fewer ones.
+pub fn bitarray(sizes: Range<usize>) -> impl Strategy<Value = BitArray> {
+    bitarray_with_density(sizes, 0.5)
+}
+
+/// Arrays with a size in sizes and each bit set with probability density,
+/// e.g. 0.01 for sparse masks.
+pub fn bitarray_with_density(sizes: Range<usize>, density: f64) -> impl Strategy<Value = BitArray> {
+    vec(proptest::bool::weighted(density), sizes).prop_map(from_bools)
+}
+
+/// Valid BitArray::rotate arguments (offset, length, amount) for an array
+/// of bit_sz bits: the range fits in the array and the amount may be
+/// negative or bigger than the range.
+pub fn rotation(bit_sz: usize) -> impl Strategy<Value = (usize, usize, isize)> {
+    (0 ..= bit_sz).prop_flat_map(move |offset| {
+        (Just(offset), 0 ..= bit_sz - offset).prop_flat_map(|(offset, len)| {
+            let reach = 2 * len as isize + 1;
+            (Just(offset), Just(len), -reach ..= reach)
+        })
+    })
+}
+
+/// An array from sizes together with a valid rotation of it.
+pub fn bitarray_and_rotation(sizes: Range<usize>)
+                             -> impl Strategy<Value = (BitArray, (usize, usize, isize))> {
+    bitarray(sizes).prop_flat_map(|ba| {
+        let n = ba.get_bit_sz();
+        (Just(ba), rotation(n))
+    })
+}
+
+impl Arbitrary for BitArray {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<BitArray>;
+
+    fn arbitrary_with(_: ()) -> BoxedStrategy<BitArray> {
+        bitarray(DEFAULT_SIZES).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_sizes_in_range(ba in bitarray(3 .. 40)) {
+            prop_assert!((3 .. 40).contains(&ba.get_bit_sz()));
+        }
+
+        #[test]
+        fn test_density(ba in bitarray_with_density(2000 .. 2001, 0.05)) {
+            prop_assert!(ba.count_ones() < 400);
+        }
+
+        #[test]
+        fn test_rotations_are_valid((ba, (offset, len, amount)) in bitarray_and_rotation(0 .. 200)) {
+            prop_assert!(offset + len <= ba.get_bit_sz());
+            prop_assert!(amount.unsigned_abs() <= 2 * len + 1);
+        }
+
+        #[test]
+        fn test_rotate_matches_naive((ba, (offset, len, amount)) in bitarray_and_rotation(0 .. 600)) {
+            let (mut got, mut want) = (ba.clone(), ba);
+            got.rotate(offset, len, amount);
+            want.rotate_with(crate::bitarray::RotateStrategy::Naive, offset, len, amount);
+            prop_assert_eq!(got, want);
+        }
+
+        #[test]
+        fn test_arbitrary(ba in any::<BitArray>()) {
+            let mut inverted = ba.clone();
+            inverted.invert();
+            prop_assert_eq!(ba.count_ones() + inverted.count_ones(), ba.get_bit_sz());
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Rotation of bits kept in a file, or anything else Read + Write + Seek,
+// rather than in memory, for datasets bigger than RAM.  The data is bytes,
+// bit i in bit i % 8 of byte i / 8, which is how a BitArray's words lie in
+// memory on a little-endian machine.
+//
+// A rotation is three reversals, as in RotateStrategy::Reverse, and each
+// reversal works inward from both ends of its range: a window of up to
+// chunk_bytes is read from each end, both are reversed, and each is
+// written back where the other was.  Only the two windows are held in
+// memory, so a rotation costs three passes over the range and a few
+// chunks of RAM.  Once the two windows would come within a byte of each
+// other, whatever is left is reversed in one piece.
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::bitarray::BitArray;
+
+/// The default window, read from each end of a reversal at a time.
+pub const STREAM_CHUNK_BYTES: usize = 1 << 20;
+
+/// The bytes of f holding bits [start, end), as an array whose bit 0 is
+/// bit 8 * (start / 8) of f, and that number.
+fn read_span<F: Read + Seek>(f: &mut F, start: usize, end: usize) -> io::Result<(BitArray, usize)> {
+    let (first, last) = (start / 8, end.div_ceil(8));
+    let mut bytes = vec![0; last - first];
+    f.seek(SeekFrom::Start(first as u64))?;
+    f.read_exact(&mut bytes)?;
+    let mut span = BitArray::new(8 * bytes.len());
+    for (i, &b) in bytes.iter().enumerate() {
+        span.set_byte(i, b);
+    }
+    Ok((span, 8 * first))
+}
+
+/// Writes a span from read_span back where it came from.
+fn write_span<F: Write + Seek>(f: &mut F, span: &BitArray, base: usize) -> io::Result<()> {
+    let bytes: Vec<u8> = (0 .. span.get_bit_sz() / 8).map(|i| span.byte(i)).collect();
+    f.seek(SeekFrom::Start((base / 8) as u64))?;
+    f.write_all(&bytes)
+}
+
+/// Reverses bits [i, j) of f, chunk_bits from each end at a time.
+fn reverse_stream<F: Read + Write + Seek>(f: &mut F, mut i: usize, mut j: usize, chunk_bits: usize)
+                                          -> io::Result<()> {
+    // past this the windows' bytes are at least a byte apart, so writing
+    // one back never undoes the other
+    while j - i > 2 * chunk_bits + 16 {
+        let w = chunk_bits;
+        let (mut front, front_base) = read_span(f, i, i + w)?;
+        let (mut back, back_base) = read_span(f, j - w, j)?;
+        let (at_front, at_back) = (i - front_base, j - w - back_base);
+        front.reverse_range(at_front, w);
+        back.reverse_range(at_back, w);
+        let reversed_front = front.clone();
+        front.copy_bits(at_front, &back, at_back, w);
+        back.copy_bits(at_back, &reversed_front, at_front, w);
+        write_span(f, &front, front_base)?;
+        write_span(f, &back, back_base)?;
+        i += w;
+        j -= w;
+    }
+    if j - i >= 2 {
+        let (mut span, base) = read_span(f, i, j)?;
+        span.reverse_range(i - base, j - i);
+        write_span(f, &span, base)?;
+    }
+    Ok(())
+}
+
+/// BitArray::rotate on the bits of f: rotates [bit_offset, bit_offset +
+/// bit_length) right by bit_right_amount, left if it is negative, a
+/// STREAM_CHUNK_BYTES window at a time.  The bits around the range are
+/// left as they were.  Fails with UnexpectedEof, before writing
+/// anything, if f is too short.
+pub fn rotate_stream<F: Read + Write + Seek>(f: &mut F,
+                                             bit_offset: usize,
+                                             bit_length: usize,
+                                             bit_right_amount: isize) -> io::Result<()> {
+    rotate_stream_chunked(f, bit_offset, bit_length, bit_right_amount, STREAM_CHUNK_BYTES)
+}
+
+/// rotate_stream with windows of chunk_bytes, at least 1.
+pub fn rotate_stream_chunked<F: Read + Write + Seek>(f: &mut F,
+                                                     bit_offset: usize,
+                                                     bit_length: usize,
+                                                     bit_right_amount: isize,
+                                                     chunk_bytes: usize) -> io::Result<()> {
+    assert!(chunk_bytes > 0);
+    if bit_length == 0 {
+        return Ok(());
+    }
+    let right = BitArray::modulo(bit_right_amount, bit_length);
+    let left = (bit_length - right) % bit_length;
+    if left == 0 {
+        return Ok(());
+    }
+    let (start, mid, end) = (bit_offset, bit_offset + left, bit_offset + bit_length);
+    // a short f would otherwise only show up after the first reversal had
+    // been written
+    let len = f.seek(SeekFrom::End(0))?;
+    if len < end.div_ceil(8) as u64 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                  format!("{} bits need {} bytes, not {}", end, end.div_ceil(8), len)));
+    }
+    reverse_stream(f, start, mid, 8 * chunk_bytes)?;
+    reverse_stream(f, mid, end, 8 * chunk_bytes)?;
+    reverse_stream(f, start, end, 8 * chunk_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// ba's bits as bytes, the layout rotate_stream works on.
+    fn to_bytes(ba: &BitArray) -> Vec<u8> {
+        (0 .. ba.get_bit_sz().div_ceil(8)).map(|i| ba.byte(i)).collect()
+    }
+
+    #[test]
+    fn test_matches_rotate() {
+        let mut ba = BitArray::new(5000);
+        ba.randfill_seeded(3);
+        for &(offset, length, amount) in &[(0, 5000, 1), (3, 4990, -1337), (64, 128, 64), (7, 8, 3), (5, 0, 2),
+                                           (1, 4999, isize::MIN), (100, 4000, 2000), (9, 31, -4)] {
+            for &chunk in &[1, 3, 64, STREAM_CHUNK_BYTES] {
+                let mut f = Cursor::new(to_bytes(&ba));
+                rotate_stream_chunked(&mut f, offset, length, amount, chunk).unwrap();
+                let mut want = ba.clone();
+                want.rotate(offset, length, amount);
+                assert_eq!(f.into_inner(), to_bytes(&want), "{} {} {} {}", offset, length, amount, chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn test_file() {
+        let path = std::env::temp_dir().join(format!("everybit-stream-{}", std::process::id()));
+        std::fs::write(&path, [0b1001_0110u8, 0xff]).unwrap();
+        let mut f = std::fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        rotate_stream(&mut f, 2, 5, 2).unwrap();
+        drop(f);
+        // as rotate does it in memory, see test_rotate_in_register
+        assert_eq!(std::fs::read(&path).unwrap(), [0b1101_0010, 0xff]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_short_file() {
+        let mut f = Cursor::new(vec![0u8; 10]);
+        f.get_mut()[0] = 0b1011;
+        let err = rotate_stream(&mut f, 0, 81, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "81 bits need 11 bytes, not 10");
+        let mut want = vec![0u8; 10];
+        want[0] = 0b1011;
+        assert_eq!(f.into_inner(), want);
+    }
+}
@@ -0,0 +1,191 @@
+// One file to attach to an assignment or a PR: the latest Criterion
+// estimates and any harness result files, with a description of the
+// machine they were measured on, as a markdown table and as JSON
+// (`everybit summary`).  The JSON is also a result file for
+// compare-results.
+use std::fmt::Write as _;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::results::{Benchmark, Results};
+
+/// Where the numbers came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Machine {
+    pub cpu: String,
+    pub cores: usize,
+    pub os: String,
+    pub arch: String,
+    /// "release" or "debug", for the harness timings; Criterion always
+    /// measures a release build.
+    pub profile: String,
+    /// The SIMD and bit manipulation target features this binary was
+    /// compiled with.
+    pub target_features: Vec<String>,
+}
+
+impl Machine {
+    pub fn current() -> Machine {
+        let mut target_features = vec![];
+        macro_rules! features {
+            ($($f:tt),*) => { $(if cfg!(target_feature = $f) { target_features.push($f.to_string()); })* };
+        }
+        features!("sse2", "sse4.2", "popcnt", "avx2", "bmi1", "bmi2", "avx512f", "neon");
+        Machine {
+            cpu: cpu_name().unwrap_or_else(|| "unknown".to_string()),
+            cores: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" }.to_string(),
+            target_features,
+        }
+    }
+}
+
+fn cpu_name() -> Option<String> {
+    let info = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    let line = info.lines().find(|l| l.starts_with("model name"))?;
+    Some(line.split_once(':')?.1.trim().to_string())
+}
+
+/// The machine and every benchmark, Criterion's first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub machine: Machine,
+    pub benchmarks: Vec<Benchmark>,
+}
+
+#[derive(Deserialize)]
+struct CriterionId {
+    full_id: String,
+}
+
+#[derive(Deserialize)]
+struct Estimate {
+    point_estimate: f64,
+}
+
+#[derive(Deserialize)]
+struct Estimates {
+    // Criterion 0.3 capitalizes, later versions don't
+    #[serde(alias = "Mean")]
+    mean: Estimate,
+}
+
+/// The mean of every benchmark under a Criterion output directory
+/// (target/criterion), from each one's latest run, sorted by name.
+pub fn criterion_benchmarks(dir: &Path) -> Result<Vec<Benchmark>, String> {
+    let mut found = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(d) = dirs.pop() {
+        let entries = std::fs::read_dir(&d).map_err(|e| format!("{}: {}", d.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let (id, estimates) = (path.join("new/benchmark.json"), path.join("new/estimates.json"));
+            if id.is_file() && estimates.is_file() {
+                let read = |p: &Path| std::fs::read_to_string(p).map_err(|e| format!("{}: {}", p.display(), e));
+                let id: CriterionId = serde_json::from_str(&read(&id)?)
+                    .map_err(|e| format!("{}: {}", id.display(), e))?;
+                let estimates: Estimates = serde_json::from_str(&read(&estimates)?)
+                    .map_err(|e| format!("{}: {}", estimates.display(), e))?;
+                found.push(Benchmark { name: id.full_id, seconds: estimates.mean.point_estimate / 1e9 });
+            } else if path.file_name().is_some_and(|n| n != "report") {
+                dirs.push(path);
+            }
+        }
+    }
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(found)
+}
+
+impl Summary {
+    pub fn new(machine: Machine, criterion: Vec<Benchmark>, harness: &[Results]) -> Summary {
+        let mut benchmarks = criterion;
+        for results in harness {
+            benchmarks.extend(results.benchmarks.iter().cloned());
+        }
+        Summary { machine, benchmarks }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let m = &self.machine;
+        let mut out = String::from("## everybit benchmarks\n\n");
+        let features = if m.target_features.is_empty() { "none".to_string() } else { m.target_features.join(", ") };
+        writeln!(out, "{} ({} cores), {} {}, {} build, target features: {}\n",
+                 m.cpu, m.cores, m.os, m.arch, m.profile, features).unwrap();
+        out.push_str("| benchmark | time |\n|---|---:|\n");
+        for b in &self.benchmarks {
+            writeln!(out, "| {} | {} |", b.name.replace('|', "\\|"), human_time(b.seconds)).unwrap();
+        }
+        out
+    }
+}
+
+/// seconds with a unit that keeps 3 or 4 significant digits.
+fn human_time(seconds: f64) -> String {
+    if seconds >= 1.0 {
+        format!("{:.3} s", seconds)
+    } else if seconds >= 1e-3 {
+        format!("{:.3} ms", seconds * 1e3)
+    } else if seconds >= 1e-6 {
+        format!("{:.3} µs", seconds * 1e6)
+    } else {
+        format!("{:.1} ns", seconds * 1e9)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Machine {
+        Machine {
+            cpu: "Test CPU".to_string(),
+            cores: 4,
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            profile: "release".to_string(),
+            target_features: vec!["sse2".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_criterion_benchmarks() {
+        let dir = std::env::temp_dir().join(format!("everybit-summary-{}", std::process::id()));
+        let bench = dir.join("rotate/1000/new");
+        std::fs::create_dir_all(&bench).unwrap();
+        std::fs::create_dir_all(dir.join("report")).unwrap();
+        std::fs::write(bench.join("benchmark.json"), r#"{"full_id": "rotate/1000", "title": "x"}"#).unwrap();
+        std::fs::write(bench.join("estimates.json"),
+                       r#"{"Mean": {"point_estimate": 2500.0, "standard_error": 1.0}}"#).unwrap();
+        let found = criterion_benchmarks(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found.unwrap(), vec![Benchmark { name: "rotate/1000".to_string(), seconds: 2.5e-6 }]);
+        assert!(criterion_benchmarks(Path::new("/nonexistent")).is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let criterion = vec![Benchmark { name: "rotate/1000".to_string(), seconds: 2.5e-6 }];
+        let harness = Results { benchmarks: vec![Benchmark { name: "small/tier0".to_string(), seconds: 0.002 }] };
+        let summary = Summary::new(machine(), criterion, &[harness]);
+        assert_eq!(summary.to_markdown(), concat!(
+            "## everybit benchmarks\n\n",
+            "Test CPU (4 cores), linux x86_64, release build, target features: sse2\n\n",
+            "| benchmark | time |\n|---|---:|\n",
+            "| rotate/1000 | 2.500 µs |\n",
+            "| small/tier0 | 2.000 ms |\n"));
+        // compare-results reads summaries too
+        assert_eq!(Results::from_json(&summary.to_json()).unwrap().benchmarks.len(), 2);
+        assert_eq!(serde_json::from_str::<Summary>(&summary.to_json()).unwrap(), summary);
+        assert!(!Machine::current().os.is_empty());
+    }
+}
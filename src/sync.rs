@@ -0,0 +1,10 @@
+// The atomics the lock-free types are built on.  Building with
+// RUSTFLAGS="--cfg loom" swaps in loom's versions, so the loom_tests in
+// atomic.rs and allocator.rs can check every interleaving of the
+// claim/release protocols instead of hoping a stress test hits the bad one:
+//
+//     RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicU64, AtomicUsize};
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicU64, AtomicUsize};
@@ -0,0 +1,426 @@
+// Functional tests read from a text file, as in the 6.172 harness
+// (`everybit -t tests/default`).
+//
+// One command per line:
+//
+//     t 3              start test number 3
+//     n 10010110       a new array from a bit string, bit 0 last
+//     h 96             a new array from hex, rightmost digit bits 0-3
+//     r 0 8 1          rotate(offset, length, right amount)
+//     e 00101101       expect the array to equal a bit string
+//
+// Blank lines and lines starting with # are ignored.  Every command
+// belongs to the test started by the t before it.
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::Output;
+use std::str::FromStr;
+
+use crate::bitarray::BitArray;
+use crate::model_test::panic_message;
+
+/// One command of a test file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Replace the array under test.
+    New(BitArray),
+    /// Rotate the array, arguments as in BitArray::rotate.
+    Rotate { offset: usize, length: usize, amount: isize },
+    /// Check the array against the expected bits.
+    Expect(BitArray),
+}
+
+/// The command as it would be written in a test file.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::New(ba) => write!(f, "n {}", ba.show()),
+            Command::Rotate { offset, length, amount } => write!(f, "r {} {} {}", offset, length, amount),
+            Command::Expect(ba) => write!(f, "e {}", ba.show()),
+        }
+    }
+}
+
+/// A command and the (1 based) line it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub line: usize,
+    pub command: Command,
+}
+
+/// A numbered test: the steps between its t line and the next one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub number: usize,
+    pub line: usize,
+    pub steps: Vec<Step>,
+}
+
+/// A line of a test file that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Why a test did not pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub test: usize,
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "test {} failed at line {}: {}", self.test, self.line, self.msg)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Reads back what Display writes, for failures reported by a child
+/// process.
+impl FromStr for Failure {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Failure, ()> {
+        let rest = s.strip_prefix("test ").ok_or(())?;
+        let (test, rest) = rest.split_once(" failed at line ").ok_or(())?;
+        let (line, msg) = rest.split_once(": ").ok_or(())?;
+        Ok(Failure { test: test.parse().map_err(|_| ())?, line: line.parse().map_err(|_| ())?, msg: msg.to_string() })
+    }
+}
+
+fn parse_bits(s: &str) -> Result<BitArray, String> {
+    match s.chars().find(|&c| c != '0' && c != '1') {
+        Some(c) => Err(format!("bad bit {:?}", c)),
+        None => Ok(BitArray::from_str(s)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: Option<&str>, what: &str) -> Result<T, String> {
+    let s = s.ok_or(format!("missing {}", what))?;
+    s.parse().map_err(|_| format!("bad {} {:?}", what, s))
+}
+
+fn parse_command(op: &str, args: &[&str]) -> Result<Command, String> {
+    let want = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(format!("{} takes {} argument(s), got {}", op, n, args.len()))
+        }
+    };
+    match op {
+        "n" => {
+            want(1)?;
+            Ok(Command::New(parse_bits(args[0])?))
+        }
+        "h" => {
+            want(1)?;
+            BitArray::from_hex(args[0]).map(Command::New).map_err(|e| e.to_string())
+        }
+        "r" => {
+            want(3)?;
+            Ok(Command::Rotate {
+                offset: parse_number(Some(args[0]), "offset")?,
+                length: parse_number(Some(args[1]), "length")?,
+                amount: parse_number(Some(args[2]), "amount")?,
+            })
+        }
+        "e" => {
+            want(1)?;
+            Ok(Command::Expect(parse_bits(args[0])?))
+        }
+        _ => Err(format!("unknown command {:?}", op)),
+    }
+}
+
+/// Parses the text of a test file.
+pub fn parse(text: &str) -> Result<Vec<TestCase>, ParseError> {
+    let mut tests: Vec<TestCase> = vec![];
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let err = |msg: String| ParseError { line, msg };
+        let words: Vec<&str> = raw.split_whitespace().collect();
+        if words.is_empty() || words[0].starts_with('#') {
+            continue;
+        }
+        if words[0] == "t" {
+            if words.len() != 2 {
+                return Err(err("t takes 1 argument(s)".to_string()));
+            }
+            let number = parse_number(Some(words[1]), "test number").map_err(err)?;
+            tests.push(TestCase { number, line, steps: vec![] });
+            continue;
+        }
+        let command = parse_command(words[0], &words[1 ..]).map_err(err)?;
+        match tests.last_mut() {
+            Some(test) => test.steps.push(Step { line, command }),
+            None => return Err(err("command before the first t line".to_string())),
+        }
+    }
+    Ok(tests)
+}
+
+impl TestCase {
+    /// Runs the steps in order, stopping at the first one that fails.  A
+    /// rotation that does not fit the array is a failure, not a panic, and
+    /// a panic inside rotate is caught and becomes the failure of its step.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self),
+                                                        fields(test = self.number), err(Display)))]
+    pub fn run(&self) -> Result<(), Failure> {
+        self.run_with(BitArray::rotate)
+    }
+
+    /// Like run, with rotate in place of BitArray::rotate, for checking
+    /// another implementation against a test file.
+    pub fn run_with<F>(&self, rotate: F) -> Result<(), Failure>
+    where F: Fn(&mut BitArray, usize, usize, isize) {
+        let fail = |line: usize, msg: String| Failure { test: self.number, line, msg };
+        let mut arr: Option<BitArray> = None;
+        for step in &self.steps {
+            if let Command::New(ba) = &step.command {
+                arr = Some(ba.clone());
+                continue;
+            }
+            let ba = arr.as_mut().ok_or_else(|| fail(step.line, "no array yet".to_string()))?;
+            match step.command {
+                Command::New(_) => unreachable!(),
+                Command::Rotate { offset, length, amount } => {
+                    let fits = offset.checked_add(length).is_some_and(|end| end <= ba.get_bit_sz());
+                    if !fits {
+                        return Err(fail(step.line, format!(
+                            "rotation [{}, {}+{}) is outside the {} bit array",
+                            offset, offset, length, ba.get_bit_sz())));
+                    }
+                    panic::catch_unwind(AssertUnwindSafe(|| rotate(ba, offset, length, amount)))
+                        .map_err(|payload| fail(step.line, format!("{} {}", step.command, panic_message(payload))))?;
+                }
+                Command::Expect(ref expected) => {
+                    if ba != expected {
+                        return Err(fail(step.line, format!(
+                            "expected {} got {}", expected.show(), ba.show())));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the tests, or just the one numbered only, and returns how many
+/// ran along with the failures.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "info", skip(tests)))]
+pub fn run_all(tests: &[TestCase], only: Option<usize>) -> (usize, Vec<Failure>) {
+    let selected: Vec<&TestCase> =
+        tests.iter().filter(|t| only.is_none_or(|n| t.number == n)).collect();
+    let failures = selected.iter().filter_map(|t| t.run().err()).collect();
+    (selected.len(), failures)
+}
+
+/// Like run_all, but each test number runs in a process of its own,
+/// started by spawn (which should run `everybit -t FILE -n N` or the
+/// like), so a panic, abort or stack overflow fails only that test.
+pub fn run_isolated<F>(tests: &[TestCase], only: Option<usize>, mut spawn: F) -> (usize, Vec<Failure>)
+where F: FnMut(usize) -> io::Result<Output> {
+    let selected: Vec<&TestCase> =
+        tests.iter().filter(|t| only.is_none_or(|n| t.number == n)).collect();
+    let mut failures = vec![];
+    let mut done = vec![];
+    for t in &selected {
+        if done.contains(&t.number) {
+            continue;
+        }
+        done.push(t.number);
+        let crashed = |msg: String| Failure { test: t.number, line: t.line, msg };
+        match spawn(t.number) {
+            Err(e) => failures.push(crashed(format!("could not start: {}", e))),
+            Ok(out) if out.status.success() => {}
+            Ok(out) if out.status.code() == Some(1) => {
+                let stdout = String::from_utf8_lossy(&out.stdout);
+                failures.extend(stdout.lines().filter_map(|l| l.parse().ok()));
+            }
+            Ok(out) => failures.push(crashed(crash_message(&out))),
+        }
+    }
+    (selected.len(), failures)
+}
+
+/// What killed a child: its exit status and the panic message, if the
+/// standard panic hook printed one.
+fn crash_message(out: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let mut lines = stderr.lines();
+    let panic = lines.by_ref().find(|l| l.contains("panicked at")).and_then(|_| lines.next());
+    let overflow = stderr.lines().find(|l| l.contains("has overflowed its stack"));
+    let why = match (panic, overflow) {
+        (Some(msg), _) => format!(": {}", msg),
+        (None, Some(msg)) => format!(": {}", msg),
+        (None, None) => String::new(),
+    };
+    format!("crashed ({}){}", out.status, why)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+# rotations of one byte
+t 0
+n 10010110
+r 0 8 1
+e 00101101
+
+t 1
+h 96
+r 2 5 2
+e 11010010
+";
+
+    #[test]
+    fn test_parse() {
+        let tests = parse(SAMPLE).unwrap();
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].number, 0);
+        assert_eq!(tests[0].line, 2);
+        assert_eq!(tests[0].steps[1], Step {
+            line: 4,
+            command: Command::Rotate { offset: 0, length: 8, amount: 1 },
+        });
+        assert_eq!(tests[1].steps[0].command, Command::New(BitArray::from_str("10010110")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let line = |text: &str| parse(text).unwrap_err().line;
+        assert_eq!(line("n 101"), 1);
+        assert_eq!(line("t 0\nn 102"), 2);
+        assert_eq!(line("t 0\nh 9x"), 2);
+        assert_eq!(line("t 0\nr 0 8"), 2);
+        assert_eq!(line("t 0\n\nr 0 8 z"), 3);
+        assert_eq!(line("t 0\nq"), 2);
+        assert_eq!(line("t zero"), 1);
+    }
+
+    #[test]
+    fn test_run() {
+        let tests = parse(SAMPLE).unwrap();
+        assert_eq!(run_all(&tests, None), (2, vec![]));
+        assert_eq!(run_all(&tests, Some(1)), (1, vec![]));
+        assert_eq!(run_all(&tests, Some(7)), (0, vec![]));
+
+        let bad = parse("t 4\nn 0011\ne 0011\nr 0 4 1\ne 0011").unwrap();
+        let (_, failures) = run_all(&bad, None);
+        assert_eq!(failures, vec![Failure {
+            test: 4,
+            line: 5,
+            msg: "expected 0011 got 0110".to_string(),
+        }]);
+
+        let outside = parse("t 0\nn 0011\nr 3 2 1").unwrap();
+        assert_eq!(outside[0].run().unwrap_err().line, 3);
+        let empty = parse("t 0\ne 1").unwrap();
+        assert_eq!(empty[0].run().unwrap_err().msg, "no array yet");
+    }
+
+    #[test]
+    fn test_run_catches_panics() {
+        let tests = parse("t 0\nn 0011\nr 3 1 1\ne 0011\nt 1\nn 01\ne 01").unwrap();
+        assert_eq!(run_all(&tests, None), (2, vec![]));
+        // the bug rotate once had
+        let broken = |ba: &mut BitArray, offset: usize, length: usize, amount: isize| {
+            assert!(offset + offset <= ba.get_bit_sz());
+            ba.rotate(offset, length, amount)
+        };
+        assert_eq!(tests[0].run_with(broken), Err(Failure {
+            test: 0,
+            line: 3,
+            msg: "r 3 1 1 panicked: assertion failed: offset + offset <= ba.get_bit_sz()".to_string(),
+        }));
+        assert_eq!(tests[1].run_with(broken), Ok(()));
+        assert_eq!(tests[0].steps[0].command.to_string(), "n 0011");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_isolated() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let tests = parse(SAMPLE).unwrap();
+        let output = |code: i32, stdout: &str, stderr: &str| Output {
+            status: ExitStatus::from_raw(code),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        // wait statuses: exit 0, exit 1, exit 101 and killed by SIGSEGV
+        let (ran, failures) = run_isolated(&tests, None, |n| Ok(match n {
+            0 => output(1 << 8, "test 0 failed at line 5: expected 1 got 0\nran 1 tests, 1 failed\n", ""),
+            _ => output(101 << 8, "", "thread 'main' panicked at src/bitarray.rs:9:9:\nboom\nnote: ...\n"),
+        }));
+        assert_eq!(ran, 2);
+        assert_eq!(failures, vec![
+            Failure { test: 0, line: 5, msg: "expected 1 got 0".to_string() },
+            Failure { test: 1, line: 7, msg: "crashed (exit status: 101): boom".to_string() },
+        ]);
+
+        let (_, failures) = run_isolated(&tests, Some(1), |_| Ok(output(11, "", "")));
+        assert_eq!(failures[0].msg, "crashed (signal: 11 (SIGSEGV))");
+        let (_, failures) = run_isolated(&tests, Some(0), |_| Ok(output(0, "", "")));
+        assert!(failures.is_empty());
+    }
+
+    #[test]
+    fn test_failure_from_str() {
+        let f = Failure { test: 3, line: 12, msg: "expected 01: got 10".to_string() };
+        assert_eq!(f.to_string().parse(), Ok(f));
+        assert!("ran 9 tests, 0 failed".parse::<Failure>().is_err());
+    }
+
+    #[test]
+    fn test_default_file() {
+        let tests = parse(include_str!("../tests/default")).unwrap();
+        assert_eq!(run_all(&tests, None), (tests.len(), vec![]));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        // records the name of every span opened
+        struct Names(Arc<Mutex<Vec<&'static str>>>);
+        impl tracing::Subscriber for Names {
+            fn enabled(&self, _: &Metadata) -> bool { true }
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name());
+                Id::from_u64(names.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let names = Arc::new(Mutex::new(vec![]));
+        let tests = parse(SAMPLE).unwrap();
+        tracing::subscriber::with_default(Names(names.clone()), || run_all(&tests, None));
+        assert_eq!(*names.lock().unwrap(), vec!["run_all", "run", "rotate", "run", "rotate"]);
+    }
+}
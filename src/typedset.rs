@@ -0,0 +1,157 @@
+// A bit set indexed by a domain type (NodeId, RowId, ...) instead of a
+// bare usize, so index spaces can't be mixed up by accident.
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::bitarray::BitArray;
+
+/// A fixed capacity set of I, where member i is bit i.into() of a
+/// BitArray.  Any newtype with From<usize> and Into<usize> conversions
+/// works as the index.
+pub struct TypedBitSet<I> {
+    bits: BitArray,
+    _marker: PhantomData<fn(I) -> I>,
+}
+
+impl<I: Into<usize> + From<usize>> TypedBitSet<I> {
+    /// An empty set able to hold the indices 0 .. capacity.
+    pub fn new(capacity: usize) -> TypedBitSet<I> {
+        TypedBitSet { bits: BitArray::new(capacity), _marker: PhantomData }
+    }
+
+    /// Wraps existing bits, bit i standing for I::from(i).
+    pub fn from_bitarray(bits: BitArray) -> TypedBitSet<I> {
+        TypedBitSet { bits, _marker: PhantomData }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bits.get_bit_sz()
+    }
+
+    /// Adds i, returning whether it was newly added.
+    pub fn insert(&mut self, i: I) -> bool {
+        let i = i.into();
+        let was = self.bits.get(i);
+        self.bits.set(i, true);
+        !was
+    }
+
+    /// Removes i, returning whether it was there.
+    pub fn remove(&mut self, i: I) -> bool {
+        let i = i.into();
+        let was = self.bits.get(i);
+        self.bits.set(i, false);
+        was
+    }
+
+    pub fn contains(&self, i: I) -> bool {
+        self.bits.get(i.into())
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(false)
+    }
+
+    /// The members in increasing index order.
+    pub fn iter(&self) -> impl Iterator<Item = I> + '_ {
+        self.bits.iter_ones().map(I::from)
+    }
+
+    /// Adds every member of other, which must have the same capacity.
+    pub fn union_with(&mut self, other: &TypedBitSet<I>) {
+        self.bits |= &other.bits;
+    }
+
+    /// Keeps only the members also in other.
+    pub fn intersect_with(&mut self, other: &TypedBitSet<I>) {
+        self.bits &= &other.bits;
+    }
+
+    pub fn as_bitarray(&self) -> &BitArray {
+        &self.bits
+    }
+
+    pub fn into_bitarray(self) -> BitArray {
+        self.bits
+    }
+}
+
+impl<I> Clone for TypedBitSet<I> {
+    fn clone(&self) -> TypedBitSet<I> {
+        TypedBitSet { bits: self.bits.clone(), _marker: PhantomData }
+    }
+}
+
+impl<I> PartialEq for TypedBitSet<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+impl<I> Eq for TypedBitSet<I> {}
+
+impl<I: Into<usize> + From<usize> + fmt::Debug> fmt::Debug for TypedBitSet<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct NodeId(usize);
+
+    impl From<usize> for NodeId {
+        fn from(i: usize) -> NodeId {
+            NodeId(i)
+        }
+    }
+
+    impl From<NodeId> for usize {
+        fn from(id: NodeId) -> usize {
+            id.0
+        }
+    }
+
+    #[test]
+    fn test_typed_set() {
+        let mut set: TypedBitSet<NodeId> = TypedBitSet::new(100);
+        assert!(set.insert(NodeId(3)));
+        assert!(!set.insert(NodeId(3)));
+        set.insert(NodeId(99));
+        assert!(set.contains(NodeId(99)) && !set.contains(NodeId(4)));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![NodeId(3), NodeId(99)]);
+        assert_eq!(format!("{:?}", set), "{NodeId(3), NodeId(99)}");
+        assert!(set.remove(NodeId(3)));
+        assert_eq!(set.len(), 1);
+        set.clear();
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_set_ops() {
+        let mut a: TypedBitSet<NodeId> = TypedBitSet::from_bitarray(BitArray::from_str("0110"));
+        let b = TypedBitSet::from_bitarray(BitArray::from_str("1100"));
+        let mut c = a.clone();
+        a.union_with(&b);
+        c.intersect_with(&b);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![NodeId(1), NodeId(2), NodeId(3)]);
+        assert_eq!(c.iter().collect::<Vec<_>>(), vec![NodeId(2)]);
+        assert_eq!(c.into_bitarray().show(), "0100");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_capacity() {
+        TypedBitSet::<NodeId>::new(10).insert(NodeId(10));
+    }
+}
@@ -0,0 +1,223 @@
+// Step-by-step pictures of a rotation, meant for lecture slides.
+//
+// The algorithms here are re-implemented one swap at a time so each
+// intermediate state can be recorded; they are not the ones BitArray
+// uses internally and are far too slow for anything but small arrays.
+use std::fmt::Write;
+
+use crate::bitarray::BitArray;
+
+/// The largest array that will be visualized, beyond this the picture
+/// is unreadable anyway.
+pub const MAX_VIZ_BITS: usize = 64;
+
+/// Which rotation algorithm to walk through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Rotate left by one, bit_left_amount times.
+    Naive,
+    /// Reverse the left part, reverse the right part, reverse the whole
+    /// subarray.
+    Reversal,
+}
+
+impl Algorithm {
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name {
+            "naive" => Some(Algorithm::Naive),
+            "reversal" => Some(Algorithm::Reversal),
+            _ => None,
+        }
+    }
+}
+
+/// One intermediate state of a rotation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// What was done to get to this state.
+    pub label: String,
+    /// The contents of the array after the step, indexed like the array.
+    pub bits: Vec<bool>,
+    /// The bit indices touched by the step.
+    pub touched: Vec<usize>,
+}
+
+fn snapshot(ba: &BitArray) -> Vec<bool> {
+    (0 .. ba.get_bit_sz()).map(|i| ba.get(i)).collect()
+}
+
+fn swap(ba: &mut BitArray, i: usize, j: usize) {
+    let (a, b) = (ba.get(i), ba.get(j));
+    ba.set(i, b);
+    ba.set(j, a);
+}
+
+/// Runs a rotation of ba (same arguments as BitArray::rotate) on a copy of
+/// the array and records every intermediate state, starting with the
+/// initial one.
+pub fn rotation_steps(ba: &BitArray,
+                      bit_offset: usize,
+                      bit_length: usize,
+                      bit_right_amount: isize,
+                      algorithm: Algorithm) -> Vec<Step> {
+    let n = ba.get_bit_sz();
+    assert!(n <= MAX_VIZ_BITS);
+    assert!(bit_offset + bit_length <= n);
+
+    let mut work = ba.clone();
+    let mut steps = vec![Step { label: String::from("start"),
+                                bits: snapshot(&work),
+                                touched: vec![] }];
+    if bit_length == 0 {
+        return steps;
+    }
+
+    let end = bit_offset + bit_length;
+    // negating the amount first would overflow on isize::MIN
+    let right = BitArray::modulo(bit_right_amount, bit_length);
+    let left_amount = (bit_length - right) % bit_length;
+    match algorithm {
+        Algorithm::Naive => {
+            for k in 0 .. left_amount {
+                let first_bit = work.get(bit_offset);
+                for i in bit_offset .. end - 1 {
+                    work.set(i, work.get(i + 1));
+                }
+                work.set(end - 1, first_bit);
+                steps.push(Step {
+                    label: format!("rotate [{}, {}) left by one ({} of {})",
+                                   bit_offset, end, k + 1, left_amount),
+                    bits: snapshot(&work),
+                    touched: (bit_offset .. end).collect(),
+                });
+            }
+        }
+        Algorithm::Reversal => {
+            let split = bit_offset + left_amount;
+            for &(lo, hi) in &[(bit_offset, split), (split, end), (bit_offset, end)] {
+                if hi - lo < 2 {
+                    continue;
+                }
+                let (mut i, mut j) = (lo, hi - 1);
+                while i < j {
+                    swap(&mut work, i, j);
+                    steps.push(Step {
+                        label: format!("reverse [{}, {}): swap {} and {}", lo, hi, i, j),
+                        bits: snapshot(&work),
+                        touched: vec![i, j],
+                    });
+                    i += 1;
+                    j -= 1;
+                }
+            }
+        }
+    }
+    steps
+}
+
+const CELL: usize = 24;
+const LABEL_HEIGHT: usize = 18;
+const FRAME_HEIGHT: usize = LABEL_HEIGHT + CELL + 10;
+const MARGIN: usize = 10;
+const SECONDS_PER_STEP: f64 = 0.8;
+
+/// Renders the steps of a rotation as an SVG storyboard, one row per step
+/// with bit 0 on the left.  The rows are revealed one after another with
+/// SMIL animation, so opening the file in a browser plays it.
+pub fn rotation_svg(ba: &BitArray,
+                    bit_offset: usize,
+                    bit_length: usize,
+                    bit_right_amount: isize,
+                    algorithm: Algorithm) -> String {
+    let steps = rotation_steps(ba, bit_offset, bit_length, bit_right_amount, algorithm);
+    let n = ba.get_bit_sz();
+    let width = 2 * MARGIN + usize::max(n * CELL, 360);
+    let height = 2 * MARGIN + LABEL_HEIGHT + steps.len() * FRAME_HEIGHT;
+
+    let mut svg = String::new();
+    writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                   font-family=\"monospace\" font-size=\"12\">", width, height).unwrap();
+    writeln!(svg, "<title>{:?} rotate(offset={}, length={}, right_amount={})</title>",
+             algorithm, bit_offset, bit_length, bit_right_amount).unwrap();
+
+    // index header
+    for i in 0 .. n {
+        writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                 MARGIN + i * CELL + CELL / 2, MARGIN + 12, i).unwrap();
+    }
+
+    for (k, step) in steps.iter().enumerate() {
+        let top = MARGIN + LABEL_HEIGHT + k * FRAME_HEIGHT;
+        let begin = k as f64 * SECONDS_PER_STEP;
+        writeln!(svg, "<g opacity=\"0\">").unwrap();
+        writeln!(svg, "<set attributeName=\"opacity\" to=\"1\" begin=\"{:.1}s\" fill=\"freeze\"/>",
+                 begin).unwrap();
+        writeln!(svg, "<text x=\"{}\" y=\"{}\">{}. {}</text>",
+                 MARGIN, top + 12, k, step.label).unwrap();
+        for (i, &bit) in step.bits.iter().enumerate() {
+            let in_range = i >= bit_offset && i < bit_offset + bit_length;
+            let fill = if step.touched.contains(&i) {
+                "#f4a261"
+            } else if in_range {
+                "#e9f5ff"
+            } else {
+                "#dddddd"
+            };
+            let x = MARGIN + i * CELL;
+            let y = top + LABEL_HEIGHT;
+            writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                           fill=\"{}\" stroke=\"#333333\"/>",
+                     x, y, CELL, CELL, fill).unwrap();
+            writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                     x + CELL / 2, y + CELL / 2 + 4, if bit { 1 } else { 0 }).unwrap();
+        }
+        writeln!(svg, "</g>").unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_state(steps: &[Step]) -> BitArray {
+        let bits = &steps.last().unwrap().bits;
+        let mut ba = BitArray::new(bits.len());
+        for (i, &b) in bits.iter().enumerate() {
+            ba.set(i, b);
+        }
+        ba
+    }
+
+    #[test]
+    fn test_steps_match_rotate() {
+        for &algorithm in &[Algorithm::Naive, Algorithm::Reversal] {
+            for amount in (-9 .. 9).chain([isize::MIN, isize::MAX]) {
+                let ba = BitArray::from_str("1100101110");
+                let mut expected = BitArray::from_str("1100101110");
+                expected.rotate(2, 7, amount);
+                let steps = rotation_steps(&ba, 2, 7, amount, algorithm);
+                assert_eq!(last_state(&steps), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reversal_labels() {
+        let ba = BitArray::from_str("10010110");
+        let steps = rotation_steps(&ba, 0, 8, -3, Algorithm::Reversal);
+        assert_eq!(steps[0].label, "start");
+        assert_eq!(steps[1].label, "reverse [0, 3): swap 0 and 2");
+        assert_eq!(steps[1].touched, vec![0, 2]);
+        assert_eq!(steps.last().unwrap().label, "reverse [0, 8): swap 3 and 4");
+    }
+
+    #[test]
+    fn test_svg_has_a_frame_per_step() {
+        let ba = BitArray::from_str("10010110");
+        let svg = rotation_svg(&ba, 2, 5, 2, Algorithm::Naive);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<g ").count(), 4);
+    }
+}